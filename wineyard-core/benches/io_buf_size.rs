@@ -0,0 +1,60 @@
+use std::io::{Read, Write};
+use std::fs::File;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const FILE_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+
+const BUF_SIZES: &[usize] = &[
+    4 * 1024,
+    16 * 1024,
+    64 * 1024,
+    256 * 1024,
+    1024 * 1024,
+    4 * 1024 * 1024
+];
+
+/// Copy a big file using buffers of different sizes, the same way it's done
+/// by the downloader and the entries hasher.
+fn copy_file(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(".wineyard-core-io-buf-size-bench");
+
+    let input = path.join("input");
+    let output = path.join("output");
+
+    std::fs::create_dir_all(&path).unwrap();
+    std::fs::write(&input, vec![0x5A; FILE_SIZE]).unwrap();
+
+    let mut group = c.benchmark_group("copy_file");
+
+    group.sample_size(20);
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+
+    for buf_size in BUF_SIZES.iter().copied() {
+        group.bench_with_input(BenchmarkId::from_parameter(buf_size), &buf_size, |b, &buf_size| {
+            let mut buf = vec![0; buf_size];
+
+            b.iter(|| {
+                let mut input = File::open(&input).unwrap();
+                let mut output = File::create(&output).unwrap();
+
+                loop {
+                    let len = input.read(&mut buf).unwrap();
+
+                    if len == 0 {
+                        break;
+                    }
+
+                    output.write_all(&buf[..len]).unwrap();
+                }
+            });
+        });
+    }
+
+    group.finish();
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+criterion_group!(benches, copy_file);
+criterion_main!(benches);
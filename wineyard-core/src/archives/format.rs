@@ -1,4 +1,5 @@
 use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
 
 const FORMATS: &[(ArchiveFormat, &[&str])] = &[
     (ArchiveFormat::Tar, &[
@@ -34,6 +35,58 @@ pub enum ArchiveFormat {
 }
 
 impl ArchiveFormat {
+    /// Amount of bytes needed to detect the archive format from its header.
+    pub const MAGIC_LEN: usize = 512;
+
+    /// Assume archive format from its header bytes.
+    ///
+    /// Compressed streams (gzip, xz, bzip2 and zstd) are reported as tar
+    /// archives because tar can extract them.
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        // Tar header contains a magic string at offset 257.
+        if header.get(257..262) == Some(b"ustar") {
+            return Some(Self::Tar);
+        }
+
+        if header.starts_with(b"PK\x03\x04")
+            || header.starts_with(b"PK\x05\x06")
+            || header.starts_with(b"PK\x07\x08")
+        {
+            return Some(Self::Zip);
+        }
+
+        if header.starts_with(b"7z\xBC\xAF\x27\x1C") {
+            return Some(Self::Sevenz);
+        }
+
+        if header.starts_with(b"\x1F\x8B")
+            || header.starts_with(b"\xFD7zXZ\x00")
+            || header.starts_with(b"BZh")
+            || header.starts_with(b"\x28\xB5\x2F\xFD")
+        {
+            return Some(Self::Tar);
+        }
+
+        None
+    }
+
+    /// Assume archive format from the header read from the given reader.
+    ///
+    /// Reader's position is restored after the header is read.
+    pub fn from_reader(mut reader: impl Read + Seek) -> std::io::Result<Option<Self>> {
+        let position = reader.stream_position()?;
+
+        let mut header = Vec::with_capacity(Self::MAGIC_LEN);
+
+        reader.by_ref()
+            .take(Self::MAGIC_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        reader.seek(SeekFrom::Start(position))?;
+
+        Ok(Self::from_magic(&header))
+    }
+
     /// Assume archive format from the fs path.
     pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
         let path = path.as_ref()
@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::io::Write;
+use std::fs::File;
+
+use super::*;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Archive opened from an in-memory buffer.
+///
+/// Archives are processed by the external binaries which can only read
+/// files, so the buffer is stored in a temporary file which is owned by
+/// this struct and removed when it's dropped.
+#[derive(Debug)]
+pub struct MemoryArchive {
+    archive: Archive
+}
+
+impl MemoryArchive {
+    /// Open archive of the given format from the bytes buffer.
+    ///
+    /// The temporary file is created in the system temp folder.
+    #[inline]
+    pub fn open(
+        data: impl AsRef<[u8]>,
+        format: ArchiveFormat
+    ) -> Result<Self, ArchiveError> {
+        Self::open_in(std::env::temp_dir(), data, format)
+    }
+
+    /// Open archive of the given format from the bytes buffer, storing
+    /// the temporary file in the given folder.
+    pub fn open_in(
+        folder: impl AsRef<Path>,
+        data: impl AsRef<[u8]>,
+        format: ArchiveFormat
+    ) -> Result<Self, ArchiveError> {
+        let folder = folder.as_ref();
+
+        let ext = match format {
+            ArchiveFormat::Tar    => "tar",
+            ArchiveFormat::Zip    => "zip",
+            ArchiveFormat::Sevenz => "7z"
+        };
+
+        loop {
+            let path = folder.join(format!(
+                ".wineyard-archive-{}-{}.{ext}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            let Some(archive) = Archive::open_with_format(&path, format) else {
+                return Err(ArchiveError::UnsupportedFormat(format.to_string()));
+            };
+
+            // Never reuse or follow an existing file.
+            let mut file = match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+
+                Err(err) => return Err(err.into())
+            };
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(?path, ?format, "open in-memory archive");
+
+            // Take ownership of the file so it's removed on errors.
+            let archive = Self {
+                archive
+            };
+
+            file.write_all(data.as_ref())?;
+            file.flush()?;
+
+            return Ok(archive);
+        }
+    }
+
+    /// Get path of the temporary file storing the archive.
+    #[inline]
+    pub fn path(&self) -> &PathBuf {
+        self.archive.path()
+    }
+}
+
+impl std::ops::Deref for MemoryArchive {
+    type Target = Archive;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.archive
+    }
+}
+
+impl Drop for MemoryArchive {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(self.archive.path());
+    }
+}
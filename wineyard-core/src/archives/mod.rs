@@ -1,11 +1,16 @@
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use std::thread::JoinHandle;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::io::{BufRead, BufReader};
 
 mod format;
+mod memory;
 
 pub use format::ArchiveFormat;
+pub use memory::MemoryArchive;
 
 #[cfg(feature = "archives-tar")]
 mod tar;
@@ -29,7 +34,106 @@ pub enum ArchiveError {
     UnsupportedFormat(String),
 
     #[error("failed to extract archive: {0}")]
-    ExtractionError(&'static str)
+    ExtractionError(&'static str),
+
+    #[error("failed to read archive: {0}")]
+    ReadError(&'static str),
+
+    #[error("archive is encrypted and requires a password")]
+    PasswordRequired,
+
+    #[error("wrong archive password")]
+    WrongPassword,
+
+    #[error("unsupported archive encryption: {0}")]
+    UnsupportedEncryption(String),
+
+    #[error("archive extraction was aborted")]
+    Aborted
+}
+
+/// Options of the archive extraction.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtractOptions {
+    /// Password of the encrypted archive.
+    ///
+    /// Zip archives encrypted with AES require 7z to be installed. Other
+    /// encrypted zip archives are extracted with unzip if 7z is not
+    /// installed, which gets the password in its command line arguments,
+    /// so it can be read by other local users while unzip is running.
+    ///
+    /// Ignored by the formats which don't support encryption.
+    pub password: Option<String>,
+
+    /// Glob patterns of the entries to extract. Extract all the entries
+    /// if empty.
+    pub include: Vec<String>,
+
+    /// Glob patterns of the entries which shouldn't be extracted.
+    pub exclude: Vec<String>,
+
+    /// Skip files which already exist in the output folder with the same
+    /// size as in the archive. This allows to continue interrupted
+    /// extraction without writing the same files again.
+    ///
+    /// Content of the existing files is not verified. Skipped files are
+    /// still counted in the extraction progress.
+    pub skip_existing: bool
+}
+
+impl ExtractOptions {
+    /// Check if only a subset of the archive entries should be extracted.
+    #[inline]
+    pub fn is_filtered(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Check if the archive entry should be extracted.
+    ///
+    /// See `glob::matches` for the patterns syntax.
+    pub fn is_selected(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref().to_string_lossy();
+
+        let included = self.include.is_empty() || self.include.iter()
+            .any(|pattern| crate::glob::matches(pattern, &path));
+
+        included && !self.exclude.iter()
+            .any(|pattern| crate::glob::matches(pattern, &path))
+    }
+
+    /// Remove already extracted files from the given entries list if
+    /// `skip_existing` is enabled.
+    ///
+    /// Return amount of the removed files and their total size.
+    #[cfg(any(feature = "archives-tar", feature = "archives-zip", feature = "archives-7z"))]
+    pub(crate) fn skip_extracted(
+        &self,
+        folder: &Path,
+        entries: &mut Vec<ArchiveEntry>
+    ) -> (usize, u64) {
+        if !self.skip_existing {
+            return (0, 0);
+        }
+
+        let len = entries.len();
+        let mut skipped = 0;
+
+        entries.retain(|entry| {
+            let is_extracted = !entry.is_dir && std::fs::metadata(folder.join(&entry.path))
+                .is_ok_and(|metadata| metadata.is_file() && metadata.len() == entry.size);
+
+            if is_extracted {
+                skipped += entry.size;
+            }
+
+            !is_extracted
+        });
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?folder, skipped = len - entries.len(), "skip extracted archive entries");
+
+        (len - entries.len(), skipped)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -37,11 +141,171 @@ pub struct ArchiveEntry {
     /// Relative path of the archive entry.
     pub path: PathBuf,
 
-    /// Size of the archive entry.
+    /// Uncompressed size of the archive entry.
     ///
-    /// Depending on implementation this could either mean compressed or
-    /// uncompressed size.
-    pub size: u64
+    /// Always zero for directories.
+    pub size: u64,
+
+    /// Whether the archive entry is a directory.
+    pub is_dir: bool,
+
+    /// Unix permissions of the archive entry, if stored in the archive.
+    pub mode: Option<u32>,
+
+    /// Last modification time of the archive entry, if stored in the archive.
+    ///
+    /// Zip and 7z archives don't always store timezones so their timestamps
+    /// are treated as UTC.
+    pub modified: Option<SystemTime>,
+
+    /// Comment of the archive entry, if stored in the archive.
+    ///
+    /// Only zip archives support entry comments. They're read only by
+    /// `Archive::get_entries_with_comments`.
+    pub comment: Option<String>
+}
+
+/// Build path of the archive entry extracted to the given folder.
+///
+/// Extractors print original entry names, so leading slashes are stripped
+/// the same way extractors do it. Return `None` if the entry has `..`
+/// components or its parent folder is not inside of the given folder,
+/// e.g. because of a symlink.
+#[cfg(any(feature = "archives-tar", feature = "archives-zip", feature = "archives-7z"))]
+fn extracted_entry_path(folder: &Path, entry: impl AsRef<Path>) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let mut path = folder.to_path_buf();
+
+    for component in entry.as_ref().components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+
+            Component::RootDir |
+            Component::CurDir |
+            Component::Prefix(_) => (),
+
+            Component::ParentDir => return None
+        }
+    }
+
+    if path == folder {
+        return None;
+    }
+
+    let folder = folder.canonicalize().ok()?;
+    let parent = path.parent()?.canonicalize().ok()?;
+
+    parent.starts_with(folder).then_some(path)
+}
+
+/// Kill the extraction process after the extractor was aborted, removing
+/// the given entry which could be partially written.
+#[cfg(any(feature = "archives-tar", feature = "archives-zip", feature = "archives-7z"))]
+fn abort_extraction(
+    child: &mut std::process::Child,
+    folder: &Path,
+    entry: impl AsRef<Path>
+) -> ArchiveError {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(?folder, entry = ?entry.as_ref(), "abort archive extraction");
+
+    // The process could already exit so errors are ignored.
+    let _ = child.kill();
+    let _ = child.wait();
+
+    if let Some(entry) = extracted_entry_path(folder, entry)
+        && entry.is_file()
+    {
+        let _ = std::fs::remove_file(entry);
+    }
+
+    ArchiveError::Aborted
+}
+
+/// Split the line into `n` whitespace-separated fields and the rest
+/// of the line.
+#[cfg(any(feature = "archives-tar", feature = "archives-zip"))]
+fn split_fields(line: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(n);
+    let mut rest = line.trim_start();
+
+    for _ in 0..n {
+        let (field, tail) = rest.split_once(char::is_whitespace)?;
+
+        fields.push(field);
+        rest = tail.trim_start();
+    }
+
+    Some((fields, rest))
+}
+
+/// Parse unix permissions from the `ls -l`-like flags string
+/// (e.g. `drwxr-xr-x`).
+#[cfg(any(feature = "archives-tar", feature = "archives-zip"))]
+fn parse_mode(flags: &str) -> Option<u32> {
+    let flags = flags.as_bytes();
+
+    if flags.len() < 10 {
+        return None;
+    }
+
+    let mut mode = 0;
+
+    for (i, flag) in flags[1..10].iter().enumerate() {
+        let bit = 1 << (8 - i);
+
+        match (i % 3, flag) {
+            (_, b'-') => (),
+
+            (0, b'r') | (1, b'w') | (2, b'x') => mode |= bit,
+
+            // setuid, setgid and sticky bits.
+            (2, b's' | b't') => mode |= bit | (0o4000 >> (i / 3)),
+            (2, b'S' | b'T') => mode |= 0o4000 >> (i / 3),
+
+            _ => return None
+        }
+    }
+
+    Some(mode)
+}
+
+/// Parse UTC date and time in `YYYY-MM-DD` and `HH:MM[:SS]` formats.
+#[cfg(any(feature = "archives-tar", feature = "archives-zip", feature = "archives-7z"))]
+fn parse_datetime(date: &str, time: &str) -> Option<SystemTime> {
+    let mut date = date.split('-').map(str::parse::<i64>);
+
+    let year = date.next()?.ok()?;
+    let month = date.next()?.ok()?;
+    let day = date.next()?.ok()?;
+
+    let mut time = time.split(':').map(str::parse::<i64>);
+
+    let hours = time.next()?.ok()?;
+    let minutes = time.next()?.ok()?;
+    let seconds = time.next().transpose().ok()?.unwrap_or_default();
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days from the unix epoch to the given date.
+    // Source: https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let timestamp = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+
+    if timestamp < 0 {
+        return None;
+    }
+
+    Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp as u64))
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -59,11 +323,16 @@ pub enum Archive {
 impl Archive {
     /// Open archive from the given file, automatically predict its format.
     ///
+    /// The format is detected from the file header, or from the file
+    /// extension if the header is not recognized.
+    ///
     /// Return `None` if format is not supported.
     pub fn open(path: impl Into<PathBuf>) -> Option<Self> {
         let path: PathBuf = path.into();
 
-        let format = ArchiveFormat::from_path(&path)?;
+        let format = std::fs::File::open(&path).ok()
+            .and_then(|file| ArchiveFormat::from_reader(file).ok().flatten())
+            .or_else(|| ArchiveFormat::from_path(&path))?;
 
         Self::open_with_format(path, format)
     }
@@ -95,6 +364,30 @@ impl Archive {
         }
     }
 
+    /// Open archive of the given format from the bytes buffer.
+    ///
+    /// See `MemoryArchive` for details.
+    #[inline]
+    pub fn open_bytes(
+        data: impl AsRef<[u8]>,
+        format: ArchiveFormat
+    ) -> Result<MemoryArchive, ArchiveError> {
+        MemoryArchive::open(data, format)
+    }
+
+    /// Open archive of the given format from the bytes buffer, storing
+    /// the temporary file in the given folder.
+    ///
+    /// See `MemoryArchive` for details.
+    #[inline]
+    pub fn open_bytes_in(
+        folder: impl AsRef<Path>,
+        data: impl AsRef<[u8]>,
+        format: ArchiveFormat
+    ) -> Result<MemoryArchive, ArchiveError> {
+        MemoryArchive::open_in(folder, data, format)
+    }
+
     /// Get path of the currently open archive.
     pub const fn path(&self) -> &PathBuf {
         match self {
@@ -126,6 +419,79 @@ impl Archive {
         }
     }
 
+    /// Get list of archive entries with their comments.
+    ///
+    /// Reading comments requires listing the archive one more time so
+    /// `get_entries` should be preferred when comments aren't needed.
+    pub fn get_entries_with_comments(&self) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = ?self.path(), "get archive entries with comments");
+
+        match self {
+            #[cfg(feature = "archives-tar")]
+            Self::Tar(archive) => tar::get_entries(archive),
+
+            #[cfg(feature = "archives-zip")]
+            Self::Zip(archive) => zip::get_entries_with_comments(archive),
+
+            #[cfg(feature = "archives-7z")]
+            Self::Sevenz(archive) => sevenz::get_entries(archive)
+        }
+    }
+
+    /// Iterate over archive entries without reading the whole list.
+    ///
+    /// Comments of the zip archive entries are not read.
+    pub fn iter_entries(&self) -> Result<ArchiveEntries, ArchiveError> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = ?self.path(), "iterate archive entries");
+
+        match self {
+            #[cfg(feature = "archives-tar")]
+            Self::Tar(archive) => tar::iter_entries(archive),
+
+            #[cfg(feature = "archives-zip")]
+            Self::Zip(archive) => zip::iter_entries(archive),
+
+            #[cfg(feature = "archives-7z")]
+            Self::Sevenz(archive) => sevenz::iter_entries(archive)
+        }
+    }
+
+    /// Get comment of the archive.
+    ///
+    /// Only zip archives support comments, `None` is returned for other
+    /// formats.
+    pub fn comment(&self) -> Result<Option<String>, ArchiveError> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = ?self.path(), "get archive comment");
+
+        match self {
+            #[cfg(feature = "archives-tar")]
+            Self::Tar(_) => Ok(None),
+
+            #[cfg(feature = "archives-zip")]
+            Self::Zip(archive) => zip::get_comment(archive),
+
+            #[cfg(feature = "archives-7z")]
+            Self::Sevenz(_) => Ok(None)
+        }
+    }
+
+    /// Get sum of uncompressed sizes of all the archive entries.
+    ///
+    /// This is the same value as the `total` reported by the extractor
+    /// when the whole archive is extracted.
+    pub fn total_uncompressed_size(&self) -> Result<u64, ArchiveError> {
+        let size = self.get_entries()?
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| entry.size)
+            .sum();
+
+        Ok(size)
+    }
+
     /// Extract archive's content to a folder.
     #[inline]
     pub fn extract(
@@ -137,10 +503,22 @@ impl Archive {
 
     /// Extract archive's content to a folder and report `(curr, total, diff)`
     /// bytes using the `progress` callback.
+    #[inline]
     pub fn extract_with_progress(
         &self,
         folder: impl AsRef<Path>,
         progress: impl FnMut(u64, u64, u64) + Send + 'static
+    ) -> Result<ArchiveExtractor, ArchiveError> {
+        self.extract_with_options(folder, &ExtractOptions::default(), progress)
+    }
+
+    /// Extract archive's content to a folder using given options and report
+    /// `(curr, total, diff)` bytes using the `progress` callback.
+    pub fn extract_with_options(
+        &self,
+        folder: impl AsRef<Path>,
+        options: &ExtractOptions,
+        progress: impl FnMut(u64, u64, u64) + Send + 'static
     ) -> Result<ArchiveExtractor, ArchiveError> {
         let folder = folder.as_ref();
 
@@ -149,24 +527,38 @@ impl Archive {
 
         match self {
             #[cfg(feature = "archives-tar")]
-            Self::Tar(archive) => tar::extract(archive, folder, progress),
+            Self::Tar(archive) => tar::extract(archive, folder, options, progress),
 
             #[cfg(feature = "archives-zip")]
-            Self::Zip(archive) => zip::extract(archive, folder, progress),
+            Self::Zip(archive) => zip::extract(archive, folder, options, progress),
 
             #[cfg(feature = "archives-7z")]
-            Self::Sevenz(archive) => sevenz::extract(archive, folder, progress)
+            Self::Sevenz(archive) => sevenz::extract(archive, folder, options, progress)
         }
     }
 }
 
 pub struct ArchiveExtractor {
-    pub(crate) worker: JoinHandle<()>,
+    pub(crate) worker: JoinHandle<Result<(), ArchiveError>>,
     pub(crate) current: Arc<AtomicU64>,
-    pub(crate) total: u64
+    pub(crate) total: u64,
+    pub(crate) aborted: Arc<AtomicBool>
 }
 
 impl ArchiveExtractor {
+    /// Create already finished extractor which didn't extract anything.
+    ///
+    /// `skipped` is the size of the already extracted files.
+    #[cfg(any(feature = "archives-tar", feature = "archives-zip", feature = "archives-7z"))]
+    pub(crate) fn finished(skipped: u64) -> Self {
+        Self {
+            worker: std::thread::spawn(|| Ok(())),
+            current: Arc::new(AtomicU64::new(skipped)),
+            total: skipped,
+            aborted: Arc::new(AtomicBool::new(false))
+        }
+    }
+
     #[inline]
     pub fn current(&self) -> u64 {
         self.current.load(Ordering::Relaxed)
@@ -182,10 +574,615 @@ impl ArchiveExtractor {
         self.worker.is_finished()
     }
 
+    /// Stop the extraction before the next archive entry is extracted.
+    ///
+    /// The entry which is being extracted at this moment is removed since
+    /// it could be written partially. Already extracted entries are kept
+    /// so the extraction can be continued using `skip_existing` option.
+    /// `wait` returns `ArchiveError::Aborted` if the extraction was stopped.
+    #[inline]
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::Release);
+    }
+
+    /// Check if the extraction was requested to stop.
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Acquire)
+    }
+
     pub fn wait(self) -> Result<(), ArchiveError> {
         self.worker
             .join()
-            .map_err(|_| ArchiveError::ExtractionError("failed to join the thread"))?;
+            .map_err(|_| ArchiveError::ExtractionError("failed to join the thread"))?
+    }
+}
+
+type EntryParser = Box<dyn FnMut(&str) -> Option<ArchiveEntry> + Send>;
+
+/// Lazy iterator over the archive entries.
+///
+/// Entries are parsed from the archiver output while it's still running.
+/// The archiver process is killed if the iterator is dropped before
+/// reaching the end of the list.
+pub struct ArchiveEntries {
+    child: Child,
+    lines: std::io::Split<BufReader<ChildStdout>>,
+    parser: EntryParser,
+    finished: bool
+}
+
+impl ArchiveEntries {
+    /// Spawn the archiver command and parse its output lines using
+    /// provided parser.
+    #[cfg(any(feature = "archives-tar", feature = "archives-zip", feature = "archives-7z"))]
+    pub(crate) fn spawn(
+        command: &mut Command,
+        parser: impl FnMut(&str) -> Option<ArchiveEntry> + Send + 'static
+    ) -> Result<Self, ArchiveError> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let Some(stdout) = child.stdout.take() else {
+            return Err(ArchiveError::ReadError("failed to read archiver output"));
+        };
+
+        Ok(Self {
+            child,
+            lines: BufReader::new(stdout).split(b'\n'),
+            parser: Box::new(parser),
+            finished: false
+        })
+    }
+}
+
+impl Iterator for ArchiveEntries {
+    type Item = Result<ArchiveEntry, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            match self.lines.next() {
+                Some(Ok(line)) => {
+                    let line = String::from_utf8_lossy(&line);
+
+                    if let Some(entry) = (self.parser)(line.trim_end_matches('\r')) {
+                        return Some(Ok(entry));
+                    }
+                }
+
+                Some(Err(err)) => {
+                    self.finished = true;
+
+                    return Some(Err(err.into()));
+                }
+
+                None => {
+                    self.finished = true;
+
+                    if let Err(err) = self.child.wait() {
+                        return Some(Err(err.into()));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Drop for ArchiveEntries {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+        }
+
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[cfg(feature = "archives-tar")]
+    #[test]
+    fn extracted_entry_path() -> std::io::Result<()> {
+        let path = std::env::temp_dir().join(".wineyard-core-extracted-entry-path-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("folder/dir"))?;
+        std::os::unix::fs::symlink("/", path.join("folder/root"))?;
+
+        let folder = path.join("folder");
+
+        assert_eq!(super::extracted_entry_path(&folder, "dir/file"), Some(folder.join("dir/file")));
+        assert_eq!(super::extracted_entry_path(&folder, "./dir/file"), Some(folder.join("dir/file")));
+
+        // Absolute names are extracted inside of the folder.
+        assert_eq!(super::extracted_entry_path(&folder, "/dir/file"), Some(folder.join("dir/file")));
+
+        assert_eq!(super::extracted_entry_path(&folder, "../file"), None);
+        assert_eq!(super::extracted_entry_path(&folder, "dir/../../file"), None);
+        assert_eq!(super::extracted_entry_path(&folder, "root/etc/passwd"), None);
+        assert_eq!(super::extracted_entry_path(&folder, "/"), None);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "archives-tar", feature = "archives-zip"))]
+    #[test]
+    fn parse_entry_metadata() {
+        assert_eq!(parse_mode("-rw-r--r--"), Some(0o644));
+        assert_eq!(parse_mode("drwxr-xr-x"), Some(0o755));
+        assert_eq!(parse_mode("-rwsr-xr-T"), Some(0o5754));
+        assert_eq!(parse_mode("-rw-a--"), None);
+
+        assert_eq!(parse_datetime("1970-01-01", "00:00"), Some(UNIX_EPOCH));
+        assert_eq!(parse_datetime("2024-02-29", "12:30:15"), Some(UNIX_EPOCH + Duration::from_secs(1709209815)));
+        assert_eq!(parse_datetime("2024-13-01", "00:00"), None);
+
+        assert_eq!(split_fields("a  b c d", 2), Some((vec!["a", "b"], "c d")));
+        assert_eq!(split_fields("a", 2), None);
+    }
+
+    #[test]
+    fn format_from_magic() -> std::io::Result<()> {
+        let mut tar_header = [0; 512];
+
+        tar_header[257..262].copy_from_slice(b"ustar");
+
+        assert_eq!(ArchiveFormat::from_magic(&tar_header), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_magic(b"\x1F\x8B\x08"), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_magic(b"PK\x03\x04"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_magic(b"7z\xBC\xAF\x27\x1C"), Some(ArchiveFormat::Sevenz));
+        assert_eq!(ArchiveFormat::from_magic(b"<!DOCTYPE html>"), None);
+        assert_eq!(ArchiveFormat::from_magic(b""), None);
+
+        let mut reader = std::io::Cursor::new(b"....PK\x03\x04".to_vec());
+
+        reader.set_position(4);
+
+        assert_eq!(ArchiveFormat::from_reader(&mut reader)?, Some(ArchiveFormat::Zip));
+        assert_eq!(reader.position(), 4);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archives-tar")]
+    #[test]
+    fn tar_entries() -> Result<(), ArchiveError> {
+        let path = std::env::temp_dir().join(".wineyard-core-tar-entries-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source/dir"))?;
+        std::fs::write(path.join("source/dir/file name.txt"), b"Hello, World!")?;
+
+        let status = std::process::Command::new("tar")
+            .arg("-cf")
+            .arg(path.join("archive.tar"))
+            .arg("-C")
+            .arg(path.join("source"))
+            .arg("dir")
+            .status()?;
+
+        assert!(status.success());
+
+        let mut entries = Archive::open(path.join("archive.tar"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("tar")))?
+            .get_entries()?;
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].path, PathBuf::from("dir/"));
+        assert!(entries[0].is_dir);
+
+        assert_eq!(entries[1].path, PathBuf::from("dir/file name.txt"));
+        assert_eq!(entries[1].size, 13);
+        assert!(!entries[1].is_dir);
+        assert!(entries[1].mode.is_some());
+        assert!(entries[1].modified.is_some());
+
+        let archive = Archive::open(path.join("archive.tar"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("tar")))?;
+
+        assert_eq!(archive.total_uncompressed_size()?, 13);
+
+        // Format is detected from the header regardless of the extension.
+        std::fs::copy(path.join("archive.tar"), path.join("archive.bin"))?;
+
+        assert!(matches!(Archive::open(path.join("archive.bin")), Some(Archive::Tar(_))));
+
+        let archive = Archive::open_bytes(
+            std::fs::read(path.join("archive.tar"))?,
+            ArchiveFormat::Tar
+        )?;
+
+        let temp_path = archive.path().clone();
+
+        assert!(temp_path.is_file());
+
+        archive.extract(path.join("extracted"))?.wait()?;
+
+        assert_eq!(std::fs::read(path.join("extracted/dir/file name.txt"))?, b"Hello, World!");
+
+        drop(archive);
+
+        assert!(!temp_path.exists());
+
+        std::fs::create_dir_all(path.join("temp"))?;
+
+        let archive = Archive::open_bytes_in(
+            path.join("temp"),
+            std::fs::read(path.join("archive.tar"))?,
+            ArchiveFormat::Tar
+        )?;
+
+        assert!(archive.path().starts_with(path.join("temp")));
+        assert_eq!(archive.get_entries()?.len(), 2);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "archives-tar", feature = "archives-zip"))]
+    #[test]
+    fn iter_entries() -> Result<(), ArchiveError> {
+        let path = std::env::temp_dir().join(".wineyard-core-iter-entries-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source/dir"))?;
+
+        for i in 0..256 {
+            std::fs::write(path.join(format!("source/dir/{i}.txt")), i.to_string())?;
+        }
+
+        let tar = std::process::Command::new("tar")
+            .arg("-cf")
+            .arg(path.join("archive.tar"))
+            .arg("-C")
+            .arg(path.join("source"))
+            .arg("dir")
+            .status()?;
+
+        let zip = std::process::Command::new("zip")
+            .arg("-qr")
+            .arg(path.join("archive.zip"))
+            .arg("dir")
+            .current_dir(path.join("source"))
+            .status()?;
+
+        assert!(tar.success() && zip.success());
+
+        for name in ["archive.tar", "archive.zip"] {
+            let archive = Archive::open(path.join(name))
+                .ok_or_else(|| ArchiveError::UnsupportedFormat(name.to_string()))?;
+
+            let entries = archive.iter_entries()?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            assert_eq!(entries.len(), 257);
+            assert_eq!(entries, archive.get_entries()?);
+
+            // Stop reading in the middle of the list.
+            let mut entries = archive.iter_entries()?;
+
+            assert!(entries.next().transpose()?.is_some());
+
+            drop(entries);
+        }
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archives-tar")]
+    #[test]
+    fn skip_existing() -> Result<(), ArchiveError> {
+        let path = std::env::temp_dir().join(".wineyard-core-skip-existing-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source/dir"))?;
+        std::fs::write(path.join("source/dir/a.txt"), b"Hello, World!")?;
+        std::fs::write(path.join("source/dir/b.txt"), b"Example text")?;
+
+        let status = std::process::Command::new("tar")
+            .arg("-cf")
+            .arg(path.join("archive.tar"))
+            .arg("-C")
+            .arg(path.join("source"))
+            .arg("dir")
+            .status()?;
+
+        assert!(status.success());
+
+        let archive = Archive::open(path.join("archive.tar"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("tar")))?;
+
+        let options = ExtractOptions {
+            skip_existing: true,
+            ..ExtractOptions::default()
+        };
+
+        // Simulate interrupted extraction.
+        std::fs::create_dir_all(path.join("extracted/dir"))?;
+        std::fs::write(path.join("extracted/dir/a.txt"), b"Hello, ")?;
+        std::fs::write(path.join("extracted/dir/b.txt"), b"Skipped text")?;
+
+        let extractor = archive.extract_with_options(path.join("extracted"), &options, |_, _, _| {})?;
+
+        assert_eq!(extractor.total(), 25);
+
+        extractor.wait()?;
+
+        assert_eq!(std::fs::read(path.join("extracted/dir/a.txt"))?, b"Hello, World!");
+        assert_eq!(std::fs::read(path.join("extracted/dir/b.txt"))?, b"Skipped text");
+
+        // Nothing is extracted if all the files exist.
+        let extractor = archive.extract_with_options(path.join("extracted"), &options, |_, _, _| {})?;
+
+        assert_eq!(extractor.current(), 25);
+        assert_eq!(extractor.total(), 25);
+
+        extractor.wait()?;
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archives-zip")]
+    #[test]
+    fn zip_comments() -> Result<(), ArchiveError> {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(".wineyard-core-zip-comments-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("dir"))?;
+        std::fs::write(path.join("dir/a.txt"), b"Hello")?;
+        std::fs::write(path.join("dir/b.txt"), b"Hello, World!")?;
+
+        let status = std::process::Command::new("zip")
+            .current_dir(&path)
+            .arg("-qr")
+            .arg("archive.zip")
+            .arg("dir")
+            .status()?;
+
+        assert!(status.success());
+
+        let archive = Archive::open(path.join("archive.zip"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("zip")))?;
+
+        assert_eq!(archive.comment()?, None);
+
+        // Comments are read by zip from stdin.
+        let comment = |args: &[&str], comment: &str| -> Result<(), ArchiveError> {
+            let mut child = std::process::Command::new("zip")
+                .current_dir(&path)
+                .args(args)
+                .stdin(std::process::Stdio::piped())
+                .spawn()?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(comment.as_bytes())?;
+            }
+
+            assert!(child.wait()?.success());
+
+            Ok(())
+        };
+
+        comment(&["-qz", "archive.zip"], "version 1.2.3\nbuild 4\n")?;
+        comment(&["-qc", "archive.zip", "dir/b.txt"], "entry comment\n")?;
+
+        assert_eq!(archive.comment()?.as_deref(), Some("version 1.2.3\nbuild 4"));
+
+        // Comments are not read unless asked.
+        assert!(archive.get_entries()?.iter().all(|entry| entry.comment.is_none()));
+
+        let mut entries = archive.get_entries_with_comments()?;
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].comment, None);
+        assert_eq!(entries[1].comment, None);
+        assert_eq!(entries[2].path, PathBuf::from("dir/b.txt"));
+        assert_eq!(entries[2].comment.as_deref(), Some("entry comment"));
+
+        // Broken archives are reported instead of having no comments.
+        std::fs::write(path.join("broken.zip"), b"PK\x03\x04 broken archive")?;
+
+        let archive = Archive::open(path.join("broken.zip"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("zip")))?;
+
+        assert!(archive.comment().is_err());
+        assert!(archive.get_entries_with_comments().is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archives-zip")]
+    #[test]
+    fn skip_existing_many() -> Result<(), ArchiveError> {
+        let path = std::env::temp_dir().join(".wineyard-core-skip-existing-many-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source/dir"))?;
+
+        // Long names which don't fit a single unzip command.
+        let name = |i: usize| format!("dir/{i:0>128}.txt");
+
+        for i in 0..1024 {
+            std::fs::write(path.join("source").join(name(i)), b"Hello, World!")?;
+        }
+
+        let status = std::process::Command::new("zip")
+            .current_dir(path.join("source"))
+            .arg("-qr")
+            .arg(path.join("archive.zip"))
+            .arg("dir")
+            .status()?;
+
+        assert!(status.success());
+
+        let archive = Archive::open(path.join("archive.zip"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("zip")))?;
+
+        let options = ExtractOptions {
+            skip_existing: true,
+            ..ExtractOptions::default()
+        };
+
+        std::fs::create_dir_all(path.join("extracted/dir"))?;
+        std::fs::write(path.join("extracted").join(name(0)), b"Hello, World!")?;
+
+        let extractor = archive.extract_with_options(path.join("extracted"), &options, |_, _, _| {})?;
+
+        assert_eq!(extractor.total(), 1024 * 13);
+
+        extractor.wait()?;
+
+        for i in 0..1024 {
+            assert_eq!(std::fs::read(path.join("extracted").join(name(i)))?, b"Hello, World!");
+        }
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archives-tar")]
+    #[test]
+    fn abort_extraction() -> Result<(), ArchiveError> {
+        let path = std::env::temp_dir().join(".wineyard-core-abort-extraction-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source/dir"))?;
+
+        for i in 0..16 {
+            std::fs::write(path.join(format!("source/dir/{i}.txt")), b"Hello, World!")?;
+        }
+
+        let status = std::process::Command::new("tar")
+            .arg("-cf")
+            .arg(path.join("archive.tar"))
+            .arg("-C")
+            .arg(path.join("source"))
+            .arg("dir")
+            .status()?;
+
+        assert!(status.success());
+
+        let archive = Archive::open(path.join("archive.tar"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("tar")))?;
+
+        // Block the extraction on the first entry until it's aborted.
+        let (started_send, started_recv) = std::sync::mpsc::channel::<()>();
+        let (aborted_send, aborted_recv) = std::sync::mpsc::channel::<()>();
+
+        let extractor = archive.extract_with_progress(path.join("extracted"), move |_, _, _| {
+            let _ = started_send.send(());
+            let _ = aborted_recv.recv();
+        })?;
+
+        let _ = started_recv.recv();
+
+        extractor.abort();
+
+        drop(aborted_send);
+
+        assert!(extractor.is_aborted());
+        assert!(matches!(extractor.wait(), Err(ArchiveError::Aborted)));
+
+        let extracted = std::fs::read_dir(path.join("extracted/dir"))?.count();
+
+        assert!(extracted < 16);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "archives-zip")]
+    #[test]
+    fn zip_password() -> Result<(), ArchiveError> {
+        let path = std::env::temp_dir().join(".wineyard-core-zip-password-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source"))?;
+        std::fs::write(path.join("source/file.txt"), b"Hello, World!")?;
+
+        let status = std::process::Command::new("zip")
+            .current_dir(path.join("source"))
+            .arg("-q")
+            .arg("-P")
+            .arg("secret")
+            .arg(path.join("archive.zip"))
+            .arg("file.txt")
+            .status()?;
+
+        assert!(status.success());
+
+        let archive = Archive::open(path.join("archive.zip"))
+            .ok_or_else(|| ArchiveError::UnsupportedFormat(String::from("zip")))?;
+
+        let extract = |password: Option<&str>| {
+            let options = ExtractOptions {
+                password: password.map(String::from),
+                ..ExtractOptions::default()
+            };
+
+            archive.extract_with_options(path.join("extracted"), &options, |_, _, _| {})?
+                .wait()
+        };
+
+        assert!(matches!(extract(None), Err(ArchiveError::PasswordRequired)));
+        assert!(matches!(extract(Some("wrong")), Err(ArchiveError::WrongPassword)));
+
+        extract(Some("secret"))?;
+
+        assert_eq!(std::fs::read(path.join("extracted/file.txt"))?, b"Hello, World!");
+
+        std::fs::remove_dir_all(path)?;
 
         Ok(())
     }
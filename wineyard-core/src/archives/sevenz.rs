@@ -1,9 +1,10 @@
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
+use std::fs::File;
 
 use super::*;
 
@@ -26,30 +27,71 @@ lazy_static::lazy_static! {
     };
 }
 
+/// Temporary file with the list of entries names passed to 7z instead of the
+/// command line arguments, which have limited size. Removed when dropped.
+struct ListFile(PathBuf);
+
+impl ListFile {
+    fn create<'a>(names: impl IntoIterator<Item = &'a String>) -> std::io::Result<Self> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let temp = std::env::temp_dir();
+
+        loop {
+            let path = temp.join(format!(
+                ".wineyard-7z-list-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            // Never reuse or follow an existing file.
+            let mut file = match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+
+                Err(err) => return Err(err)
+            };
+
+            let list = Self(path);
+
+            for name in names {
+                file.write_all(name.as_bytes())?;
+                file.write_all(b"\n")?;
+            }
+
+            file.flush()?;
+
+            return Ok(list);
+        }
+    }
+}
+
+impl Drop for ListFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
 pub fn get_entries(
     path: impl AsRef<Path>
 ) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    iter_entries(path)?.collect()
+}
+
+pub fn iter_entries(
+    path: impl AsRef<Path>
+) -> Result<ArchiveEntries, ArchiveError> {
     let Some(binary) = SEVENZ_BINARY.as_ref() else {
         return Err(ArchiveError::SevenzNotAvailable);
     };
 
-    let output = Command::new(binary)
-        .arg("l")
-        .arg(path.as_ref())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()?;
-
-    let output = String::from_utf8_lossy(&output.stdout);
-
-    let output = output
-        .split("-------------------")
-        .skip(1)
-        .collect::<Vec<_>>();
+    let mut command = Command::new(binary);
 
-    let mut output = output[..output.len() - 1].join("-------------------");
+    command.arg("l").arg(path.as_ref());
 
-    // In some cases 7z can report two ending sequences instead of one:
+    // Entries are listed between the first two separator lines. In some
+    // cases 7z can report two ending sequences instead of one:
     //
     // ```
     // ------------------- ----- ------------ ------------  ------------------------
@@ -59,42 +101,62 @@ pub fn get_entries(
     // 2023-09-15 10:20:44        66677218871  65387995385  13810 files, 81 folders
     // ```
     //
-    // This should filter this case.
-    if let Some((files_list, _)) = output.split_once("\n-------------------") {
-        output = files_list.to_string();
-    }
+    // so everything after the second separator is ignored.
+    let mut separators = 0;
 
-    let entries = output
-        .split('\n')
-        .filter(|line| !line.starts_with('-') && !line.starts_with(" -"))
-        .map(|line| {
-            line.split("  ").filter_map(|word| {
-                let word = word.trim();
+    ArchiveEntries::spawn(&mut command, move |line| {
+        if line.starts_with("-------------------") {
+            separators += 1;
 
-                if word.is_empty() { None } else { Some(word) }
-            })
-        })
-        .flat_map(|mut words| {
-            let size = words.nth(1).map(|size| size.parse());
-            let path = words.last().map(PathBuf::from);
-
-            if let (Some(path), Some(Ok(size))) = (path, size) {
-                Some(ArchiveEntry {
-                    path,
-                    size
-                })
-            } else {
-                None
-            }
-        })
-        .collect();
+            return None;
+        }
+
+        if separators != 1 || line.starts_with(" -") {
+            return None;
+        }
 
-    Ok(entries)
+        parse_entry(line)
+    })
+}
+
+fn parse_entry(line: &str) -> Option<ArchiveEntry> {
+    let mut words = line.split("  ").filter_map(|word| {
+        let word = word.trim();
+
+        if word.is_empty() { None } else { Some(word) }
+    });
+
+    // 2023-09-15 10:20:44 D....            0            0  path/to/folder
+    let info = words.next();
+    let size = words.next().map(|size| size.parse());
+    let path = words.last().map(PathBuf::from);
+
+    if let (Some(info), Some(path), Some(Ok(size))) = (info, path, size) {
+        let mut info = info.split_whitespace();
+
+        let date = info.next().unwrap_or_default();
+        let time = info.next().unwrap_or_default();
+        let attributes = info.next().unwrap_or_default();
+
+        let is_dir = attributes.starts_with('D');
+
+        Some(ArchiveEntry {
+            path,
+            size: if is_dir { 0 } else { size },
+            is_dir,
+            mode: None,
+            modified: parse_datetime(date, time),
+            comment: None
+        })
+    } else {
+        None
+    }
 }
 
 pub fn extract(
     archive: impl AsRef<Path>,
     folder: impl AsRef<Path>,
+    options: &ExtractOptions,
     mut progress: impl FnMut(u64, u64, u64) + Send + 'static
 ) -> Result<ArchiveExtractor, ArchiveError> {
     let Some(binary) = SEVENZ_BINARY.as_ref() else {
@@ -104,28 +166,90 @@ pub fn extract(
     let archive = archive.as_ref();
     let folder = folder.as_ref();
 
-    let files = get_entries(archive)?
-        .into_iter()
+    let mut entries = get_entries(archive)?;
+
+    entries.retain(|entry| options.is_selected(&entry.path));
+
+    let (skipped, skipped_size) = options.skip_extracted(folder, &mut entries);
+
+    // Extract only listed entries if some of them were skipped.
+    let is_filtered = options.is_filtered() || skipped > 0;
+
+    // Folders are extracted recursively by 7z so only files are selected.
+    let files = entries.into_iter()
+        .filter(|entry| !is_filtered || !entry.is_dir)
         .map(|entry| (entry.path.to_string_lossy().to_string(), entry.size))
         .collect::<HashMap<String, u64>>();
 
-    let child = Command::new(binary)
-        .stdout(Stdio::piped())
+    if is_filtered && files.is_empty() {
+        if skipped_size > 0 {
+            progress(skipped_size, skipped_size, skipped_size);
+        }
+
+        return Ok(ArchiveExtractor::finished(skipped_size));
+    }
+
+    let mut command = Command::new(binary);
+
+    command.stdout(Stdio::piped())
         .arg("x")
         .arg(archive)
         .arg(format!("-o{}", folder.to_string_lossy()))
         .arg("-aoa")
-        .arg("-bb1")
-        .spawn()?;
+        .arg("-bb1");
+
+    // Password is written to stdin when 7z asks it so it's not exposed
+    // in the process arguments. Empty password prevents 7z from asking it.
+    if options.password.is_some() {
+        command.stdin(Stdio::piped());
+    } else {
+        command.stdin(Stdio::null()).arg("-p");
+    }
+
+    // Disable wildcards matching for the selected names and pass them in a
+    // list file so that they don't overflow the command line.
+    let list = if is_filtered {
+        let list = ListFile::create(files.keys())?;
 
-    let current = Arc::new(AtomicU64::new(0));
-    let total = files.values().sum::<u64>();
+        command.arg("-spd")
+            .arg("-scsUTF-8")
+            .arg(format!("@{}", list.0.to_string_lossy()));
+
+        Some(list)
+    } else {
+        None
+    };
+
+    let mut child = command.spawn()?;
+
+    if let Some(password) = &options.password
+        && let Some(mut stdin) = child.stdin.take()
+    {
+        // 7z doesn't read the password if the archive is not encrypted.
+        let _ = writeln!(stdin, "{password}");
+    }
+
+    let has_password = options.password.is_some();
+
+    let current = Arc::new(AtomicU64::new(skipped_size));
+    let total = files.values().sum::<u64>() + skipped_size;
+    let aborted = Arc::new(AtomicBool::new(false));
+
+    let folder = folder.to_path_buf();
 
     let worker = {
         let current = current.clone();
+        let aborted = aborted.clone();
 
         std::thread::spawn(move || {
-            if let Some(stdout) = child.stdout {
+            // Keep the list file until 7z is finished.
+            let _list = list;
+
+            if skipped_size > 0 {
+                progress(skipped_size, total, skipped_size);
+            }
+
+            if let Some(stdout) = child.stdout.take() {
                 let stdout = BufReader::new(stdout);
 
                 for line in stdout.lines() {
@@ -135,6 +259,12 @@ pub fn extract(
                     };
 
                     if let Some(file) = line.strip_prefix("- ") {
+                        // Stop before the next entry, removing the one which is
+                        // being extracted now.
+                        if aborted.load(Ordering::Acquire) {
+                            return Err(abort_extraction(&mut child, &folder, file));
+                        }
+
                         // If we have this file listed in the entries
                         // sum its size with the current counter.
                         if let Some(size) = files.get(file).copied() {
@@ -145,12 +275,24 @@ pub fn extract(
                     }
                 }
             }
+
+            // 7z doesn't distinguish wrong passwords from other errors.
+            if !child.wait()?.success() {
+                return Err(if has_password {
+                    ArchiveError::WrongPassword
+                } else {
+                    ArchiveError::ExtractionError("7z exited with an error")
+                });
+            }
+
+            Ok(())
         })
     };
 
     Ok(ArchiveExtractor {
         worker,
         current,
-        total
+        total,
+        aborted
     })
 }
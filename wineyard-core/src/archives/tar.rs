@@ -1,63 +1,71 @@
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 
 use super::*;
 
 pub fn get_entries(
     path: impl AsRef<Path>
 ) -> Result<Vec<ArchiveEntry>, ArchiveError> {
-    let output = Command::new("tar")
+    iter_entries(path)?.collect()
+}
+
+pub fn iter_entries(
+    path: impl AsRef<Path>
+) -> Result<ArchiveEntries, ArchiveError> {
+    let mut finished = false;
+
+    // Print full timestamps in UTC.
+    let mut command = Command::new("tar");
+
+    command.env("TZ", "UTC")
+        .arg("--full-time")
         .arg("-tvf")
-        .arg(path.as_ref())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()?;
-
-    let output = String::from_utf8_lossy(&output.stdout);
-
-    let entries = output
-        .trim()
-        .split('\n')
-        .take_while(|line| !line.starts_with("---------"))
-        .map(|line| {
-            line.split(' ').filter_map(|word| {
-                let word = word.trim();
-
-                if word.is_empty() { None } else { Some(word) }
-            })
-        })
-        .flat_map(|mut words| {
-            let flags = words.next();
-            let size = words.nth(1).map(|size| size.parse());
-            let path = words.next_back().map(PathBuf::from);
-
-            if let (Some(flags), Some(path), Some(Ok(size))) = (flags, path, size) {
-                // Skip symlinks
-                // FIXME: parse them as well
-                if flags.starts_with('l') {
-                    None
-                } else {
-                    Some(ArchiveEntry {
-                        path,
-                        size
-                    })
-                }
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
+        .arg(path.as_ref());
+
+    ArchiveEntries::spawn(&mut command, move |line| {
+        if finished || line.starts_with("---------") {
+            finished = true;
 
-    Ok(entries)
+            return None;
+        }
+
+        parse_entry(line)
+    })
+}
+
+fn parse_entry(line: &str) -> Option<ArchiveEntry> {
+    // -rw-r--r-- user/group 1024 2024-01-01 12:00:00 path/to/file
+    let (fields, path) = split_fields(line, 5)?;
+
+    let flags = fields[0];
+    let size = fields[2].parse().ok()?;
+
+    // Skip symlinks
+    // FIXME: parse them as well
+    if flags.starts_with('l') || path.is_empty() {
+        return None;
+    }
+
+    let is_dir = flags.starts_with('d');
+
+    Some(ArchiveEntry {
+        path: PathBuf::from(path),
+        size: if is_dir { 0 } else { size },
+        is_dir,
+        mode: parse_mode(flags),
+        modified: parse_datetime(fields[3], fields[4]),
+        comment: None
+    })
 }
 
 pub fn extract(
     archive: impl AsRef<Path>,
     folder: impl AsRef<Path>,
+    options: &ExtractOptions,
     mut progress: impl FnMut(u64, u64, u64) + Send + 'static
 ) -> Result<ArchiveExtractor, ArchiveError> {
     let archive = archive.as_ref();
@@ -68,27 +76,74 @@ pub fn extract(
         std::fs::create_dir_all(folder)?;
     }
 
-    let files = get_entries(archive)?
-        .into_iter()
+    let mut entries = get_entries(archive)?;
+
+    entries.retain(|entry| options.is_selected(&entry.path));
+
+    let (skipped, skipped_size) = options.skip_extracted(folder, &mut entries);
+
+    // Extract only listed entries if some of them were skipped.
+    let is_filtered = options.is_filtered() || skipped > 0;
+
+    let files = entries.into_iter()
         .map(|entry| (entry.path.to_string_lossy().to_string(), entry.size))
         .collect::<HashMap<String, u64>>();
 
-    let child = Command::new("tar")
-        .stdout(Stdio::piped())
+    if is_filtered && files.is_empty() {
+        if skipped_size > 0 {
+            progress(skipped_size, skipped_size, skipped_size);
+        }
+
+        return Ok(ArchiveExtractor::finished(skipped_size));
+    }
+
+    let mut command = Command::new("tar");
+
+    command.stdout(Stdio::piped())
         .arg("-xhvf")
         .arg(archive)
         .arg("-C")
-        .arg(folder)
-        .spawn()?;
+        .arg(folder);
+
+    // Read names of the selected entries from stdin.
+    if is_filtered {
+        command.stdin(Stdio::piped())
+            .arg("--no-recursion")
+            .arg("--verbatim-files-from")
+            .arg("-T")
+            .arg("-");
+    }
+
+    let mut child = command.spawn()?;
 
-    let current = Arc::new(AtomicU64::new(0));
-    let total = files.values().sum::<u64>();
+    // Write names in a separate thread so tar doesn't get blocked
+    // by the full stdout pipe.
+    if let Some(mut stdin) = child.stdin.take() {
+        let names = files.keys()
+            .map(|name| format!("{name}\n"))
+            .collect::<String>();
+
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(names.as_bytes());
+        });
+    }
+
+    let current = Arc::new(AtomicU64::new(skipped_size));
+    let total = files.values().sum::<u64>() + skipped_size;
+    let aborted = Arc::new(AtomicBool::new(false));
+
+    let folder = folder.to_path_buf();
 
     let worker = {
         let current = current.clone();
+        let aborted = aborted.clone();
 
         std::thread::spawn(move || {
-            if let Some(stdout) = child.stdout {
+            if skipped_size > 0 {
+                progress(skipped_size, total, skipped_size);
+            }
+
+            if let Some(stdout) = child.stdout.take() {
                 let stdout = BufReader::new(stdout);
 
                 for line in stdout.lines() {
@@ -97,6 +152,12 @@ pub fn extract(
                         break;
                     };
 
+                    // Stop before the next entry, removing the one which is
+                    // being extracted now.
+                    if aborted.load(Ordering::Acquire) {
+                        return Err(abort_extraction(&mut child, &folder, &line));
+                    }
+
                     // If we have this file listed in the entries
                     // sum its size with the current counter.
                     if let Some(size) = files.get(&line).copied() {
@@ -106,13 +167,16 @@ pub fn extract(
                     }
                 }
             }
+
+            Ok(())
         })
     };
 
     Ok(ArchiveExtractor {
         worker,
         current,
-        total
+        total,
+        aborted
     })
 }
 
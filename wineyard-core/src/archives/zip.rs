@@ -1,119 +1,412 @@
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read};
 
 use super::*;
 
+/// Maximal total length of the entries names passed to a single unzip
+/// process so that the command line doesn't exceed the system limit.
+const MAX_NAMES_LENGTH: usize = 64 * 1024;
+
+/// Encryption method of the zip archive entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Encryption {
+    ZipCrypto,
+    Aes
+}
+
 pub fn get_entries(
     path: impl AsRef<Path>
 ) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let entries = read_entries(path)?
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .collect();
+
+    Ok(entries)
+}
+
+pub fn get_entries_with_comments(
+    path: impl AsRef<Path>
+) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let path = path.as_ref();
+
+    let mut comments = read_comments(path)?;
+
+    let entries = read_entries(path)?
+        .into_iter()
+        .map(|(mut entry, _)| {
+            entry.comment = comments.remove(entry.path.to_string_lossy().as_ref());
+
+            entry
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+pub fn get_comment(
+    path: impl AsRef<Path>
+) -> Result<Option<String>, ArchiveError> {
     let output = Command::new("unzip")
-        .arg("-l")
+        .arg("-qz")
         .arg(path.as_ref())
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .output()?;
 
+    if !output.status.success() {
+        return Err(ArchiveError::ReadError("failed to read archive comment"));
+    }
+
+    let comment = String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches(['\r', '\n'])
+        .to_string();
+
+    if comment.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(comment))
+}
+
+/// Read comments of the archive entries, returning map of entry paths
+/// to their comments.
+fn read_comments(
+    path: impl AsRef<Path>
+) -> Result<HashMap<String, String>, ArchiveError> {
+    // zipinfo prints entry comments only in the verbose mode.
+    let output = Command::new("unzip")
+        .arg("-Zv")
+        .arg(path.as_ref())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(ArchiveError::ReadError("failed to read entries comments"));
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    let mut comments = HashMap::new();
+    let mut lines = output.lines();
+    let mut entry = None;
+
+    while let Some(line) = lines.next() {
+        if line.starts_with("Central directory entry #") {
+            // Entry header is followed by a separator line, an empty line
+            // and the entry path.
+            entry = lines.nth(2).and_then(|path| path.strip_prefix("  "));
+        } else if line.starts_with('-')
+            && line.contains(" file comment begins ")
+            && let Some(path) = entry.take()
+        {
+            let comment = lines.by_ref()
+                .take_while(|line| !(line.starts_with('-') && line.contains(" file comment ends ")))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            comments.insert(path.to_string(), comment);
+        }
+    }
+
+    Ok(comments)
+}
+
+fn read_entries(
+    path: impl AsRef<Path>
+) -> Result<Vec<(ArchiveEntry, Option<Encryption>)>, ArchiveError> {
+    let output = entries_command(path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()?;
+
     let output = String::from_utf8_lossy(&output.stdout);
 
     let entries = output
         .trim()
         .split('\n')
-        .skip(3)
-        .take_while(|line| !line.starts_with("---------"))
-        .map(|line| {
-            line.split("  ").filter_map(|word| {
-                let word = word.trim();
-
-                if word.is_empty() { None } else { Some(word) }
-            })
-        })
-        .flat_map(|mut words| {
-            let size = words.next().map(|size| size.parse());
-            let path = words.last().map(PathBuf::from);
-
-            if let (Some(path), Some(Ok(size))) = (path, size) {
-                Some(ArchiveEntry {
-                    path,
-                    size
-                })
-            } else {
-                None
-            }
-        })
+        .skip(2)
+        .flat_map(parse_entry)
         .collect::<Vec<_>>();
 
     Ok(entries)
 }
 
+pub fn iter_entries(
+    path: impl AsRef<Path>
+) -> Result<ArchiveEntries, ArchiveError> {
+    let mut header = 2;
+
+    ArchiveEntries::spawn(&mut entries_command(path), move |line| {
+        if header > 0 {
+            header -= 1;
+
+            return None;
+        }
+
+        parse_entry(line).map(|(entry, _)| entry)
+    })
+}
+
+fn entries_command(path: impl AsRef<Path>) -> Command {
+    // Use zipinfo mode to get entries permissions and print full timestamps
+    // in UTC.
+    let mut command = Command::new("unzip");
+
+    command.env("TZ", "UTC")
+        .arg("-ZT")
+        .arg(path.as_ref());
+
+    command
+}
+
+fn parse_entry(line: &str) -> Option<(ArchiveEntry, Option<Encryption>)> {
+    // -rw-r--r--  3.0 unx     1024 tx defN 20240101.120000 path/to/file
+    let (fields, path) = split_fields(line, 7)?;
+
+    let size = fields[3].parse().ok()?;
+
+    // Skip the summary line.
+    let (date, time) = fields[6].split_once('.')?;
+
+    if date.len() != 8 || time.len() != 6 || path.is_empty() {
+        return None;
+    }
+
+    let date = format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]);
+    let time = format!("{}:{}:{}", &time[..2], &time[2..4], &time[4..]);
+
+    // Encrypted entries have uppercased type (e.g. "TX" instead
+    // of "tx"). AES encryption is reported as 99 compression method.
+    let encryption = if !fields[4].starts_with(char::is_uppercase) {
+        None
+    } else if fields[5] == "u099" {
+        Some(Encryption::Aes)
+    } else {
+        Some(Encryption::ZipCrypto)
+    };
+
+    let is_dir = path.ends_with('/');
+
+    let entry = ArchiveEntry {
+        path: PathBuf::from(path),
+        size: if is_dir { 0 } else { size },
+        is_dir,
+        mode: parse_mode(fields[0]),
+        modified: parse_datetime(&date, &time),
+        comment: None
+    };
+
+    Some((entry, encryption))
+}
+
 pub fn extract(
     archive: impl AsRef<Path>,
     folder: impl AsRef<Path>,
+    options: &ExtractOptions,
     mut progress: impl FnMut(u64, u64, u64) + Send + 'static
 ) -> Result<ArchiveExtractor, ArchiveError> {
     let archive = archive.as_ref();
     let folder = folder.as_ref();
 
-    let files = get_entries(archive)?
-        .into_iter()
+    let entries = read_entries(archive)?;
+
+    let encryption = entries.iter()
+        .filter_map(|(_, encryption)| *encryption)
+        .max_by_key(|encryption| *encryption == Encryption::Aes);
+
+    if encryption.is_some() && options.password.is_none() {
+        return Err(ArchiveError::PasswordRequired);
+    }
+
+    // unzip doesn't support AES and accepts the password only in its
+    // arguments which are visible to other users, so try to use 7z instead.
+    if encryption.is_some() {
+        #[cfg(feature = "archives-7z")]
+        if super::sevenz::SEVENZ_BINARY.is_some() {
+            return super::sevenz::extract(archive, folder, options, progress);
+        }
+
+        if encryption == Some(Encryption::Aes) {
+            return Err(ArchiveError::UnsupportedEncryption(String::from("AES")));
+        }
+    }
+
+    let mut entries = entries.into_iter()
+        .map(|(entry, _)| entry)
+        .filter(|entry| options.is_selected(&entry.path))
+        .collect::<Vec<_>>();
+
+    let (skipped, skipped_size) = options.skip_extracted(folder, &mut entries);
+
+    // Extract only listed entries if some of them were skipped.
+    let is_filtered = options.is_filtered() || skipped > 0;
+
+    let files = entries.into_iter()
         .map(|entry| (entry.path.to_string_lossy().to_string(), entry.size))
         .collect::<HashMap<String, u64>>();
 
-    let child = Command::new("unzip")
-        .stdout(Stdio::piped())
-        .arg("-o")
-        .arg(archive)
-        .arg("-d")
-        .arg(folder)
-        .spawn()?;
+    if is_filtered && files.is_empty() {
+        if skipped_size > 0 {
+            progress(skipped_size, skipped_size, skipped_size);
+        }
+
+        return Ok(ArchiveExtractor::finished(skipped_size));
+    }
+
+    // Split listed names between multiple unzip processes so that they
+    // don't overflow the command line.
+    let mut chunks = VecDeque::from([Vec::new()]);
+
+    if is_filtered {
+        let mut length = 0;
+
+        for name in files.keys() {
+            // unzip treats names as wildcards so escape them.
+            let name = name
+                .replace('[', "[[]")
+                .replace('*', "[*]")
+                .replace('?', "[?]");
+
+            if length + name.len() > MAX_NAMES_LENGTH
+                && let Some(chunk) = chunks.back()
+                && !chunk.is_empty()
+            {
+                chunks.push_back(Vec::new());
+
+                length = 0;
+            }
+
+            length += name.len() + 1;
+
+            if let Some(chunk) = chunks.back_mut() {
+                chunk.push(name);
+            }
+        }
+    }
+
+    let spawn = {
+        let archive = archive.to_path_buf();
+        let folder = folder.to_path_buf();
+        let password = options.password.clone();
+
+        move |names: Vec<String>| {
+            let mut command = Command::new("unzip");
+
+            command.stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .arg("-o");
+
+            if let Some(password) = &password {
+                command.arg("-P").arg(password);
+            }
+
+            command.arg(&archive)
+                .args(names)
+                .arg("-d")
+                .arg(&folder)
+                .spawn()
+        }
+    };
+
+    let mut child = spawn(chunks.pop_front().unwrap_or_default())?;
 
     let prefix = format!("{}/", folder.to_string_lossy());
 
-    let current = Arc::new(AtomicU64::new(0));
-    let total = files.values().sum::<u64>();
+    let current = Arc::new(AtomicU64::new(skipped_size));
+    let total = files.values().sum::<u64>() + skipped_size;
+    let aborted = Arc::new(AtomicBool::new(false));
+
+    let folder = folder.to_path_buf();
 
     let worker = {
         let current = current.clone();
+        let aborted = aborted.clone();
 
         std::thread::spawn(move || {
-            if let Some(stdout) = child.stdout {
-                let stdout = BufReader::new(stdout);
-
-                for line in stdout.lines() {
-                    let Ok(line) = line else {
-                        // TODO: throw the error to the context.
-                        break;
-                    };
-
-                    // Strip 'Archive: ...' and other top-level info messages.
-                    if let Some(line) = line.strip_prefix(' ') {
-                        // extracting: sus/1001.pck
-                        // inflating: sus/3x.webp
-                        // linking: sus/3x.symlink          -> 3x.webp
-                        if let Some((_, file)) = line.split_once(": ") {
-                            // Remove output directory prefix.
-                            let file = file.strip_prefix(&prefix).unwrap_or(file).trim_end();
-
-                            // If we have this file listed in the entries
-                            // sum its size with the current counter.
-                            if let Some(size) = files.get(file).copied() {
-                                let prev = current.fetch_add(size, Ordering::Relaxed);
-
-                                progress(prev + size, total, prev);
+            if skipped_size > 0 {
+                progress(skipped_size, total, skipped_size);
+            }
+
+            loop {
+                // Read stderr in a separate thread so unzip doesn't get blocked
+                // by the full pipe.
+                let stderr = child.stderr.take().map(|mut stderr| {
+                    std::thread::spawn(move || {
+                        let mut errors = String::new();
+
+                        stderr.read_to_string(&mut errors).map(|_| errors)
+                    })
+                });
+
+                if let Some(stdout) = child.stdout.take() {
+                    let stdout = BufReader::new(stdout);
+
+                    for line in stdout.lines() {
+                        let Ok(line) = line else {
+                            // TODO: throw the error to the context.
+                            break;
+                        };
+
+                        // Strip 'Archive: ...' and other top-level info messages.
+                        if let Some(line) = line.strip_prefix(' ') {
+                            // extracting: sus/1001.pck
+                            // inflating: sus/3x.webp
+                            // linking: sus/3x.symlink          -> 3x.webp
+                            if let Some((_, file)) = line.split_once(": ") {
+                                // Remove output directory prefix.
+                                let file = file.strip_prefix(&prefix).unwrap_or(file).trim_end();
+
+                                // Stop before the next entry, removing the one which is
+                                // being extracted now.
+                                if aborted.load(Ordering::Acquire) {
+                                    return Err(abort_extraction(&mut child, &folder, file));
+                                }
+
+                                // If we have this file listed in the entries
+                                // sum its size with the current counter.
+                                if let Some(size) = files.get(file).copied() {
+                                    let prev = current.fetch_add(size, Ordering::Relaxed);
+
+                                    progress(prev + size, total, prev);
+                                }
                             }
                         }
                     }
                 }
+
+                if let Some(stderr) = stderr {
+                    let errors = stderr.join()
+                        .map_err(|_| ArchiveError::ExtractionError("failed to join the thread"))??;
+
+                    // skipping: sus/1001.pck          incorrect password
+                    if errors.contains("incorrect password") {
+                        return Err(ArchiveError::WrongPassword);
+                    }
+                }
+
+                child.wait()?;
+
+                match chunks.pop_front() {
+                    Some(names) => child = spawn(names)?,
+                    None => break
+                }
             }
+
+            Ok(())
         })
     };
 
     Ok(ArchiveExtractor {
         worker,
         current,
-        total
+        total,
+        aborted
     })
 }
@@ -1,4 +1,30 @@
 use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Default size of the IO buffers used to copy big files.
+///
+/// See `benches/io_buf_size.rs` for throughput of different sizes.
+pub const DEFAULT_IO_BUF_SIZE: usize = 1024 * 1024; // 1 MiB
+
+static IO_BUF_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_IO_BUF_SIZE);
+
+/// Get size of the IO buffers used to copy big files, e.g. by the downloader
+/// or the entries hasher.
+#[inline]
+pub fn io_buf_size() -> usize {
+    IO_BUF_SIZE.load(Ordering::Relaxed)
+}
+
+/// Override size of the IO buffers used to copy big files. Zero size
+/// restores the default value.
+pub fn set_io_buf_size(size: usize) {
+    let size = if size == 0 { DEFAULT_IO_BUF_SIZE } else { size };
+
+    #[cfg(feature = "tracing")]
+    tracing::trace!(?size, "set io buffer size");
+
+    IO_BUF_SIZE.store(size, Ordering::Relaxed);
+}
 
 /// Simple bytes container which appends bytes on `Write` trait use, and pops
 /// them on `Read` trait use.
@@ -1,3 +1,5 @@
+use std::ops::RangeInclusive;
+
 use super::CompressionError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -22,7 +24,8 @@ pub enum CompressionAlgorithm {
 }
 
 impl CompressionAlgorithm {
-    pub const fn name(&self) -> &str {
+    /// Get name of the algorithm which can be parsed back using `FromStr`.
+    pub const fn name(&self) -> &'static str {
         match self {
             #[cfg(feature = "compression-lz4")]
             Self::Lz4 => "lz4",
@@ -43,6 +46,52 @@ impl CompressionAlgorithm {
             Self::Zstd => "zstd"
         }
     }
+
+    /// Get range of the native compression levels of the algorithm.
+    ///
+    /// Lz4 doesn't have compression levels so its range is `0..=0`.
+    pub const fn levels_range(&self) -> RangeInclusive<i32> {
+        match self {
+            #[cfg(feature = "compression-lz4")]
+            Self::Lz4 => RangeInclusive::new(0, 0),
+
+            #[cfg(feature = "compression-bzip2")]
+            Self::Bzip2 => RangeInclusive::new(1, 9),
+
+            #[cfg(feature = "compression-deflate")]
+            Self::Deflate |
+            Self::Gzip |
+            Self::Zlib => RangeInclusive::new(0, 9),
+
+            #[cfg(feature = "compression-zstd")]
+            Self::Zstd => RangeInclusive::new(1, 22)
+        }
+    }
+}
+
+impl CompressionAlgorithm {
+    /// Predict compression algorithm from the magic bytes at the beginning
+    /// of the compressed data.
+    ///
+    /// Only formats with magic bytes are detected: zstd, gzip, bzip2 and
+    /// lz4 frame. Return `None` if none of them match.
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        match data {
+            #[cfg(feature = "compression-zstd")]
+            [0x28, 0xB5, 0x2F, 0xFD, ..] => Some(Self::Zstd),
+
+            #[cfg(feature = "compression-deflate")]
+            [0x1F, 0x8B, ..] => Some(Self::Gzip),
+
+            #[cfg(feature = "compression-bzip2")]
+            [b'B', b'Z', b'h', ..] => Some(Self::Bzip2),
+
+            #[cfg(feature = "compression-lz4")]
+            [0x04, 0x22, 0x4D, 0x18, ..] => Some(Self::Lz4),
+
+            _ => None
+        }
+    }
 }
 
 impl std::fmt::Display for CompressionAlgorithm {
@@ -56,7 +105,7 @@ impl std::str::FromStr for CompressionAlgorithm {
     type Err = CompressionError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        match s.to_ascii_lowercase().as_str() {
             #[cfg(feature = "compression-lz4")]
             "lz4" => Ok(Self::Lz4),
 
@@ -73,7 +122,20 @@ impl std::str::FromStr for CompressionAlgorithm {
             "zlib" => Ok(Self::Zlib),
 
             #[cfg(feature = "compression-zstd")]
-            "zstd" => Ok(Self::Zstd),
+            "zstd" | "zst" => Ok(Self::Zstd),
+
+            // Known names of the algorithms disabled at compile time.
+            #[cfg(not(feature = "compression-lz4"))]
+            "lz4" => Err(CompressionError::DisabledAlgorithm("compression-lz4")),
+
+            #[cfg(not(feature = "compression-bzip2"))]
+            "bzip2" | "bz2" => Err(CompressionError::DisabledAlgorithm("compression-bzip2")),
+
+            #[cfg(not(feature = "compression-deflate"))]
+            "deflate" | "gzip" | "gz" | "zlib" => Err(CompressionError::DisabledAlgorithm("compression-deflate")),
+
+            #[cfg(not(feature = "compression-zstd"))]
+            "zstd" | "zst" => Err(CompressionError::DisabledAlgorithm("compression-zstd")),
 
             _ => Err(CompressionError::UnknownAlgorithm(s.to_string()))
         }
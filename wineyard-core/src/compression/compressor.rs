@@ -33,6 +33,10 @@ impl Compressor {
         let algorithm: CompressionAlgorithm = algorithm.into();
         let level: CompressionLevel = level.into();
 
+        // Reject invalid custom levels. Valid ones are clamped to the
+        // algorithm's levels range.
+        level.native_level(algorithm)?;
+
         #[cfg(feature = "tracing")]
         tracing::trace!(?algorithm, ?level, "create compressor");
 
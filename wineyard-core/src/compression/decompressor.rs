@@ -88,6 +88,18 @@ impl Decompressor {
         }
     }
 
+    /// Create new decompressor for the algorithm predicted from the magic
+    /// bytes of the given compressed data header.
+    ///
+    /// The header is only used to choose the algorithm and is not written
+    /// to the decompressor.
+    pub fn auto(header: impl AsRef<[u8]>) -> Result<Self, CompressionError> {
+        let algorithm = CompressionAlgorithm::from_magic(header.as_ref())
+            .ok_or(CompressionError::UnknownFormat)?;
+
+        Self::new(algorithm)
+    }
+
     /// Get compression algorithm from the current decompressor struct.
     pub const fn algorithm(&self) -> CompressionAlgorithm {
         match self {
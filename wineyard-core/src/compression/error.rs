@@ -6,6 +6,12 @@ pub enum CompressionError {
     #[error("unknown compression algorithm name: {0}")]
     UnknownAlgorithm(String),
 
+    #[error("compression algorithm is disabled, enable `{0}` feature to use it")]
+    DisabledAlgorithm(&'static str),
+
     #[error("invalid compression level value: {0}")]
-    InvalidLevel(String)
+    InvalidLevel(String),
+
+    #[error("unknown compressed data format")]
+    UnknownFormat
 }
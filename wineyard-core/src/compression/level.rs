@@ -1,7 +1,12 @@
-use super::CompressionError;
+use super::{CompressionError, CompressionAlgorithm};
 
+/// Compression level which is mapped to the native levels range of each
+/// compression algorithm.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CompressionLevel {
+    /// Fastest native compression level which still compresses the data.
+    Fastest,
+
     /// 1/5 - very fast, small compression ratio.
     Quick,
 
@@ -21,30 +26,87 @@ pub enum CompressionLevel {
     #[default]
     Default,
 
-    /// Custom native compression level for the selected algorithm.
-    Custom(i8)
+    /// Custom native compression level for the selected algorithm. Values
+    /// out of the algorithm's levels range are clamped to it, negative
+    /// values are invalid.
+    Custom(i32)
+}
+
+impl CompressionLevel {
+    /// Convert into native compression level of the given algorithm.
+    ///
+    /// Custom levels are clamped to the algorithm's levels range. Return
+    /// error if custom level is negative.
+    pub fn native_level(
+        &self,
+        algorithm: impl Into<CompressionAlgorithm>
+    ) -> Result<i32, CompressionError> {
+        if let Self::Custom(level) = self
+            && *level < 0
+        {
+            return Err(CompressionError::InvalidLevel(level.to_string()));
+        }
+
+        Ok(self.clamped_level(algorithm.into()))
+    }
+
+    /// Convert into native compression level of the given algorithm,
+    /// clamping all the custom levels to its levels range.
+    const fn clamped_level(&self, algorithm: CompressionAlgorithm) -> i32 {
+        // [fastest, quick, fast, balanced, good, best, default]
+        let levels: [i32; 7] = match algorithm {
+            #[cfg(feature = "compression-lz4")]
+            CompressionAlgorithm::Lz4 => [0; 7],
+
+            #[cfg(feature = "compression-bzip2")]
+            CompressionAlgorithm::Bzip2 => [1, 1, 3, 5, 7, 9, 4],
+
+            #[cfg(feature = "compression-deflate")]
+            CompressionAlgorithm::Deflate |
+            CompressionAlgorithm::Gzip |
+            CompressionAlgorithm::Zlib => [1, 1, 3, 5, 7, 9, 6],
+
+            #[cfg(feature = "compression-zstd")]
+            CompressionAlgorithm::Zstd => [1, 3, 9, 13, 17, 22, 10]
+        };
+
+        match self {
+            Self::Fastest  => levels[0],
+            Self::Quick    => levels[1],
+            Self::Fast     => levels[2],
+            Self::Balanced => levels[3],
+            Self::Good     => levels[4],
+            Self::Best     => levels[5],
+            Self::Default  => levels[6],
+
+            Self::Custom(level) => {
+                let range = algorithm.levels_range();
+
+                if *level < *range.start() {
+                    *range.start()
+                } else if *level > *range.end() {
+                    *range.end()
+                } else {
+                    *level
+                }
+            }
+        }
+    }
 }
 
 #[cfg(feature = "compression-zstd")]
 impl CompressionLevel {
     /// Convert into zstd compression level.
+    #[inline]
     pub const fn zstd_level(&self) -> i32 {
-        match self {
-            Self::Quick    => 3,
-            Self::Fast     => 9,
-            Self::Balanced => 13,
-            Self::Good     => 17,
-            Self::Best     => 22,
-            Self::Default  => 10,
-
-            Self::Custom(level) => *level as i32
-        }
+        self.clamped_level(CompressionAlgorithm::Zstd)
     }
 }
 
 impl std::fmt::Display for CompressionLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Fastest  => f.write_str("fastest"),
             Self::Quick    => f.write_str("quick"),
             Self::Fast     => f.write_str("fast"),
             Self::Balanced => f.write_str("balanced"),
@@ -62,6 +124,7 @@ impl std::str::FromStr for CompressionLevel {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "fastest"  => Ok(Self::Fastest),
             "quick"    => Ok(Self::Quick),
             "fast"     => Ok(Self::Fast),
             "balanced" => Ok(Self::Balanced),
@@ -70,7 +133,7 @@ impl std::str::FromStr for CompressionLevel {
             "default"  => Ok(Self::Default),
 
             _ => {
-                let Ok(level) = s.parse::<i8>() else {
+                let Ok(level) = s.parse::<i32>() else {
                     return Err(CompressionError::InvalidLevel(s.to_string()));
                 };
 
@@ -82,33 +145,17 @@ impl std::str::FromStr for CompressionLevel {
 
 #[cfg(feature = "compression-bzip2")]
 impl From<CompressionLevel> for bzip2::Compression {
+    #[inline]
     fn from(value: CompressionLevel) -> Self {
-        match value {
-            CompressionLevel::Quick    => Self::new(1),
-            CompressionLevel::Fast     => Self::new(3),
-            CompressionLevel::Balanced => Self::new(5),
-            CompressionLevel::Good     => Self::new(7),
-            CompressionLevel::Best     => Self::new(9),
-            CompressionLevel::Default  => Self::new(4),
-
-            CompressionLevel::Custom(level) => Self::new(level as u32)
-        }
+        Self::new(value.clamped_level(CompressionAlgorithm::Bzip2) as u32)
     }
 }
 
 #[cfg(feature = "compression-deflate")]
 impl From<CompressionLevel> for flate2::Compression {
+    #[inline]
     fn from(value: CompressionLevel) -> Self {
-        match value {
-            CompressionLevel::Quick    => Self::new(1),
-            CompressionLevel::Fast     => Self::new(3),
-            CompressionLevel::Balanced => Self::new(5),
-            CompressionLevel::Good     => Self::new(7),
-            CompressionLevel::Best     => Self::new(9),
-            CompressionLevel::Default  => Self::new(6),
-
-            CompressionLevel::Custom(level) => Self::new(level as u32)
-        }
+        Self::new(value.clamped_level(CompressionAlgorithm::Deflate) as u32)
     }
 }
 
@@ -125,7 +172,13 @@ macro_rules! impl_from {
             impl From<$num> for CompressionLevel {
                 #[inline(always)]
                 fn from(value: $num) -> Self {
-                    Self::Custom(value as i8)
+                    let level = i32::try_from(value).unwrap_or(if value > <$num>::default() {
+                        i32::MAX
+                    } else {
+                        i32::MIN
+                    });
+
+                    Self::Custom(level)
                 }
             }
         )+
@@ -3,6 +3,7 @@ use std::io::{Read, Write};
 use super::*;
 
 const LEVELS: &[CompressionLevel] = &[
+    CompressionLevel::Fastest,
     CompressionLevel::Quick,
     CompressionLevel::Fast,
     CompressionLevel::Balanced,
@@ -79,3 +80,138 @@ fn zlib() -> Result<(), CompressionError> {
 fn zstd() -> Result<(), CompressionError> {
     test(CompressionAlgorithm::Zstd)
 }
+
+#[test]
+fn levels_mapping() -> Result<(), CompressionError> {
+    #[cfg(feature = "compression-bzip2")]
+    {
+        let algorithm = CompressionAlgorithm::Bzip2;
+
+        assert_eq!(CompressionLevel::Fastest.native_level(algorithm)?, 1);
+        assert_eq!(CompressionLevel::Best.native_level(algorithm)?, 9);
+        assert_eq!(CompressionLevel::Custom(0).native_level(algorithm)?, 1);
+        assert_eq!(CompressionLevel::Custom(10).native_level(algorithm)?, 9);
+    }
+
+    #[cfg(feature = "compression-deflate")]
+    {
+        let algorithm = CompressionAlgorithm::Deflate;
+
+        assert_eq!(CompressionLevel::Fastest.native_level(algorithm)?, 1);
+        assert_eq!(CompressionLevel::Best.native_level(algorithm)?, 9);
+        assert_eq!(CompressionLevel::Custom(0).native_level(algorithm)?, 0);
+        assert_eq!(CompressionLevel::Custom(10).native_level(algorithm)?, 9);
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    {
+        let algorithm = CompressionAlgorithm::Zstd;
+
+        assert_eq!(CompressionLevel::Fastest.native_level(algorithm)?, 1);
+        assert_eq!(CompressionLevel::Best.native_level(algorithm)?, 22);
+        assert_eq!(CompressionLevel::Custom(0).native_level(algorithm)?, 1);
+        assert_eq!(CompressionLevel::Custom(100).native_level(algorithm)?, 22);
+        assert_eq!(CompressionLevel::from(u64::MAX).native_level(algorithm)?, 22);
+
+        assert!(CompressionLevel::Custom(-1).native_level(algorithm).is_err());
+        assert!(Compressor::new(algorithm, CompressionLevel::Custom(-1)).is_err());
+    }
+
+    #[cfg(feature = "compression-lz4")]
+    assert_eq!(CompressionLevel::Custom(5).native_level(CompressionAlgorithm::Lz4)?, 0);
+
+    assert_eq!("fastest".parse::<CompressionLevel>()?, CompressionLevel::Fastest);
+    assert_eq!("-5".parse::<CompressionLevel>()?, CompressionLevel::Custom(-5));
+
+    Ok(())
+}
+
+#[test]
+fn names() -> Result<(), CompressionError> {
+    let algorithms: &[CompressionAlgorithm] = &[
+        #[cfg(feature = "compression-lz4")]
+        CompressionAlgorithm::Lz4,
+
+        #[cfg(feature = "compression-bzip2")]
+        CompressionAlgorithm::Bzip2,
+
+        #[cfg(feature = "compression-deflate")]
+        CompressionAlgorithm::Deflate,
+
+        #[cfg(feature = "compression-deflate")]
+        CompressionAlgorithm::Gzip,
+
+        #[cfg(feature = "compression-deflate")]
+        CompressionAlgorithm::Zlib,
+
+        #[cfg(feature = "compression-zstd")]
+        CompressionAlgorithm::Zstd
+    ];
+
+    for algorithm in algorithms {
+        assert_eq!(&algorithm.name().parse::<CompressionAlgorithm>()?, algorithm);
+        assert_eq!(&algorithm.name().to_uppercase().parse::<CompressionAlgorithm>()?, algorithm);
+    }
+
+    #[cfg(feature = "compression-deflate")]
+    assert_eq!("gz".parse::<CompressionAlgorithm>()?, CompressionAlgorithm::Gzip);
+
+    #[cfg(feature = "compression-zstd")]
+    assert_eq!("zst".parse::<CompressionAlgorithm>()?, CompressionAlgorithm::Zstd);
+
+    assert!(matches!(
+        "brotli".parse::<CompressionAlgorithm>(),
+        Err(CompressionError::UnknownAlgorithm(name)) if name == "brotli"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn auto() -> Result<(), CompressionError> {
+    let algorithms: &[CompressionAlgorithm] = &[
+        #[cfg(feature = "compression-lz4")]
+        CompressionAlgorithm::Lz4,
+
+        #[cfg(feature = "compression-bzip2")]
+        CompressionAlgorithm::Bzip2,
+
+        #[cfg(feature = "compression-deflate")]
+        CompressionAlgorithm::Gzip,
+
+        #[cfg(feature = "compression-zstd")]
+        CompressionAlgorithm::Zstd
+    ];
+
+    for algorithm in algorithms.iter().copied() {
+        let mut compressor = Compressor::new(algorithm, CompressionLevel::Default)?;
+
+        compressor.write_all(b"Hello, World!")?;
+        compressor.flush()?;
+        compressor.try_finish()?;
+
+        let mut compressed = Vec::new();
+
+        compressor.read_to_end(&mut compressed)?;
+
+        let mut decompressor = Decompressor::auto(&compressed)?;
+
+        assert_eq!(decompressor.algorithm(), algorithm);
+
+        decompressor.write_all(&compressed)?;
+        decompressor.flush()?;
+
+        let mut decompressed = Vec::new();
+
+        decompressor.read_to_end(&mut decompressed)?;
+
+        assert_eq!(decompressed, b"Hello, World!");
+    }
+
+    assert!(matches!(
+        Decompressor::auto(b"Hello, World!"),
+        Err(CompressionError::UnknownFormat)
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+/// Check if the given path matches the glob pattern.
+///
+/// Supported syntax:
+///
+/// - `?` matches any single character except `/`.
+/// - `*` matches any amount of characters except `/`.
+/// - `**` matches any amount of characters including `/`. `**/` also matches
+///   zero directories, so `**/*.dll` matches both `a.dll` and `b/a.dll`.
+///
+/// Patterns without `/` are matched against the file name only, so `*.dll`
+/// matches `a.dll` and `b/a.dll` too.
+pub fn matches(pattern: impl AsRef<str>, path: impl AsRef<str>) -> bool {
+    let pattern = pattern.as_ref();
+
+    let path = path.as_ref()
+        .trim_start_matches("./")
+        .trim_end_matches('/');
+
+    let path = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit_once('/')
+            .map(|(_, name)| name)
+            .unwrap_or(path)
+    };
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let path = path.chars().collect::<Vec<_>>();
+
+    matches_chars(&pattern, &path)
+}
+
+fn matches_chars(pattern: &[char], path: &[char]) -> bool {
+    // Results of matching pattern suffixes against path suffixes. Without
+    // them patterns with many wildcards take exponential time.
+    let mut memo = vec![None; (pattern.len() + 1) * (path.len() + 1)];
+
+    matches_from(pattern, path, 0, 0, &mut memo)
+}
+
+/// Check if the pattern suffix starting from `i` matches the path suffix
+/// starting from `j`.
+fn matches_from(
+    pattern: &[char],
+    path: &[char],
+    i: usize,
+    j: usize,
+    memo: &mut [Option<bool>]
+) -> bool {
+    let key = i * (path.len() + 1) + j;
+
+    if let Some(result) = memo[key] {
+        return result;
+    }
+
+    let result = match &pattern[i..] {
+        [] => j == path.len(),
+
+        ['*', '*', rest @ ..] => {
+            (matches!(rest, ['/', ..]) && matches_from(pattern, path, i + 3, j, memo))
+                || (j..=path.len()).any(|k| matches_from(pattern, path, i + 2, k, memo))
+        }
+
+        ['*', ..] => {
+            // Single star can't match past the next separator.
+            let end = path[j..].iter()
+                .position(|char| *char == '/')
+                .map(|end| j + end)
+                .unwrap_or(path.len());
+
+            (j..=end).any(|k| matches_from(pattern, path, i + 1, k, memo))
+        }
+
+        ['?', ..] => {
+            matches!(path.get(j), Some(char) if *char != '/')
+                && matches_from(pattern, path, i + 1, j + 1, memo)
+        }
+
+        [char, ..] => {
+            path.get(j) == Some(char) && matches_from(pattern, path, i + 1, j + 1, memo)
+        }
+    };
+
+    memo[key] = Some(result);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        assert!(matches("*.dll", "a.dll"));
+        assert!(matches("*.dll", "b/a.dll"));
+        assert!(!matches("*.dll", "a.dll.bak"));
+
+        assert!(matches("data/*", "data/a.txt"));
+        assert!(matches("data/*", "./data/a/"));
+        assert!(!matches("data/*", "data/a/b.txt"));
+
+        assert!(matches("data/**", "data/a/b.txt"));
+        assert!(!matches("data/**", "other/data/a.txt"));
+
+        assert!(matches("**/*.pdb", "a.pdb"));
+        assert!(matches("**/*.pdb", "a/b/c.pdb"));
+
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file10.txt"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        let path = format!("{}b", "a/".repeat(64));
+
+        assert!(!matches("**/**/**/**/**/**/**/**/**/**/**/**/**/**/**/**/c", &path));
+        assert!(matches("**/**/**/**/**/**/**/**/**/**/**/**/**/**/**/**/b", &path));
+
+        let path = "a".repeat(128);
+
+        assert!(!matches("*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b", &path));
+        assert!(matches("*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*", &path));
+    }
+}
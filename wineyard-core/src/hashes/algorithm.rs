@@ -265,10 +265,85 @@ impl HashAlgorithm {
             #[cfg(feature = "hashes-blake2")]
             Self::Blake2b => "blake2b",
 
-            #[cfg(feature = "hashes-blake2")]
+            #[cfg(feature = "hashes-blake3")]
             Self::Blake3 => "blake3"
         }
     }
+
+    /// Get family of the hash algorithm, as listed in the table above.
+    pub const fn family(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "hashes-seahash")]
+            Self::Seahash => "seahash",
+
+            #[cfg(feature = "hashes-crc32")]
+            Self::Crc32 => "crc",
+
+            #[cfg(feature = "hashes-crc32c")]
+            Self::Crc32c => "crc",
+
+            #[cfg(feature = "hashes-siphash")]
+            Self::Siphash_1_3_64 | Self::Siphash_1_3_128 |
+            Self::Siphash_2_4_64 | Self::Siphash_2_4_128 => "siphash",
+
+            #[cfg(feature = "hashes-xxh")]
+            Self::Xxh_32 | Self::Xxh_64 | Self::Xxh3_64 | Self::Xxh3_128 => "xxh",
+
+            #[cfg(feature = "hashes-md5")]
+            Self::Md5 => "md",
+
+            #[cfg(feature = "hashes-sha1")]
+            Self::Sha1 => "sha",
+
+            #[cfg(feature = "hashes-sha2")]
+            Self::Sha2_224 | Self::Sha2_256 | Self::Sha2_384 | Self::Sha2_512 |
+            Self::Sha2_512_224 | Self::Sha2_512_256 => "sha",
+
+            #[cfg(feature = "hashes-sha3")]
+            Self::Shake_128 | Self::Shake_256 |
+            Self::TurboShake_128 | Self::TurboShake_256 |
+            Self::CShake_128 | Self::CShake_256 |
+            Self::Keccak_224 | Self::Keccak_256 | Self::Keccak_256_Full |
+            Self::Keccak_384 | Self::Keccak_512 |
+            Self::Sha3_224 | Self::Sha3_256 | Self::Sha3_384 | Self::Sha3_512 => "sha",
+
+            #[cfg(feature = "hashes-blake2")]
+            Self::Blake2s | Self::Blake2b => "blake",
+
+            #[cfg(feature = "hashes-blake3")]
+            Self::Blake3 => "blake"
+        }
+    }
+
+    /// Check if the hash algorithm is cryptographically secure and can be
+    /// used for security-sensitive tasks like signatures or authentication.
+    ///
+    /// md5 and sha1 are cryptographic by design but broken, so they're
+    /// not considered secure.
+    pub const fn is_cryptographic(&self) -> bool {
+        match self {
+            #[cfg(feature = "hashes-sha2")]
+            Self::Sha2_224 | Self::Sha2_256 | Self::Sha2_384 | Self::Sha2_512 |
+            Self::Sha2_512_224 | Self::Sha2_512_256 => true,
+
+            #[cfg(feature = "hashes-sha3")]
+            Self::Shake_128 | Self::Shake_256 |
+            Self::TurboShake_128 | Self::TurboShake_256 |
+            Self::CShake_128 | Self::CShake_256 |
+            Self::Keccak_224 | Self::Keccak_256 | Self::Keccak_256_Full |
+            Self::Keccak_384 | Self::Keccak_512 |
+            Self::Sha3_224 | Self::Sha3_256 | Self::Sha3_384 | Self::Sha3_512 => true,
+
+            #[cfg(feature = "hashes-blake2")]
+            Self::Blake2s | Self::Blake2b => true,
+
+            #[cfg(feature = "hashes-blake3")]
+            Self::Blake3 => true,
+
+            #[allow(unreachable_patterns)]
+            _ => false
+        }
+    }
 }
 
 impl FromStr for HashAlgorithm {
@@ -1,8 +1,10 @@
 mod algorithm;
 mod hasher;
+mod multi_hasher;
 
 #[cfg(test)]
 mod tests;
 
 pub use algorithm::HashAlgorithm;
 pub use hasher::Hasher;
+pub use multi_hasher::MultiHasher;
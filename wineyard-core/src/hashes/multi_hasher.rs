@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use super::algorithm::HashAlgorithm;
+use super::hasher::Hasher;
+
+/// Hasher which computes several digests over the same stream of bytes.
+///
+/// Every written chunk is passed to all the inner hashers, so e.g. a fast
+/// checksum and a secure hash can be calculated without reading the data
+/// twice.
+#[derive(Debug, Default)]
+pub struct MultiHasher {
+    hashers: Vec<Hasher>
+}
+
+impl MultiHasher {
+    /// Create new multi hasher for the given hash algorithms. Duplicate
+    /// algorithms are ignored.
+    pub fn new(
+        algorithms: impl IntoIterator<Item = impl Into<HashAlgorithm>>
+    ) -> Self {
+        let mut hasher = Self::default();
+
+        for algorithm in algorithms {
+            hasher.push(Hasher::new(algorithm));
+        }
+
+        hasher
+    }
+
+    /// Add hasher to the list. If hasher with the same algorithm is already
+    /// added then the new one is ignored.
+    pub fn push(&mut self, hasher: Hasher) {
+        let algorithm = hasher.algorithm();
+
+        if !self.hashers.iter().any(|hasher| hasher.algorithm() == algorithm) {
+            self.hashers.push(hasher);
+        }
+    }
+
+    /// Add hasher to the list.
+    #[inline]
+    pub fn with_hasher(mut self, hasher: Hasher) -> Self {
+        self.push(hasher);
+
+        self
+    }
+
+    /// Get list of used hash algorithms.
+    #[inline]
+    pub fn algorithms(&self) -> impl Iterator<Item = HashAlgorithm> {
+        self.hashers.iter().map(Hasher::algorithm)
+    }
+
+    /// Calculate hashes from all the written bytes.
+    pub fn finalize(self) -> HashMap<HashAlgorithm, Box<[u8]>> {
+        self.hashers.into_iter()
+            .map(|hasher| (hasher.algorithm(), hasher.finalize().0))
+            .collect()
+    }
+
+    /// Calculate hashes of the given bytes slice.
+    pub fn hash(
+        mut self,
+        buf: impl AsRef<[u8]>
+    ) -> std::io::Result<HashMap<HashAlgorithm, Box<[u8]>>> {
+        self.write_all(buf.as_ref())?;
+        self.flush()?;
+
+        Ok(self.finalize())
+    }
+}
+
+impl Write for MultiHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for hasher in &mut self.hashers {
+            hasher.write_all(buf)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for hasher in &mut self.hashers {
+            hasher.flush()?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use std::io::Write;
 
-use super::{HashAlgorithm, Hasher};
+use super::{HashAlgorithm, Hasher, MultiHasher};
 
 #[allow(unused)]
 fn test(
@@ -481,3 +481,57 @@ fn blake3() -> std::io::Result<()> {
             178, 103]
     )
 }
+
+#[cfg(all(
+    feature = "hashes-crc32",
+    feature = "hashes-xxh",
+    feature = "hashes-md5",
+    feature = "hashes-sha2",
+    feature = "hashes-blake3"
+))]
+#[test]
+fn is_cryptographic() {
+    assert!(!HashAlgorithm::Crc32.is_cryptographic());
+    assert!(!HashAlgorithm::Xxh3_64.is_cryptographic());
+    assert!(!HashAlgorithm::Md5.is_cryptographic());
+    assert!(HashAlgorithm::Sha2_256.is_cryptographic());
+    assert!(HashAlgorithm::Blake3.is_cryptographic());
+
+    assert_eq!(HashAlgorithm::Crc32.family(), "crc");
+    assert_eq!(HashAlgorithm::Xxh3_64.family(), "xxh");
+    assert_eq!(HashAlgorithm::Md5.family(), "md");
+    assert_eq!(HashAlgorithm::Sha2_256.family(), "sha");
+    assert_eq!(HashAlgorithm::Blake3.family(), "blake");
+}
+
+#[cfg(all(feature = "hashes-crc32", feature = "hashes-sha2"))]
+#[test]
+fn multi_hasher() -> std::io::Result<()> {
+    let mut hasher = MultiHasher::new([
+        HashAlgorithm::Crc32,
+        HashAlgorithm::Sha2_256,
+        HashAlgorithm::Crc32
+    ]);
+
+    assert_eq!(hasher.algorithms().count(), 2);
+
+    hasher.write_all(b"Hello, ")?;
+    hasher.write_all(b"World!")?;
+    hasher.flush()?;
+
+    let hashes = hasher.finalize();
+
+    assert_eq!(hashes.len(), 2);
+
+    assert_eq!(
+        hashes[&HashAlgorithm::Crc32],
+        Hasher::new(HashAlgorithm::Crc32).hash(b"Hello, World!")?
+    );
+
+    assert_eq!(
+        hashes[&HashAlgorithm::Sha2_256],
+        Hasher::new(HashAlgorithm::Sha2_256).hash(b"Hello, World!")?
+    );
+
+    Ok(())
+}
@@ -3,6 +3,7 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub mod rw_sync;
 pub mod buffer;
+pub mod glob;
 
 pub mod tasks;
 
@@ -1,13 +1,23 @@
 use std::path::PathBuf;
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::io::SeekFrom;
 
 use tokio::fs::File;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio::sync::Notify;
 use tokio::task::{JoinError, JoinHandle};
-use reqwest::Client;
+use reqwest::{Client, Url};
+
+use crate::tasks::TimeoutError;
+use crate::buffer::io_buf_size;
+
+use super::proxy::{client_with_proxy, ProxyError};
+use super::transport::{
+    Transport, TransportStream, TransportResponse, HttpTransport, FileStream
+};
 
 // TODO: make a global vector of atomics for all active downloads.
 // Each one will store download speed in b/s. Then, using a user-
@@ -18,9 +28,12 @@ use reqwest::Client;
 /// Used to slow down downloading speed.
 const DOWNLOADER_CHUNKS_REQUESTS_TIMEOUT: Option<Duration> = None;
 
-lazy_static::lazy_static! {
-    static ref CLIENT: Client = Client::new();
-}
+/// Maximal amount of `(timestamp, current)` samples stored to calculate
+/// the download speed.
+const DOWNLOADER_SPEED_SAMPLES: usize = 64;
+
+/// Time window used to calculate the rolling download speed.
+const DOWNLOADER_SPEED_WINDOW: Duration = Duration::from_secs(1);
 
 #[derive(Debug, thiserror::Error)]
 pub enum DownloaderError {
@@ -34,7 +47,16 @@ pub enum DownloaderError {
     SendError,
 
     #[error("Failed to await downloader task: {0}")]
-    RuntimeJoin(#[from] JoinError)
+    RuntimeJoin(#[from] JoinError),
+
+    #[error("Downloader task timed out")]
+    Timeout(#[from] TimeoutError),
+
+    #[error("Copying of local files is disabled: {}", .0.display())]
+    LocalFilesDisabled(PathBuf),
+
+    #[error("Downloaded chunk was rejected")]
+    ChunkRejected
 }
 
 pub struct DownloadOptions {
@@ -44,6 +66,13 @@ pub struct DownloadOptions {
     /// Enabled by default.
     pub continue_download: bool,
 
+    /// Path to the file storing entity tag of the downloading content.
+    ///
+    /// If set, the stored tag is sent when downloading is continued so
+    /// the output file is downloaded from scratch if the content was
+    /// changed in between.
+    pub etag_file: Option<PathBuf>,
+
     /// Callback executed every time downloader reads a chunk of data.
     ///
     /// Provides `(current, total, diff)` values where `diff` is the change
@@ -51,6 +80,14 @@ pub struct DownloadOptions {
     #[allow(clippy::type_complexity)]
     pub on_update: Option<Box<dyn Fn(u64, u64, u64) + Send + Sync>>,
 
+    /// Callback executed before writing every chunk of data.
+    ///
+    /// Provides `(current, total, len)` values where `len` is the length
+    /// of the chunk. If it returns `false` then the chunk is not written
+    /// and downloading is stopped with `ChunkRejected` error.
+    #[allow(clippy::type_complexity)]
+    pub on_chunk: Option<Box<dyn Fn(u64, u64, u64) -> bool + Send + Sync>>,
+
     /// Callback executed when downloading is successfully finished.
     pub on_finish: Option<Box<dyn FnOnce(u64) + Send + Sync>>
 }
@@ -60,14 +97,76 @@ impl Default for DownloadOptions {
     fn default() -> Self {
         Self {
             continue_download: true,
+            etag_file: None,
             on_update: None,
+            on_chunk: None,
             on_finish: None
         }
     }
 }
 
+/// Ring buffer of timestamped downloaded bytes amounts used to calculate
+/// the rolling average download speed.
+#[derive(Debug, Default)]
+struct SpeedSamples(VecDeque<(Instant, u64)>);
+
+impl SpeedSamples {
+    /// Store new `current` value sample.
+    fn push(&mut self, timestamp: Instant, current: u64) {
+        while self.0.len() >= DOWNLOADER_SPEED_SAMPLES {
+            self.0.pop_front();
+        }
+
+        // Keep at most one sample older than the window so the speed
+        // is calculated over the whole window.
+        while self.0.len() > 1 && timestamp.duration_since(self.0[1].0) >= DOWNLOADER_SPEED_WINDOW {
+            self.0.pop_front();
+        }
+
+        self.0.push_back((timestamp, current));
+    }
+
+    /// Calculate average speed (bytes per second) within the window ending
+    /// at the given timestamp.
+    fn speed(&self, now: Instant) -> u64 {
+        let Some((last_time, last_current)) = self.0.back().copied() else {
+            return 0;
+        };
+
+        // Downloading is stalled.
+        if now.duration_since(last_time) > DOWNLOADER_SPEED_WINDOW {
+            return 0;
+        }
+
+        let Some((first_time, first_current)) = self.0.front().copied() else {
+            return 0;
+        };
+
+        let elapsed = now.duration_since(first_time).as_secs_f64();
+
+        if elapsed <= 0.0 {
+            return 0;
+        }
+
+        (last_current.saturating_sub(first_current) as f64 / elapsed) as u64
+    }
+}
+
+/// Get path of the local file if the given URL is a `file://` URL or
+/// a filesystem path.
+pub fn local_source(url: &str) -> Option<PathBuf> {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+        Ok(_) => None,
+        Err(_) => Some(PathBuf::from(url))
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct Downloader(Client);
+pub struct Downloader {
+    transport: Arc<dyn Transport>,
+    local_files: bool
+}
 
 impl Default for Downloader {
     #[inline(always)]
@@ -78,18 +177,56 @@ impl Default for Downloader {
 
 impl Downloader {
     /// Create new file downloader using shared reqwest client.
+    ///
+    /// The client honors `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and
+    /// `NO_PROXY` env variables.
     #[inline]
     pub fn new() -> Self {
         #[cfg(feature = "tracing")]
         tracing::trace!("create default downloader");
 
-        Self(CLIENT.clone())
+        Self::from_transport(Arc::new(HttpTransport::default()))
+    }
+
+    /// Create new file downloader which sends requests through the given
+    /// proxy, except the hosts listed in the `NO_PROXY` env variable.
+    ///
+    /// See `client_with_proxy` for details.
+    #[inline]
+    pub fn with_proxy(proxy: impl AsRef<str>) -> Result<Self, ProxyError> {
+        Ok(Self::from_transport(Arc::new(HttpTransport::new(client_with_proxy(proxy, None)?))))
     }
 
     /// Create new file downloader from the given reqwest client.
     #[inline(always)]
-    pub const fn from_client(client: Client) -> Self {
-        Self(client)
+    pub fn from_client(client: Client) -> Self {
+        Self::from_transport(Arc::new(HttpTransport::new(client)))
+    }
+
+    /// Create new file downloader which requests the content using the
+    /// given transport.
+    #[inline]
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self::from_transport(Arc::new(transport))
+    }
+
+    #[inline(always)]
+    fn from_transport(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            local_files: false
+        }
+    }
+
+    /// Allow copying of `file://` URLs and filesystem paths.
+    ///
+    /// Disabled by default since any readable file could be copied this
+    /// way, so callers must check the source path themselves.
+    #[inline(always)]
+    pub const fn with_local_files(mut self, local_files: bool) -> Self {
+        self.local_files = local_files;
+
+        self
     }
 
     /// Start downloading of the file using default options.
@@ -103,6 +240,9 @@ impl Downloader {
     /// This function doesn't block the caller's thread until the file is
     /// downloaded. Async is used to perform initial HTTP GET request to obtain
     /// the header of the file to get its content size.
+    ///
+    /// `file://` URLs and filesystem paths are copied instead, reporting
+    /// progress the same way, if allowed by `with_local_files`.
     pub fn download_with_options(
         &self,
         url: impl ToString,
@@ -123,13 +263,18 @@ impl Downloader {
         let current = Arc::new(AtomicU64::new(0));
         let total = Arc::new(AtomicU64::new(0));
         let aborted = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(Notify::new());
+        let samples = Arc::new(Mutex::new(SpeedSamples::default()));
 
-        let client = self.0.clone();
+        let transport = self.transport.clone();
+        let local_files = self.local_files;
 
         let task = {
             let current = current.clone();
             let total = total.clone();
             let aborted = aborted.clone();
+            let stopped = stopped.clone();
+            let samples = samples.clone();
 
             crate::tasks::spawn(async move {
                 // Open output file.
@@ -146,78 +291,142 @@ impl Downloader {
 
                 current.store(downloaded, Ordering::Release);
 
+                if let Ok(mut samples) = samples.lock() {
+                    samples.push(Instant::now(), downloaded);
+                }
+
                 // Add an inner buffer to the output file to optimize disk writes.
-                let mut output_file = BufWriter::new(output_file);
+                let mut output_file = BufWriter::with_capacity(io_buf_size(), output_file);
 
                 output_file.seek(SeekFrom::Start(downloaded)).await?;
 
-                // Prepare HTTP request.
-                let request = client
-                    .get(url)
-                    .header("range", format!("bytes={downloaded}-"))
-                    .build()?;
+                let mut source: Box<dyn TransportStream> = match local_source(&url) {
+                    Some(path) if !local_files => {
+                        return Err(DownloaderError::LocalFilesDisabled(path));
+                    }
 
-                let mut response = client.execute(request).await?;
+                    Some(path) => {
+                        let mut file = File::open(path).await?;
 
-                // HTTP 416 = provided range is greater than the actual
-                // content length (means the file is downloaded).
-                //
-                // Source: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/416
-                if response.status() == 416 {
-                    total.store(downloaded, Ordering::Release);
+                        let size = file.metadata().await?.len();
 
-                    return Ok(downloaded);
-                }
+                        // Nothing to copy (means the file is copied).
+                        if downloaded >= size {
+                            total.store(downloaded, Ordering::Release);
+
+                            return Ok(downloaded);
+                        }
 
-                // Try to read the `Content-Length` HTTP header and if successful,
-                // store its value as the partial length of downloadable content.
-                if let Some(content_length) = response.headers().get("Content-Length") {
-                    let content_length = String::from_utf8_lossy(content_length.as_bytes());
+                        total.store(size, Ordering::Release);
 
-                    if let Ok(content_length) = content_length.parse::<u64>() {
-                        // If we already downloaded part of the content -
-                        // `Content-Length` will contain a length of the
-                        // remaining content.
-                        total.store(downloaded + content_length, Ordering::Release);
+                        file.seek(SeekFrom::Start(downloaded)).await?;
+
+                        Box::new(FileStream(file))
                     }
-                }
 
-                // Request content range (downloaded + remained content size).
-                //
-                // If finished or overcame: `bytes */10611646760`.
-                // If not finished: `bytes 10611646759-10611646759/10611646760`.
-                //
-                // Content-Range: <unit> <range>/<size>
-                // Content-Range: <unit> <range>/*
-                // Content-Range: <unit> */<size>
-                //
-                // Source: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Range
-                if let Some(range) = response.headers().get("Content-Range") {
-                    let range = String::from_utf8_lossy(range.as_bytes());
-
-                    if let Some(range) = range.strip_prefix("bytes ") {
-                        if let Some((range, size)) = range.split_once('/') {
-                            // Downloading finished.
-                            if range == "*" {
+                    None => {
+                        // Entity tag of the already downloaded content.
+                        let if_range = match &options.etag_file {
+                            Some(etag_file) if downloaded > 0 => tokio::fs::read_to_string(etag_file).await.ok(),
+                            _ => None
+                        };
+
+                        let response = tokio::select! {
+                            response = transport.request(&url, downloaded, if_range.as_deref()) => response?,
+
+                            // Nothing is received yet.
+                            _ = stopped.notified() => return Ok(downloaded)
+                        };
+
+                        match response {
+                            TransportResponse::Finished => {
                                 total.store(downloaded, Ordering::Release);
 
                                 return Ok(downloaded);
                             }
 
-                            if let Ok(size) = size.parse::<u64>() {
-                                total.store(size, Ordering::Release);
+                            TransportResponse::Content { offset, total: size, etag, stream } => {
+                                if let Some(etag_file) = &options.etag_file {
+                                    match etag {
+                                        Some(etag) => tokio::fs::write(etag_file, etag).await?,
+
+                                        None if tokio::fs::try_exists(etag_file).await? => {
+                                            tokio::fs::remove_file(etag_file).await?;
+                                        }
+
+                                        None => ()
+                                    }
+                                }
+
+                                if offset > downloaded {
+                                    return Err(std::io::Error::other("transport skipped not downloaded content").into());
+                                }
+
+                                // Overwrite already downloaded content if the
+                                // transport sends it again, otherwise it would
+                                // be duplicated in the output file.
+                                if offset < downloaded {
+                                    output_file.get_ref().set_len(offset).await?;
+                                    output_file.seek(SeekFrom::Start(offset)).await?;
+
+                                    current.store(offset, Ordering::Release);
+                                }
+
+                                if let Some(size) = size {
+                                    total.store(size, Ordering::Release);
+                                }
+
+                                stream
                             }
                         }
                     }
-                }
+                };
 
-                // Read chunks of data from the stream and redirect them to the writer.
-                while let Some(chunk) = response.chunk().await? {
-                    output_file.write_all(&chunk).await?;
+                let mut chunk = Vec::new();
+
+                // Read chunks of data from the source and redirect them to the writer.
+                loop {
+                    let result = tokio::select! {
+                        result = source.read_chunk(&mut chunk) => result,
+
+                        _ = stopped.notified() => {
+                            output_file.flush().await?;
+
+                            return Ok(current.load(Ordering::Acquire));
+                        }
+                    };
+
+                    match result {
+                        Ok(true) => (),
+                        Ok(false) => break,
+
+                        // Keep already received content so the downloading
+                        // can be continued after e.g. dropped connection.
+                        Err(err) => {
+                            output_file.flush().await?;
+
+                            return Err(err);
+                        }
+                    }
 
                     let len = chunk.len() as u64;
+
+                    if let Some(callback) = &options.on_chunk
+                        && !callback(current.load(Ordering::Relaxed), total.load(Ordering::Relaxed), len)
+                    {
+                        output_file.flush().await?;
+
+                        return Err(DownloaderError::ChunkRejected);
+                    }
+
+                    output_file.write_all(&chunk).await?;
+
                     let prev = current.fetch_add(len, Ordering::Relaxed);
 
+                    if let Ok(mut samples) = samples.lock() {
+                        samples.push(Instant::now(), prev + len);
+                    }
+
                     if let Some(callback) = &options.on_update {
                         callback(prev + len, total.load(Ordering::Relaxed), len);
                     }
@@ -225,7 +434,7 @@ impl Downloader {
                     if aborted.load(Ordering::Acquire) {
                         output_file.flush().await?;
 
-                        return Ok(total.load(Ordering::Acquire));
+                        return Ok(current.load(Ordering::Acquire));
                     }
 
                     if let Some(timeout) = DOWNLOADER_CHUNKS_REQUESTS_TIMEOUT {
@@ -236,11 +445,17 @@ impl Downloader {
                 // Flush remaining buffer to the disk.
                 output_file.flush().await?;
 
+                // Report the actual output file size since the total size
+                // can be unknown.
+                let downloaded = current.load(Ordering::Acquire);
+
+                total.store(downloaded, Ordering::Release);
+
                 if let Some(callback) = options.on_finish {
-                    callback(total.load(Ordering::Acquire));
+                    callback(downloaded);
                 }
 
-                Ok::<u64, DownloaderError>(total.load(Ordering::Acquire))
+                Ok::<u64, DownloaderError>(downloaded)
             })
         };
 
@@ -248,6 +463,8 @@ impl Downloader {
             current,
             total,
             aborted,
+            stopped,
+            samples,
             task
         }
     }
@@ -258,6 +475,8 @@ pub struct DownloaderTask {
     current: Arc<AtomicU64>,
     total: Arc<AtomicU64>,
     aborted: Arc<AtomicBool>,
+    stopped: Arc<Notify>,
+    samples: Arc<Mutex<SpeedSamples>>,
     task: JoinHandle<Result<u64, DownloaderError>>
 }
 
@@ -290,6 +509,41 @@ impl DownloaderTask {
         current as f64 / total as f64
     }
 
+    /// Get downloading speed in bytes per second.
+    ///
+    /// This is a rolling average over the last second of downloading.
+    pub fn speed(&self) -> u64 {
+        self.samples.lock()
+            .map(|samples| samples.speed(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    /// Get estimated time remaining until the downloading is finished.
+    ///
+    /// Return `None` if the total size is unknown or the downloading
+    /// is stalled.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total();
+
+        if total == 0 {
+            return None;
+        }
+
+        let remaining = total.saturating_sub(self.current());
+
+        if remaining == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        let speed = self.speed();
+
+        if speed == 0 {
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(remaining as f64 / speed as f64))
+    }
+
     /// Check if downloading has finished.
     ///
     /// Note that it could fail so this doesn't mean that we've successfully
@@ -306,6 +560,20 @@ impl DownloaderTask {
         self.task.await?
     }
 
+    /// Wait until the download task is finished or the given duration
+    /// passes. The task is aborted on timeout.
+    pub async fn wait_timeout(mut self, duration: Duration) -> Result<u64, DownloaderError> {
+        match crate::tasks::with_timeout(duration, &mut self.task).await {
+            Ok(result) => result?,
+
+            Err(err) => {
+                self.abort();
+
+                Err(err.into())
+            }
+        }
+    }
+
     /// Abort the task execution, stopping the file downloading.
     #[inline]
     pub fn abort(self) {
@@ -313,12 +581,173 @@ impl DownloaderTask {
 
         self.task.abort();
     }
+
+    /// Stop the file downloading and wait until the task is finished,
+    /// returning amount of output file bytes.
+    ///
+    /// Unlike `abort`, already received content is written to the output
+    /// file so the downloading can be continued later.
+    pub async fn stop(self) -> Result<u64, DownloaderError> {
+        self.aborted.store(true, Ordering::Release);
+
+        // Permit is stored if the task is not waiting for it yet.
+        self.stopped.notify_one();
+
+        self.task.await?
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::network::transport::TransportFuture;
+
     use super::*;
 
+    /// Transport serving the content from memory.
+    #[derive(Debug)]
+    struct MemoryTransport {
+        content: Vec<u8>,
+
+        /// Drop the connection after sending this amount of bytes.
+        drop_after: Mutex<Option<usize>>,
+
+        /// Always send the whole content like HTTP servers which don't
+        /// support range requests.
+        ignore_range: bool,
+
+        etag: Option<String>
+    }
+
+    struct MemoryStream {
+        content: Vec<u8>,
+        drop_after: Option<usize>
+    }
+
+    impl Transport for MemoryTransport {
+        fn request<'a>(
+            &'a self,
+            _url: &'a str,
+            offset: u64,
+            if_range: Option<&'a str>
+        ) -> TransportFuture<'a, TransportResponse> {
+            Box::pin(async move {
+                // Send the whole content if it was changed.
+                let offset = match if_range {
+                    Some(etag) if self.etag.as_deref() != Some(etag) => 0,
+                    _ => offset
+                };
+
+                let mut offset = offset as usize;
+
+                // Simulate HTTP 416.
+                if offset >= self.content.len() {
+                    return Ok(TransportResponse::Finished);
+                }
+
+                if self.ignore_range {
+                    offset = 0;
+                }
+
+                Ok(TransportResponse::Content {
+                    offset: offset as u64,
+                    total: Some(self.content.len() as u64),
+                    etag: self.etag.clone(),
+                    stream: Box::new(MemoryStream {
+                        content: self.content[offset..].to_vec(),
+                        drop_after: self.drop_after.lock().unwrap().take()
+                    })
+                })
+            })
+        }
+    }
+
+    impl TransportStream for MemoryStream {
+        fn read_chunk<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> TransportFuture<'a, bool> {
+            Box::pin(async move {
+                if self.drop_after == Some(0) {
+                    return Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into());
+                }
+
+                let mut len = self.content.len().min(1024);
+
+                if let Some(drop_after) = &mut self.drop_after {
+                    len = len.min(*drop_after);
+
+                    *drop_after -= len;
+                }
+
+                buf.clear();
+                buf.extend(self.content.drain(..len));
+
+                Ok(len > 0)
+            })
+        }
+    }
+
+    /// Transport sending the content and then waiting forever.
+    #[derive(Debug)]
+    struct StalledTransport(Vec<u8>);
+
+    struct StalledStream(Option<Vec<u8>>);
+
+    impl Transport for StalledTransport {
+        fn request<'a>(
+            &'a self,
+            _url: &'a str,
+            _offset: u64,
+            _if_range: Option<&'a str>
+        ) -> TransportFuture<'a, TransportResponse> {
+            Box::pin(async move {
+                Ok(TransportResponse::Content {
+                    offset: 0,
+                    total: Some(self.0.len() as u64 * 2),
+                    etag: None,
+                    stream: Box::new(StalledStream(Some(self.0.clone())))
+                })
+            })
+        }
+    }
+
+    impl TransportStream for StalledStream {
+        fn read_chunk<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> TransportFuture<'a, bool> {
+            Box::pin(async move {
+                let Some(content) = self.0.take() else {
+                    return std::future::pending().await;
+                };
+
+                buf.clear();
+                buf.extend(content);
+
+                Ok(true)
+            })
+        }
+    }
+
+    #[test]
+    fn speed_samples() {
+        let mut samples = SpeedSamples::default();
+
+        let start = Instant::now();
+
+        assert_eq!(samples.speed(start), 0);
+
+        for i in 0..=10 {
+            samples.push(start + Duration::from_millis(i * 100), i * 1000);
+        }
+
+        assert_eq!(samples.speed(start + Duration::from_secs(1)), 10000);
+
+        // Old samples are removed from the window.
+        for i in 11..=20 {
+            samples.push(start + Duration::from_millis(i * 100), 10000 + (i - 10) * 500);
+        }
+
+        assert_eq!(samples.speed(start + Duration::from_secs(2)), 5000);
+
+        // Stalled downloading.
+        assert_eq!(samples.speed(start + Duration::from_secs(4)), 0);
+    }
+
     #[tokio::test]
     async fn download() -> Result<(), DownloaderError> {
         let path = std::env::temp_dir().join(".wineyard-core-download-test");
@@ -337,6 +766,240 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn transport_download() -> Result<(), DownloaderError> {
+        let path = std::env::temp_dir().join(".wineyard-core-transport-download-test");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let content = (0..100 * 1024)
+            .map(|i| i as u8)
+            .collect::<Vec<u8>>();
+
+        let downloader = Downloader::with_transport(MemoryTransport {
+            content: content.clone(),
+            drop_after: Mutex::new(Some(10000)),
+            ignore_range: false,
+            etag: None
+        });
+
+        // Connection is dropped but received content is kept.
+        let task = downloader.download("memory://content", &path);
+
+        assert!(matches!(task.wait().await, Err(DownloaderError::Io(_))));
+        assert_eq!(std::fs::read(&path)?, &content[..10000]);
+
+        // Downloading is continued from the received content.
+        let task = downloader.download("memory://content", &path);
+
+        assert_eq!(task.wait().await?, content.len() as u64);
+        assert_eq!(std::fs::read(&path)?, content);
+
+        // Already downloaded content is not requested again.
+        let task = downloader.download("memory://content", &path);
+
+        assert_eq!(task.wait().await?, content.len() as u64);
+        assert_eq!(std::fs::read(&path)?, content);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_download() -> Result<(), DownloaderError> {
+        let path = std::env::temp_dir().join(".wineyard-core-resume-download-test");
+
+        let content = (0..100 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+
+        for ignore_range in [false, true] {
+            for offset in [1, 1023, 1024, 1025, 50000, content.len() - 1] {
+                if path.exists() {
+                    std::fs::remove_file(&path)?;
+                }
+
+                let downloader = Downloader::with_transport(MemoryTransport {
+                    content: content.clone(),
+                    drop_after: Mutex::new(Some(offset)),
+                    ignore_range,
+                    etag: None
+                });
+
+                let task = downloader.download("memory://content", &path);
+
+                assert!(matches!(task.wait().await, Err(DownloaderError::Io(_))));
+                assert_eq!(std::fs::read(&path)?, &content[..offset]);
+
+                let task = downloader.download("memory://content", &path);
+
+                assert_eq!(task.wait().await?, content.len() as u64);
+
+                let downloaded = std::fs::read(&path)?;
+
+                assert_eq!(downloaded.len(), content.len());
+                assert!(downloaded == content, "content mismatch at offset {offset}, ignore_range: {ignore_range}");
+            }
+        }
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn changed_download() -> Result<(), DownloaderError> {
+        let path = std::env::temp_dir().join(".wineyard-core-changed-download-test");
+        let etag_path = std::env::temp_dir().join(".wineyard-core-changed-download-test.etag");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let old_content = vec![1; 10000];
+        let new_content = vec![2; 20000];
+
+        let download = |content: &[u8], etag: &str, drop_after: Option<usize>| {
+            let downloader = Downloader::with_transport(MemoryTransport {
+                content: content.to_vec(),
+                drop_after: Mutex::new(drop_after),
+                ignore_range: false,
+                etag: Some(etag.to_string())
+            });
+
+            downloader.download_with_options("memory://content", &path, DownloadOptions {
+                etag_file: Some(etag_path.clone()),
+                ..DownloadOptions::default()
+            })
+        };
+
+        assert!(download(&old_content, "\"v1\"", Some(5000)).wait().await.is_err());
+        assert_eq!(std::fs::read_to_string(&etag_path)?, "\"v1\"");
+
+        // Content was changed so it's downloaded from scratch.
+        assert_eq!(download(&new_content, "\"v2\"", None).wait().await?, new_content.len() as u64);
+        assert_eq!(std::fs::read(&path)?, new_content);
+        assert_eq!(std::fs::read_to_string(&etag_path)?, "\"v2\"");
+
+        std::fs::remove_file(path)?;
+        std::fs::remove_file(etag_path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejected_download() -> Result<(), DownloaderError> {
+        let path = std::env::temp_dir().join(".wineyard-core-rejected-download-test");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let downloader = Downloader::with_transport(MemoryTransport {
+            content: vec![0; 10000],
+            drop_after: Mutex::new(None),
+            ignore_range: false,
+            etag: None
+        });
+
+        // Chunks are rejected before being written.
+        let task = downloader.download_with_options("memory://content", &path, DownloadOptions {
+            on_chunk: Some(Box::new(|current, total, len| {
+                assert_eq!(total, 10000);
+
+                current + len <= 5000
+            })),
+            ..DownloadOptions::default()
+        });
+
+        assert!(matches!(task.wait().await, Err(DownloaderError::ChunkRejected)));
+        assert_eq!(std::fs::metadata(&path)?.len(), 4096);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_download() -> Result<(), DownloaderError> {
+        let path = std::env::temp_dir().join(".wineyard-core-local-download-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let content = (0..3 * 1024 * 1024)
+            .map(|i| i as u8)
+            .collect::<Vec<u8>>();
+
+        std::fs::write(path.join("source"), &content)?;
+
+        // Partially downloaded file is continued.
+        std::fs::write(path.join("output"), &content[..1000])?;
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+
+        let downloader = Downloader::default()
+            .with_local_files(true);
+
+        let task = downloader.download_with_options(
+            Url::from_file_path(path.join("source")).unwrap(),
+            path.join("output"),
+            DownloadOptions {
+                on_update: Some(Box::new({
+                    let updates = updates.clone();
+
+                    move |current, total, _| {
+                        updates.lock().unwrap().push((current, total));
+                    }
+                })),
+
+                ..DownloadOptions::default()
+            }
+        );
+
+        assert_eq!(task.wait().await?, content.len() as u64);
+        assert_eq!(std::fs::read(path.join("output"))?, content);
+
+        let updates = updates.lock().unwrap().clone();
+
+        assert!(updates.len() > 1);
+        assert_eq!(updates.last(), Some(&(content.len() as u64, content.len() as u64)));
+
+        // Plain paths are supported too.
+        let task = downloader.download(
+            path.join("source").to_string_lossy(),
+            path.join("copy")
+        );
+
+        assert_eq!(task.wait().await?, content.len() as u64);
+        assert_eq!(std::fs::read(path.join("copy"))?, content);
+
+        let task = downloader.download(
+            path.join("missing").to_string_lossy(),
+            path.join("missing-copy")
+        );
+
+        assert!(matches!(task.wait().await, Err(DownloaderError::Io(_))));
+
+        // Local files are not copied unless allowed.
+        let task = Downloader::default().download(
+            path.join("source").to_string_lossy(),
+            path.join("disabled-copy")
+        );
+
+        assert!(matches!(task.wait().await, Err(DownloaderError::LocalFilesDisabled(_))));
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn abort_download() -> Result<(), DownloaderError> {
         let path = std::env::temp_dir().join(".wineyard-core-abort-download-test");
@@ -369,6 +1032,40 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn stop_download() -> Result<(), DownloaderError> {
+        let path = std::env::temp_dir().join(".wineyard-core-stop-download-test");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let content = (0..4096)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+
+        let downloader = Downloader::with_transport(StalledTransport(content.clone()));
+
+        let task = downloader.download("memory://content", &path);
+
+        while task.current() < content.len() as u64 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Buffered content is written before the task is stopped.
+        assert_eq!(task.stop().await?, content.len() as u64);
+        assert_eq!(std::fs::read(&path)?, content);
+
+        // Stopping right after the start doesn't wait for the content.
+        let task = downloader.download("memory://content", &path);
+
+        task.stop().await?;
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn continue_download() -> Result<(), DownloaderError> {
         let path = std::env::temp_dir().join(".wineyard-core-continue-download-test");
@@ -404,11 +1101,8 @@ mod tests {
             &path
         );
 
-        let total = task.wait().await?;
-
-        // FIXME: for some reason sometimes returned length is greater.
-        // Likely because we download a bit more than needed?
-        assert!(total >= 67233060);
+        assert_eq!(task.wait().await?, 67233060);
+        assert_eq!(std::fs::metadata(&path)?.len(), 67233060);
 
         std::fs::remove_file(path)?;
 
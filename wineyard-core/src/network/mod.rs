@@ -1,2 +1,7 @@
+pub mod proxy;
+
 #[cfg(feature = "network-downloader")]
 pub mod downloader;
+
+#[cfg(feature = "network-downloader")]
+pub mod transport;
@@ -0,0 +1,111 @@
+use reqwest::{Client, Proxy, NoProxy, Url};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+    #[error("Invalid proxy URL '{url}': {reason}")]
+    InvalidUrl {
+        url: String,
+        reason: String
+    },
+
+    #[error("Failed to build proxied client: {0}")]
+    Client(#[from] reqwest::Error)
+}
+
+/// Build reqwest client which sends all the requests through the given proxy.
+///
+/// Supported proxy schemes are `http`, `https`, `socks5` and `socks5h`.
+/// Requests to the hosts listed in `no_proxy` (comma separated, same format
+/// as the `NO_PROXY` env variable) bypass the proxy. If `no_proxy` is not
+/// provided then the `NO_PROXY` env variable is used.
+///
+/// Note that default clients already honor `HTTP_PROXY`, `HTTPS_PROXY`,
+/// `ALL_PROXY` and `NO_PROXY` env variables.
+pub fn client_with_proxy(
+    proxy: impl AsRef<str>,
+    no_proxy: Option<&str>
+) -> Result<Client, ProxyError> {
+    let proxy = proxy.as_ref();
+
+    let url = Url::parse(proxy).map_err(|err| ProxyError::InvalidUrl {
+        url: proxy.to_string(),
+        reason: err.to_string()
+    })?;
+
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(ProxyError::InvalidUrl {
+            url: proxy.to_string(),
+            reason: format!("unsupported scheme '{}'", url.scheme())
+        });
+    }
+
+    if url.host_str().is_none() {
+        return Err(ProxyError::InvalidUrl {
+            url: proxy.to_string(),
+            reason: String::from("missing host")
+        });
+    }
+
+    let no_proxy = match no_proxy {
+        Some(no_proxy) => NoProxy::from_string(no_proxy),
+        None => NoProxy::from_env()
+    };
+
+    #[cfg(feature = "tracing")]
+    tracing::trace!(?proxy, "build proxied client");
+
+    let proxy = Proxy::all(url)?.no_proxy(no_proxy);
+
+    Ok(Client::builder().proxy(proxy).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn invalid_proxy() {
+        for proxy in ["", "not a url", "ftp://127.0.0.1:21", "socks5://"] {
+            assert!(matches!(
+                client_with_proxy(proxy, None),
+                Err(ProxyError::InvalidUrl { .. })
+            ));
+        }
+
+        assert!(client_with_proxy("socks5h://127.0.0.1:1080", None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn no_proxy() -> Result<(), Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0; 4096];
+
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok");
+            }
+        });
+
+        // Nothing is listening on the proxy's port.
+        let unused = TcpListener::bind("127.0.0.1:0")?;
+        let proxy = format!("http://{}", unused.local_addr()?);
+
+        drop(unused);
+
+        let client = client_with_proxy(&proxy, Some(""))?;
+
+        assert!(client.get(&address).send().await.is_err());
+
+        let client = client_with_proxy(&proxy, Some("127.0.0.1"))?;
+
+        assert_eq!(client.get(&address).send().await?.text().await?, "ok");
+
+        Ok(())
+    }
+}
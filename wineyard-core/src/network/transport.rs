@@ -0,0 +1,310 @@
+use std::pin::Pin;
+
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use reqwest::{Client, Response};
+
+use crate::buffer::io_buf_size;
+
+use super::downloader::DownloaderError;
+
+lazy_static::lazy_static! {
+    static ref CLIENT: Client = Client::new();
+}
+
+/// Boxed future returned by the transport methods.
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, DownloaderError>> + Send + 'a>>;
+
+/// Stream of the content returned by the transport.
+pub trait TransportStream: Send {
+    /// Read next chunk of the content to the buffer, replacing its previous
+    /// value. Return `false` if there's no content left.
+    fn read_chunk<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> TransportFuture<'a, bool>;
+}
+
+/// Response of the transport to the content request.
+pub enum TransportResponse {
+    /// Requested offset is not less than the content size, meaning that
+    /// the content is already downloaded.
+    Finished,
+
+    /// Content starting from the given offset.
+    Content {
+        /// Offset of the first streamed byte. Can be less than the requested
+        /// one if the transport can't skip the content, e.g. when HTTP server
+        /// doesn't support range requests.
+        offset: u64,
+
+        /// Total size of the content, including the skipped bytes.
+        total: Option<u64>,
+
+        /// Strong entity tag of the content, if known.
+        etag: Option<String>,
+
+        stream: Box<dyn TransportStream>
+    }
+}
+
+/// Way of requesting the content used by the downloader.
+///
+/// Default one is `HttpTransport` which sends HTTP requests using reqwest
+/// client. Custom transports can be used to support other URL schemes or to
+/// serve the content from memory in tests. Local files are always copied
+/// without using the transport.
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    /// Request content of the given URL starting from the given offset.
+    ///
+    /// `if_range` is the entity tag of already downloaded content. If it
+    /// doesn't match the current one then the whole content must be sent.
+    fn request<'a>(
+        &'a self,
+        url: &'a str,
+        offset: u64,
+        if_range: Option<&'a str>
+    ) -> TransportFuture<'a, TransportResponse>;
+}
+
+/// HTTP transport using reqwest client.
+#[derive(Debug, Clone)]
+pub struct HttpTransport(Client);
+
+impl Default for HttpTransport {
+    #[inline]
+    fn default() -> Self {
+        Self(CLIENT.clone())
+    }
+}
+
+impl HttpTransport {
+    /// Create new HTTP transport using the given reqwest client.
+    #[inline(always)]
+    pub const fn new(client: Client) -> Self {
+        Self(client)
+    }
+
+    /// Get reqwest client used by the transport.
+    #[inline(always)]
+    pub const fn client(&self) -> &Client {
+        &self.0
+    }
+}
+
+impl Transport for HttpTransport {
+    fn request<'a>(
+        &'a self,
+        url: &'a str,
+        offset: u64,
+        if_range: Option<&'a str>
+    ) -> TransportFuture<'a, TransportResponse> {
+        Box::pin(async move {
+            // Prepare HTTP request.
+            let mut request = self.0
+                .get(url)
+                .header("range", format!("bytes={offset}-"));
+
+            // Server sends the whole content if it was changed since
+            // the previous request.
+            //
+            // Source: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/If-Range
+            if offset > 0
+                && let Some(etag) = if_range
+            {
+                request = request.header("if-range", etag);
+            }
+
+            let request = request.build()?;
+
+            let response = self.0.execute(request).await?;
+
+            // HTTP 416 = provided range is greater than the actual
+            // content length (means the file is downloaded).
+            //
+            // Source: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/416
+            if response.status() == 416 {
+                return Ok(TransportResponse::Finished);
+            }
+
+            let content_range = response.headers().get("Content-Range")
+                .and_then(|range| parse_content_range(&String::from_utf8_lossy(range.as_bytes())));
+
+            // Downloading finished.
+            if let Some((None, _)) = content_range {
+                return Ok(TransportResponse::Finished);
+            }
+
+            // Servers which don't support range requests send the whole
+            // content with 200 status instead of 206. Partial content can
+            // start from another offset than the requested one.
+            let offset = if response.status() == 206 {
+                content_range
+                    .and_then(|(start, _)| start)
+                    .unwrap_or(offset)
+            } else {
+                0
+            };
+
+            let mut total = None;
+
+            // Try to read the `Content-Length` HTTP header and if successful,
+            // store its value as the partial length of downloadable content.
+            if let Some(content_length) = response.headers().get("Content-Length") {
+                let content_length = String::from_utf8_lossy(content_length.as_bytes());
+
+                if let Ok(content_length) = content_length.parse::<u64>() {
+                    // If we already downloaded part of the content -
+                    // `Content-Length` will contain a length of the
+                    // remaining content.
+                    total = Some(offset + content_length);
+                }
+            }
+
+            // Size from the content range (downloaded + remained content size).
+            if let Some((_, Some(size))) = content_range {
+                total = Some(size);
+            }
+
+            // Weak entity tags can't be used in the `If-Range` header.
+            let etag = response.headers().get("ETag")
+                .and_then(|etag| etag.to_str().ok())
+                .filter(|etag| !etag.starts_with("W/"))
+                .map(String::from);
+
+            Ok(TransportResponse::Content {
+                offset,
+                total,
+                etag,
+                stream: Box::new(HttpStream(response))
+            })
+        })
+    }
+}
+
+/// Parse value of the `Content-Range` HTTP header, returning start of the
+/// sent range and the total content size if known. Start is `None` if
+/// the range is unsatisfied.
+///
+/// If finished or overcame: `bytes */10611646760`.
+/// If not finished: `bytes 10611646759-10611646759/10611646760`.
+///
+/// ```text
+/// Content-Range: <unit> <range>/<size>
+/// Content-Range: <unit> <range>/*
+/// Content-Range: <unit> */<size>
+/// ```
+///
+/// Source: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Range
+fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, size) = value.trim()
+        .strip_prefix("bytes ")?
+        .split_once('/')?;
+
+    let size = size.parse::<u64>().ok();
+
+    if range == "*" {
+        return Some((None, size));
+    }
+
+    let (start, end) = range.split_once('-')?;
+
+    let start = start.parse::<u64>().ok()?;
+    let end = end.parse::<u64>().ok()?;
+
+    if start > end {
+        return None;
+    }
+
+    Some((Some(start), size))
+}
+
+/// Body of the HTTP response.
+struct HttpStream(Response);
+
+impl TransportStream for HttpStream {
+    fn read_chunk<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> TransportFuture<'a, bool> {
+        Box::pin(async move {
+            let Some(chunk) = self.0.chunk().await? else {
+                return Ok(false);
+            };
+
+            buf.clear();
+            buf.extend_from_slice(&chunk);
+
+            Ok(true)
+        })
+    }
+}
+
+/// Content of the local file.
+pub(crate) struct FileStream(pub File);
+
+impl TransportStream for FileStream {
+    fn read_chunk<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> TransportFuture<'a, bool> {
+        Box::pin(async move {
+            buf.resize(io_buf_size(), 0);
+
+            let len = self.0.read(buf).await?;
+
+            buf.truncate(len);
+
+            Ok(len > 0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn content_range() {
+        assert_eq!(parse_content_range("bytes 0-99/100"), Some((Some(0), Some(100))));
+        assert_eq!(parse_content_range("bytes 10-99/*"), Some((Some(10), None)));
+        assert_eq!(parse_content_range("bytes */100"), Some((None, Some(100))));
+
+        assert_eq!(parse_content_range("bytes 99-10/100"), None);
+        assert_eq!(parse_content_range("bytes 10/100"), None);
+        assert_eq!(parse_content_range("items 0-99/100"), None);
+        assert_eq!(parse_content_range(""), None);
+    }
+
+    #[tokio::test]
+    async fn partial_content_offset() -> Result<(), Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+
+        // Server sends content from another offset than requested.
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0; 4096];
+
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 4-9/10\r\nContent-Length: 6\r\nConnection: close\r\n\r\n456789");
+            }
+        });
+
+        let response = HttpTransport::default()
+            .request(&address, 6, None)
+            .await?;
+
+        let TransportResponse::Content { offset, total, mut stream, .. } = response else {
+            panic!("content expected");
+        };
+
+        assert_eq!(offset, 4);
+        assert_eq!(total, Some(10));
+
+        let mut content = Vec::new();
+        let mut buf = Vec::new();
+
+        while stream.read_chunk(&mut buf).await? {
+            content.extend_from_slice(&buf);
+        }
+
+        assert_eq!(content, b"456789");
+
+        Ok(())
+    }
+}
@@ -1,5 +1,17 @@
 use std::io::{Read, Write};
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::ops::{Deref, DerefMut};
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    Condvar,
+    RwLock,
+    RwLockReadGuard,
+    RwLockWriteGuard,
+    PoisonError,
+    TryLockError
+};
+use std::time::{Duration, Instant};
 
 /// Shared read-write interface hidden behind `Arc<Mutex<T>>`. Can be used if
 /// you need to read and write data from multiple places.
@@ -73,3 +85,263 @@ impl<T: Read + Write> Write for ReadWriteMutex<T> {
         self.inner()?.write_fmt(args)
     }
 }
+
+#[derive(Debug, Default)]
+struct RwSyncInner<T> {
+    value: RwLock<T>,
+
+    // Locked while checking the value lock so releases which happen
+    // in between are not missed.
+    released_lock: Mutex<()>,
+    released: Condvar
+}
+
+impl<T> RwSyncInner<T> {
+    fn notify_released(&self) {
+        let _released = self.released_lock.lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        self.released.notify_all();
+    }
+}
+
+/// Shared value hidden behind `Arc<RwLock<T>>` which supports locking with
+/// a timeout and waiting until the value satisfies some condition.
+///
+/// Locks poisoning is ignored.
+#[derive(Debug, Default)]
+pub struct RwSync<T>(Arc<RwSyncInner<T>>);
+
+impl<T> Clone for RwSync<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> RwSync<T> {
+    #[inline]
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(RwSyncInner {
+            value: RwLock::new(value),
+            released_lock: Mutex::new(()),
+            released: Condvar::new()
+        }))
+    }
+
+    /// Lock the value for reading, blocking the current thread until
+    /// it's available.
+    pub fn read(&self) -> RwSyncReadGuard<'_, T> {
+        let guard = self.0.value.read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        RwSyncReadGuard {
+            guard: Some(guard),
+            inner: &self.0
+        }
+    }
+
+    /// Lock the value for writing, blocking the current thread until
+    /// it's available.
+    pub fn write(&self) -> RwSyncWriteGuard<'_, T> {
+        let guard = self.0.value.write()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        RwSyncWriteGuard {
+            guard: Some(guard),
+            inner: &self.0
+        }
+    }
+
+    /// Try to lock the value for reading within the given timeout.
+    pub fn try_read_timeout(&self, timeout: Duration) -> Option<RwSyncReadGuard<'_, T>> {
+        let guard = self.wait(Some(timeout), |value| {
+            match value.try_read() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+                Err(TryLockError::WouldBlock) => None
+            }
+        })?;
+
+        Some(RwSyncReadGuard {
+            guard: Some(guard),
+            inner: &self.0
+        })
+    }
+
+    /// Try to lock the value for writing within the given timeout.
+    #[inline]
+    pub fn try_write_timeout(&self, timeout: Duration) -> Option<RwSyncWriteGuard<'_, T>> {
+        self.write_when(|_| true, Some(timeout))
+    }
+
+    /// Lock the value for writing when it satisfies the given condition.
+    ///
+    /// The condition is checked every time another guard is released.
+    /// Return `None` if the condition wasn't satisfied within the timeout.
+    pub fn write_when(
+        &self,
+        condition: impl Fn(&T) -> bool,
+        timeout: Option<Duration>
+    ) -> Option<RwSyncWriteGuard<'_, T>> {
+        let guard = self.wait(timeout, |value| {
+            let guard = match value.try_write() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(err)) => err.into_inner(),
+                Err(TryLockError::WouldBlock) => return None
+            };
+
+            condition(&guard).then_some(guard)
+        })?;
+
+        Some(RwSyncWriteGuard {
+            guard: Some(guard),
+            inner: &self.0
+        })
+    }
+
+    /// Call `lock` until it returns a value, waiting for the guards releases
+    /// in between.
+    fn wait<'a, G>(
+        &'a self,
+        timeout: Option<Duration>,
+        lock: impl Fn(&'a RwLock<T>) -> Option<G>
+    ) -> Option<G> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut released = self.0.released_lock.lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        loop {
+            if let Some(guard) = lock(&self.0.value) {
+                return Some(guard);
+            }
+
+            released = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if now >= deadline {
+                        return None;
+                    }
+
+                    self.0.released.wait_timeout(released, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+
+                None => self.0.released.wait(released)
+                    .unwrap_or_else(PoisonError::into_inner)
+            };
+        }
+    }
+}
+
+/// Read guard of the `RwSync` value.
+pub struct RwSyncReadGuard<'a, T> {
+    guard: Option<RwLockReadGuard<'a, T>>,
+    inner: &'a RwSyncInner<T>
+}
+
+impl<T> Deref for RwSyncReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref()
+            .expect("guard is taken only when dropped")
+    }
+}
+
+impl<T> Drop for RwSyncReadGuard<'_, T> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        self.inner.notify_released();
+    }
+}
+
+/// Write guard of the `RwSync` value.
+pub struct RwSyncWriteGuard<'a, T> {
+    guard: Option<RwLockWriteGuard<'a, T>>,
+    inner: &'a RwSyncInner<T>
+}
+
+impl<T> Deref for RwSyncWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref()
+            .expect("guard is taken only when dropped")
+    }
+}
+
+impl<T> DerefMut for RwSyncWriteGuard<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.as_mut()
+            .expect("guard is taken only when dropped")
+    }
+}
+
+impl<T> Drop for RwSyncWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        self.inner.notify_released();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rw_sync_timeouts() {
+        let sync = RwSync::new(0);
+
+        {
+            let _a = sync.read();
+            let _b = sync.try_read_timeout(Duration::from_millis(10))
+                .expect("multiple readers are allowed");
+
+            assert!(sync.try_write_timeout(Duration::from_millis(10)).is_none());
+        }
+
+        let mut guard = sync.write();
+
+        *guard += 1;
+
+        assert!(sync.try_read_timeout(Duration::from_millis(10)).is_none());
+
+        drop(guard);
+
+        assert_eq!(*sync.read(), 1);
+    }
+
+    #[test]
+    fn rw_sync_write_when() {
+        let sync = RwSync::new(0);
+
+        let thread = {
+            let sync = sync.clone();
+
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    *sync.write() += 1;
+                }
+            })
+        };
+
+        let guard = sync.write_when(|value| *value == 10, Some(Duration::from_secs(5)));
+
+        assert_eq!(guard.as_deref(), Some(&10));
+
+        drop(guard);
+
+        assert!(sync.write_when(|value| *value > 10, Some(Duration::from_millis(10))).is_none());
+
+        thread.join().unwrap();
+    }
+}
@@ -1,6 +1,8 @@
-pub use tokio::{fs, io, net, sync};
+pub use tokio::{fs, io, net, sync, time};
 pub use tokio::task::{JoinHandle, JoinError};
 
+use std::time::Duration;
+
 use tokio::runtime::{Runtime, Builder};
 
 lazy_static::lazy_static! {
@@ -12,6 +14,10 @@ lazy_static::lazy_static! {
 }
 
 /// Spawn future in the shared tokio runtime.
+///
+/// The runtime is started on first use, so it's not needed to call this
+/// function from within another tokio runtime. Returned handle can be awaited
+/// by any async executor.
 #[inline(always)]
 pub fn spawn<T: Send + 'static>(
     future: impl Future<Output = T> + Send + 'static
@@ -20,7 +26,145 @@ pub fn spawn<T: Send + 'static>(
 }
 
 /// Block current thread to execute the future.
+///
+/// # Panics
+///
+/// Panics if called from within an async context since it would block
+/// the thread which executes other tasks. Await the future instead.
 #[inline(always)]
 pub fn block_on<T>(future: impl Future<Output = T>) -> T {
     RUNTIME.block_on(future)
 }
+
+/// Spawn blocking function in the shared tokio runtime's blocking threads
+/// pool. Use it for CPU-heavy work like hashing within async contexts.
+#[inline(always)]
+pub fn spawn_blocking<T: Send + 'static>(
+    callback: impl FnOnce() -> T + Send + 'static
+) -> JoinHandle<T> {
+    RUNTIME.spawn_blocking(callback)
+}
+
+/// Wait until all the given tasks are finished, returning their results
+/// in the same order.
+///
+/// Tasks are executed concurrently since they were spawned, so this function
+/// only collects their outputs.
+pub async fn join_all<T>(
+    tasks: impl IntoIterator<Item = JoinHandle<T>>
+) -> Vec<Result<T, JoinError>> {
+    let tasks = tasks.into_iter();
+
+    let mut results = Vec::with_capacity(tasks.size_hint().0);
+
+    for task in tasks {
+        results.push(task.await);
+    }
+
+    results
+}
+
+/// Future didn't finish within the given time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, thiserror::Error)]
+#[error("task timed out")]
+pub struct TimeoutError;
+
+/// Wait until the given future is finished or the given duration passes.
+///
+/// The future is dropped on timeout. Note that dropping a `JoinHandle` doesn't
+/// stop its task, so spawned tasks must be aborted by the caller.
+///
+/// Timer of the shared tokio runtime is used, so the returned future can be
+/// awaited by any async executor.
+pub async fn with_timeout<T>(
+    duration: Duration,
+    future: impl Future<Output = T>
+) -> Result<T, TimeoutError> {
+    // Tokio timers are bound to the runtime which is entered when they're
+    // created, and panic if there's none.
+    let timeout = {
+        let _guard = RUNTIME.enter();
+
+        tokio::time::timeout(duration, future)
+    };
+
+    timeout.await.map_err(|_| TimeoutError)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use super::*;
+
+    /// Minimal executor which doesn't provide a tokio runtime.
+    fn park_on<T>(future: impl Future<Output = T>) -> T {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut context = Context::from_waker(&waker);
+
+        let mut future = std::pin::pin!(future);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn join_all() {
+        let tasks = (0..4).map(|i| {
+            if i % 2 == 0 {
+                spawn(async move { i * 2 })
+            } else {
+                spawn_blocking(move || i * 2)
+            }
+        });
+
+        let results = block_on(super::join_all(tasks))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(results, [0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn with_timeout() {
+        let result = block_on(super::with_timeout(Duration::from_secs(1), async {
+            42
+        }));
+
+        assert_eq!(result, Ok(42));
+
+        let result = block_on(super::with_timeout(Duration::from_millis(10), async {
+            time::sleep(Duration::from_secs(1)).await;
+        }));
+
+        assert_eq!(result, Err(TimeoutError));
+    }
+
+    #[test]
+    fn without_runtime() {
+        assert!(tokio::runtime::Handle::try_current().is_err());
+
+        assert_eq!(park_on(spawn(async { 42 })).unwrap(), 42);
+
+        let result = park_on(super::with_timeout(Duration::from_millis(10), async {
+            spawn(async { time::sleep(Duration::from_secs(1)).await }).await.is_ok()
+        }));
+
+        assert_eq!(result, Err(TimeoutError));
+    }
+}
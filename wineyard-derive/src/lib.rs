@@ -0,0 +1,87 @@
+use proc_macro::TokenStream;
+
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+/// Derive `AsHash` trait for a struct.
+///
+/// Hashes of all the fields are chained in declaration order.
+/// Fields marked with `#[as_hash(skip)]` are ignored.
+#[proc_macro_derive(AsHash, attributes(as_hash))]
+pub fn derive_as_hash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let Data::Struct(data) = &input.data else {
+        return syn::Error::new_spanned(&input, "AsHash can only be derived for structs")
+            .to_compile_error()
+            .into();
+    };
+
+    let mut fields = Vec::new();
+
+    for (i, field) in data.fields.iter().enumerate() {
+        let mut skip = false;
+
+        for attr in &field.attrs {
+            if !attr.path().is_ident("as_hash") {
+                continue;
+            }
+
+            let result = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+
+                    Ok(())
+                }
+
+                else {
+                    Err(meta.error("unknown as_hash attribute"))
+                }
+            });
+
+            if let Err(err) = result {
+                return err.to_compile_error().into();
+            }
+        }
+
+        if skip {
+            continue;
+        }
+
+        let field = match &field.ident {
+            Some(ident) => quote! { #ident },
+
+            None => {
+                let index = Index::from(i);
+
+                quote! { #index }
+            }
+        };
+
+        fields.push(field);
+    }
+
+    // Unit structs have no fields to hash.
+    if let Fields::Unit = data.fields {
+        fields.clear();
+    }
+
+    let output = quote! {
+        impl #impl_generics ::wineyard_runtime::hash::AsHash for #name #type_generics #where_clause {
+            fn hash(&self) -> ::wineyard_runtime::hash::Hash {
+                ::wineyard_runtime::hash::Hash::default()
+                    #(.chain(::wineyard_runtime::hash::AsHash::hash(&self.#fields)))*
+            }
+
+            fn partial_hash(&self) -> ::wineyard_runtime::hash::Hash {
+                ::wineyard_runtime::hash::Hash::default()
+                    #(.chain(::wineyard_runtime::hash::AsHash::partial_hash(&self.#fields)))*
+            }
+        }
+    };
+
+    output.into()
+}
@@ -1,12 +1,16 @@
 use std::path::{Path, PathBuf};
 use std::hash::Hasher;
-use std::io::Read;
+use std::io::{Read, Write};
 
 use wineyard_core::export::hashes::seahash;
+use wineyard_core::hashes::{self, HashAlgorithm};
+use wineyard_core::buffer::io_buf_size;
 
 use serde::{Serialize, Deserialize};
 use base32::Alphabet;
 
+pub use wineyard_derive::AsHash;
+
 const ALPHABET: Alphabet = Alphabet::Rfc4648HexLower {
     padding: false
 };
@@ -28,6 +32,14 @@ pub trait AsHash {
 }
 
 /// Wrapper around an integer used as hash value.
+///
+/// This is a fast content-addressing identity, not a cryptographic checksum.
+/// Its value is the 64 bit [seahash](https://crates.io/crates/seahash) 4.x
+/// digest (`seahash::hash` with default keys) of the data, so it can be
+/// reproduced externally with any seahash implementation. String
+/// representation is base32hex (RFC 4648, lowercase, no padding) of the
+/// big-endian bytes of the value. Use `Hash::for_slice_with` or
+/// `Hash::for_entry_with` to get full-width digests of other algorithms.
 #[derive(
     Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
     Serialize, Deserialize
@@ -42,12 +54,39 @@ impl Hash {
     }
 
     /// Chain two hashes together, making a new one.
+    ///
+    /// This is a plain XOR of the hash values, so chaining a hash with itself
+    /// cancels it out. Use `combine_unordered` to aggregate sets of hashes.
     #[inline]
     pub fn chain(self, other: impl Into<Hash>) -> Self {
         self ^ other.into()
     }
 
+    /// Combine hashes into a new one which doesn't depend on their order.
+    ///
+    /// Unlike folding with `chain`, every hash is mixed before being added
+    /// to the result, so duplicated hashes don't cancel each other out and
+    /// the amount of hashes affects the result. This makes it suitable for
+    /// hashing sets of values processed in arbitrary order, like contents
+    /// of a directory hashed in parallel.
+    pub fn combine_unordered(iter: impl IntoIterator<Item = Hash>) -> Self {
+        // splitmix64 finalizer.
+        const fn mix(mut value: u64) -> u64 {
+            value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+            value ^ (value >> 31)
+        }
+
+        let sum = iter.into_iter()
+            .fold(0_u64, |sum, hash| sum.wrapping_add(mix(hash.0)));
+
+        Self(mix(sum))
+    }
+
     /// Generate hash from the given data buffer.
+    ///
+    /// Equivalent to `seahash::hash(buf)`.
     #[inline]
     pub fn for_slice(buf: impl AsRef<[u8]>) -> Self {
         let hash = seahash::hash(buf.as_ref());
@@ -56,22 +95,19 @@ impl Hash {
     }
 
     /// Generate hash for a given filesystem entry.
+    ///
+    /// Files are hashed by their content exactly as `for_slice` would do.
+    /// Folders are hashed by xoring together hashes of relative paths of all
+    /// the nested files and folders (as raw OS bytes) and hashes of all the
+    /// nested files' content. Symlinks are resolved before hashing.
     pub fn for_entry(path: impl Into<PathBuf>) -> std::io::Result<Self> {
-        let mut path: PathBuf = path.into();
-
-        // Resolve symlinks before hashing.
-        while path.is_symlink() {
-            path = path.read_link()?;
-        }
-
-        fn hash_file(path: &Path) -> std::io::Result<Hash> {
+        // Buffer is shared by all the hashed files.
+        fn hash_file(path: &Path, buf: &mut [u8]) -> std::io::Result<Hash> {
             let mut file = std::fs::File::open(path)?;
             let mut hasher = seahash::SeaHasher::new();
 
-            let mut buf = [0; 4096];
-
             loop {
-                let len = file.read(&mut buf)?;
+                let len = file.read(buf)?;
 
                 if len == 0 {
                     break;
@@ -83,53 +119,106 @@ impl Hash {
             Ok(Hash(hasher.finish()))
         }
 
+        let path = resolve_symlinks(path.into())?;
+
+        let mut buf = vec![0; io_buf_size()];
+
         // Handle file by hashing it by chunks.
         if path.is_file() {
-            return hash_file(&path);
+            return hash_file(&path, &mut buf);
         }
 
         // Otherwise expect it to be a folder and handle it by hashing each
         // individual file and names of files and folders, and xoring all the
         // values together.
-        let root = path.clone();
+        let mut result = Hash::default();
 
-        let mut folders = vec![path];
-        let mut result = 0;
+        for (name, file) in folder_entries(&path)? {
+            if let Some(name) = name {
+                result ^= Hash::for_slice(name);
+            }
 
-        while let Some(path) = folders.pop() {
-            for entry in path.read_dir()?.flatten() {
-                let mut path = entry.path();
+            if let Some(file) = file {
+                result ^= hash_file(&file, &mut buf)?;
+            }
+        }
 
-                // Resolve symlinks before hashing.
-                while path.is_symlink() {
-                    path = path.read_link()?;
-                }
+        Ok(result)
+    }
 
-                // Hash the file and xor it with the result value.
-                if path.is_file() {
-                    // Hash the file's relative path to ensure the structure.
-                    if let Ok(name) = path.strip_prefix(&root) {
-                        result ^= seahash::hash(name.as_os_str().as_encoded_bytes());
-                    }
+    /// Generate full-width digest of the given data buffer using provided
+    /// hashing algorithm.
+    ///
+    /// Unlike `Hash` which is a fast content-addressing identity this value
+    /// is suitable for verifying data against externally provided checksums.
+    pub fn for_slice_with(
+        algorithm: HashAlgorithm,
+        buf: impl AsRef<[u8]>
+    ) -> std::io::Result<Digest> {
+        let digest = hashes::Hasher::new(algorithm).hash(buf)?;
 
-                    // Hash the file's content.
-                    result ^= hash_file(&path)?.0;
+        Ok(Digest::new(algorithm, digest))
+    }
+
+    /// Generate full-width digest of the given filesystem entry using provided
+    /// hashing algorithm.
+    ///
+    /// Files are hashed by their content exactly as `for_slice_with` would
+    /// do. Folders are hashed by feeding relative paths of all the nested
+    /// entries (as raw OS bytes, empty if resolved outside of the folder)
+    /// sorted in ascending order, each followed by a zero byte and, for
+    /// files, by `f` and the digest of their content or by `d` for folders,
+    /// through one hasher. Symlinks are resolved before hashing.
+    pub fn for_entry_with(
+        algorithm: HashAlgorithm,
+        path: impl Into<PathBuf>
+    ) -> std::io::Result<Digest> {
+        let mut buf = vec![0; io_buf_size()];
+
+        let mut hash_file = |path: &Path| -> std::io::Result<Digest> {
+            let mut file = std::fs::File::open(path)?;
+            let mut hasher = hashes::Hasher::new(algorithm);
+
+            loop {
+                let len = file.read(&mut buf)?;
+
+                if len == 0 {
+                    break;
                 }
 
-                // Otherwise it's a folder and we need to push it
-                // to the hasing queue.
-                else {
-                    // Hash the folder's relative path to ensure the structure.
-                    if let Ok(name) = path.strip_prefix(&root) {
-                        result ^= seahash::hash(name.as_os_str().as_encoded_bytes());
-                    }
+                hasher.write_all(&buf[..len])?;
+            }
+
+            hasher.flush()?;
+
+            Ok(Digest::new(algorithm, hasher.finalize().0))
+        };
 
-                    folders.push(path);
+        let path = resolve_symlinks(path.into())?;
+
+        if path.is_file() {
+            return hash_file(&path);
+        }
+
+        let mut hasher = hashes::Hasher::new(algorithm);
+
+        for (name, file) in folder_entries(&path)? {
+            hasher.write_all(name.as_deref().unwrap_or_default())?;
+            hasher.write_all(&[0])?;
+
+            match file {
+                Some(file) => {
+                    hasher.write_all(b"f")?;
+                    hasher.write_all(hash_file(&file)?.as_bytes())?;
                 }
+
+                None => hasher.write_all(b"d")?
             }
         }
 
-        Ok(Self(result))
+        hasher.flush()?;
+
+        Ok(Digest::new(algorithm, hasher.finalize().0))
     }
 
     /// Convert hash to the base32 string.
@@ -139,15 +228,111 @@ impl Hash {
     }
 
     /// Try to parse hash value from the base32 string.
+    ///
+    /// Return `None` unless the string encodes exactly 8 bytes.
     pub fn from_base32(str: impl AsRef<str>) -> Option<Self> {
-        let mut buf = [0; 8];
+        let str = str.as_ref();
 
-        let hash = base32::decode(ALPHABET, str.as_ref())?;
+        // Encoded 8 bytes are always 13 characters long, and trailing bits
+        // of longer strings would be silently dropped otherwise.
+        if str.len() != 13 {
+            return None;
+        }
 
-        buf.copy_from_slice(&hash[..8]);
+        let hash = base32::decode(ALPHABET, str)?;
+        let buf = <[u8; 8]>::try_from(hash.as_slice()).ok()?;
 
         Some(Self(u64::from_be_bytes(buf)))
     }
+
+    /// Convert hash to the hex string.
+    #[inline]
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0.to_be_bytes())
+    }
+
+    /// Try to parse hash value from the hex string.
+    pub fn from_hex(str: impl AsRef<str>) -> Option<Self> {
+        let mut buf = [0; 8];
+
+        hex::decode_to_slice(str.as_ref(), &mut buf).ok()?;
+
+        Some(Self(u64::from_be_bytes(buf)))
+    }
+
+    /// Convert hash to the multibase string.
+    ///
+    /// Base32 encoding with `v` prefix is used.
+    #[inline]
+    pub fn to_multibase(&self) -> String {
+        format!("v{}", self.to_base32())
+    }
+
+    /// Try to parse hash value from the multibase string.
+    ///
+    /// Base16 (`f`, `F`) and base32hex (`v`, `V`) encodings are supported.
+    pub fn from_multibase(str: impl AsRef<str>) -> Option<Self> {
+        let str = str.as_ref();
+
+        let mut chars = str.chars();
+
+        let prefix = chars.next()?;
+        let value = chars.as_str().to_ascii_lowercase();
+
+        match prefix {
+            'f' | 'F' => Self::from_hex(value),
+            'v' | 'V' => Self::from_base32(value),
+
+            _ => None
+        }
+    }
+}
+
+/// Resolve symlinks of the filesystem entry before hashing.
+fn resolve_symlinks(mut path: PathBuf) -> std::io::Result<PathBuf> {
+    while path.is_symlink() {
+        path = path.read_link()?;
+    }
+
+    Ok(path)
+}
+
+/// Relative path of the folder's entry (as raw OS bytes) and resolved path
+/// for files or `None` for folders.
+///
+/// Entries resolved outside of the folder have no relative path.
+type FolderEntry = (Option<Vec<u8>>, Option<PathBuf>);
+
+/// Walk the folder, returning all the nested entries sorted in ascending
+/// order.
+fn folder_entries(root: &Path) -> std::io::Result<Vec<FolderEntry>> {
+    let mut entries = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+
+    while let Some(path) = folders.pop() {
+        for entry in path.read_dir()?.flatten() {
+            let path = resolve_symlinks(entry.path())?;
+
+            let name = path.strip_prefix(root)
+                .ok()
+                .map(|name| name.as_os_str().as_encoded_bytes().to_vec());
+
+            if path.is_file() {
+                entries.push((name, Some(path)));
+            }
+
+            // Otherwise it's a folder and we need to push it
+            // to the hasing queue.
+            else {
+                entries.push((name, None));
+                folders.push(path);
+            }
+        }
+    }
+
+    entries.sort();
+
+    Ok(entries)
 }
 
 impl std::fmt::Display for Hash {
@@ -202,6 +387,60 @@ macro_rules! impl_from_num {
 
 impl_from_num!(u8 u16 u32 u64 u128 i8 i16 i32 i64 i128);
 
+/// Full-width digest of some data calculated with a specified algorithm.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest {
+    algorithm: HashAlgorithm,
+    value: Box<[u8]>
+}
+
+impl Digest {
+    #[inline]
+    pub fn new(algorithm: HashAlgorithm, value: impl Into<Box<[u8]>>) -> Self {
+        Self {
+            algorithm,
+            value: value.into()
+        }
+    }
+
+    /// Algorithm used to calculate this digest.
+    #[inline(always)]
+    pub const fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Raw bytes of the digest.
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.value
+    }
+
+    #[inline(always)]
+    pub fn into_bytes(self) -> Box<[u8]> {
+        self.value
+    }
+
+    /// Convert digest to the hex string.
+    #[inline]
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.value)
+    }
+}
+
+impl std::fmt::Display for Digest {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.to_hex())
+    }
+}
+
+impl AsRef<[u8]> for Digest {
+    #[inline(always)]
+    fn as_ref(&self) -> &[u8] {
+        &self.value
+    }
+}
+
 macro_rules! impl_as_hash {
     (num $($type:ty)+) => {
         $(
@@ -354,19 +593,72 @@ impl<T: AsHash> AsHash for std::collections::HashSet<T> {
     }
 }
 
+macro_rules! impl_as_hash_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: AsHash),+> AsHash for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn hash(&self) -> Hash {
+                let ($($name,)+) = self;
+
+                Hash::default()
+                    $(.chain($name.hash()))+
+            }
+
+            #[allow(non_snake_case)]
+            fn partial_hash(&self) -> Hash {
+                let ($($name,)+) = self;
+
+                Hash::default()
+                    $(.chain($name.partial_hash()))+
+            }
+        }
+    };
+}
+
+impl_as_hash_tuple!(A);
+impl_as_hash_tuple!(A B);
+impl_as_hash_tuple!(A B C);
+impl_as_hash_tuple!(A B C D);
+impl_as_hash_tuple!(A B C D E);
+impl_as_hash_tuple!(A B C D E F);
+
+/// Hash map entries in a stable order.
+///
+/// Entries are sorted by their keys' hashes so the result doesn't depend on
+/// the map iteration order, and then hashed together so the keys can't be
+/// swapped between values without changing the result.
+fn hash_map_entries(mut entries: Vec<(Hash, Hash)>) -> Hash {
+    if entries.is_empty() {
+        return Hash::default();
+    }
+
+    entries.sort();
+
+    let mut buf = Vec::with_capacity(entries.len() * 16);
+
+    for (key, value) in entries {
+        buf.extend(key.0.to_be_bytes());
+        buf.extend(value.0.to_be_bytes());
+    }
+
+    Hash::for_slice(buf)
+}
+
 impl<K: AsHash, V: AsHash> AsHash for std::collections::HashMap<K, V> {
-    #[inline]
     fn hash(&self) -> Hash {
-        self.iter()
-            .map(|(k, v)| k.hash().chain(v.hash()))
-            .fold(Hash::default(), Hash::chain)
+        let entries = self.iter()
+            .map(|(k, v)| (k.hash(), v.hash()))
+            .collect();
+
+        hash_map_entries(entries)
     }
 
-    #[inline]
     fn partial_hash(&self) -> Hash {
-        self.iter()
-            .map(|(k, v)| k.partial_hash().chain(v.partial_hash()))
-            .fold(Hash::default(), Hash::chain)
+        let entries = self.iter()
+            .map(|(k, v)| (k.partial_hash(), v.partial_hash()))
+            .collect();
+
+        hash_map_entries(entries)
     }
 }
 
@@ -383,10 +675,151 @@ mod tests {
         assert_eq!([1_i16, -2, 3].hash(), Hash(7455816351535058648));
     }
 
+    #[test]
+    fn as_hash_map() {
+        use std::collections::HashMap;
+
+        let a = HashMap::from([("a", 1_u32), ("b", 2), ("c", 3)]);
+        let b = HashMap::from([("c", 3_u32), ("a", 1), ("b", 2)]);
+        let c = HashMap::from([("a", 2_u32), ("b", 1), ("c", 3)]);
+
+        assert_eq!(a.hash(), b.hash());
+        assert_ne!(a.hash(), c.hash());
+
+        assert_eq!(HashMap::<String, u32>::new().hash(), Hash::default());
+    }
+
+    #[test]
+    fn as_hash_derive() {
+        #[derive(AsHash)]
+        struct Named {
+            a: u32,
+            b: Vec<String>,
+
+            #[as_hash(skip)]
+            #[allow(dead_code)]
+            c: u64
+        }
+
+        #[derive(AsHash)]
+        struct Unnamed(u32, Option<String>);
+
+        #[derive(AsHash)]
+        struct Unit;
+
+        let named = Named {
+            a: 123,
+            b: vec![String::from("Hello"), String::from("World")],
+            c: 456
+        };
+
+        assert_eq!(named.hash(), 123_u32.hash().chain(named.b.hash()));
+        assert_eq!(named.hash(), (123_u32, vec!["Hello", "World"]).hash());
+
+        assert_eq!(Unnamed(1, None).hash(), 1_u32.hash());
+        assert_eq!(Unit.hash(), Hash::default());
+    }
+
+    #[test]
+    fn combine_unordered() {
+        let hashes = [Hash(1), Hash(2), Hash(3)];
+
+        assert_eq!(
+            Hash::combine_unordered(hashes),
+            Hash::combine_unordered([Hash(3), Hash(1), Hash(2)])
+        );
+
+        assert_ne!(Hash::combine_unordered(hashes), Hash::combine_unordered([Hash(1), Hash(2)]));
+        assert_ne!(Hash::combine_unordered([Hash(1), Hash(1)]), Hash::combine_unordered([]));
+        assert_ne!(Hash::combine_unordered([Hash(1), Hash(1)]), Hash::combine_unordered([Hash(2), Hash(2)]));
+    }
+
     #[test]
     fn base32() {
         assert_eq!(Hash::for_slice(b"Hello, World!").to_base32(), "5r15eab6q03fq");
         assert_eq!(Hash::from_base32("5r15eab6q03fq"), Some(Hash(3369351306556737277)));
         assert_eq!(Hash::from_base32("Hello, World!"), None);
+        assert_eq!(Hash::from_base32("5r15"), None);
+        assert_eq!(Hash::from_base32("5r15eab6q03fq0"), None);
+        assert_eq!(Hash::from_base32("5r15eab6q03fq5r15eab6q03fq"), None);
+
+        // Short names of unknown files in the resources store.
+        for name in ["", "a", "ab", "abc", ".lock", "5r15eab6q03f", "5r15eab6q03fq.tmp"] {
+            assert_eq!(Hash::from_base32(name), None);
+        }
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(Hash::for_slice(b"Hello, World!").to_hex(), "2ec2572966d006fd");
+        assert_eq!(Hash::from_hex("2ec2572966d006fd"), Some(Hash(3369351306556737277)));
+        assert_eq!(Hash::from_hex("2EC2572966D006FD"), Some(Hash(3369351306556737277)));
+        assert_eq!(Hash::from_hex("2ec2572966d006"), None);
+        assert_eq!(Hash::from_hex("Hello, World!"), None);
+    }
+
+    #[test]
+    fn multibase() {
+        let hash = Hash::for_slice(b"Hello, World!");
+
+        assert_eq!(hash.to_multibase(), "v5r15eab6q03fq");
+        assert_eq!(Hash::from_multibase("v5r15eab6q03fq"), Some(hash));
+        assert_eq!(Hash::from_multibase("V5R15EAB6Q03FQ"), Some(hash));
+        assert_eq!(Hash::from_multibase("f2ec2572966d006fd"), Some(hash));
+        assert_eq!(Hash::from_multibase("5r15eab6q03fq"), None);
+        assert_eq!(Hash::from_multibase(""), None);
+    }
+
+    #[test]
+    fn digest() -> std::io::Result<()> {
+        assert_eq!(Hash::for_slice("Hello, World!"), Hash(seahash::hash(b"Hello, World!")));
+
+        let digest = Hash::for_slice_with(HashAlgorithm::Seahash, "Hello, World!")?;
+
+        assert_eq!(digest.algorithm(), HashAlgorithm::Seahash);
+        assert_eq!(digest.as_bytes(), Hash::for_slice("Hello, World!").0.to_be_bytes());
+
+        let path = std::env::temp_dir().join(".wineyard-runtime-digest-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("folder"))?;
+        std::fs::write(path.join("folder/file"), "Hello, World!")?;
+
+        assert_eq!(Hash::for_entry_with(HashAlgorithm::Seahash, path.join("folder/file"))?, digest);
+
+        let a = Hash::for_entry_with(HashAlgorithm::Seahash, &path)?;
+
+        std::fs::write(path.join("folder/file"), "Hello, World")?;
+
+        assert_ne!(Hash::for_entry_with(HashAlgorithm::Seahash, &path)?, a);
+
+        // Swapping files' content changes the digest.
+        std::fs::write(path.join("folder/file"), "a")?;
+        std::fs::write(path.join("folder/file 2"), "b")?;
+
+        let a = Hash::for_entry_with(HashAlgorithm::Seahash, &path)?;
+
+        std::fs::write(path.join("folder/file"), "b")?;
+        std::fs::write(path.join("folder/file 2"), "a")?;
+
+        assert_ne!(Hash::for_entry_with(HashAlgorithm::Seahash, &path)?, a);
+
+        // Empty files and folders with the same name are different.
+        std::fs::remove_file(path.join("folder/file 2"))?;
+        std::fs::write(path.join("folder/entry"), "")?;
+
+        let a = Hash::for_entry_with(HashAlgorithm::Seahash, &path)?;
+
+        std::fs::remove_file(path.join("folder/entry"))?;
+        std::fs::create_dir(path.join("folder/entry"))?;
+
+        assert_ne!(Hash::for_entry_with(HashAlgorithm::Seahash, &path)?, a);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
     }
 }
@@ -1,3 +1,9 @@
+// Nested conditions are kept instead of let chains.
+#![allow(clippy::collapsible_if)]
+
+// Allow derive macros to refer to this crate by its name.
+extern crate self as wineyard_runtime;
+
 /// Version of the wineyard runtime library.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
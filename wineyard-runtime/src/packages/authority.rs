@@ -0,0 +1,125 @@
+use std::io::Read;
+use std::path::Path;
+
+use ed25519_dalek::{Digest, Sha512, Signature, VerifyingKey};
+
+/// Size of the chunks used to hash verified content.
+const AUTHORITY_READ_CHUNK_LEN: usize = 8192;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthorityValidatorError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid public key: {0}")]
+    InvalidKey(#[from] ed25519_dalek::SignatureError)
+}
+
+/// Validator of the resources signatures.
+///
+/// Resources are signed using the Ed25519ph algorithm (Ed25519 with SHA-512
+/// pre-hashing, RFC 8032) so their content is never fully loaded in memory.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct AuthorityValidator {
+    trusted_keys: Vec<VerifyingKey>
+}
+
+impl AuthorityValidator {
+    /// Create new validator without trusted keys.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create new validator with given trusted keys.
+    #[inline]
+    pub fn with_keys(keys: impl IntoIterator<Item = VerifyingKey>) -> Self {
+        Self {
+            trusted_keys: keys.into_iter().collect()
+        }
+    }
+
+    /// Add trusted public key.
+    #[inline]
+    pub fn add_key(&mut self, key: VerifyingKey) -> &mut Self {
+        self.trusted_keys.push(key);
+
+        self
+    }
+
+    /// Add trusted public key from its raw bytes.
+    pub fn add_key_bytes(&mut self, key: &[u8; 32]) -> Result<&mut Self, AuthorityValidatorError> {
+        self.trusted_keys.push(VerifyingKey::from_bytes(key)?);
+
+        Ok(self)
+    }
+
+    /// Get list of trusted public keys.
+    #[inline(always)]
+    pub fn trusted_keys(&self) -> &[VerifyingKey] {
+        &self.trusted_keys
+    }
+
+    /// Verify that the given signature of the content is made by one of
+    /// the trusted keys.
+    pub fn verify(&self, mut content: impl Read, signature: &[u8]) -> Result<bool, AuthorityValidatorError> {
+        let Ok(signature) = Signature::from_slice(signature) else {
+            return Ok(false);
+        };
+
+        let mut hasher = Sha512::new();
+        let mut buf = [0; AUTHORITY_READ_CHUNK_LEN];
+
+        loop {
+            let n = content.read(&mut buf)?;
+
+            if n == 0 {
+                break;
+            }
+
+            hasher.update(&buf[..n]);
+        }
+
+        let verified = self.trusted_keys.iter().any(|key| {
+            key.verify_prehashed(hasher.clone(), None, &signature).is_ok()
+        });
+
+        Ok(verified)
+    }
+
+    /// Verify that the given signature of the file is made by one of
+    /// the trusted keys.
+    #[inline]
+    pub fn verify_file(&self, path: impl AsRef<Path>, signature: &[u8]) -> Result<bool, AuthorityValidatorError> {
+        self.verify(std::fs::File::open(path)?, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+
+    use super::*;
+
+    #[test]
+    fn verify() -> Result<(), AuthorityValidatorError> {
+        let trusted = SigningKey::from_bytes(&[1; 32]);
+        let untrusted = SigningKey::from_bytes(&[2; 32]);
+
+        let content = b"Hello, World!";
+
+        let sign = |key: &SigningKey| {
+            key.sign_prehashed(Sha512::new().chain_update(content), None)
+                .map(|signature| signature.to_bytes())
+        };
+
+        let validator = AuthorityValidator::with_keys([trusted.verifying_key()]);
+
+        assert!(validator.verify(content.as_slice(), &sign(&trusted)?)?);
+        assert!(!validator.verify(content.as_slice(), &sign(&untrusted)?)?);
+        assert!(!validator.verify(b"Hello, World".as_slice(), &sign(&trusted)?)?);
+        assert!(!validator.verify(content.as_slice(), b"invalid signature")?);
+
+        Ok(())
+    }
+}
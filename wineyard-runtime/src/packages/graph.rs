@@ -0,0 +1,373 @@
+use std::collections::VecDeque;
+
+use crate::hash::Hash;
+
+use super::manifest::ResourceFormat;
+use super::lock_file::LockFile;
+
+/// Resource of the dependency graph.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DependencyNode {
+    /// Index of the resource in the lock file.
+    pub index: usize,
+
+    pub hash: Hash,
+    pub url: String,
+    pub format: ResourceFormat
+}
+
+/// Relationship between a package and its resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    Input,
+    Output
+}
+
+/// Reference from a package to its input or output resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DependencyEdge {
+    /// Index of the package.
+    pub from: usize,
+
+    /// Index of the referenced resource.
+    pub to: usize,
+
+    /// Name of the reference in the package manifest.
+    pub name: String,
+
+    pub kind: DependencyKind
+}
+
+/// Read-only view of the resources graph of a lock file.
+///
+/// Unlike the packages engine it doesn't load any resources, so it can be
+/// used to inspect the dependencies cheaply.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    root: Vec<usize>,
+    nodes: Vec<DependencyNode>,
+    edges: Vec<DependencyEdge>
+}
+
+impl DependencyGraph {
+    /// Get root packages of the graph.
+    pub fn root(&self) -> impl Iterator<Item = &DependencyNode> {
+        self.root.iter()
+            .filter_map(|index| self.nodes.get(*index))
+    }
+
+    /// Get all the resources of the graph in the lock file order.
+    #[inline(always)]
+    pub fn nodes(&self) -> &[DependencyNode] {
+        &self.nodes
+    }
+
+    /// Get all the references of the graph.
+    #[inline(always)]
+    pub fn edges(&self) -> &[DependencyEdge] {
+        &self.edges
+    }
+
+    /// Find resource with the given hash.
+    pub fn node(&self, hash: &Hash) -> Option<&DependencyNode> {
+        self.nodes.iter()
+            .find(|node| &node.hash == hash)
+    }
+
+    /// Get resources referenced by the resources with the given hash.
+    pub fn dependencies_of(&self, hash: &Hash) -> Vec<&DependencyNode> {
+        let mut dependencies = self.edges.iter()
+            .filter(|edge| &self.nodes[edge.from].hash == hash)
+            .map(|edge| edge.to)
+            .collect::<Vec<_>>();
+
+        dependencies.sort();
+        dependencies.dedup();
+
+        dependencies.into_iter()
+            .map(|index| &self.nodes[index])
+            .collect()
+    }
+
+    /// Get packages which reference resources with the given hash.
+    pub fn dependents_of(&self, hash: &Hash) -> Vec<&DependencyNode> {
+        let mut dependents = self.edges.iter()
+            .filter(|edge| &self.nodes[edge.to].hash == hash)
+            .map(|edge| edge.from)
+            .collect::<Vec<_>>();
+
+        dependents.sort();
+        dependents.dedup();
+
+        dependents.into_iter()
+            .map(|index| &self.nodes[index])
+            .collect()
+    }
+
+    /// Sort resources so every resource goes after all of its dependencies.
+    ///
+    /// Return `None` if resources reference each other in a loop.
+    pub fn topological_sort(&self) -> Option<Vec<&DependencyNode>> {
+        // Amount of not yet sorted dependencies of each resource.
+        let mut remaining = vec![0; self.nodes.len()];
+
+        for edge in &self.edges {
+            remaining[edge.from] += 1;
+        }
+
+        let mut queue = remaining.iter()
+            .enumerate()
+            .filter(|(_, remaining)| **remaining == 0)
+            .map(|(index, _)| index)
+            .collect::<VecDeque<_>>();
+
+        let mut sorted = Vec::with_capacity(self.nodes.len());
+
+        while let Some(index) = queue.pop_front() {
+            sorted.push(&self.nodes[index]);
+
+            for edge in self.edges.iter().filter(|edge| edge.to == index) {
+                remaining[edge.from] -= 1;
+
+                if remaining[edge.from] == 0 {
+                    queue.push_back(edge.from);
+                }
+            }
+        }
+
+        if sorted.len() < self.nodes.len() {
+            return None;
+        }
+
+        Some(sorted)
+    }
+
+    /// Find resources which reference each other in a loop, returning the
+    /// loop's resources with the first one repeated at the end.
+    pub fn find_cycle(&self) -> Option<Vec<&DependencyNode>> {
+        let mut dependencies = vec![Vec::new(); self.nodes.len()];
+
+        for edge in &self.edges {
+            dependencies[edge.from].push(edge.to);
+        }
+
+        let path = find_cycle(&dependencies)?;
+
+        Some(path.into_iter().map(|index| &self.nodes[index]).collect())
+    }
+}
+
+/// Find nodes which depend on each other in a loop, returning indexes of the
+/// loop's nodes with the first one repeated at the end.
+///
+/// Nodes and their dependencies are visited in ascending order so the same
+/// loop is always reported.
+pub(crate) fn find_cycle(dependencies: &[Vec<usize>]) -> Option<Vec<usize>> {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum State {
+        Unvisited,
+        Visiting,
+        Visited
+    }
+
+    fn visit(
+        index: usize,
+        dependencies: &[Vec<usize>],
+        states: &mut [State],
+        stack: &mut Vec<usize>
+    ) -> Option<Vec<usize>> {
+        states[index] = State::Visiting;
+        stack.push(index);
+
+        let mut references = dependencies[index].iter()
+            .copied()
+            .filter(|reference| *reference < dependencies.len())
+            .collect::<Vec<_>>();
+
+        references.sort();
+        references.dedup();
+
+        for reference in references {
+            match states[reference] {
+                State::Visiting => {
+                    let start = stack.iter()
+                        .position(|index| *index == reference)
+                        .unwrap_or_default();
+
+                    let mut path = stack[start..].to_vec();
+
+                    path.push(reference);
+
+                    return Some(path);
+                }
+
+                State::Unvisited => {
+                    if let Some(path) = visit(reference, dependencies, states, stack) {
+                        return Some(path);
+                    }
+                }
+
+                State::Visited => ()
+            }
+        }
+
+        stack.pop();
+        states[index] = State::Visited;
+
+        None
+    }
+
+    let mut states = vec![State::Unvisited; dependencies.len()];
+    let mut stack = Vec::new();
+
+    for index in 0..dependencies.len() {
+        if states[index] == State::Unvisited
+            && let Some(path) = visit(index, dependencies, &mut states, &mut stack)
+        {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+impl From<&LockFile> for DependencyGraph {
+    fn from(value: &LockFile) -> Self {
+        let nodes = value.resources.iter()
+            .enumerate()
+            .map(|(index, resource)| DependencyNode {
+                index,
+                hash: resource.lock.hash,
+                url: resource.url.clone(),
+                format: resource.format
+            })
+            .collect::<Vec<_>>();
+
+        let mut edges = Vec::new();
+
+        for (from, resource) in value.resources.iter().enumerate() {
+            let references = [
+                (DependencyKind::Input, &resource.inputs),
+                (DependencyKind::Output, &resource.outputs)
+            ];
+
+            for (kind, references) in references {
+                let mut references = references.iter()
+                    .flatten()
+                    .filter(|(_, to)| (**to as usize) < nodes.len())
+                    .collect::<Vec<_>>();
+
+                // Sort references to keep the graph reproducible.
+                references.sort();
+
+                for (name, to) in references {
+                    edges.push(DependencyEdge {
+                        from,
+                        to: *to as usize,
+                        name: name.clone(),
+                        kind
+                    });
+                }
+            }
+        }
+
+        let root = value.lock.root.iter()
+            .map(|index| *index as usize)
+            .filter(|index| *index < nodes.len())
+            .collect();
+
+        Self {
+            root,
+            nodes,
+            edges
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packages::lock_file::{LockFileInfo, ResourceLock};
+
+    use super::*;
+
+    #[test]
+    fn dependency_graph() {
+        let mut lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![
+                ResourceLock::test_package("a", Hash::for_slice(b"a"), &[("b", 1), ("file", 2)]),
+                ResourceLock::test_package("b", Hash::for_slice(b"b"), &[("file", 2)]),
+                ResourceLock::test_file("file", Hash::for_slice(b"file"))
+            ]
+        };
+
+        let graph = DependencyGraph::from(&lock_file);
+
+        let hash = |url: &str| Hash::for_slice(url.as_bytes());
+        let urls = |nodes: Vec<&DependencyNode>| {
+            nodes.into_iter()
+                .map(|node| node.url.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(graph.nodes().len(), 3);
+        assert_eq!(graph.edges().len(), 3);
+        assert_eq!(urls(graph.root().collect()), ["a"]);
+
+        assert_eq!(urls(graph.dependents_of(&hash("file"))), ["a", "b"]);
+        assert_eq!(urls(graph.dependencies_of(&hash("a"))), ["b", "file"]);
+        assert!(graph.dependents_of(&hash("a")).is_empty());
+
+        assert_eq!(graph.topological_sort().map(urls), Some(vec![
+            String::from("file"),
+            String::from("b"),
+            String::from("a")
+        ]));
+
+        // Reference the root package from its dependency.
+        lock_file.resources[1] = ResourceLock::test_package("b", Hash::for_slice(b"b"), &[("a", 0)]);
+
+        assert_eq!(DependencyGraph::from(&lock_file).topological_sort(), None);
+    }
+
+    #[test]
+    fn find_cycle() {
+        let cycle = |resources: Vec<ResourceLock>| {
+            let lock_file = LockFile {
+                lock: LockFileInfo {
+                    root: vec![]
+                },
+                resources
+            };
+
+            lock_file.graph()
+                .find_cycle()
+                .map(|path| path.into_iter().map(|node| node.url.clone()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(cycle(vec![
+            ResourceLock::test_package("a", Hash::for_slice(b"a"), &[("1", 1), ("2", 2)]),
+            ResourceLock::test_package("b", Hash::for_slice(b"b"), &[("2", 2)]),
+            ResourceLock::test_package("c", Hash::for_slice(b"c"), &[])
+        ]), None);
+
+        assert_eq!(cycle(vec![
+            ResourceLock::test_package("a", Hash::for_slice(b"a"), &[("1", 1)]),
+            ResourceLock::test_package("b", Hash::for_slice(b"b"), &[("2", 2)]),
+            ResourceLock::test_package("c", Hash::for_slice(b"c"), &[("1", 1)])
+        ]), Some(vec![
+            String::from("b"),
+            String::from("c"),
+            String::from("b")
+        ]));
+
+        assert_eq!(cycle(vec![
+            ResourceLock::test_package("a", Hash::for_slice(b"a"), &[("0", 0)])
+        ]), Some(vec![
+            String::from("a"),
+            String::from("a")
+        ]));
+    }
+}
@@ -7,12 +7,22 @@ use toml::{Value as Toml, Table as TomlTable};
 use crate::hash::{Hash, AsHash};
 
 use super::manifest::{ResourceFormat, PackageManifestError};
+use super::graph::DependencyGraph;
+
+/// Latest supported version of the lock file format.
+pub const LOCK_FILE_FORMAT_VERSION: u16 = 1;
 
 #[derive(Debug, thiserror::Error)]
 pub enum LockFileError {
     #[error(transparent)]
     PackageManifestError(#[from] PackageManifestError),
 
+    #[error(transparent)]
+    Serialize(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    Deserialize(#[from] toml::de::Error),
+
     #[error("invalid lock file format version: {0}")]
     InvalidFormatVersion(u16),
 
@@ -23,12 +33,244 @@ pub enum LockFileError {
     }
 }
 
+/// Lock file of the resolved packages.
+///
+/// Lock files are stored as TOML documents:
+///
+/// ```toml
+/// [lock]
+/// format = 1
+/// root = [0]
+///
+/// [[resources]]
+/// url = "https://example.com/package.toml"
+/// format = "package"
+/// lock = { hash = "<base32>", size = 1024 }
+/// inputs = { module = 1 }
+/// outputs = {}
+/// ```
+///
+/// Root contains indexes of the packages added to the resolver, and package
+/// resources reference their inputs and outputs by their indexes too.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LockFile {
     pub lock: LockFileInfo,
     pub resources: Vec<ResourceLock>
 }
 
+impl LockFile {
+    /// Serialize lock file to a TOML string.
+    #[inline]
+    pub fn to_toml(&self) -> Result<String, LockFileError> {
+        Ok(toml::to_string(&TomlTable::from(self))?)
+    }
+
+    /// Deserialize lock file from a TOML string.
+    #[inline]
+    pub fn from_toml(lock_file: impl AsRef<str>) -> Result<Self, LockFileError> {
+        Self::try_from(&toml::from_str::<TomlTable>(lock_file.as_ref())?)
+    }
+
+    /// Sort resources by their URL and hash, and root resources by their
+    /// indexes, updating all the references.
+    ///
+    /// Resolver produces resources in the order they were processed, so this
+    /// is needed to make the lock file reproducible.
+    pub fn sort(&mut self) {
+        let mut resources = std::mem::take(&mut self.resources)
+            .into_iter()
+            .enumerate()
+            .collect::<Vec<_>>();
+
+        resources.sort_by(|(_, a), (_, b)| {
+            a.url.cmp(&b.url).then(a.lock.hash.cmp(&b.lock.hash))
+        });
+
+        let mut indexes = vec![0; resources.len()];
+
+        for (new_index, (old_index, _)) in resources.iter().enumerate() {
+            indexes[*old_index] = new_index as u32;
+        }
+
+        // Keep invalid references untouched.
+        let remap = |index: &mut u32| {
+            if let Some(new_index) = indexes.get(*index as usize) {
+                *index = *new_index;
+            }
+        };
+
+        self.resources = resources.into_iter()
+            .map(|(_, mut resource)| {
+                resource.inputs.iter_mut()
+                    .chain(resource.outputs.iter_mut())
+                    .flat_map(|references| references.values_mut())
+                    .for_each(remap);
+
+                resource
+            })
+            .collect();
+
+        self.lock.root.iter_mut().for_each(remap);
+
+        self.lock.root.sort();
+        self.lock.root.dedup();
+    }
+
+    /// Merge resources with the same content hash, format and references
+    /// into one, updating all the references.
+    ///
+    /// The same file can be served from different URLs, so this prevents
+    /// it from being downloaded and stored twice. The resource with the
+    /// lowest URL is kept to make the lock file reproducible.
+    ///
+    /// Root resources are never merged since they're looked up by their
+    /// URLs, e.g. to reuse them in offline mode.
+    pub fn dedup(&mut self) {
+        type Key = (Hash, ResourceFormat, Option<Vec<(String, u32)>>, Option<Vec<(String, u32)>>);
+
+        fn references(references: &Option<HashMap<String, u32>>) -> Option<Vec<(String, u32)>> {
+            references.as_ref().map(|references| {
+                let mut references = references.iter()
+                    .map(|(name, index)| (name.clone(), *index))
+                    .collect::<Vec<_>>();
+
+                references.sort();
+
+                references
+            })
+        }
+
+        // Merging resources can make packages referencing them identical,
+        // so repeat until nothing is merged.
+        loop {
+            let mut groups = HashMap::<Key, usize>::new();
+            let mut targets = (0..self.resources.len()).collect::<Vec<_>>();
+
+            for (index, resource) in self.resources.iter().enumerate() {
+                if self.lock.root.contains(&(index as u32)) {
+                    continue;
+                }
+
+                let key = (
+                    resource.lock.hash,
+                    resource.format,
+                    references(&resource.inputs),
+                    references(&resource.outputs)
+                );
+
+                match groups.get_mut(&key) {
+                    Some(target) if self.resources[*target].url <= resource.url => {
+                        targets[index] = *target;
+                    }
+
+                    Some(target) => {
+                        targets[*target] = index;
+
+                        *target = index;
+                    }
+
+                    None => {
+                        groups.insert(key, index);
+                    }
+                }
+            }
+
+            if targets.iter().enumerate().all(|(index, target)| index == *target) {
+                break;
+            }
+
+            // Resolve chains of merged resources to their final targets.
+            for index in 0..targets.len() {
+                let mut target = targets[index];
+
+                while targets[target] != target {
+                    target = targets[target];
+                }
+
+                targets[index] = target;
+            }
+
+            let mut indexes = vec![0; self.resources.len()];
+            let mut kept = 0;
+
+            for (index, target) in targets.iter().enumerate() {
+                if index == *target {
+                    indexes[index] = kept;
+
+                    kept += 1;
+                }
+            }
+
+            // Keep invalid references untouched.
+            let remap = |index: &mut u32| {
+                if let Some(target) = targets.get(*index as usize) {
+                    *index = indexes[*target];
+                }
+            };
+
+            self.resources = std::mem::take(&mut self.resources)
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| targets[*index] == *index)
+                .map(|(_, mut resource)| {
+                    resource.inputs.iter_mut()
+                        .chain(resource.outputs.iter_mut())
+                        .flat_map(|references| references.values_mut())
+                        .for_each(remap);
+
+                    resource
+                })
+                .collect();
+
+            self.lock.root.iter_mut().for_each(remap);
+
+            self.lock.root.sort();
+            self.lock.root.dedup();
+        }
+    }
+
+    /// Compare this lock file with an older one.
+    ///
+    /// Resources are matched by their URL and format.
+    pub fn diff(&self, old: &LockFile) -> LockFileDiff {
+        let old_resources = old.resources.iter()
+            .map(|resource| ((&resource.url, resource.format), resource))
+            .collect::<HashMap<_, _>>();
+
+        let new_resources = self.resources.iter()
+            .map(|resource| ((&resource.url, resource.format), resource))
+            .collect::<HashMap<_, _>>();
+
+        let mut diff = LockFileDiff::default();
+
+        for resource in &self.resources {
+            match old_resources.get(&(&resource.url, resource.format)) {
+                Some(old_resource) if old_resource.lock.hash != resource.lock.hash => {
+                    diff.changed.push(((*old_resource).clone(), resource.clone()));
+                }
+
+                Some(_) => (),
+
+                None => diff.added.push(resource.clone())
+            }
+        }
+
+        for resource in &old.resources {
+            if !new_resources.contains_key(&(&resource.url, resource.format)) {
+                diff.removed.push(resource.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Build read-only view of the resources dependency graph.
+    #[inline]
+    pub fn graph(&self) -> DependencyGraph {
+        DependencyGraph::from(self)
+    }
+}
+
 impl AsHash for LockFile {
     fn hash(&self) -> Hash {
         self.lock.hash()
@@ -72,6 +314,37 @@ impl AsHash for ResourceLock {
     }
 }
 
+#[cfg(test)]
+impl ResourceLock {
+    /// Create file resource lock for tests.
+    pub fn test_file(url: &str, hash: Hash) -> Self {
+        Self::test(url, ResourceFormat::File, hash)
+    }
+
+    /// Create package resource lock for tests with the given inputs.
+    pub fn test_package(url: &str, hash: Hash, inputs: &[(&str, u32)]) -> Self {
+        Self {
+            inputs: Some(inputs.iter().map(|(name, index)| (name.to_string(), *index)).collect()),
+            outputs: Some(HashMap::new()),
+            ..Self::test(url, ResourceFormat::Package, hash)
+        }
+    }
+
+    /// Create resource lock for tests without inputs and outputs.
+    pub fn test(url: &str, format: ResourceFormat, hash: Hash) -> Self {
+        Self {
+            url: url.to_string(),
+            format,
+            lock: ResourceLockData {
+                hash,
+                size: 0
+            },
+            inputs: None,
+            outputs: None
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ResourceLockData {
     pub hash: Hash,
@@ -85,6 +358,27 @@ impl AsHash for ResourceLockData {
     }
 }
 
+/// Difference between two lock files.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct LockFileDiff {
+    /// Resources which are missing in the old lock file.
+    pub added: Vec<ResourceLock>,
+
+    /// Resources which are missing in the new lock file.
+    pub removed: Vec<ResourceLock>,
+
+    /// Resources with changed hashes as `(old, new)` pairs.
+    pub changed: Vec<(ResourceLock, ResourceLock)>
+}
+
+impl LockFileDiff {
+    /// Check if lock files are equal.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
 impl From<&LockFile> for TomlTable {
     fn from(value: &LockFile) -> Self {
         let mut lock_file = TomlTable::new();
@@ -97,7 +391,7 @@ impl From<&LockFile> for TomlTable {
 
         lock_info.insert(
             String::from("format"),
-            Toml::Integer(1)
+            Toml::Integer(LOCK_FILE_FORMAT_VERSION as i64)
         );
 
         lock_info.insert(
@@ -128,7 +422,7 @@ impl From<&LockFile> for TomlTable {
 
                 lock.insert(
                     String::from("hash"),
-                    Toml::String(resource.lock.hash.to_string())
+                    Toml::String(resource.lock.hash.to_base32())
                 );
 
                 lock.insert(
@@ -345,3 +639,145 @@ impl TryFrom<&TomlTable> for LockFile {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        let old = LockFile {
+            lock: LockFileInfo {
+                root: vec![]
+            },
+            resources: vec![
+                ResourceLock::test_file("a", Hash(1)),
+                ResourceLock::test_file("b", Hash(2)),
+                ResourceLock::test_file("c", Hash(3))
+            ]
+        };
+
+        let new = LockFile {
+            lock: LockFileInfo {
+                root: vec![]
+            },
+            resources: vec![
+                ResourceLock::test_file("a", Hash(1)),
+                ResourceLock::test_file("c", Hash(4)),
+                ResourceLock::test_file("d", Hash(5))
+            ]
+        };
+
+        assert!(old.diff(&old).is_empty());
+
+        let diff = new.diff(&old);
+
+        assert_eq!(diff.added, vec![ResourceLock::test_file("d", Hash(5))]);
+        assert_eq!(diff.removed, vec![ResourceLock::test_file("b", Hash(2))]);
+        assert_eq!(diff.changed, vec![(ResourceLock::test_file("c", Hash(3)), ResourceLock::test_file("c", Hash(4)))]);
+    }
+
+    #[test]
+    fn sort() {
+        let mut a = LockFile {
+            lock: LockFileInfo {
+                root: vec![2, 0]
+            },
+            resources: vec![
+                ResourceLock::test_package("b", Hash(0), &[("module", 1)]),
+                ResourceLock::test_file("b/module", Hash(1)),
+                ResourceLock::test_package("a", Hash(0), &[("dep", 0)])
+            ]
+        };
+
+        let mut b = LockFile {
+            lock: LockFileInfo {
+                root: vec![0, 1]
+            },
+            resources: vec![
+                ResourceLock::test_package("a", Hash(0), &[("dep", 1)]),
+                ResourceLock::test_package("b", Hash(0), &[("module", 2)]),
+                ResourceLock::test_file("b/module", Hash(1))
+            ]
+        };
+
+        a.sort();
+        b.sort();
+
+        assert_eq!(a, b);
+
+        assert_eq!(a.lock.root, [0, 1]);
+        assert_eq!(a.resources[0].inputs.as_ref().unwrap()["dep"], 1);
+        assert_eq!(a.resources[1].inputs.as_ref().unwrap()["module"], 2);
+
+        assert_eq!(a.to_toml().unwrap(), b.to_toml().unwrap());
+    }
+
+    #[test]
+    fn dedup() {
+        let mut lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0, 2, 4]
+            },
+            resources: vec![
+                ResourceLock::test_package("b", Hash(0), &[("module", 1)]),
+                ResourceLock::test_file("mirror-b/module", Hash(1)),
+                ResourceLock::test_package("a", Hash(0), &[("module", 3)]),
+                ResourceLock::test_file("mirror-a/module", Hash(1)),
+                ResourceLock::test_package("c", Hash(0), &[("module", 5)]),
+                ResourceLock::test_file("c/module", Hash(2))
+            ]
+        };
+
+        lock_file.dedup();
+        lock_file.sort();
+
+        // Root packages are kept even if they become identical.
+        assert_eq!(lock_file, LockFile {
+            lock: LockFileInfo {
+                root: vec![0, 1, 2]
+            },
+            resources: vec![
+                ResourceLock::test_package("a", Hash(0), &[("module", 4)]),
+                ResourceLock::test_package("b", Hash(0), &[("module", 4)]),
+                ResourceLock::test_package("c", Hash(0), &[("module", 3)]),
+                ResourceLock::test_file("c/module", Hash(2)),
+                ResourceLock::test_file("mirror-a/module", Hash(1))
+            ]
+        });
+    }
+
+    #[test]
+    fn toml() -> Result<(), LockFileError> {
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![
+                ResourceLock {
+                    url: String::from("https://example.com/package.toml"),
+                    format: ResourceFormat::Package,
+                    lock: ResourceLockData {
+                        hash: Hash::for_slice(b"package"),
+                        size: 1024
+                    },
+                    inputs: Some(HashMap::from([(String::from("module"), 1)])),
+                    outputs: Some(HashMap::new())
+                },
+                ResourceLock::test_file("https://example.com/module.lua", Hash(123))
+            ]
+        };
+
+        let serialized = lock_file.to_toml()?;
+
+        assert!(serialized.contains(&Hash::for_slice(b"package").to_base32()));
+        assert_eq!(LockFile::from_toml(&serialized)?, lock_file);
+
+        assert!(matches!(
+            LockFile::from_toml("[lock]\nformat = 2\nroot = []"),
+            Err(LockFileError::InvalidFormatVersion(2))
+        ));
+
+        Ok(())
+    }
+}
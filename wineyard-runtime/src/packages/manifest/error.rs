@@ -1,3 +1,5 @@
+use super::ResourceFormat;
+
 #[derive(Debug, thiserror::Error)]
 pub enum PackageManifestError {
     #[error("unknown resource format: {0}")]
@@ -15,6 +17,25 @@ pub enum PackageManifestError {
     #[error("invalid resource hash format: {0}")]
     ResourceInvalidHashFormat(String),
 
+    #[error("resource '{0}' has empty uri")]
+    ResourceEmptyUri(String),
+
+    #[error("resource name '{0}' is used more than once")]
+    ResourceDuplicateName(String),
+
+    #[error("resource '{name}' has invalid signature length: expected 64 bytes, got {length}")]
+    ResourceInvalidSignature {
+        name: String,
+        length: usize
+    },
+
+    #[error("resource '{name}' format '{format}' contradicts its uri '{uri}'")]
+    ResourceFormatMismatch {
+        name: String,
+        uri: String,
+        format: ResourceFormat
+    },
+
     #[error("unknown package format version: {0}")]
     PackageUnknownFormatVersion(u16),
 
@@ -12,12 +12,13 @@ mod resource;
 pub use error::*;
 pub use resource::*;
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, AsHash)]
 pub struct PackageManifest {
     /// Information about the current package (metadata fields).
     pub package: PackageInfo,
 
     /// Requirements for the modules runtime.
+    #[as_hash(skip)]
     pub runtime: RuntimeInfo,
 
     /// Table of resources which will be imported and available for the current
@@ -29,37 +30,25 @@ pub struct PackageManifest {
     pub outputs: HashMap<String, ResourceInfo>
 }
 
-impl AsHash for PackageManifest {
-    fn hash(&self) -> Hash {
-        self.package.hash()
-            .chain(self.inputs.hash())
-            .chain(self.outputs.hash())
-    }
-}
-
-#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, AsHash)]
 pub struct PackageInfo {
     /// Description of the package.
     pub description: Option<String>,
 
     /// List of the package's authors.
-    pub authors: Vec<String>
+    pub authors: Vec<String>,
+
+    /// SPDX license expression of the package. Available since format 2.
+    pub license: Option<String>
 }
 
 impl PackageInfo {
     pub fn is_empty(&self) -> bool {
-        self.description.is_none() && self.authors.is_empty()
+        self.description.is_none() && self.authors.is_empty() && self.license.is_none()
     }
 }
 
-impl AsHash for PackageInfo {
-    fn hash(&self) -> Hash {
-        self.description.hash()
-            .chain(self.authors.hash())
-    }
-}
-
-#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, AsHash)]
 pub struct RuntimeInfo {
     /// Minimal required version of the modules runtime.
     pub minimal_version: u32
@@ -71,14 +60,7 @@ impl RuntimeInfo {
     }
 }
 
-impl AsHash for RuntimeInfo {
-    #[inline]
-    fn hash(&self) -> Hash {
-        self.minimal_version.hash()
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, AsHash)]
 pub struct ResourceInfo {
     /// Relative or absolute path to the resource.
     pub uri: String,
@@ -89,14 +71,103 @@ pub struct ResourceInfo {
 
     /// Hash of the resource. If specified and is not matched at validation time
     /// then the package will not be accepted.
-    pub hash: Option<Hash>
+    pub hash: Option<Hash>,
+
+    /// Ed25519ph signature of the resource content. If specified and resolver
+    /// has an authority validator then the resource will not be accepted
+    /// unless the signature is made by one of the trusted keys.
+    pub signature: Option<Vec<u8>>,
+
+    /// Alternative URIs of the same resource. Available since format 2.
+    /// Mirrors don't change the resource itself so they're not hashed.
+    #[as_hash(skip)]
+    pub mirrors: Vec<String>
 }
 
-impl AsHash for ResourceInfo {
-    fn hash(&self) -> Hash {
-        Hash::for_slice(&self.uri)
-            .chain(self.format.hash())
-            .chain(self.hash.hash())
+impl PackageManifest {
+    /// Get the lowest manifest format version which can store all the
+    /// fields of the current manifest.
+    pub fn format_version(&self) -> u16 {
+        let has_mirrors = self.inputs.values()
+            .chain(self.outputs.values())
+            .any(|resource| !resource.mirrors.is_empty());
+
+        if self.package.license.is_some() || has_mirrors {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Check the manifest for semantic issues and return all of them.
+    ///
+    /// Unlike `try_from` which only checks fields types, this reports empty
+    /// URIs, resource names which differ only by case, zero hashes, invalid
+    /// signature lengths and explicit formats which contradict the URI.
+    pub fn validate(&self) -> Result<(), Vec<PackageManifestError>> {
+        let mut errors = Vec::new();
+
+        for (table, resources) in [("inputs", &self.inputs), ("outputs", &self.outputs)] {
+            let mut names = resources.iter().collect::<Vec<_>>();
+
+            names.sort_by(|a, b| a.0.cmp(b.0));
+
+            let mut lowercase_names = HashMap::with_capacity(names.len());
+
+            for (name, resource) in names {
+                let name = format!("{table}.{name}");
+
+                if let Some(other) = lowercase_names.insert(name.to_lowercase(), name.clone()) {
+                    errors.push(PackageManifestError::ResourceDuplicateName(other));
+                }
+
+                if resource.uri.trim().is_empty()
+                    || resource.mirrors.iter().any(|mirror| mirror.trim().is_empty())
+                {
+                    errors.push(PackageManifestError::ResourceEmptyUri(name.clone()));
+                }
+
+                if let Some(hash) = resource.hash
+                    && hash == Hash(0)
+                {
+                    errors.push(PackageManifestError::ResourceInvalidHashFormat(name.clone()));
+                }
+
+                if let Some(signature) = &resource.signature
+                    && signature.len() != 64
+                {
+                    errors.push(PackageManifestError::ResourceInvalidSignature {
+                        name: name.clone(),
+                        length: signature.len()
+                    });
+                }
+
+                let is_mismatched = match (resource.format, ResourceFormat::from_uri(&resource.uri)) {
+                    (ResourceFormat::Archive(format), ResourceFormat::Archive(predicted)) => {
+                        format != ResourceArchiveFormat::Auto && format != predicted
+                    }
+
+                    (ResourceFormat::Archive(_), ResourceFormat::Module(_)) |
+                    (ResourceFormat::Module(_), ResourceFormat::Archive(_)) => true,
+
+                    _ => false
+                };
+
+                if is_mismatched {
+                    errors.push(PackageManifestError::ResourceFormatMismatch {
+                        name,
+                        uri: resource.uri.clone(),
+                        format: resource.format
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 }
 
@@ -108,7 +179,7 @@ impl From<&PackageManifest> for TomlTable {
 
         package.insert(
             String::from("format"),
-            Toml::Integer(1)
+            Toml::Integer(value.format_version() as i64)
         );
 
         if let Some(description) = &value.package.description {
@@ -131,6 +202,13 @@ impl From<&PackageManifest> for TomlTable {
             );
         }
 
+        if let Some(license) = &value.package.license {
+            package.insert(
+                String::from("license"),
+                Toml::String(license.clone())
+            );
+        }
+
         manifest.insert(
             String::from("package"),
             Toml::Table(package)
@@ -170,6 +248,26 @@ impl From<&PackageManifest> for TomlTable {
                 );
             }
 
+            if let Some(signature) = &resource.signature {
+                table.insert(
+                    String::from("signature"),
+                    Toml::String(hex::encode(signature))
+                );
+            }
+
+            if !resource.mirrors.is_empty() {
+                let mirrors = resource.mirrors
+                    .iter()
+                    .cloned()
+                    .map(Toml::String)
+                    .collect();
+
+                table.insert(
+                    String::from("mirrors"),
+                    Toml::Array(mirrors)
+                );
+            }
+
             table
         }
 
@@ -231,8 +329,10 @@ impl TryFrom<&TomlTable> for PackageManifest {
                 }
             })? as u16;
 
+        // Unknown fields are ignored so newer manifests of a known format
+        // version can still be read.
         match format {
-            1 => {
+            1 | 2 => {
                 if let Some(description) = package.get("description") {
                     let Some(description) = description.as_str() else {
                         return Err(PackageManifestError::PackageInvalidFieldFormat {
@@ -263,6 +363,19 @@ impl TryFrom<&TomlTable> for PackageManifest {
                         })?;
                 }
 
+                if format >= 2
+                    && let Some(license) = package.get("license")
+                {
+                    let Some(license) = license.as_str() else {
+                        return Err(PackageManifestError::PackageInvalidFieldFormat {
+                            field: "package.license",
+                            expected: "string"
+                        });
+                    };
+
+                    manifest.package.license = Some(license.to_string());
+                }
+
                 if let Some(runtime) = value.get("runtime") {
                     let Some(runtime) = runtime.as_table() else {
                         return Err(PackageManifestError::PackageInvalidFieldFormat {
@@ -284,7 +397,8 @@ impl TryFrom<&TomlTable> for PackageManifest {
                 }
 
                 fn parse_resource(
-                    resource: &TomlTable
+                    resource: &TomlTable,
+                    format: u16
                 ) -> Result<ResourceInfo, PackageManifestError> {
                     let Some(uri) = resource.get("uri") else {
                         return Err(PackageManifestError::ResourceMissingUri);
@@ -297,7 +411,7 @@ impl TryFrom<&TomlTable> for PackageManifest {
                         });
                     };
 
-                    let format = match resource.get("format") {
+                    let resource_format = match resource.get("format") {
                         Some(format) => {
                             let Some(format) = format.as_str() else {
                                 return Err(PackageManifestError::PackageInvalidFieldFormat {
@@ -337,10 +451,44 @@ impl TryFrom<&TomlTable> for PackageManifest {
                         })
                         .transpose()?;
 
+                    let signature = resource.get("signature")
+                        .map(|signature| {
+                            signature.as_str()
+                                .and_then(|signature| hex::decode(signature).ok())
+                                .ok_or({
+                                    PackageManifestError::PackageInvalidFieldFormat {
+                                        field: "<resource>.signature",
+                                        expected: "hex string"
+                                    }
+                                })
+                        })
+                        .transpose()?;
+
+                    let mirrors = match resource.get("mirrors") {
+                        Some(mirrors) if format >= 2 => {
+                            mirrors.as_array()
+                                .and_then(|mirrors| {
+                                    mirrors.iter()
+                                        .map(|mirror| mirror.as_str().map(String::from))
+                                        .collect::<Option<Vec<String>>>()
+                                })
+                                .ok_or({
+                                    PackageManifestError::PackageInvalidFieldFormat {
+                                        field: "<resource>.mirrors",
+                                        expected: "string[]"
+                                    }
+                                })?
+                        }
+
+                        _ => Vec::new()
+                    };
+
                     Ok(ResourceInfo {
                         uri: uri.to_string(),
-                        format,
-                        hash
+                        format: resource_format,
+                        hash,
+                        signature,
+                        mirrors
                     })
                 }
 
@@ -358,12 +506,14 @@ impl TryFrom<&TomlTable> for PackageManifest {
                                 Ok((name.to_owned(), ResourceInfo {
                                     uri: uri.to_string(),
                                     format: ResourceFormat::from_uri(uri),
-                                    hash: None
+                                    hash: None,
+                                    signature: None,
+                                    mirrors: Vec::new()
                                 }))
                             }
 
                             else if let Some(resource) = resource.as_table() {
-                                parse_resource(resource)
+                                parse_resource(resource, format)
                                     .map(|resource| (name.to_owned(), resource))
                             }
 
@@ -391,12 +541,14 @@ impl TryFrom<&TomlTable> for PackageManifest {
                                 Ok((name.to_owned(), ResourceInfo {
                                     uri: uri.to_string(),
                                     format: ResourceFormat::from_uri(uri),
-                                    hash: None
+                                    hash: None,
+                                    signature: None,
+                                    mirrors: Vec::new()
                                 }))
                             }
 
                             else if let Some(resource) = resource.as_table() {
-                                parse_resource(resource)
+                                parse_resource(resource, format)
                                     .map(|resource| (name.to_owned(), resource))
                             }
 
@@ -417,3 +569,103 @@ impl TryFrom<&TomlTable> for PackageManifest {
         Ok(manifest)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_versions() -> Result<(), PackageManifestError> {
+        let manifest: TomlTable = toml::from_str(r#"
+            [package]
+            format = 1
+            license = "MIT"
+
+            [inputs.module]
+            uri = "module.lua"
+            mirrors = ["https://example.com/module.lua"]
+        "#).unwrap();
+
+        let manifest = PackageManifest::try_from(&manifest)?;
+
+        assert_eq!(manifest.package.license, None);
+        assert!(manifest.inputs["module"].mirrors.is_empty());
+        assert_eq!(manifest.format_version(), 1);
+
+        let manifest: TomlTable = toml::from_str(r#"
+            [package]
+            format = 2
+            license = "MIT"
+            unknown = true
+
+            [inputs.module]
+            uri = "module.lua"
+            mirrors = ["https://example.com/module.lua"]
+        "#).unwrap();
+
+        let manifest = PackageManifest::try_from(&manifest)?;
+
+        assert_eq!(manifest.package.license.as_deref(), Some("MIT"));
+        assert_eq!(manifest.inputs["module"].mirrors, ["https://example.com/module.lua"]);
+        assert_eq!(manifest.format_version(), 2);
+
+        let table = TomlTable::from(&manifest);
+
+        assert_eq!(table["package"]["format"].as_integer(), Some(2));
+        assert_eq!(PackageManifest::try_from(&table)?, manifest);
+
+        let manifest: TomlTable = toml::from_str("[package]\nformat = 3").unwrap();
+
+        assert!(matches!(
+            PackageManifest::try_from(&manifest),
+            Err(PackageManifestError::PackageUnknownFormatVersion(3))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate() -> Result<(), PackageManifestError> {
+        let manifest: TomlTable = toml::from_str(r#"
+            [package]
+            format = 2
+
+            [inputs]
+            module = "module.lua"
+            archive = { uri = "archive.zip?sig=123", format = "archive/zip", hash = 1 }
+            file = { uri = "archive.tar.gz", format = "file" }
+
+            [outputs]
+            Module = "module.lua"
+        "#).unwrap();
+
+        assert!(PackageManifest::try_from(&manifest)?.validate().is_ok());
+
+        let manifest: TomlTable = toml::from_str(r#"
+            [package]
+            format = 2
+
+            [inputs]
+            module = { uri = "module.lua", format = "archive/auto" }
+            Module = { uri = "", hash = 0 }
+            archive = { uri = "archive.7z", format = "archive/tar", signature = "abcd" }
+            mirrored = { uri = "module.lua", mirrors = [" "] }
+        "#).unwrap();
+
+        let errors = PackageManifest::try_from(&manifest)?
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(errors.len(), 7, "{errors:?}");
+
+        assert!(matches!(&errors[0], PackageManifestError::ResourceEmptyUri(name) if name == "inputs.Module"));
+        assert!(matches!(&errors[1], PackageManifestError::ResourceInvalidHashFormat(name) if name == "inputs.Module"));
+        assert!(matches!(&errors[2], PackageManifestError::ResourceInvalidSignature { length: 2, .. }));
+        assert!(matches!(&errors[3], PackageManifestError::ResourceFormatMismatch { name, .. } if name == "inputs.archive"));
+        assert!(matches!(&errors[4], PackageManifestError::ResourceEmptyUri(name) if name == "inputs.mirrored"));
+        assert!(matches!(&errors[5], PackageManifestError::ResourceDuplicateName(name) if name == "inputs.Module"));
+        assert!(matches!(&errors[6], PackageManifestError::ResourceFormatMismatch { name, .. } if name == "inputs.module"));
+
+        Ok(())
+    }
+}
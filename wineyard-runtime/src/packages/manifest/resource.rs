@@ -26,13 +26,27 @@ pub enum ResourceFormat {
 
 impl ResourceFormat {
     /// Predict resource format from the URI.
+    ///
+    /// Query string and fragment are ignored, so signed URLs like
+    /// `https://example.com/file.tar.gz?sig=...` are still recognized.
     pub fn from_uri(uri: impl AsRef<str>) -> Self {
-        let uri = uri.as_ref()
-            .replace('\\', "/")
-            .replace("//", "/");
+        let uri = uri.as_ref();
 
-        let (_, file_name) = uri.rsplit_once('/')
-            .unwrap_or((&uri, "index.html"));
+        let uri = uri.split_once('#')
+            .map(|(uri, _)| uri)
+            .unwrap_or(uri);
+
+        let uri = uri.split_once('?')
+            .map(|(uri, _)| uri)
+            .unwrap_or(uri);
+
+        let uri = uri.replace('\\', "/")
+            .replace("//", "/")
+            .to_ascii_lowercase();
+
+        let file_name = uri.rsplit_once('/')
+            .map(|(_, file_name)| file_name)
+            .unwrap_or(&uri);
 
         if let Some(format) = ArchiveFormat::from_path(file_name) {
             Self::Archive(ResourceArchiveFormat::from(format))
@@ -190,3 +204,44 @@ impl AsHash for ResourceArchiveFormat {
         Hash::for_slice(self.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_from_uri() {
+        let tests = [
+            ("https://example.com/archive.tar.gz", ResourceFormat::Archive(ResourceArchiveFormat::Tar)),
+            ("https://example.com/archive.tar.gz?sig=abc&exp=123", ResourceFormat::Archive(ResourceArchiveFormat::Tar)),
+            ("https://example.com/archive.TAR.ZST#files", ResourceFormat::Archive(ResourceArchiveFormat::Tar)),
+            ("https://example.com/archive.zip?token=a/b.lua", ResourceFormat::Archive(ResourceArchiveFormat::Zip)),
+            ("https://example.com/archive.7z.001#part", ResourceFormat::Archive(ResourceArchiveFormat::Sevenz)),
+            ("https://example.com/module.luau?v=2", ResourceFormat::Module(ResourceModuleFormat::Luau)),
+            ("modules\\module.lua", ResourceFormat::Module(ResourceModuleFormat::Luau)),
+            ("module.lua", ResourceFormat::Module(ResourceModuleFormat::Luau)),
+            ("https://example.com/download?file=archive.tar.gz", ResourceFormat::File),
+            ("https://example.com/", ResourceFormat::File)
+        ];
+
+        for (uri, format) in tests {
+            assert_eq!(ResourceFormat::from_uri(uri), format, "{uri}");
+        }
+    }
+
+    #[test]
+    fn module_format() -> Result<(), PackageManifestError> {
+        for format in [ResourceModuleFormat::Auto, ResourceModuleFormat::Luau] {
+            let format = ResourceFormat::Module(format);
+
+            assert_eq!(ResourceFormat::from_str(&format.to_string())?, format);
+        }
+
+        // Luau doesn't verify bytecode so it can't be loaded from remote
+        // resources.
+        assert!(ResourceFormat::from_str("module/luau-bytecode").is_err());
+        assert!(ResourceFormat::from_str("module/luauc").is_err());
+
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 pub mod manifest;
 pub mod lock_file;
+pub mod graph;
 pub mod store;
+pub mod authority;
 
 #[cfg(feature = "packages-resolver")]
 pub mod resolver;
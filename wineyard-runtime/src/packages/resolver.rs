@@ -1,22 +1,29 @@
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
 
 use wineyard_core::network::downloader::{
     Downloader, DownloadOptions, DownloaderError
 };
+use wineyard_core::network::proxy::ProxyError;
 use wineyard_core::archives::{Archive, ArchiveFormat, ArchiveError};
+use wineyard_core::tasks::{self, JoinHandle};
+use wineyard_core::tasks::sync::{Semaphore, OwnedSemaphorePermit};
 
 use toml::Table as TomlTable;
 
 use crate::hash::Hash;
 
 use super::manifest::{
-    PackageManifest, PackageManifestError, ResourceFormat, ResourceArchiveFormat
+    PackageManifest, PackageManifestError, ResourceInfo, ResourceFormat, ResourceArchiveFormat
 };
 use super::lock_file::{
     LockFile, LockFileInfo, ResourceLock, ResourceLockData, LockFileError
 };
-use super::store::ResourceStore;
+use super::store::{ResourceStore, ResourceStoreError, entry_size};
+use super::authority::{AuthorityValidator, AuthorityValidatorError};
 
 #[derive(Debug, thiserror::Error)]
 pub enum PackagesResolverError {
@@ -26,6 +33,9 @@ pub enum PackagesResolverError {
     #[error(transparent)]
     DownloaderError(#[from] DownloaderError),
 
+    #[error(transparent)]
+    ProxyError(#[from] ProxyError),
+
     #[error("archive format is not supported: {0:?}")]
     ArchiveNotSupported(PathBuf),
 
@@ -41,17 +51,275 @@ pub enum PackagesResolverError {
     #[error(transparent)]
     Serialize(#[from] toml::de::Error),
 
+    #[error(transparent)]
+    ResourceStoreError(#[from] ResourceStoreError),
+
     #[error("resource with hash {current} was expected to have hash {expected}")]
     HashMismatch {
         current: String,
         expected: String
+    },
+
+    #[error(transparent)]
+    AuthorityValidatorError(#[from] AuthorityValidatorError),
+
+    #[error("resource signature is not trusted: {url}")]
+    UntrustedResource {
+        url: String
+    },
+
+    #[error("resource is not signed: {url}")]
+    UnsignedResource {
+        url: String
+    },
+
+    #[error("resource content doesn't match its format '{format}': {url}")]
+    InvalidContent {
+        url: String,
+        format: ResourceFormat
+    },
+
+    #[error("resource can't be downloaded in offline mode: {0}")]
+    Offline(String),
+
+    #[error("packages resolution was cancelled")]
+    Cancelled,
+
+    #[error("resource exceeded {limit} bytes size limit: {url}")]
+    ResourceTooLarge {
+        url: String,
+        limit: u64
+    },
+
+    #[error("circular package dependency: {}", path.join(" -> "))]
+    CircularDependency {
+        path: Vec<String>
+    }
+}
+
+/// Progress of the packages resolution.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ResolverProgress {
+    /// Resource is being downloaded.
+    Download {
+        url: String,
+        current: u64,
+        total: u64
+    },
+
+    /// Resource is resolved and added to the lock file.
+    ///
+    /// `total` is the amount of resources discovered so far. It grows when
+    /// resolver finds new dependencies, so it's only final when `resolved`
+    /// is equal to it after the resolution is finished.
+    Resolved {
+        url: String,
+        resolved: usize,
+        total: usize
+    }
+}
+
+type ProgressCallback = Arc<dyn Fn(ResolverProgress) + Send + Sync>;
+
+/// Resource discovered by the resolver's dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlannedResource {
+    pub url: String,
+    pub format: ResourceFormat,
+
+    /// Hash of the resource. For packages it's the hash of the downloaded
+    /// manifest, for other resources it's the hash declared in the manifest.
+    pub hash: Option<Hash>,
+
+    /// Size of the resource in bytes. Manifests don't declare sizes of the
+    /// resources so it's only known for packages and cached resources.
+    pub size: Option<u64>,
+
+    /// Whether the resource is already available in the store and will not
+    /// be downloaded by the resolver.
+    pub cached: bool
+}
+
+/// Result of the packages resolver's dry run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResolvePlan {
+    /// List of all the discovered packages and resources in order they
+    /// were discovered.
+    pub resources: Vec<PlannedResource>
+}
+
+impl ResolvePlan {
+    /// Get resources which will be downloaded by the resolver.
+    #[inline]
+    pub fn to_fetch(&self) -> impl Iterator<Item = &PlannedResource> {
+        self.resources.iter()
+            .filter(|resource| !resource.cached)
+    }
+
+    /// Get resources which are already available in the store.
+    #[inline]
+    pub fn cached(&self) -> impl Iterator<Item = &PlannedResource> {
+        self.resources.iter()
+            .filter(|resource| resource.cached)
+    }
+
+    /// Get total size of the known resources which will be downloaded.
+    #[inline]
+    pub fn known_fetch_size(&self) -> u64 {
+        self.to_fetch()
+            .filter_map(|resource| resource.size)
+            .sum()
+    }
+}
+
+/// How often download tasks check if the resolution was cancelled
+/// or exceeded the size limits.
+const CANCELLATION_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Check that the downloaded resource's content looks like its format.
+///
+/// This is not a full validation and is only meant to catch obviously wrong
+/// files like HTML error pages served instead of the actual resources.
+fn is_valid_content(path: &Path, format: &ResourceFormat) -> std::io::Result<bool> {
+    use std::io::Read;
+
+    let mut header = Vec::with_capacity(ArchiveFormat::MAGIC_LEN);
+
+    std::fs::File::open(path)?
+        .take(ArchiveFormat::MAGIC_LEN as u64)
+        .read_to_end(&mut header)?;
+
+    let archive_format = ArchiveFormat::from_magic(&header);
+
+    let is_valid = match format {
+        // Split zip archives are extracted by 7z as well.
+        ResourceFormat::Archive(ResourceArchiveFormat::Sevenz) => matches!(archive_format, Some(ArchiveFormat::Sevenz | ArchiveFormat::Zip)),
+        ResourceFormat::Archive(ResourceArchiveFormat::Zip) => archive_format == Some(ArchiveFormat::Zip),
+        ResourceFormat::Archive(ResourceArchiveFormat::Tar) => archive_format == Some(ArchiveFormat::Tar),
+        ResourceFormat::Archive(ResourceArchiveFormat::Auto) => archive_format.is_some(),
+
+        ResourceFormat::Module(_) => {
+            let start = header.iter()
+                .position(|byte| !byte.is_ascii_whitespace())
+                .unwrap_or(header.len());
+
+            let start = header[start..].iter()
+                .take(14)
+                .map(u8::to_ascii_lowercase)
+                .collect::<Vec<_>>();
+
+            !start.starts_with(b"<!doctype html") && !start.starts_with(b"<html")
+        }
+
+        ResourceFormat::File |
+        ResourceFormat::Package => true
+    };
+
+    Ok(is_valid)
+}
+
+/// Get normalized URL of the package's manifest, appending "package.json"
+/// to the end of the URL if it's missing.
+fn package_manifest_url(mut url: String) -> String {
+    if !url.ends_with("/package.json") {
+        url += "/package.json";
     }
+
+    normalize_url(url)
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+/// Get normalized URL of the package's resource. Relative URIs are resolved
+/// from the package's root folder.
+fn package_resource_url(package_url: &str, uri: &str) -> String {
+    if uri.starts_with("http") {
+        return normalize_url(uri);
+    }
+
+    let root_url = package_url
+        .strip_suffix("package.json")
+        .unwrap_or(package_url);
+
+    normalize_url(format!("{root_url}/{uri}"))
+}
+
+/// Normalize given URL.
+fn normalize_url(url: impl AsRef<str>) -> String {
+    let (scheme, url) = url.as_ref()
+        .split_once("://")
+        .map(|(scheme, url)| (Some(scheme), url))
+        .unwrap_or((None, url.as_ref()));
+
+    let url = url
+        .replace('\\', "/")
+        .replace("/./", "/")
+        .replace("//", "/");
+
+    let url = url.split('/')
+        .collect::<Vec<_>>();
+
+    let mut clean_parts = Vec::with_capacity(url.len());
+
+    let mut i = 0;
+    let n = url.len() - 1;
+
+    while i < n {
+        if url[i + 1] == ".." {
+            i += 2;
+
+            continue;
+        }
+
+        clean_parts.push(url[i]);
+
+        i += 1;
+    }
+
+    clean_parts.push(url[n]);
+
+    let url = clean_parts.join("/");
+
+    if let Some(scheme) = scheme {
+        format!("{scheme}://{url}")
+    } else {
+        url
+    }
+}
+
+/// Default maximal amount of simultaneous downloads.
+pub const DEFAULT_CONCURRENCY: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PackagesResolver {
     /// URLs to the root packages for the lock file.
-    root_packages: HashSet<String>
+    root_packages: HashSet<String>,
+
+    /// Maximal amount of simultaneous downloads.
+    concurrency: usize,
+
+    /// Fail instead of downloading anything.
+    offline: bool,
+
+    /// Validator of the resources signatures.
+    validator: Option<AuthorityValidator>,
+
+    /// Reject resources without signatures if validator is set.
+    strict_signatures: bool,
+
+    /// URL of the proxy used to download resources.
+    proxy: Option<String>,
+
+    /// Maximal size of a single downloaded resource in bytes.
+    max_resource_size: Option<u64>,
+
+    /// Maximal amount of bytes downloaded during the resolution.
+    max_total_size: Option<u64>
+}
+
+impl Default for PackagesResolver {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PackagesResolver {
@@ -59,7 +327,14 @@ impl PackagesResolver {
     #[inline]
     pub fn new() -> Self {
         Self {
-            root_packages: HashSet::new()
+            root_packages: HashSet::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+            offline: false,
+            validator: None,
+            strict_signatures: true,
+            proxy: None,
+            max_resource_size: None,
+            max_total_size: None
         }
     }
 
@@ -70,10 +345,40 @@ impl PackagesResolver {
             .map(|package| package.to_string());
 
         Self {
-            root_packages: HashSet::from_iter(packages)
+            root_packages: HashSet::from_iter(packages),
+            concurrency: DEFAULT_CONCURRENCY,
+            offline: false,
+            validator: None,
+            strict_signatures: true,
+            proxy: None,
+            max_resource_size: None,
+            max_total_size: None
         }
     }
 
+    /// Change maximal amount of simultaneous downloads.
+    ///
+    /// Downloads are started in the same order they were queued.
+    /// Zero value is treated as one.
+    #[inline]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+
+        self
+    }
+
+    /// Change offline mode of the resolver.
+    ///
+    /// In offline mode resolver will use resources already available in the
+    /// store and fail if something must be downloaded. Packages can only be
+    /// reused from the old lock file passed to the `update` method.
+    #[inline]
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+
+        self
+    }
+
     /// Add root package URL.
     #[inline]
     pub fn add_package(&mut self, url: impl ToString) -> &mut Self {
@@ -82,6 +387,66 @@ impl PackagesResolver {
         self
     }
 
+    /// Verify signatures of the resources using given validator.
+    ///
+    /// Resources with signatures which are not made by any of the validator's
+    /// trusted keys will be rejected. Resources without signatures are
+    /// rejected as well unless `with_strict_signatures` is disabled. Root
+    /// packages are accepted as is.
+    #[inline]
+    pub fn with_validator(mut self, validator: AuthorityValidator) -> Self {
+        self.validator = Some(validator);
+
+        self
+    }
+
+    /// Change whether resources without signatures are rejected when
+    /// validator is set.
+    ///
+    /// Enabled by default. Otherwise anyone who can modify a package
+    /// manifest could remove signatures of its resources to skip their
+    /// validation.
+    #[inline]
+    pub fn with_strict_signatures(mut self, strict_signatures: bool) -> Self {
+        self.strict_signatures = strict_signatures;
+
+        self
+    }
+
+    /// Download resources through the given proxy.
+    ///
+    /// Hosts listed in the `NO_PROXY` env variable bypass the proxy. If not
+    /// set, proxy env variables are used.
+    #[inline]
+    pub fn with_proxy(mut self, proxy: impl ToString) -> Self {
+        self.proxy = Some(proxy.to_string());
+
+        self
+    }
+
+    /// Limit size of every downloaded resource. Archives are limited by
+    /// their compressed size.
+    ///
+    /// Downloads are aborted as soon as the server reports bigger size
+    /// or more bytes are received.
+    #[inline]
+    pub fn with_max_resource_size(mut self, size: u64) -> Self {
+        self.max_resource_size = Some(size);
+
+        self
+    }
+
+    /// Limit amount of bytes downloaded during the resolution.
+    ///
+    /// Bytes of partially downloaded resources which are continued are
+    /// counted as well.
+    #[inline]
+    pub fn with_max_total_size(mut self, size: u64) -> Self {
+        self.max_total_size = Some(size);
+
+        self
+    }
+
     /// Build lock file with provided root packages URLs and a packages store.
     ///
     /// This method will download all the packages to a temporary directory,
@@ -90,10 +455,275 @@ impl PackagesResolver {
     ///
     /// Note: Since this function will download (potentially) many files and
     /// archives you should run it in a separate thread.
+    #[inline]
     pub async fn build(&self, store: &ResourceStore) -> Result<LockFile, PackagesResolverError> {
+        self.resolve(store, None, None, None).await
+    }
+
+    /// Works the same way as `build` but reports the resolution progress
+    /// using the given callback.
+    #[inline]
+    pub async fn build_with_progress(
+        &self,
+        store: &ResourceStore,
+        on_progress: impl Fn(ResolverProgress) + Send + Sync + 'static
+    ) -> Result<LockFile, PackagesResolverError> {
+        self.resolve(store, None, Some(Arc::new(on_progress)), None).await
+    }
+
+    /// Works the same way as `build` but stops the resolution when the given
+    /// flag is set, returning `PackagesResolverError::Cancelled`.
+    ///
+    /// In-flight downloads are aborted. Partially downloaded resources are
+    /// kept in the store's temp files and continued by the next resolution.
+    #[inline]
+    pub async fn build_cancellable(
+        &self,
+        store: &ResourceStore,
+        cancelled: Arc<AtomicBool>
+    ) -> Result<LockFile, PackagesResolverError> {
+        self.resolve(store, None, None, Some(cancelled)).await
+    }
+
+    /// Update the given lock file using provided root packages URLs and
+    /// a packages store.
+    ///
+    /// Works the same way as `build` but reuses resources from the old lock
+    /// file which are still available in the store instead of downloading
+    /// them again. Packages are always downloaded because their dependencies
+    /// could be updated upstream, unless the resolver is in offline mode.
+    ///
+    /// Use `LockFile::diff` to get the list of updated resources.
+    #[inline]
+    pub async fn update(&self, store: &ResourceStore, old: &LockFile) -> Result<LockFile, PackagesResolverError> {
+        self.resolve(store, Some(old), None, None).await
+    }
+
+    /// Works the same way as `update` but reports the resolution progress
+    /// using the given callback.
+    #[inline]
+    pub async fn update_with_progress(
+        &self,
+        store: &ResourceStore,
+        old: &LockFile,
+        on_progress: impl Fn(ResolverProgress) + Send + Sync + 'static
+    ) -> Result<LockFile, PackagesResolverError> {
+        self.resolve(store, Some(old), Some(Arc::new(on_progress)), None).await
+    }
+
+    /// Walk the packages manifests without downloading their resources and
+    /// return the list of resources which would be fetched by `build`.
+    ///
+    /// Only packages' manifests are downloaded. They are not saved to the
+    /// store. Resources without declared hashes can't be matched with the
+    /// store so they're always reported as not cached.
+    pub async fn plan(&self, store: &ResourceStore) -> Result<ResolvePlan, PackagesResolverError> {
         let mut packages = self.root_packages.iter()
             .cloned()
-            .map(|url| (url, Hash::rand(), true))
+            .map(|url| (url, None))
+            .collect::<Vec<_>>();
+
+        let mut plan = ResolvePlan::default();
+        let mut requested_urls = HashSet::new();
+
+        // Packages can be resolved from local paths.
+        let downloader = match &self.proxy {
+            Some(proxy) => Downloader::with_proxy(proxy)?,
+            None => Downloader::new()
+        }.with_local_files(true);
+
+        let semaphore = Arc::new(Semaphore::new(self.concurrency.max(1)));
+
+        while !packages.is_empty() {
+            let mut packages_download_tasks = Vec::with_capacity(packages.len());
+
+            for (package_url, signature) in packages.drain(..) {
+                let package_url = package_manifest_url(package_url);
+
+                if !requested_urls.insert((package_url.clone(), ResourceFormat::Package)) {
+                    continue;
+                }
+
+                if self.offline {
+                    return Err(PackagesResolverError::Offline(package_url));
+                }
+
+                let temp_path = store.get_temp_path(&Hash::rand());
+
+                // Acquire the permit before spawning the task so downloads
+                // are started in the queue order. Semaphore is never closed
+                // so this can't fail.
+                let permit = semaphore.clone().acquire_owned().await.ok();
+
+                let task = {
+                    let downloader = downloader.clone();
+
+                    let url = package_url.clone();
+                    let path = temp_path.clone();
+
+                    tasks::spawn(async move {
+                        let _permit = permit;
+
+                        downloader.download(url, path).wait().await
+                    })
+                };
+
+                packages_download_tasks.push((temp_path, package_url, signature, task));
+            }
+
+            for (temp_path, package_url, signature, task) in packages_download_tasks {
+                task.await.map_err(DownloaderError::from)??;
+
+                let manifest = self.read_manifest(&temp_path, &package_url, signature.as_deref());
+
+                std::fs::remove_file(&temp_path)?;
+
+                let (manifest, manifest_hash, manifest_size) = manifest?;
+
+                plan.resources.push(PlannedResource {
+                    url: package_url.clone(),
+                    format: ResourceFormat::Package,
+                    hash: Some(manifest_hash),
+                    size: Some(manifest_size),
+                    cached: false
+                });
+
+                for resource in manifest.inputs.into_values().chain(manifest.outputs.into_values()) {
+                    let resource_url = self.resource_url(&package_url, &resource)?;
+
+                    if resource.format == ResourceFormat::Package {
+                        packages.push((resource_url, resource.signature));
+
+                        continue;
+                    }
+
+                    if !requested_urls.insert((resource_url.clone(), resource.format)) {
+                        continue;
+                    }
+
+                    let cached_hash = self.installed_hash(store, &resource, None);
+
+                    let size = match cached_hash {
+                        Some(hash) => Some(entry_size(store.get_path(&hash))?),
+                        None => None
+                    };
+
+                    plan.resources.push(PlannedResource {
+                        url: resource_url,
+                        format: resource.format,
+                        hash: resource.hash,
+                        size,
+                        cached: cached_hash.is_some()
+                    });
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// Verify signature of the downloaded package's manifest and parse it.
+    /// Return the manifest, its hash and size.
+    fn read_manifest(
+        &self,
+        path: &Path,
+        url: &str,
+        signature: Option<&[u8]>
+    ) -> Result<(PackageManifest, Hash, u64), PackagesResolverError> {
+        self.verify_signature(path, url, signature)?;
+
+        let manifest_slice = std::fs::read_to_string(path)?;
+        let manifest_hash = Hash::for_slice(manifest_slice.as_bytes());
+
+        let manifest = toml::from_str::<TomlTable>(&manifest_slice)?;
+        let manifest = PackageManifest::try_from(&manifest)?;
+
+        Ok((manifest, manifest_hash, manifest_slice.len() as u64))
+    }
+
+    /// Get normalized URL of the package's resource, rejecting it if it's
+    /// not signed when signatures are required.
+    fn resource_url(
+        &self,
+        package_url: &str,
+        resource: &ResourceInfo
+    ) -> Result<String, PackagesResolverError> {
+        let resource_url = package_resource_url(package_url, &resource.uri);
+
+        self.check_signed(&resource_url, resource.signature.as_deref())?;
+
+        Ok(resource_url)
+    }
+
+    /// Get hash of the resource if it's already available in the store.
+    /// Expected hash from the manifest has higher priority than the given
+    /// fallback one. Signed resources are always downloaded to verify them.
+    fn installed_hash(
+        &self,
+        store: &ResourceStore,
+        resource: &ResourceInfo,
+        fallback: Option<Hash>
+    ) -> Option<Hash> {
+        resource.hash
+            .or(fallback)
+            .filter(|hash| store.has_resource(hash))
+            .filter(|_| self.validator.is_none() || resource.signature.is_none())
+    }
+
+    /// Reject the package's resource without signature if validator is set
+    /// in strict mode.
+    fn check_signed(&self, url: &str, signature: Option<&[u8]>) -> Result<(), PackagesResolverError> {
+        if self.validator.is_some() && self.strict_signatures && signature.is_none() {
+            return Err(PackagesResolverError::UnsignedResource {
+                url: url.to_string()
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Verify signature of the downloaded resource if validator is set.
+    fn verify_signature(
+        &self,
+        path: &Path,
+        url: &str,
+        signature: Option<&[u8]>
+    ) -> Result<(), PackagesResolverError> {
+        if let Some(validator) = &self.validator
+            && let Some(signature) = signature
+            && !validator.verify_file(path, signature)?
+        {
+            return Err(PackagesResolverError::UntrustedResource {
+                url: url.to_string()
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn resolve(
+        &self,
+        store: &ResourceStore,
+        old: Option<&LockFile>,
+        on_progress: Option<ProgressCallback>,
+        cancelled: Option<Arc<AtomicBool>>
+    ) -> Result<LockFile, PackagesResolverError> {
+        // Wait until other resolvers finish working with the store.
+        let _lock = loop {
+            if let Some(lock) = store.try_lock()? {
+                break lock;
+            }
+
+            if cancelled.as_ref().is_some_and(|cancelled| cancelled.load(Ordering::Acquire)) {
+                return Err(PackagesResolverError::Cancelled);
+            }
+
+            tasks::time::sleep(CANCELLATION_CHECK_INTERVAL).await;
+        };
+
+        let mut packages = self.root_packages.iter()
+            .cloned()
+            .map(|url| (url, Hash::rand(), None, true))
             .collect::<HashSet<_>>();
 
         // Lock file building logic.
@@ -108,67 +738,167 @@ impl PackagesResolver {
         let mut assigned_hashes = HashMap::new(); // temp_hash => unique_key
         let mut assign_references = Vec::new(); // temp_hash => index to assign
 
-        #[inline]
-        /// Normalize given URL.
-        fn normalize_url(url: impl AsRef<str>) -> String {
-            let (scheme, url) = url.as_ref()
-                .split_once("://")
-                .map(|(scheme, url)| (Some(scheme), url))
-                .unwrap_or((None, url.as_ref()));
+        // Resources from the old lock file which could be reused.
+        let old_resources = old.map(|old| {
+            old.resources.iter()
+                .map(|resource| ((resource.url.clone(), resource.format), resource.lock.hash))
+                .collect::<HashMap<_, _>>()
+        }).unwrap_or_default(); // unique_key => hash
+
+        // Report the last resolved resource.
+        let report_resolved = |lock_resources: &[ResourceLock], total: usize| {
+            if let Some(on_progress) = &on_progress
+                && let Some(resource) = lock_resources.last()
+            {
+                on_progress(ResolverProgress::Resolved {
+                    url: resource.url.clone(),
+                    resolved: lock_resources.len(),
+                    total
+                });
+            }
+        };
 
-            let url = url
-                .replace('\\', "/")
-                .replace("/./", "/")
-                .replace("//", "/");
+        // Prepare packages downloader.
+        // Packages can be resolved from local paths.
+        let downloader = match &self.proxy {
+            Some(proxy) => Downloader::with_proxy(proxy)?,
+            None => Downloader::new()
+        }.with_local_files(true);
+        let semaphore = Arc::new(Semaphore::new(self.concurrency.max(1)));
+
+        // Amount of bytes downloaded by all the tasks.
+        let downloaded_total = Arc::new(AtomicU64::new(0));
+
+        let max_resource_size = self.max_resource_size;
+        let max_total_size = self.max_total_size;
+        let is_limited = max_resource_size.is_some() || max_total_size.is_some();
+
+        let is_cancelled = |cancelled: &Option<Arc<AtomicBool>>| {
+            cancelled.as_ref()
+                .map(|cancelled| cancelled.load(Ordering::Acquire))
+                .unwrap_or_default()
+        };
+
+        // Start downloading of the file holding a permit of the semaphore.
+        // Permits are acquired before calling this function so downloads
+        // are started in the queue order.
+        //
+        // Partially downloaded file is continued if path to its entity
+        // tag file is given.
+        let download = |url: &str, path: &Path, etag_path: Option<PathBuf>, permit: Option<OwnedSemaphorePermit>| -> JoinHandle<Result<u64, PackagesResolverError>> {
+            let downloader = downloader.clone();
+            let cancelled = cancelled.clone();
+            let downloaded_total = downloaded_total.clone();
+
+            let url = url.to_string();
+            let path = path.to_path_buf();
+
+            // Exceeded size limit.
+            let exceeded = Arc::new(Mutex::new(None));
+
+            // Check the limits before writing every chunk so no bytes
+            // over the limits are stored.
+            let on_chunk = is_limited.then(|| {
+                let exceeded = exceeded.clone();
+
+                // Bytes of the file counted to the total limit.
+                let counted = AtomicU64::new(0);
+
+                Box::new(move |current: u64, total: u64, len: u64| {
+                    let current = current + len;
+                    let diff = current.saturating_sub(counted.fetch_max(current, Ordering::AcqRel));
+
+                    let downloaded = downloaded_total.fetch_add(diff, Ordering::AcqRel) + diff;
+
+                    let limit = max_resource_size
+                        .filter(|limit| total > *limit || current > *limit)
+                        .or_else(|| max_total_size.filter(|limit| downloaded > *limit));
+
+                    if let Some(limit) = limit
+                        && let Ok(mut exceeded) = exceeded.lock()
+                    {
+                        *exceeded = Some(limit);
+                    }
 
-            let url = url.split('/')
-                .collect::<Vec<_>>();
+                    limit.is_none()
+                }) as Box<dyn Fn(u64, u64, u64) -> bool + Send + Sync>
+            });
 
-            let mut clean_parts = Vec::with_capacity(url.len());
+            let on_update = on_progress.clone().map(|on_progress| {
+                let url = url.clone();
 
-            let mut i = 0;
-            let n = url.len() - 1;
+                Box::new(move |current, total, _| {
+                    on_progress(ResolverProgress::Download {
+                        url: url.clone(),
+                        current,
+                        total
+                    });
+                }) as Box<dyn Fn(u64, u64, u64) + Send + Sync>
+            });
 
-            while i < n {
-                if url[i + 1] == ".." {
-                    i += 2;
+            tasks::spawn(async move {
+                let _permit = permit;
 
-                    continue;
+                if is_cancelled(&cancelled) {
+                    return Err(PackagesResolverError::Cancelled);
                 }
 
-                clean_parts.push(url[i]);
+                let task = downloader.download_with_options(&url, &path, DownloadOptions {
+                    continue_download: etag_path.is_some(),
+                    etag_file: etag_path.clone(),
+                    on_update,
+                    on_chunk,
+                    on_finish: None
+                });
 
-                i += 1;
-            }
+                if cancelled.is_some() {
+                    while !task.is_finished() {
+                        if is_cancelled(&cancelled) {
+                            // Keep the received content so the next
+                            // resolution could continue downloading.
+                            let _ = task.stop().await;
 
-            clean_parts.push(url[n]);
+                            return Err(PackagesResolverError::Cancelled);
+                        }
 
-            let url = clean_parts.join("/");
+                        tasks::time::sleep(CANCELLATION_CHECK_INTERVAL).await;
+                    }
+                }
 
-            if let Some(scheme) = scheme {
-                format!("{scheme}://{url}")
-            } else {
-                url
-            }
-        }
+                match task.wait().await {
+                    Err(DownloaderError::ChunkRejected) => {
+                        // Don't continue downloading of the file.
+                        for path in std::iter::once(path).chain(etag_path) {
+                            let _ = std::fs::remove_file(path);
+                        }
 
-        // Prepare packages downloader.
-        let downloader = Downloader::new();
+                        let limit = exceeded.lock()
+                            .ok()
+                            .and_then(|exceeded| *exceeded)
+                            .unwrap_or_default();
+
+                        Err(PackagesResolverError::ResourceTooLarge {
+                            url,
+                            limit
+                        })
+                    }
+
+                    result => Ok(result?)
+                }
+            })
+        };
 
         // Keep downloading stuff while we have packages to process.
         while !packages.is_empty() {
+            if is_cancelled(&cancelled) {
+                return Err(PackagesResolverError::Cancelled);
+            }
+
             let mut packages_download_tasks = Vec::with_capacity(packages.len());
 
             // Go through the list of packages to process.
-            for (mut package_url, temp_hash, is_root) in packages.drain() {
-                // Append "package.json" to the end of the URL
-                // if it's missing.
-                if !package_url.ends_with("/package.json") {
-                    package_url += "/package.json";
-                }
-
-                // Normalize URL.
-                package_url = normalize_url(package_url);
+            for (package_url, temp_hash, signature, is_root) in packages.drain() {
+                let package_url = package_manifest_url(package_url);
 
                 let unique_key = (package_url.clone(), ResourceFormat::Package);
 
@@ -182,39 +912,48 @@ impl PackagesResolver {
                     continue;
                 }
 
-                // Prepare URL to the package's root folder.
-                let root_url = package_url
-                    .strip_suffix("package.json")
-                    .map(String::from)
-                    .unwrap_or_else(|| package_url.clone());
-
                 // Prepare tmp path to the package.
                 let temp_path = store.get_temp_path(&temp_hash);
 
-                // Start downloading the package.
-                let task = downloader.download_with_options(&package_url, &temp_path, DownloadOptions {
-                    continue_download: false,
-                    on_update: None,
-                    on_finish: None
-                });
+                // Start downloading the package. In offline mode copy
+                // it from the store if it was in the old lock file.
+                let task = if self.offline {
+                    let hash = old_resources.get(&unique_key)
+                        .filter(|hash| store.has_resource(hash));
+
+                    let Some(hash) = hash else {
+                        return Err(PackagesResolverError::Offline(package_url));
+                    };
+
+                    std::fs::copy(store.get_path(hash), &temp_path)?;
+
+                    None
+                } else {
+                    // Semaphore is never closed so this can't fail.
+                    let permit = semaphore.clone().acquire_owned().await.ok();
+
+                    Some(download(&package_url, &temp_path, None, permit))
+                };
 
                 requested_urls.insert(unique_key.clone());
-                packages_download_tasks.push((temp_path, package_url, root_url, unique_key, task, is_root));
+                packages_download_tasks.push((temp_path, package_url, unique_key, task, signature, is_root));
             }
 
             let mut resources = Vec::new();
 
             // Go through the list of queued packages.
-            for (temp_path, package_url, root_url, unique_key, context, is_root) in packages_download_tasks.drain(..) {
+            for (temp_path, package_url, unique_key, task, signature, is_root) in packages_download_tasks.drain(..) {
                 // Await package downloading.
-                context.wait().await?;
+                if let Some(task) = task {
+                    task.await.map_err(DownloaderError::from)??;
+                }
 
-                // Read the package's manifest and hash it.
-                let manifest_slice = std::fs::read_to_string(&temp_path)?;
-                let manifest_hash = Hash::for_slice(manifest_slice.as_bytes());
+                if is_cancelled(&cancelled) {
+                    return Err(PackagesResolverError::Cancelled);
+                }
 
-                let manifest = toml::from_str::<TomlTable>(&manifest_slice)?;
-                let manifest = PackageManifest::try_from(&manifest)?;
+                // Verify the package's signature, read its manifest and hash it.
+                let (manifest, manifest_hash, manifest_size) = self.read_manifest(&temp_path, &package_url, signature.as_deref())?;
 
                 // Update the lock file info.
                 let lock_resource_index = lock_resources.len();
@@ -223,16 +962,18 @@ impl PackagesResolver {
                 resources_indexes.insert(unique_key, lock_resource_index);
 
                 lock_resources.push(ResourceLock {
-                    url: package_url,
+                    url: package_url.clone(),
                     format: ResourceFormat::Package,
                     lock: ResourceLockData {
                         hash: manifest_hash,
-                        size: manifest_slice.len() as u64
+                        size: manifest_size
                     },
                     inputs: Some(HashMap::with_capacity(manifest.inputs.len())),
                     outputs: Some(HashMap::with_capacity(manifest.outputs.len()))
                 });
 
+                report_resolved(&lock_resources, requested_urls.len());
+
                 if is_root {
                     lock_root.insert(lock_resource_index as u32);
                 }
@@ -242,7 +983,7 @@ impl PackagesResolver {
                     let temp_hash = Hash::rand();
 
                     assign_references.push((temp_hash, name, lock_resource_index, true));
-                    resources.push((temp_hash, root_url.clone(), resource));
+                    resources.push((temp_hash, package_url.clone(), resource));
                 }
 
                 // Process outputs.
@@ -250,7 +991,7 @@ impl PackagesResolver {
                     let temp_hash = Hash::rand();
 
                     assign_references.push((temp_hash, name, lock_resource_index, false));
-                    resources.push((temp_hash, root_url.clone(), resource));
+                    resources.push((temp_hash, package_url.clone(), resource));
                 }
 
                 // Move the manifest from the temp location to the correct one.
@@ -260,27 +1001,11 @@ impl PackagesResolver {
             }
 
             let mut resources_download_tasks = Vec::with_capacity(resources.len());
+            let mut resources_downloads = Vec::with_capacity(resources.len());
 
             // Go through the list of packages' resources to process.
-            for (temp_hash, root_url, resource) in resources.drain(..) {
-                // Skip resource processing if it's already installed.
-                if let Some(hash) = resource.hash {
-                    // Do not check for packages because their dependencies
-                    // could be updated upstream.
-                    if store.has_resource(&hash) {
-                        continue;
-                    }
-                }
-
-                // Prepare URL to the resource.
-                let mut resource_url = if resource.uri.starts_with("http") {
-                    resource.uri.clone()
-                } else {
-                    format!("{root_url}/{}", resource.uri)
-                };
-
-                // Normalize URL.
-                resource_url = normalize_url(resource_url);
+            for (temp_hash, package_url, resource) in resources.drain(..) {
+                let resource_url = self.resource_url(&package_url, &resource)?;
 
                 let unique_key = (resource_url.clone(), resource.format);
 
@@ -298,50 +1023,109 @@ impl PackagesResolver {
                 // processing. Otherwise process the resource by downloading and
                 // extracting it.
                 if resource.format == ResourceFormat::Package {
-                    packages.insert((resource_url, temp_hash, false));
+                    packages.insert((resource_url, temp_hash, resource.signature.clone(), false));
 
                     continue;
                 }
 
-                // Prepare temp path to the resource.
-                let temp_path = store.get_temp_path(&temp_hash);
+                // Skip resource downloading if it's already installed.
+                let installed_hash = self.installed_hash(store, &resource, old_resources.get(&unique_key).copied());
 
-                // Start resource downloading.
-                let task = downloader.download_with_options(&resource_url, &temp_path, DownloadOptions {
-                    continue_download: false,
-                    on_update: None,
-                    on_finish: None
-                });
+                if let Some(hash) = installed_hash {
+                    let lock_resource_index = lock_resources.len();
+
+                    resources_indexes.insert(unique_key.clone(), lock_resource_index);
+                    requested_urls.insert(unique_key);
+
+                    lock_resources.push(ResourceLock {
+                        url: resource_url,
+                        format: resource.format,
+                        lock: ResourceLockData {
+                            hash,
+                            size: entry_size(store.get_path(&hash))?
+                        },
+                        inputs: None,
+                        outputs: None
+                    });
+
+                    report_resolved(&lock_resources, requested_urls.len());
+
+                    continue;
+                }
+
+                if self.offline {
+                    return Err(PackagesResolverError::Offline(resource_url));
+                }
+
+                // Prepare temp path to the resource. It depends on the URL
+                // so partially downloaded resources could be continued.
+                let temp_path = store.get_partial_path(&resource_url);
+
+                // Start resource downloading. Semaphore is never closed
+                // so this can't fail.
+                let permit = semaphore.clone().acquire_owned().await.ok();
+
+                let task = download(&resource_url, &temp_path, Some(store.get_partial_etag_path(&resource_url)), permit);
 
                 requested_urls.insert(unique_key.clone());
-                resources_download_tasks.push((temp_path, resource_url, unique_key, resource, task));
+                resources_download_tasks.push(task);
+                resources_downloads.push((temp_path, resource_url, unique_key, resource));
             }
 
-            // Go through the list of queued resources.
-            for (temp_path, resource_url, unique_key, resource, context) in resources_download_tasks.drain(..) {
-                // Await resource downloading.
-                context.wait().await?;
+            // Await all the resources downloading.
+            let results = tasks::join_all(resources_download_tasks).await;
+
+            // Go through the list of downloaded resources.
+            for ((temp_path, resource_url, unique_key, resource), result) in resources_downloads.into_iter().zip(results) {
+                result.map_err(DownloaderError::from)??;
+
+                if is_cancelled(&cancelled) {
+                    return Err(PackagesResolverError::Cancelled);
+                }
+
+                // Verify the resource's signature.
+                if let Err(err) = self.verify_signature(&temp_path, &resource_url, resource.signature.as_deref()) {
+                    // Don't continue downloading of the broken file.
+                    store.remove_partial(&resource_url)?;
+
+                    return Err(err);
+                }
+
+                // Verify that the resource is not obviously broken.
+                if !is_valid_content(&temp_path, &resource.format)? {
+                    store.remove_partial(&resource_url)?;
+
+                    return Err(PackagesResolverError::InvalidContent {
+                        url: resource_url,
+                        format: resource.format
+                    });
+                }
 
                 match resource.format {
                     ResourceFormat::Package => unreachable!("package must have been queued to be processed in a different place"),
 
                     ResourceFormat::Module(_) |
                     ResourceFormat::File => {
-                        // Move downloaded file to the correct location.
                         let hash = Hash::for_entry(&temp_path)?;
+
+                        // Verify hashes match before promoting the file.
+                        if let Some(expected_hash) = resource.hash
+                            && expected_hash != hash
+                        {
+                            store.remove_partial(&resource_url)?;
+
+                            return Err(PackagesResolverError::HashMismatch {
+                                current: hash.to_base32(),
+                                expected: expected_hash.to_base32()
+                            });
+                        }
+
+                        // Move downloaded file to the correct location.
                         let src_path = store.get_path(&hash);
 
                         std::fs::rename(temp_path, &src_path)?;
 
-                        // Verify hashes match.
-                        if let Some(expected_hash) = resource.hash {
-                            if expected_hash != hash {
-                                return Err(PackagesResolverError::HashMismatch {
-                                    current: hash.to_base32(),
-                                    expected: expected_hash.to_base32()
-                                });
-                            }
-                        }
+                        store.remove_partial(&resource_url)?;
 
                         // Update the lock file info.
                         let lock_resource_index = lock_resources.len();
@@ -360,6 +1144,8 @@ impl PackagesResolver {
                             inputs: None,
                             outputs: None
                         });
+
+                        report_resolved(&lock_resources, requested_urls.len());
                     }
 
                     ResourceFormat::Archive(_) => {
@@ -386,9 +1172,24 @@ impl PackagesResolver {
                             .extract(&temp_extract_path)?
                             .wait()?;
 
+                        let hash = Hash::for_entry(&temp_extract_path)?;
+
+                        // Verify hashes match before promoting the files.
+                        if let Some(expected_hash) = resource.hash
+                            && expected_hash != hash
+                        {
+                            std::fs::remove_dir_all(&temp_extract_path)?;
+
+                            store.remove_partial(&resource_url)?;
+
+                            return Err(PackagesResolverError::HashMismatch {
+                                current: hash.to_base32(),
+                                expected: expected_hash.to_base32()
+                            });
+                        }
+
                         // Move extracted files to the correct location
                         // and delete downloaded archive.
-                        let hash = Hash::for_entry(&temp_extract_path)?;
                         let src_path = store.get_path(&hash);
 
                         if src_path.exists() {
@@ -396,17 +1197,8 @@ impl PackagesResolver {
                         }
 
                         std::fs::rename(temp_extract_path, &src_path)?;
-                        std::fs::remove_file(temp_path)?;
-
-                        // Verify hashes match.
-                        if let Some(expected_hash) = resource.hash {
-                            if expected_hash != hash {
-                                return Err(PackagesResolverError::HashMismatch {
-                                    current: hash.to_base32(),
-                                    expected: expected_hash.to_base32()
-                                });
-                            }
-                        }
+
+                        store.remove_partial(&resource_url)?;
 
                         // Update the lock file info.
                         let lock_resource_index = lock_resources.len();
@@ -420,13 +1212,13 @@ impl PackagesResolver {
                             format: resource.format,
                             lock: ResourceLockData {
                                 hash,
-
-                                // FIXME: incorrect! sum lengths of all the files.
-                                size: src_path.metadata()?.len()
+                                size: entry_size(&src_path)?
                             },
                             inputs: None,
                             outputs: None
                         });
+
+                        report_resolved(&lock_resources, requested_urls.len());
                     }
                 }
             }
@@ -450,11 +1242,568 @@ impl PackagesResolver {
             }
         }
 
-        Ok(LockFile {
+        let mut lock_file = LockFile {
             lock: LockFileInfo {
                 root: lock_root.drain().collect()
             },
             resources: lock_resources
-        })
+        };
+
+        // Verify that packages don't reference each other in a loop.
+        if let Some(path) = lock_file.graph().find_cycle() {
+            return Err(PackagesResolverError::CircularDependency {
+                path: path.into_iter().map(|node| node.url.clone()).collect()
+            });
+        }
+
+        // The same content can be served from different URLs.
+        lock_file.dedup();
+
+        // Resources are processed in a random order, so sort them to keep
+        // the lock file reproducible.
+        lock_file.sort();
+
+        Ok(lock_file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packages::manifest::ResourceModuleFormat;
+
+    use super::*;
+
+    #[test]
+    fn build_progress() -> Result<(), PackagesResolverError> {
+        use std::sync::Mutex;
+
+        let path = std::env::temp_dir().join(".wineyard-runtime-build-progress-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let store = ResourceStore::new(&path);
+
+        let module_hash = Hash::for_slice(b"module");
+        let manifest = format!("[package]\nformat = 1\n\n[inputs.module]\nuri = \"module.lua\"\nhash = \"{module_hash}\"");
+        let manifest_hash = Hash::for_slice(manifest.as_bytes());
+
+        std::fs::write(store.get_path(&module_hash), "return {}")?;
+        std::fs::write(store.get_path(&manifest_hash), &manifest)?;
+
+        // Offline resolver reuses the package from the old lock file.
+        let old = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![ResourceLock {
+                url: String::from("https://example.com/package.json"),
+                format: ResourceFormat::Package,
+                lock: ResourceLockData {
+                    hash: manifest_hash,
+                    size: manifest.len() as u64
+                },
+                inputs: None,
+                outputs: None
+            }]
+        };
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+
+        let resolver = PackagesResolver::with_packages(["https://example.com"])
+            .with_offline(true);
+
+        let lock_file = tasks::block_on({
+            let progress = progress.clone();
+
+            resolver.update_with_progress(&store, &old, move |event| {
+                progress.lock().unwrap().push(event);
+            })
+        })?;
+
+        assert_eq!(lock_file.resources.len(), 2);
+
+        assert_eq!(*progress.lock().unwrap(), [
+            ResolverProgress::Resolved {
+                url: String::from("https://example.com/package.json"),
+                resolved: 1,
+                total: 1
+            },
+            ResolverProgress::Resolved {
+                url: String::from("https://example.com/module.lua"),
+                resolved: 2,
+                total: 2
+            }
+        ]);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_local_mirror() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-build-local-mirror-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("mirror"))?;
+        std::fs::create_dir_all(path.join("store"))?;
+
+        std::fs::write(path.join("mirror/package.json"), "[package]\nformat = 1\n\n[inputs]\nmodule = \"module.lua\"")?;
+        std::fs::write(path.join("mirror/module.lua"), "return {}")?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let resolver = PackagesResolver::with_packages([
+            format!("file://{}", path.join("mirror").to_string_lossy())
+        ]);
+
+        let lock_file = tasks::block_on(resolver.build(&store))?;
+
+        assert_eq!(lock_file.resources.len(), 2);
+        assert!(store.missing_resources(&lock_file).is_empty());
+        assert!(store.has_resource(&Hash::for_slice(b"return {}")));
+
+        // Unsigned resources are rejected when validator is set.
+        let resolver = PackagesResolver::with_packages([
+            format!("file://{}", path.join("mirror").to_string_lossy())
+        ]).with_validator(AuthorityValidator::new());
+
+        assert!(matches!(
+            tasks::block_on(resolver.build(&store)),
+            Err(PackagesResolverError::UnsignedResource { .. })
+        ));
+
+        let resolver = resolver.with_strict_signatures(false);
+
+        assert_eq!(tasks::block_on(resolver.build(&store))?.resources.len(), 2);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_reproducible() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-build-reproducible-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        for package in ["a", "b", "shared"] {
+            std::fs::create_dir_all(path.join("mirror").join(package))?;
+        }
+
+        // Two root packages depend on the same shared package.
+        std::fs::write(path.join("mirror/a/package.json"), "[package]\nformat = 1\n\n[inputs]\nhelper = \"helper.lua\"\n\n[inputs.shared]\nuri = \"../shared/package.json\"\nformat = \"package\"\n\n[outputs]\nmain = \"main.lua\"\nreadme = \"readme.txt\"")?;
+        std::fs::write(path.join("mirror/a/helper.lua"), "return { name = 'a helper' }")?;
+        std::fs::write(path.join("mirror/a/main.lua"), "return { name = 'a' }")?;
+        std::fs::write(path.join("mirror/a/readme.txt"), "Package a")?;
+
+        std::fs::write(path.join("mirror/b/package.json"), "[package]\nformat = 1\n\n[inputs.shared]\nuri = \"../shared/package.json\"\nformat = \"package\"\n\n[outputs]\nmain = \"main.lua\"")?;
+        std::fs::write(path.join("mirror/b/main.lua"), "return { name = 'b' }")?;
+
+        std::fs::write(path.join("mirror/shared/package.json"), "[package]\nformat = 1\n\n[outputs]\nmodule = \"module.lua\"\nfile = \"file.txt\"")?;
+        std::fs::write(path.join("mirror/shared/module.lua"), "return { name = 'shared' }")?;
+        std::fs::write(path.join("mirror/shared/file.txt"), "Shared file")?;
+
+        let build = |store: &str| {
+            let store = ResourceStore::new(path.join(store));
+
+            let resolver = PackagesResolver::with_packages([
+                format!("file://{}", path.join("mirror/a").to_string_lossy()),
+                format!("file://{}", path.join("mirror/b").to_string_lossy())
+            ]);
+
+            tasks::block_on(resolver.build(&store))
+        };
+
+        let lock_file = build("store_a")?;
+
+        assert_eq!(lock_file.lock.root.len(), 2);
+        assert_eq!(lock_file.resources.len(), 9);
+
+        let lock_file = lock_file.to_toml()?;
+
+        for store in ["store_b", "store_c", "store_d"] {
+            assert_eq!(build(store)?.to_toml()?, lock_file);
+        }
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn plan() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-resolver-plan-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("mirror"))?;
+        std::fs::create_dir_all(path.join("store"))?;
+
+        let module_hash = Hash::for_slice(b"return {}");
+
+        std::fs::write(path.join("mirror/package.json"), format!("[package]\nformat = 1\n\n[inputs.module]\nuri = \"module.lua\"\nhash = \"{module_hash}\"\n\n[outputs]\nfile = \"file.txt\""))?;
+        std::fs::write(path.join("mirror/module.lua"), "return {}")?;
+        std::fs::write(path.join("mirror/file.txt"), "Hello, World!")?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let resolver = PackagesResolver::with_packages([
+            format!("file://{}", path.join("mirror").to_string_lossy())
+        ]);
+
+        let plan = tasks::block_on(resolver.plan(&store))?;
+
+        assert_eq!(plan.resources.len(), 3);
+        assert_eq!(plan.cached().count(), 0);
+        assert_eq!(plan.resources[0].format, ResourceFormat::Package);
+
+        // Only the manifest is downloaded and it's not saved to the store.
+        assert!(!store.has_resource(&module_hash));
+        assert!(path.join("store").read_dir()?.next().is_none());
+
+        // Resources with declared hashes are matched with the store.
+        std::fs::write(store.get_path(&module_hash), "return {}")?;
+
+        let plan = tasks::block_on(resolver.plan(&store))?;
+
+        let cached = plan.cached().collect::<Vec<_>>();
+
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].hash, Some(module_hash));
+        assert_eq!(cached[0].size, Some(9));
+
+        assert_eq!(plan.to_fetch().count(), 2);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_size_limits() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-build-size-limits-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("mirror"))?;
+        std::fs::create_dir_all(path.join("store"))?;
+
+        std::fs::write(path.join("mirror/package.json"), "[package]\nformat = 1\n\n[inputs]\nfile = \"file.bin\"")?;
+        std::fs::write(path.join("mirror/file.bin"), vec![0; 4096])?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let root = format!("file://{}", path.join("mirror").to_string_lossy());
+
+        // Maximal amount of stored bytes of the limited file.
+        let stored = Arc::new(AtomicU64::new(0));
+
+        let result = tasks::block_on(PackagesResolver::with_packages([&root])
+            .with_max_resource_size(1024)
+            .build_with_progress(&store, {
+                let stored = stored.clone();
+
+                move |progress| {
+                    if let ResolverProgress::Download { url, current, .. } = progress
+                        && url.ends_with("/file.bin")
+                    {
+                        stored.fetch_max(current, Ordering::Relaxed);
+                    }
+                }
+            }));
+
+        assert!(matches!(
+            result,
+            Err(PackagesResolverError::ResourceTooLarge { url, limit: 1024 }) if url.ends_with("/file.bin")
+        ));
+
+        // Chunks over the limit are never written.
+        assert!(stored.load(Ordering::Relaxed) <= 1024);
+
+        let result = tasks::block_on(PackagesResolver::with_packages([&root])
+            .with_max_total_size(4096)
+            .build(&store));
+
+        assert!(matches!(
+            result,
+            Err(PackagesResolverError::ResourceTooLarge { limit: 4096, .. })
+        ));
+
+        let lock_file = tasks::block_on(PackagesResolver::with_packages([&root])
+            .with_max_resource_size(4096)
+            .with_max_total_size(8192)
+            .build(&store))?;
+
+        assert_eq!(lock_file.resources.len(), 2);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_cancellable() {
+        let store = ResourceStore::new(std::env::temp_dir().join(".wineyard-runtime-build-cancellable-test"));
+        let resolver = PackagesResolver::with_packages(["https://example.com"]);
+
+        let cancelled = Arc::new(AtomicBool::new(true));
+        let result = tasks::block_on(resolver.build_cancellable(&store, cancelled));
+
+        assert!(matches!(result, Err(PackagesResolverError::Cancelled)));
+    }
+
+    #[test]
+    fn build_cancelled_download() -> Result<(), PackagesResolverError> {
+        use std::io::Write;
+        use std::net::TcpListener;
+        use std::sync::mpsc;
+
+        let path = std::env::temp_dir().join(".wineyard-runtime-build-cancelled-download-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("mirror"))?;
+
+        let data = (0..8192).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/file.bin", listener.local_addr()?);
+
+        std::fs::write(path.join("mirror/package.json"), format!("[package]\nformat = 1\n\n[inputs]\nfile = \"{url}\""))?;
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let (close_sender, close_receiver) = mpsc::channel::<()>();
+        let (request_sender, request_receiver) = mpsc::channel::<String>();
+
+        // Read the request header from the stream.
+        fn read_request(stream: &mut std::net::TcpStream) -> String {
+            use std::io::Read;
+
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(len) => request.extend_from_slice(&buf[..len])
+                }
+            }
+
+            String::from_utf8_lossy(&request).to_lowercase()
+        }
+
+        // Send half of the file and cancel the resolution while it's being
+        // downloaded, then send the rest of it to the next request.
+        std::thread::spawn({
+            let cancelled = cancelled.clone();
+            let data = data.clone();
+
+            move || {
+                let Ok((mut stream, _)) = listener.accept() else {
+                    return;
+                };
+
+                read_request(&mut stream);
+
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8192\r\nETag: \"v1\"\r\n\r\n");
+                let _ = stream.write_all(&data[..4096]);
+                let _ = stream.flush();
+
+                cancelled.store(true, Ordering::Release);
+
+                let _ = close_receiver.recv();
+
+                drop(stream);
+
+                let Ok((mut stream, _)) = listener.accept() else {
+                    return;
+                };
+
+                let _ = request_sender.send(read_request(&mut stream));
+
+                let _ = stream.write_all(b"HTTP/1.1 206 Partial Content\r\nContent-Length: 4096\r\nContent-Range: bytes 4096-8191/8192\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n");
+                let _ = stream.write_all(&data[4096..]);
+            }
+        });
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let resolver = PackagesResolver::with_packages([
+            format!("file://{}", path.join("mirror").to_string_lossy())
+        ]);
+
+        let result = tasks::block_on(resolver.build_cancellable(&store, cancelled));
+
+        assert!(matches!(result, Err(PackagesResolverError::Cancelled)));
+
+        let _ = close_sender.send(());
+
+        // Cancelled download keeps the received content.
+        let partial_path = store.get_partial_path(&url);
+
+        assert_eq!(std::fs::read(&partial_path)?, &data[..4096]);
+        assert_eq!(std::fs::read_to_string(store.get_partial_etag_path(&url))?, "\"v1\"");
+
+        let lock_file = tasks::block_on(resolver.build(&store))?;
+
+        let request = request_receiver.recv_timeout(Duration::from_secs(5))
+            .unwrap_or_default();
+
+        assert!(request.contains("range: bytes=4096-"));
+        assert!(request.contains("if-range: \"v1\""));
+
+        let resource = lock_file.resources.iter()
+            .find(|resource| resource.format == ResourceFormat::File)
+            .map(|resource| resource.lock.hash);
+
+        assert_eq!(resource, Some(Hash::for_slice(&data)));
+        assert_eq!(std::fs::read(store.get_path(&Hash::for_slice(&data)))?, data);
+        assert!(!partial_path.exists());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn valid_content() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-valid-content-test");
+
+        let module = ResourceFormat::Module(ResourceModuleFormat::Luau);
+        let zip = ResourceFormat::Archive(ResourceArchiveFormat::Zip);
+        let tar = ResourceFormat::Archive(ResourceArchiveFormat::Tar);
+        let auto = ResourceFormat::Archive(ResourceArchiveFormat::Auto);
+
+        std::fs::write(&path, "\n  <!DOCTYPE html><html><body>404</body></html>")?;
+
+        assert!(!is_valid_content(&path, &module)?);
+        assert!(!is_valid_content(&path, &auto)?);
+        assert!(is_valid_content(&path, &ResourceFormat::File)?);
+
+        std::fs::write(&path, "return { value = \"<html>\" }")?;
+
+        assert!(is_valid_content(&path, &module)?);
+
+        std::fs::write(&path, b"PK\x03\x04")?;
+
+        assert!(is_valid_content(&path, &zip)?);
+        assert!(is_valid_content(&path, &auto)?);
+        assert!(!is_valid_content(&path, &tar)?);
+
+        let mut tar_header = [0; 512];
+
+        tar_header[257..262].copy_from_slice(b"ustar");
+
+        std::fs::write(&path, tar_header)?;
+
+        assert!(is_valid_content(&path, &tar)?);
+        assert!(!is_valid_content(&path, &zip)?);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn extracted_archive_size() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-archive-size-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        let source = path.join("source");
+        let extracted = path.join("extracted");
+
+        std::fs::create_dir_all(source.join("nested"))?;
+
+        std::fs::write(source.join("a.txt"), [0; 1024])?;
+        std::fs::write(source.join("nested/b.txt"), [0; 4096])?;
+        std::fs::write(source.join("nested/c.txt"), b"Hello, World!")?;
+
+        let status = std::process::Command::new("tar")
+            .arg("-cf")
+            .arg(path.join("archive.tar"))
+            .arg("-C")
+            .arg(&source)
+            .arg(".")
+            .status()?;
+
+        assert!(status.success());
+
+        Archive::open_with_format(path.join("archive.tar"), ArchiveFormat::Tar)
+            .ok_or_else(|| PackagesResolverError::ArchiveNotSupported(path.join("archive.tar")))?
+            .extract(&extracted)?
+            .wait()?;
+
+        assert_eq!(entry_size(&extracted)?, 1024 + 4096 + 13);
+        assert_eq!(entry_size(extracted.join("a.txt"))?, 1024);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_locked_store() -> Result<(), PackagesResolverError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-build-locked-store-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("mirror"))?;
+
+        std::fs::write(path.join("mirror/package.json"), "[package]\nformat = 1")?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let resolver = PackagesResolver::with_packages([
+            format!("file://{}", path.join("mirror").to_string_lossy())
+        ]);
+
+        let lock = store.lock()?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        // Resolver waits for the store lock until it's cancelled.
+        std::thread::spawn({
+            let cancelled = cancelled.clone();
+
+            move || {
+                std::thread::sleep(Duration::from_millis(200));
+
+                cancelled.store(true, Ordering::Release);
+            }
+        });
+
+        let result = tasks::block_on(resolver.build_cancellable(&store, cancelled));
+
+        assert!(matches!(result, Err(PackagesResolverError::Cancelled)));
+
+        drop(lock);
+
+        assert_eq!(tasks::block_on(resolver.build(&store))?.resources.len(), 1);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
     }
 }
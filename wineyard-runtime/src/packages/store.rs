@@ -1,4 +1,6 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::fs::{File, TryLockError};
 
 use toml::Table as TomlTable;
 
@@ -19,6 +21,46 @@ pub enum ResourceStoreError {
     PackageManifestError(#[from] PackageManifestError)
 }
 
+/// Calculate total size of the filesystem entry. For folders this is a
+/// sum of sizes of all the files inside of it. Symlinks are not followed.
+pub(crate) fn entry_size(path: impl AsRef<Path>) -> std::io::Result<u64> {
+    let metadata = path.as_ref().symlink_metadata()?;
+
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+
+    for entry in path.as_ref().read_dir()? {
+        size += entry_size(entry?.path())?;
+    }
+
+    Ok(size)
+}
+
+/// Name of the file in the store folder used to lock the store.
+pub const STORE_LOCK_FILE_NAME: &str = ".lock";
+
+/// Exclusive lock of the resource store. Released when dropped.
+///
+/// This is an advisory lock, so it only prevents races between the
+/// operations which acquire it, from this or other processes.
+#[derive(Debug)]
+pub struct ResourceStoreLock {
+    _file: File
+}
+
+/// Statistics of the removed store entries.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GcReport {
+    /// Amount of removed resources.
+    pub entries: u64,
+
+    /// Total size of removed resources.
+    pub bytes: u64
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ResourceStore {
     folder: PathBuf
@@ -50,6 +92,98 @@ impl ResourceStore {
         self.folder.join(format!("{}.tmp", hash.to_base32()))
     }
 
+    /// Build path to the luau bytecode compiled locally from the source
+    /// module resource with the given hash.
+    #[inline]
+    pub fn get_bytecode_path(&self, hash: &Hash) -> PathBuf {
+        self.folder.join(format!("{}.luauc", hash.to_base32()))
+    }
+
+    /// Build path to the file storing digest of the luau bytecode compiled
+    /// from the source module resource with the given hash.
+    #[inline]
+    pub fn get_bytecode_hash_path(&self, hash: &Hash) -> PathBuf {
+        self.folder.join(format!("{}.luauc.hash", hash.to_base32()))
+    }
+
+    /// Build path to the partially downloaded resource in the store.
+    ///
+    /// Unlike temp paths it depends on the resource URL so interrupted
+    /// downloads can be continued later.
+    #[inline]
+    pub fn get_partial_path(&self, url: impl AsRef<str>) -> PathBuf {
+        let hash = Hash::for_slice(url.as_ref().as_bytes());
+
+        self.folder.join(format!("{}.part", hash.to_base32()))
+    }
+
+    /// Build path to the file storing entity tag of the partially
+    /// downloaded resource.
+    #[inline]
+    pub fn get_partial_etag_path(&self, url: impl AsRef<str>) -> PathBuf {
+        let hash = Hash::for_slice(url.as_ref().as_bytes());
+
+        self.folder.join(format!("{}.part.etag", hash.to_base32()))
+    }
+
+    /// Remove partially downloaded resource so its downloading
+    /// is not continued.
+    pub fn remove_partial(&self, url: impl AsRef<str>) -> std::io::Result<()> {
+        let url = url.as_ref();
+
+        for path in [self.get_partial_path(url), self.get_partial_etag_path(url)] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Open the store lock file, creating the store folder if needed.
+    fn open_lock_file(&self) -> std::io::Result<File> {
+        if !self.folder.exists() {
+            std::fs::create_dir_all(&self.folder)?;
+        }
+
+        File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.folder.join(STORE_LOCK_FILE_NAME))
+    }
+
+    /// Acquire exclusive lock of the store, blocking the current thread until
+    /// it's released by other owners.
+    ///
+    /// Mutating operations like packages resolution or garbage collection
+    /// acquire it automatically.
+    pub fn lock(&self) -> Result<ResourceStoreLock, ResourceStoreError> {
+        let file = self.open_lock_file()?;
+
+        file.lock()?;
+
+        Ok(ResourceStoreLock {
+            _file: file
+        })
+    }
+
+    /// Try to acquire exclusive lock of the store without blocking.
+    ///
+    /// Return `None` if the store is already locked.
+    pub fn try_lock(&self) -> Result<Option<ResourceStoreLock>, ResourceStoreError> {
+        let file = self.open_lock_file()?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Some(ResourceStoreLock {
+                _file: file
+            })),
+
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => Err(err.into())
+        }
+    }
+
     /// Check if a resource with given hash is installed.
     #[inline]
     pub fn has_resource(&self, hash: &Hash) -> bool {
@@ -70,6 +204,17 @@ impl ResourceStore {
         Ok(Some(PackageManifest::try_from(&package)?))
     }
 
+    /// Get hashes of the lock file resources which are not installed.
+    ///
+    /// Unlike `validate` this method doesn't verify content of the resources
+    /// so it can be used to quickly check if the lock file can be loaded.
+    pub fn missing_resources(&self, lock_file: &LockFile) -> Vec<Hash> {
+        lock_file.resources.iter()
+            .map(|resource| resource.lock.hash)
+            .filter(|hash| !self.has_resource(hash))
+            .collect()
+    }
+
     /// Validate packages in the lock file.
     ///
     /// This method will scan current store and validate hashes of the locked
@@ -87,4 +232,239 @@ impl ResourceStore {
 
         Ok(true)
     }
+
+    /// Re-hash installed resources of the lock file and get hashes of those
+    /// which content doesn't match their hash anymore, e.g. after a disk
+    /// corruption.
+    ///
+    /// Resources which are not installed are not reported, use
+    /// `missing_resources` to get them.
+    pub fn verify(&self, lock_file: &LockFile) -> Result<Vec<Hash>, ResourceStoreError> {
+        let mut verified = HashSet::with_capacity(lock_file.resources.len());
+        let mut corrupted = Vec::new();
+
+        for resource in &lock_file.resources {
+            let hash = resource.lock.hash;
+
+            if !verified.insert(hash) {
+                continue;
+            }
+
+            let path = self.get_path(&hash);
+
+            if path.exists() && Hash::for_entry(&path)? != hash {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(?path, url = resource.url, "stored resource is corrupted");
+
+                corrupted.push(hash);
+            }
+        }
+
+        Ok(corrupted)
+    }
+
+    /// Remove resources which are not referenced by any of the given lock
+    /// files.
+    ///
+    /// Temporary entries and files with names which are not resource hashes
+    /// are not touched. The store is locked until the collection is finished
+    /// so this method must not be called while holding the store lock.
+    pub fn garbage_collect(&self, keep: &[&LockFile]) -> Result<GcReport, ResourceStoreError> {
+        let keep = keep.iter()
+            .flat_map(|lock_file| lock_file.resources.iter())
+            .map(|resource| resource.lock.hash)
+            .collect::<HashSet<_>>();
+
+        let mut report = GcReport::default();
+
+        if !self.folder.exists() {
+            return Ok(report);
+        }
+
+        let _lock = self.lock()?;
+
+        for entry in self.folder.read_dir()? {
+            let entry = entry?;
+            let name = entry.file_name();
+
+            // Compiled bytecode is removed together with its source module.
+            let name = name.to_str().map(|name| {
+                name.strip_suffix(".luauc.hash")
+                    .or_else(|| name.strip_suffix(".luauc"))
+                    .unwrap_or(name)
+            });
+
+            // Skip temp files and unknown entries.
+            let Some(hash) = name.and_then(Hash::from_base32) else {
+                continue;
+            };
+
+            if keep.contains(&hash) {
+                continue;
+            }
+
+            let path = entry.path();
+            let size = entry_size(&path)?;
+
+            if entry.file_type()?.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else {
+                std::fs::remove_file(&path)?;
+            }
+
+            report.entries += 1;
+            report.bytes += size;
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::packages::manifest::ResourceFormat;
+    use crate::packages::lock_file::{LockFileInfo, ResourceLock, ResourceLockData};
+
+    #[test]
+    fn garbage_collect() -> Result<(), ResourceStoreError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-store-gc-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        let store = ResourceStore::new(&path);
+
+        let kept = Hash::for_slice(b"kept");
+        let removed = Hash::for_slice(b"removed");
+        let removed_dir = Hash::for_slice(b"removed dir");
+
+        std::fs::create_dir_all(store.get_path(&removed_dir))?;
+
+        std::fs::write(store.get_path(&kept), [0; 16])?;
+        std::fs::write(store.get_path(&removed), [0; 32])?;
+        std::fs::write(store.get_path(&removed_dir).join("file"), [0; 64])?;
+        std::fs::write(store.get_temp_path(&removed), [0; 128])?;
+        std::fs::write(store.get_partial_path("removed"), [0; 256])?;
+        std::fs::write(store.get_partial_etag_path("removed"), "\"v1\"")?;
+        std::fs::write(store.get_bytecode_path(&kept), [0; 512])?;
+        std::fs::write(store.get_bytecode_path(&removed), [0; 1024])?;
+        std::fs::write(store.get_bytecode_hash_path(&kept), [0; 2048])?;
+        std::fs::write(store.get_bytecode_hash_path(&removed), [0; 4096])?;
+
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![
+                ResourceLock {
+                    url: String::from("kept"),
+                    format: ResourceFormat::File,
+                    lock: ResourceLockData {
+                        hash: kept,
+                        size: 16
+                    },
+                    inputs: None,
+                    outputs: None
+                }
+            ]
+        };
+
+        let report = store.garbage_collect(&[&lock_file])?;
+
+        assert_eq!(report, GcReport {
+            entries: 4,
+            bytes: 32 + 64 + 1024 + 4096
+        });
+
+        assert!(store.has_resource(&kept));
+        assert!(!store.has_resource(&removed));
+        assert!(!store.has_resource(&removed_dir));
+        assert!(store.get_bytecode_path(&kept).exists());
+        assert!(!store.get_bytecode_path(&removed).exists());
+        assert!(store.get_bytecode_hash_path(&kept).exists());
+        assert!(!store.get_bytecode_hash_path(&removed).exists());
+        assert!(store.get_temp_path(&removed).exists());
+        assert!(store.get_partial_path("removed").exists());
+        assert!(store.get_partial_etag_path("removed").exists());
+        assert_ne!(store.get_partial_path("removed"), store.get_partial_path("kept"));
+
+        store.remove_partial("removed")?;
+
+        assert!(!store.get_partial_path("removed").exists());
+        assert!(!store.get_partial_etag_path("removed").exists());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify() -> Result<(), ResourceStoreError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-store-verify-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        let store = ResourceStore::new(&path);
+
+        let valid = Hash::for_slice(b"valid");
+        let corrupted = Hash::for_slice(b"corrupted");
+        let missing = Hash::for_slice(b"missing");
+
+        std::fs::create_dir_all(&path)?;
+
+        std::fs::write(store.get_path(&valid), b"valid")?;
+        std::fs::write(store.get_path(&corrupted), b"corrupte\0")?;
+
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![]
+            },
+            resources: vec![
+                ResourceLock::test_file("valid", valid),
+                ResourceLock::test_file("corrupted", corrupted),
+                ResourceLock::test_file("corrupted mirror", corrupted),
+                ResourceLock::test_file("missing", missing)
+            ]
+        };
+
+        assert_eq!(store.verify(&lock_file)?, [corrupted]);
+        assert_eq!(store.missing_resources(&lock_file), [missing]);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn lock() -> Result<(), ResourceStoreError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-store-lock-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        let store = ResourceStore::new(&path);
+
+        let lock = store.lock()?;
+
+        assert!(path.join(STORE_LOCK_FILE_NAME).is_file());
+        assert!(store.try_lock()?.is_none());
+
+        drop(lock);
+
+        assert!(store.try_lock()?.is_some());
+
+        // Lock file is not treated as a resource.
+        assert_eq!(store.garbage_collect(&[])?, GcReport::default());
+        assert!(path.join(STORE_LOCK_FILE_NAME).is_file());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
 }
@@ -1,25 +1,91 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
 
-use wineyard_core::archives::{Archive, ArchiveFormat};
+use wineyard_core::archives::{Archive, ArchiveEntry, ArchiveEntries, ArchiveFormat, ArchiveError, ExtractOptions};
 
 use mlua::prelude::*;
 
 use super::*;
 
+/// How often the extraction thread checks if `archive.abort` was called.
+const ARCHIVE_ABORT_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Read archive extraction options from the lua table.
+fn extract_options(options: Option<&LuaTable>) -> Result<ExtractOptions, LuaError> {
+    let Some(options) = options else {
+        return Ok(ExtractOptions::default());
+    };
+
+    let password = options.get::<Option<LuaString>>("password")?
+        .map(|password| password.to_string_lossy());
+
+    let read_patterns = |key: &str| -> Result<Vec<String>, LuaError> {
+        let Some(patterns) = options.get::<Option<LuaTable>>(key)? else {
+            return Ok(vec![]);
+        };
+
+        patterns.sequence_values::<LuaString>()
+            .map(|pattern| pattern.map(|pattern| pattern.to_string_lossy()))
+            .collect()
+    };
+
+    Ok(ExtractOptions {
+        password,
+        include: read_patterns("include")?,
+        exclude: read_patterns("exclude")?,
+        skip_existing: options.get::<Option<bool>>("skip_existing")?.unwrap_or_default()
+    })
+}
+
+/// Convert archive entry to the lua table.
+fn entry_table(lua: &Lua, entry: ArchiveEntry) -> Result<LuaTable, LuaError> {
+    let entry_table = lua.create_table_with_capacity(0, 6)?;
+
+    entry_table.raw_set("path", entry.path.to_string_lossy())?;
+    entry_table.raw_set("size", entry.size)?;
+    entry_table.raw_set("is_dir", entry.is_dir)?;
+    entry_table.raw_set("mode", entry.mode)?;
+
+    let modified = entry.modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs());
+
+    entry_table.raw_set("modified", modified)?;
+    entry_table.raw_set("comment", entry.comment)?;
+
+    Ok(entry_table)
+}
+
+/// Cursor over the archive entries. Entries are read from the archiver
+/// output and converted to lua tables one by one.
+struct EntriesCursor {
+    archive: i32,
+    entries: ArchiveEntries
+}
+
 pub struct ArchivesAPI {
     lua: Lua,
 
     archive_open: LuaFunctionBuilder,
     archive_entries: LuaFunction,
+    archive_comment: LuaFunction,
+    archive_iter: LuaFunction,
+    archive_next: LuaFunction,
     archive_extract: LuaFunctionBuilder,
+    archive_abort: LuaFunction,
     archive_close: LuaFunction
 }
 
 impl ArchivesAPI {
     pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
         let archive_handles = Arc::new(Mutex::new(HashMap::new()));
+        let entries_cursors = Arc::new(Mutex::new(HashMap::<i32, EntriesCursor>::new()));
+
+        // archive_handle => abort flag of the running extraction
+        let extractions = Arc::new(Mutex::new(HashMap::<i32, Arc<AtomicBool>>::new()));
 
         Ok(Self {
             archive_open: {
@@ -29,7 +95,7 @@ impl ArchivesAPI {
                     let context = context.to_owned();
                     let archive_handles = archive_handles.clone();
 
-                    lua.create_function(move |_, (path, format): (LuaString, Option<LuaString>)| {
+                    lua.create_function(move |_, (path, format, options): (LuaString, Option<LuaString>, Option<LuaTable>)| {
                         let mut path = resolve_path(path.to_string_lossy())?;
 
                         if path.is_relative() {
@@ -53,6 +119,8 @@ impl ArchivesAPI {
                         let archive = Archive::open_with_format(&path, format)
                             .ok_or_else(|| LuaError::external("failed to open archive"))?;
 
+                        let options = extract_options(options.as_ref())?;
+
                         // Prepare new handle and store the open archive.
                         let mut handles = archive_handles.lock()
                             .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?;
@@ -63,7 +131,7 @@ impl ArchivesAPI {
                             handle = rand::random::<i32>();
                         }
 
-                        handles.insert(handle, archive);
+                        handles.insert(handle, (archive, options));
 
                         Ok(handle)
                     })
@@ -78,38 +146,119 @@ impl ArchivesAPI {
                         .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
 
                     // Get archive object using the given handle.
-                    let Some(archive) = handles.get(&handle) else {
+                    let Some((archive, _)) = handles.get(&handle) else {
                         return Err(LuaError::external("invalid archive handle"));
                     };
 
                     // Get list of archive entries depending on its format.
-                    let mut entries = archive.get_entries()
+                    let mut entries = archive.get_entries_with_comments()
                         .map_err(|err| LuaError::external(format!("failed to get archive entries: {err}")))?;
 
                     // Prepare the lua output.
                     let entries_table = lua.create_table_with_capacity(entries.len(), 0)?;
 
                     for entry in entries.drain(..) {
-                        let entry_table = lua.create_table_with_capacity(0, 2)?;
+                        entries_table.raw_push(entry_table(lua, entry)?)?;
+                    }
 
-                        entry_table.raw_set("path", entry.path.to_string_lossy())?;
-                        entry_table.raw_set("size", entry.size)?;
+                    Ok(entries_table)
+                })?
+            },
+
+            archive_comment: {
+                let archive_handles = archive_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let handles = archive_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    // Get archive object using the given handle.
+                    let Some((archive, _)) = handles.get(&handle) else {
+                        return Err(LuaError::external("invalid archive handle"));
+                    };
+
+                    archive.comment()
+                        .map_err(|err| LuaError::external(format!("failed to get archive comment: {err}")))
+                })?
+            },
+
+            archive_iter: {
+                let archive_handles = archive_handles.clone();
+                let entries_cursors = entries_cursors.clone();
 
-                        entries_table.raw_push(entry_table)?;
+                lua.create_function(move |_, handle: i32| {
+                    let handles = archive_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    // Get archive object using the given handle.
+                    let Some((archive, _)) = handles.get(&handle) else {
+                        return Err(LuaError::external("invalid archive handle"));
+                    };
+
+                    let entries = archive.iter_entries()
+                        .map_err(|err| LuaError::external(format!("failed to get archive entries: {err}")))?;
+
+                    drop(handles);
+
+                    // Prepare new cursor over the entries.
+                    let mut cursors = entries_cursors.lock()
+                        .map_err(|err| LuaError::external(format!("failed to register cursor: {err}")))?;
+
+                    let mut cursor = rand::random::<i32>();
+
+                    while cursors.contains_key(&cursor) {
+                        cursor = rand::random::<i32>();
                     }
 
-                    Ok(entries_table)
+                    cursors.insert(cursor, EntriesCursor {
+                        archive: handle,
+                        entries
+                    });
+
+                    Ok(cursor)
+                })?
+            },
+
+            archive_next: {
+                let entries_cursors = entries_cursors.clone();
+
+                lua.create_function(move |lua, cursor: i32| {
+                    let mut cursors = entries_cursors.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read cursor: {err}")))?;
+
+                    let Some(entries) = cursors.get_mut(&cursor) else {
+                        return Err(LuaError::external("invalid entries cursor"));
+                    };
+
+                    match entries.entries.next() {
+                        Some(Ok(entry)) => Ok(Some(entry_table(lua, entry)?)),
+
+                        Some(Err(err)) => {
+                            cursors.remove(&cursor);
+
+                            Err(LuaError::external(format!("failed to get archive entry: {err}")))
+                        }
+
+                        // Close the cursor when all the entries are read.
+                        None => {
+                            cursors.remove(&cursor);
+
+                            Ok(None)
+                        }
+                    }
                 })?
             },
 
             archive_extract: {
                 let archive_handles = archive_handles.clone();
+                let extractions = extractions.clone();
 
                 Box::new(move |lua: &Lua, context: &Context| {
                     let context = context.to_owned();
                     let archive_handles = archive_handles.clone();
+                    let extractions = extractions.clone();
 
-                    lua.create_function(move |_, (handle, target, progress): (i32, LuaString, Option<LuaFunction>)| {
+                    lua.create_function(move |_, (handle, target, progress, options): (i32, LuaString, Option<LuaFunction>, Option<LuaTable>)| {
                         let mut target = resolve_path(target.to_string_lossy())?;
 
                         if target.is_relative() {
@@ -120,53 +269,127 @@ impl ArchivesAPI {
                             return Err(LuaError::external("target path is inaccessible"));
                         }
 
+                        context.track_temp(&target);
+
+                        let options = extract_options(options.as_ref())?;
+
+                        let aborted = Arc::new(AtomicBool::new(false));
+
+                        extractions.lock()
+                            .map_err(|err| LuaError::external(format!("failed to register extraction: {err}")))?
+                            .insert(handle, aborted.clone());
+
                         // Start extracting the archive in a background thread depending on its format.
                         let (send, recv) = std::sync::mpsc::channel();
 
                         let archive_handles = archive_handles.clone();
+                        let abort_requested = aborted.clone();
 
-                        let handle = std::thread::spawn(move || {
+                        let worker = std::thread::spawn(move || {
                             let handles = archive_handles.lock()
                                 .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
 
                             // Get archive object using the given handle.
-                            let Some(archive) = handles.get(&handle) else {
+                            let Some((archive, open_options)) = handles.get(&handle) else {
                                 return Err(LuaError::external("invalid archive handle"));
                             };
 
-                            archive
-                                .extract_with_progress(target, move |curr, total, diff| {
+                            // Options given to this function have higher
+                            // priority than the ones given when opening.
+                            let options = ExtractOptions {
+                                password: options.password.or_else(|| open_options.password.clone()),
+
+                                include: if options.include.is_empty() {
+                                    open_options.include.clone()
+                                } else {
+                                    options.include
+                                },
+
+                                exclude: if options.exclude.is_empty() {
+                                    open_options.exclude.clone()
+                                } else {
+                                    options.exclude
+                                },
+
+                                skip_existing: options.skip_existing || open_options.skip_existing
+                            };
+
+                            let extractor = archive
+                                .extract_with_options(target, &options, move |curr, total, diff| {
                                     let _ = send.send((curr, total, diff));
                                 })
-                                .map_err(|err| LuaError::external(format!("failed to start extracting archive: {err}")))?
-                                .wait()
-                                .map_err(|err| LuaError::external(format!("failed to extract archive: {err:?}")))?;
+                                .map_err(|err| LuaError::external(format!("failed to start extracting archive: {err}")))?;
 
-                            Ok::<_, LuaError>(())
+                            // Forward abort requests to the extractor.
+                            while !extractor.is_finished() {
+                                if abort_requested.load(Ordering::Acquire) && !extractor.is_aborted() {
+                                    extractor.abort();
+                                }
+
+                                std::thread::sleep(ARCHIVE_ABORT_CHECK_INTERVAL);
+                            }
+
+                            match extractor.wait() {
+                                Ok(()) | Err(ArchiveError::Aborted) => Ok::<_, LuaError>(()),
+
+                                Err(err) => Err(LuaError::external(format!("failed to extract archive: {err:?}")))
+                            }
                         });
 
                         // Handle extraction progress events.
                         let mut finished = false;
 
-                        while !handle.is_finished() {
+                        let mut callback_result = Ok(());
+
+                        while !worker.is_finished() {
                             for (curr, total, diff) in recv.try_iter() {
                                 finished = curr >= total;
 
-                                if let Some(callback) = &progress {
-                                    callback.call::<()>((curr, total, diff))?;
+                                if let Some(callback) = &progress
+                                    && callback_result.is_ok()
+                                {
+                                    callback_result = callback.call::<()>((curr, total, diff));
+
+                                    // Stop the extraction if the callback failed.
+                                    if callback_result.is_err() {
+                                        aborted.store(true, Ordering::Release);
+                                    }
                                 }
                             }
                         }
 
-                        handle.join().map_err(|err| {
+                        extractions.lock()
+                            .map_err(|err| LuaError::external(format!("failed to unregister extraction: {err}")))?
+                            .remove(&handle);
+
+                        worker.join().map_err(|err| {
                             LuaError::external(format!("failed to extract archive: {err:?}"))
                         })??;
 
-                        Ok(finished)
+                        callback_result?;
+
+                        Ok(finished && !aborted.load(Ordering::Acquire))
                     })
                 })
             },
 
+            archive_abort: {
+                let extractions = extractions.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let extractions = extractions.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read extraction: {err}")))?;
+
+                    let Some(aborted) = extractions.get(&handle) else {
+                        return Ok(false);
+                    };
+
+                    aborted.store(true, Ordering::Release);
+
+                    Ok(true)
+                })?
+            },
+
             archive_close: {
                 let archive_handles = archive_handles.clone();
 
@@ -175,6 +398,11 @@ impl ArchivesAPI {
                         .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?
                         .remove(&handle);
 
+                    // Close entries cursors of the archive as well.
+                    entries_cursors.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read cursor: {err}")))?
+                        .retain(|_, cursor| cursor.archive != handle);
+
                     Ok(())
                 })?
             },
@@ -190,11 +418,15 @@ impl ArchivesAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 4)?;
+        let env = self.lua.create_table_with_capacity(0, 8)?;
 
         env.raw_set("open", (self.archive_open)(&self.lua, context)?)?;
         env.raw_set("entries", self.archive_entries.clone())?;
+        env.raw_set("comment", self.archive_comment.clone())?;
+        env.raw_set("iter", self.archive_iter.clone())?;
+        env.raw_set("next", self.archive_next.clone())?;
         env.raw_set("extract", (self.archive_extract)(&self.lua, context)?)?;
+        env.raw_set("abort", self.archive_abort.clone())?;
         env.raw_set("close", self.archive_close.clone())?;
 
         Ok(env)
@@ -316,3 +548,102 @@ impl ArchivesAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_iter() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-archive-iter-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("source/dir"))?;
+        std::fs::write(path.join("source/dir/a.txt"), "Hello")?;
+        std::fs::write(path.join("source/dir/b.txt"), "Hello, World!")?;
+
+        let status = std::process::Command::new("tar")
+            .arg("-cf")
+            .arg(path.join("archive.tar"))
+            .arg("-C")
+            .arg(path.join("source"))
+            .arg("dir")
+            .status()?;
+
+        assert!(status.success());
+
+        let lua = Lua::new();
+        let api = ArchivesAPI::new(lua.clone())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        lua.globals().set("archive", env)?;
+
+        lua.load(r#"
+            local handle = archive.open("archive.tar")
+            local cursor = archive.iter(handle)
+
+            -- Tar archives don't support comments.
+            assert(archive.comment(handle) == nil)
+            assert(archive.entries(handle)[1].comment == nil)
+
+            local count, size = 0, 0
+
+            while true do
+                local entry = archive.next(cursor)
+
+                if not entry then
+                    break
+                end
+
+                count += 1
+                size += entry.size
+            end
+
+            assert(count == 3)
+            assert(size == 18)
+
+            -- Exhausted cursors are closed.
+            assert(not pcall(archive.next, cursor))
+
+            -- Closing the archive closes its cursors.
+            cursor = archive.iter(handle)
+
+            assert(archive.next(cursor).path)
+
+            archive.close(handle)
+
+            assert(not pcall(archive.next, cursor))
+            assert(not pcall(archive.iter, handle))
+
+            -- Aborted extraction is not finished.
+            handle = archive.open("archive.tar")
+
+            assert(not archive.abort(handle))
+
+            local aborted = false
+
+            local finished = archive.extract(handle, "extracted", function()
+                aborted = aborted or archive.abort(handle)
+            end)
+
+            assert(aborted)
+            assert(not finished)
+            assert(not archive.abort(handle))
+
+            -- Extraction can be continued.
+            assert(archive.extract(handle, "extracted", nil, { skip_existing = true }))
+
+            archive.close(handle)
+        "#).exec()?;
+
+        assert_eq!(std::fs::read(path.join("extracted/dir/b.txt"))?, b"Hello, World!");
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
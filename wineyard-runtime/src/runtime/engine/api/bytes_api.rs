@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use wineyard_core::buffer::Buffer;
+
+use mlua::prelude::*;
+
+use super::*;
+
+/// Maximal length of the LEB128 encoded 64 bit integer.
+const VARINT_MAX_LEN: usize = 10;
+
+/// Maximal absolute value of integers which are represented exactly
+/// by lua numbers.
+const MAX_EXACT_INTEGER: i64 = 1 << 53;
+
+/// Check that the integer is represented exactly by lua numbers.
+fn check_exact(value: i128) -> Result<(), LuaError> {
+    if !(-(MAX_EXACT_INTEGER as i128)..=MAX_EXACT_INTEGER as i128).contains(&value) {
+        return Err(LuaError::external("integer can't be represented exactly"));
+    }
+
+    Ok(())
+}
+
+type BuffersHandles = Arc<Mutex<HashMap<i32, Buffer>>>;
+
+/// Read exact amount of bytes from the buffer, keeping it untouched
+/// if there's not enough bytes.
+fn read_bytes(buffer: &mut Buffer, len: usize) -> Result<Vec<u8>, LuaError> {
+    if buffer.as_ref().len() < len {
+        return Err(LuaError::external("not enough bytes in buffer"));
+    }
+
+    let mut bytes = vec![0; len];
+
+    buffer.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Create function writing integers of the given size into the buffer.
+fn int_writer(
+    lua: &Lua,
+    buffers: BuffersHandles,
+    size: usize,
+    signed: bool
+) -> Result<LuaFunction, LuaError> {
+    let bits = size as u32 * 8;
+
+    lua.create_function(move |_, (handle, value, endianness): (i32, i64, Option<LuaString>)| {
+        let is_valid = match (signed, bits) {
+            (_, 64) => (signed || value >= 0) && check_exact(value as i128).is_ok(),
+
+            (true, _)  => (-(1 << (bits - 1))..(1 << (bits - 1))).contains(&value),
+            (false, _) => (0..(1 << bits)).contains(&value)
+        };
+
+        if !is_valid {
+            return Err(LuaError::external("integer is out of range"));
+        }
+
+        let bytes = if is_little_endian(endianness)? {
+            value.to_le_bytes()[..size].to_vec()
+        } else {
+            value.to_be_bytes()[8 - size..].to_vec()
+        };
+
+        let mut buffers = buffers.lock()
+            .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+        let Some(buffer) = buffers.get_mut(&handle) else {
+            return Err(LuaError::external("invalid buffer handle"));
+        };
+
+        buffer.write_all(&bytes)?;
+
+        Ok(())
+    })
+}
+
+/// Create function reading integers of the given size from the buffer.
+fn int_reader(
+    lua: &Lua,
+    buffers: BuffersHandles,
+    size: usize,
+    signed: bool
+) -> Result<LuaFunction, LuaError> {
+    let shift = 64 - size as u32 * 8;
+
+    lua.create_function(move |lua, (handle, endianness): (i32, Option<LuaString>)| {
+        let is_little_endian = is_little_endian(endianness)?;
+
+        let mut buffers = buffers.lock()
+            .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+        let Some(buffer) = buffers.get_mut(&handle) else {
+            return Err(LuaError::external("invalid buffer handle"));
+        };
+
+        let Some(bytes) = buffer.as_ref().get(..size) else {
+            return Err(LuaError::external("not enough bytes in buffer"));
+        };
+
+        let mut value = [0; 8];
+
+        if is_little_endian {
+            value[..size].copy_from_slice(bytes);
+        } else {
+            value[8 - size..].copy_from_slice(bytes);
+        }
+
+        let value = if is_little_endian {
+            u64::from_le_bytes(value)
+        } else {
+            u64::from_be_bytes(value)
+        };
+
+        // Extend the sign bit of smaller integers.
+        let value = if signed {
+            ((value << shift) as i64 >> shift) as i128
+        } else {
+            value as i128
+        };
+
+        // Keep the bytes in the buffer if the value can't be returned.
+        check_exact(value)?;
+
+        read_bytes(buffer, size)?;
+
+        (value as i64).into_lua(lua)
+    })
+}
+
+pub struct BytesAPI {
+    lua: Lua,
+
+    bytes_new: LuaFunction,
+    bytes_len: LuaFunction,
+    bytes_write: LuaFunction,
+    bytes_read: LuaFunction,
+    bytes_write_string: LuaFunction,
+    bytes_read_string: LuaFunction,
+    bytes_write_varint: LuaFunction,
+    bytes_read_varint: LuaFunction,
+    bytes_write_u8: LuaFunction,
+    bytes_write_u16: LuaFunction,
+    bytes_write_u32: LuaFunction,
+    bytes_write_u64: LuaFunction,
+    bytes_write_i8: LuaFunction,
+    bytes_write_i16: LuaFunction,
+    bytes_write_i32: LuaFunction,
+    bytes_write_i64: LuaFunction,
+    bytes_read_u8: LuaFunction,
+    bytes_read_u16: LuaFunction,
+    bytes_read_u32: LuaFunction,
+    bytes_read_u64: LuaFunction,
+    bytes_read_i8: LuaFunction,
+    bytes_read_i16: LuaFunction,
+    bytes_read_i32: LuaFunction,
+    bytes_read_i64: LuaFunction,
+    bytes_to_table: LuaFunction,
+    bytes_to_string: LuaFunction,
+    bytes_close: LuaFunction
+}
+
+impl BytesAPI {
+    pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
+        let buffers: BuffersHandles = Arc::new(Mutex::new(HashMap::new()));
+
+        Ok(Self {
+            bytes_new: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |_, data: Option<LuaValue>| {
+                    let mut buffer = Buffer::default();
+
+                    if let Some(data) = data {
+                        buffer.write_all(&lua_value_to_bytes(data)?)?;
+                    }
+
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?;
+
+                    let mut handle = rand::random::<i32>();
+
+                    while buffers.contains_key(&handle) {
+                        handle = rand::random::<i32>();
+                    }
+
+                    buffers.insert(handle, buffer);
+
+                    Ok(handle)
+                })?
+            },
+
+            bytes_len: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    Ok(buffer.as_ref().len())
+                })?
+            },
+
+            bytes_write: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |_, (handle, data): (i32, LuaValue)| {
+                    let data = lua_value_to_bytes(data)?;
+
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    buffer.write_all(&data)?;
+
+                    Ok(())
+                })?
+            },
+
+            bytes_read: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |lua, (handle, len): (i32, Option<usize>)| {
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    let len = len.unwrap_or(buffer.as_ref().len());
+
+                    bytes_to_lua_table(lua, read_bytes(buffer, len)?)
+                })?
+            },
+
+            bytes_write_string: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |_, (handle, string): (i32, LuaString)| {
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    buffer.write_all(&string.as_bytes())?;
+
+                    Ok(())
+                })?
+            },
+
+            bytes_read_string: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |lua, (handle, len): (i32, Option<usize>)| {
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    let len = len.unwrap_or(buffer.as_ref().len());
+
+                    lua.create_string(read_bytes(buffer, len)?)
+                })?
+            },
+
+            bytes_write_varint: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |_, (handle, value): (i32, i64)| {
+                    if value < 0 {
+                        return Err(LuaError::external("varint can't be negative"));
+                    }
+
+                    check_exact(value as i128)?;
+
+                    let mut value = value as u64;
+                    let mut bytes = Vec::with_capacity(VARINT_MAX_LEN);
+
+                    loop {
+                        let byte = (value & 0x7F) as u8;
+
+                        value >>= 7;
+
+                        if value == 0 {
+                            bytes.push(byte);
+
+                            break;
+                        }
+
+                        bytes.push(byte | 0x80);
+                    }
+
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    buffer.write_all(&bytes)?;
+
+                    Ok(())
+                })?
+            },
+
+            bytes_read_varint: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |lua, handle: i32| {
+                    let mut buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    // Find the varint's end before reading it so incomplete
+                    // values are kept in the buffer.
+                    let len = buffer.as_ref()
+                        .iter()
+                        .take(VARINT_MAX_LEN)
+                        .position(|byte| byte & 0x80 == 0)
+                        .ok_or_else(|| LuaError::external("invalid or incomplete varint"))?;
+
+                    let value = buffer.as_ref()[..=len]
+                        .iter()
+                        .enumerate()
+                        .fold(0_u64, |value, (i, byte)| {
+                            value | ((byte & 0x7F) as u64).wrapping_shl(i as u32 * 7)
+                        });
+
+                    check_exact(value as i128)?;
+
+                    read_bytes(buffer, len + 1)?;
+
+                    (value as i64).into_lua(lua)
+                })?
+            },
+
+            bytes_write_u8: int_writer(&lua, buffers.clone(), 1, false)?,
+            bytes_write_u16: int_writer(&lua, buffers.clone(), 2, false)?,
+            bytes_write_u32: int_writer(&lua, buffers.clone(), 4, false)?,
+            bytes_write_u64: int_writer(&lua, buffers.clone(), 8, false)?,
+            bytes_write_i8: int_writer(&lua, buffers.clone(), 1, true)?,
+            bytes_write_i16: int_writer(&lua, buffers.clone(), 2, true)?,
+            bytes_write_i32: int_writer(&lua, buffers.clone(), 4, true)?,
+            bytes_write_i64: int_writer(&lua, buffers.clone(), 8, true)?,
+
+            bytes_read_u8: int_reader(&lua, buffers.clone(), 1, false)?,
+            bytes_read_u16: int_reader(&lua, buffers.clone(), 2, false)?,
+            bytes_read_u32: int_reader(&lua, buffers.clone(), 4, false)?,
+            bytes_read_u64: int_reader(&lua, buffers.clone(), 8, false)?,
+            bytes_read_i8: int_reader(&lua, buffers.clone(), 1, true)?,
+            bytes_read_i16: int_reader(&lua, buffers.clone(), 2, true)?,
+            bytes_read_i32: int_reader(&lua, buffers.clone(), 4, true)?,
+            bytes_read_i64: int_reader(&lua, buffers.clone(), 8, true)?,
+
+            bytes_to_table: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |lua, handle: i32| {
+                    let buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    bytes_to_lua_table(lua, buffer)
+                })?
+            },
+
+            bytes_to_string: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |lua, handle: i32| {
+                    let buffers = buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(buffer) = buffers.get(&handle) else {
+                        return Err(LuaError::external("invalid buffer handle"));
+                    };
+
+                    lua.create_string(buffer)
+                })?
+            },
+
+            bytes_close: {
+                let buffers = buffers.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    buffers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?
+                        .remove(&handle);
+
+                    Ok(())
+                })?
+            },
+
+            lua
+        })
+    }
+
+    #[inline(always)]
+    pub const fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    /// Create new lua table with API functions.
+    pub fn create_env(&self) -> Result<LuaTable, PackagesEngineError> {
+        let env = self.lua.create_table_with_capacity(0, 27)?;
+
+        env.raw_set("new", self.bytes_new.clone())?;
+        env.raw_set("len", self.bytes_len.clone())?;
+        env.raw_set("write", self.bytes_write.clone())?;
+        env.raw_set("read", self.bytes_read.clone())?;
+        env.raw_set("write_string", self.bytes_write_string.clone())?;
+        env.raw_set("read_string", self.bytes_read_string.clone())?;
+        env.raw_set("write_varint", self.bytes_write_varint.clone())?;
+        env.raw_set("read_varint", self.bytes_read_varint.clone())?;
+        env.raw_set("write_u8", self.bytes_write_u8.clone())?;
+        env.raw_set("write_u16", self.bytes_write_u16.clone())?;
+        env.raw_set("write_u32", self.bytes_write_u32.clone())?;
+        env.raw_set("write_u64", self.bytes_write_u64.clone())?;
+        env.raw_set("write_i8", self.bytes_write_i8.clone())?;
+        env.raw_set("write_i16", self.bytes_write_i16.clone())?;
+        env.raw_set("write_i32", self.bytes_write_i32.clone())?;
+        env.raw_set("write_i64", self.bytes_write_i64.clone())?;
+        env.raw_set("read_u8", self.bytes_read_u8.clone())?;
+        env.raw_set("read_u16", self.bytes_read_u16.clone())?;
+        env.raw_set("read_u32", self.bytes_read_u32.clone())?;
+        env.raw_set("read_u64", self.bytes_read_u64.clone())?;
+        env.raw_set("read_i8", self.bytes_read_i8.clone())?;
+        env.raw_set("read_i16", self.bytes_read_i16.clone())?;
+        env.raw_set("read_i32", self.bytes_read_i32.clone())?;
+        env.raw_set("read_i64", self.bytes_read_i64.clone())?;
+        env.raw_set("to_table", self.bytes_to_table.clone())?;
+        env.raw_set("to_string", self.bytes_to_string.clone())?;
+        env.raw_set("close", self.bytes_close.clone())?;
+
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_integers() -> Result<(), PackagesEngineError> {
+        let api = BytesAPI::new(Lua::new())?;
+
+        let handle = api.bytes_new.call::<i32>(())?;
+
+        api.bytes_write_u8.call::<()>((handle, 255))?;
+        api.bytes_write_u16.call::<()>((handle, 0x1234, "le"))?;
+        api.bytes_write_u32.call::<()>((handle, 0x12345678))?;
+        api.bytes_write_i16.call::<()>((handle, -2, "be"))?;
+        api.bytes_write_i64.call::<()>((handle, -MAX_EXACT_INTEGER, "le"))?;
+
+        assert!(api.bytes_write_u8.call::<()>((handle, 256)).is_err());
+        assert!(api.bytes_write_i8.call::<()>((handle, -129)).is_err());
+        assert!(api.bytes_write_u16.call::<()>((handle, 1, "middle")).is_err());
+
+        assert_eq!(api.bytes_len.call::<usize>(handle)?, 17);
+
+        assert_eq!(api.bytes_to_table.call::<Vec<u8>>(handle)?[..9], [
+            0xFF,
+            0x34, 0x12,
+            0x12, 0x34, 0x56, 0x78,
+            0xFF, 0xFE
+        ]);
+
+        assert_eq!(api.bytes_read_u8.call::<u8>(handle)?, 255);
+        assert_eq!(api.bytes_read_u16.call::<u16>((handle, "le"))?, 0x1234);
+        assert_eq!(api.bytes_read_u32.call::<u32>(handle)?, 0x12345678);
+        assert_eq!(api.bytes_read_i16.call::<i16>(handle)?, -2);
+
+        // Bytes of values which can't be represented exactly are kept.
+        assert!(api.bytes_read_u64.call::<u64>((handle, "le")).is_err());
+        assert_eq!(api.bytes_read_i64.call::<i64>((handle, "le"))?, -MAX_EXACT_INTEGER);
+
+        // Not enough bytes are kept in the buffer.
+        api.bytes_write_u8.call::<()>((handle, 1))?;
+
+        assert!(api.bytes_read_i64.call::<i64>(handle).is_err());
+        assert_eq!(api.bytes_read_u8.call::<u8>(handle)?, 1);
+
+        // 64 bit integers are limited to the exactly represented range.
+        for value in [MAX_EXACT_INTEGER, MAX_EXACT_INTEGER - 1, 0] {
+            api.bytes_write_u64.call::<()>((handle, value))?;
+
+            assert_eq!(api.bytes_read_u64.call::<i64>(handle)?, value);
+        }
+
+        for value in [MAX_EXACT_INTEGER, -MAX_EXACT_INTEGER, -1] {
+            api.bytes_write_i64.call::<()>((handle, value, "le"))?;
+
+            assert_eq!(api.bytes_read_i64.call::<i64>((handle, "le"))?, value);
+        }
+
+        assert!(api.bytes_write_u64.call::<()>((handle, MAX_EXACT_INTEGER * 2)).is_err());
+        assert!(api.bytes_write_i64.call::<()>((handle, -MAX_EXACT_INTEGER * 2)).is_err());
+        assert!(api.bytes_write_i64.call::<()>((handle, i64::MAX)).is_err());
+
+        api.bytes_write.call::<()>((handle, vec![0xFF; 8]))?;
+
+        assert!(api.bytes_read_u64.call::<i64>(handle).is_err());
+        assert_eq!(api.bytes_read_i64.call::<i64>(handle)?, -1);
+
+        api.bytes_close.call::<()>(handle)?;
+
+        assert!(api.bytes_len.call::<usize>(handle).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_strings_and_varints() -> Result<(), PackagesEngineError> {
+        let api = BytesAPI::new(Lua::new())?;
+
+        let handle = api.bytes_new.call::<i32>("abc")?;
+
+        api.bytes_write_varint.call::<()>((handle, 300))?;
+        api.bytes_write_varint.call::<()>((handle, 1))?;
+        api.bytes_write_string.call::<()>((handle, "Hello"))?;
+        api.bytes_write.call::<()>((handle, vec![1, 2, 3]))?;
+
+        assert!(api.bytes_write_varint.call::<()>((handle, -1)).is_err());
+
+        assert_eq!(api.bytes_read_string.call::<String>((handle, 3))?, "abc");
+        assert_eq!(api.bytes_read_varint.call::<u64>(handle)?, 300);
+        assert_eq!(api.bytes_read_varint.call::<u64>(handle)?, 1);
+        assert_eq!(api.bytes_read_string.call::<String>((handle, 5))?, "Hello");
+        assert_eq!(api.bytes_to_string.call::<LuaString>(handle)?, [1, 2, 3]);
+        assert_eq!(api.bytes_read.call::<Vec<u8>>(handle)?, [1, 2, 3]);
+
+        assert!(api.bytes_read_varint.call::<u64>(handle).is_err());
+
+        // Varints are limited to the exactly represented range.
+        api.bytes_write_varint.call::<()>((handle, MAX_EXACT_INTEGER))?;
+
+        assert_eq!(api.bytes_read_varint.call::<i64>(handle)?, MAX_EXACT_INTEGER);
+        assert!(api.bytes_write_varint.call::<()>((handle, MAX_EXACT_INTEGER * 2)).is_err());
+
+        api.bytes_write.call::<()>((handle, vec![0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]))?;
+
+        assert!(api.bytes_read_varint.call::<i64>(handle).is_err());
+        assert_eq!(api.bytes_len.call::<usize>(handle)?, 9);
+
+        Ok(())
+    }
+}
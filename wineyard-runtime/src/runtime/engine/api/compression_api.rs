@@ -272,3 +272,29 @@ impl CompressionAPI {
 //     use super::*;
 
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression() -> Result<(), PackagesEngineError> {
+        let api = CompressionAPI::new(Lua::new())?;
+
+        let env = api.create_env()?;
+
+        let data = b"Hello, World!".repeat(64);
+
+        for algorithm in ["lz4", "bzip2", "deflate", "gzip", "zlib", "zstd"] {
+            let compressed = env.call_function::<Vec<u8>>("compress", (algorithm, data.as_slice()))?;
+
+            assert!(compressed.len() < data.len());
+
+            let decompressed = env.call_function::<Vec<u8>>("decompress", (algorithm, compressed))?;
+
+            assert_eq!(decompressed, data);
+        }
+
+        Ok(())
+    }
+}
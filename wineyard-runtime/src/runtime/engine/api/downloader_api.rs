@@ -1,9 +1,9 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use wineyard_core::tasks;
-use wineyard_core::network::downloader::{Downloader, DownloadOptions};
+use wineyard_core::network::downloader::{Downloader, DownloaderTask, DownloadOptions, local_source};
 
 use mlua::prelude::*;
 
@@ -11,28 +11,171 @@ use super::*;
 
 pub const DOWNLOADER_WAIT_UPDATE_INTERVAL: Duration = Duration::from_millis(50);
 
+/// Wrapper of the `downloader.wait` function. Rust functions can't yield
+/// lua coroutines, so the waiting loop is implemented in lua: it yields
+/// control when called from a coroutine and sleeps otherwise.
+const DOWNLOADER_WAIT_WRAPPER: &str = r#"
+    local wait_step, wait_finish = ...
+
+    local function wait(handle)
+        while not wait_step(handle) do end
+
+        return wait_finish(handle)
+    end
+
+    local function wait_async(handle)
+        while not wait_step(handle) do
+            coroutine.yield()
+        end
+
+        return wait_finish(handle)
+    end
+
+    return wait, wait_async
+"#;
+
+struct DownloaderTaskHandle {
+    task: DownloaderTask,
+    on_update: Option<LuaFunction>,
+    on_finish: Option<LuaFunction>,
+
+    /// Maximal time without downloading progress before the task is aborted.
+    timeout: Option<Duration>,
+
+    last_current: u64,
+    last_progress: Instant,
+
+    /// Time of the last task status check used to throttle waiting.
+    last_step: Option<Instant>
+}
+
 pub struct DownloaderAPI {
     lua: Lua,
 
     downloader_create: LuaFunction,
     downloader_download: LuaFunctionBuilder,
     downloader_progress: LuaFunction,
+    downloader_poll: LuaFunction,
     downloader_wait: LuaFunction,
+    downloader_wait_async: LuaFunction,
     downloader_abort: LuaFunction,
     downloader_close: LuaFunction
 }
 
 impl DownloaderAPI {
-    pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
+    pub fn new(lua: Lua, downloader: Downloader) -> Result<Self, PackagesEngineError> {
         let downloader_handles = Arc::new(Mutex::new(HashMap::new()));
-        let tasks_handles = Arc::new(Mutex::new(HashMap::new()));
+        let tasks_handles = Arc::new(Mutex::new(HashMap::<i32, DownloaderTaskHandle>::new()));
+
+        let (downloader_wait, downloader_wait_async) = {
+            // Check the task status, abort it if it timed out and call
+            // the update callback. Return true if the task is finished.
+            //
+            // Do not hold the handles lock while calling the callback
+            // so the task can be aborted from it.
+            //
+            // Consecutive checks are throttled to the update interval so
+            // the waiting doesn't spin.
+            let wait_step = {
+                let tasks_handles = tasks_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let delay = tasks_handles.lock()
+                        .map_err(|err| {
+                            LuaError::external("failed to read downloader handle")
+                                .context(err)
+                        })?
+                        .get(&handle)
+                        .and_then(|task| task.last_step)
+                        .map(|last_step| DOWNLOADER_WAIT_UPDATE_INTERVAL.saturating_sub(last_step.elapsed()))
+                        .unwrap_or_default();
+
+                    if !delay.is_zero() {
+                        std::thread::sleep(delay);
+                    }
+
+                    let (current, total, on_update) = {
+                        let mut handles = tasks_handles.lock()
+                            .map_err(|err| {
+                                LuaError::external("failed to read downloader handle")
+                                    .context(err)
+                            })?;
+
+                        let Some(task) = handles.get_mut(&handle) else {
+                            return Err(LuaError::external("download task was aborted"));
+                        };
+
+                        if task.task.is_finished() {
+                            return Ok(true);
+                        }
+
+                        task.last_step = Some(Instant::now());
+
+                        let current = task.task.current();
+
+                        if current != task.last_current {
+                            task.last_current = current;
+                            task.last_progress = Instant::now();
+                        }
+
+                        else if let Some(timeout) = task.timeout
+                            && task.last_progress.elapsed() > timeout
+                        {
+                            if let Some(task) = handles.remove(&handle) {
+                                task.task.abort();
+                            }
+
+                            return Err(LuaError::external("download task timed out"));
+                        }
+
+                        (current, task.task.total(), task.on_update.clone())
+                    };
+
+                    if let Some(on_update) = &on_update {
+                        on_update.call::<()>((current, total))?;
+                    }
+
+                    Ok(false)
+                })?
+            };
+
+            let wait_finish = {
+                let tasks_handles = tasks_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let task = tasks_handles.lock()
+                        .map_err(|err| {
+                            LuaError::external("failed to read downloader handle")
+                                .context(err)
+                        })?
+                        .remove(&handle);
+
+                    let Some(task) = task else {
+                        return Err(LuaError::external("download task was aborted"));
+                    };
+
+                    let result = tasks::block_on(task.task.wait())
+                        .map_err(LuaError::external)?;
+
+                    if let Some(on_finish) = task.on_finish {
+                        on_finish.call::<()>(result)?;
+                    }
+
+                    Ok(result)
+                })?
+            };
+
+            lua.load(DOWNLOADER_WAIT_WRAPPER)
+                .set_name("downloader.wait")
+                .call::<(LuaFunction, LuaFunction)>((wait_step, wait_finish))?
+        };
 
         Ok(Self {
             downloader_create: {
                 let downloader_handles = downloader_handles.clone();
 
                 lua.create_function(move |_, _: ()| {
-                    let downloader = Downloader::new();
+                    let downloader = downloader.clone();
 
                     let mut handles = downloader_handles.lock()
                         .map_err(|err| {
@@ -62,9 +205,28 @@ impl DownloaderAPI {
                     let tasks_handles = tasks_handles.clone();
 
                     lua.create_function(move |_, (handle, options): (i32, LuaTable)| {
-                        let url = options.get::<LuaString>("url")?;
+                        let mut url = options.get::<LuaString>("url")?
+                            .to_string_lossy();
+
                         let output_file = options.get::<LuaString>("output_file")?;
 
+                        // Local files are copied by the downloader so make
+                        // sure that they're accessible by the module.
+                        if let Some(mut source) = local_source(&url) {
+                            if source.is_relative() {
+                                source = context.module_folder.join(source);
+                            }
+
+                            // Resolve symlinks and ".." components.
+                            let source = source.canonicalize()?;
+
+                            if !context.is_accessible(&source) {
+                                return Err(LuaError::external("path is inaccessible"));
+                            }
+
+                            url = source.to_string_lossy().to_string();
+                        }
+
                         let mut output_file = resolve_path(output_file.to_string_lossy())?;
 
                         if output_file.is_relative() {
@@ -75,6 +237,8 @@ impl DownloaderAPI {
                             return Err(LuaError::external("path is inaccessible"));
                         }
 
+                        context.track_temp(&output_file);
+
                         if let Some(parent) = output_file.parent() {
                             if !parent.is_dir() {
                                 std::fs::create_dir_all(parent)?;
@@ -83,7 +247,9 @@ impl DownloaderAPI {
 
                         let mut download_options = DownloadOptions {
                             continue_download: true,
+                            etag_file: None,
                             on_update: None,
+                            on_chunk: None,
                             on_finish: None
                         };
 
@@ -97,6 +263,11 @@ impl DownloaderAPI {
                         let on_update = options.get::<LuaFunction>("on_update").ok();
                         let on_finish = options.get::<LuaFunction>("on_finish").ok();
 
+                        // Maximal time in milliseconds without downloading
+                        // progress before the task is aborted.
+                        let timeout = options.get::<Option<u64>>("timeout")?
+                            .map(Duration::from_millis);
+
                         let downloader_handles = downloader_handles.lock()
                             .map_err(|err| {
                                 LuaError::external("failed to register downloader handle")
@@ -114,7 +285,7 @@ impl DownloaderAPI {
                             })?;
 
                         let task = downloader.download_with_options(
-                            url.to_string_lossy(),
+                            url,
                             output_file,
                             download_options
                         );
@@ -125,7 +296,15 @@ impl DownloaderAPI {
                             handle = rand::random::<i32>();
                         }
 
-                        tasks_handles.insert(handle, (task, on_update, on_finish));
+                        tasks_handles.insert(handle, DownloaderTaskHandle {
+                            task,
+                            on_update,
+                            on_finish,
+                            timeout,
+                            last_current: 0,
+                            last_progress: Instant::now(),
+                            last_step: None
+                        });
 
                         Ok(handle)
                     })
@@ -142,11 +321,11 @@ impl DownloaderAPI {
                                 .context(err)
                         })?;
 
-                    let Some((task, on_update, _)) = handles.get(&handle) else {
+                    let Some(DownloaderTaskHandle { task, on_update, .. }) = handles.get(&handle) else {
                         return Err(LuaError::external("invalid download task handle"));
                     };
 
-                    let progress = lua.create_table_with_capacity(0, 4)?;
+                    let progress = lua.create_table_with_capacity(0, 6)?;
 
                     let current = task.current();
                     let total = task.total();
@@ -154,8 +333,15 @@ impl DownloaderAPI {
                     progress.raw_set("current", current)?;
                     progress.raw_set("total", total)?;
                     progress.raw_set("fraction", task.fraction())?;
+                    progress.raw_set("speed", task.speed())?;
+                    progress.raw_set("eta", task.eta().map(|eta| eta.as_secs_f64()))?;
                     progress.raw_set("finished", task.is_finished())?;
 
+                    // Release the lock so the callback could abort the task.
+                    let on_update = on_update.clone();
+
+                    drop(handles);
+
                     if let Some(on_update) = on_update {
                         on_update.call::<()>((current, total))?;
                     }
@@ -164,42 +350,27 @@ impl DownloaderAPI {
                 })?
             },
 
-            downloader_wait: {
+            downloader_poll: {
                 let tasks_handles = tasks_handles.clone();
 
                 lua.create_function(move |_, handle: i32| {
-                    let mut handles = tasks_handles.lock()
+                    let handles = tasks_handles.lock()
                         .map_err(|err| {
                             LuaError::external("failed to read downloader handle")
                                 .context(err)
                         })?;
 
-                    let Some((task, on_update, on_finish)) = handles.remove(&handle) else {
+                    let Some(DownloaderTaskHandle { task, .. }) = handles.get(&handle) else {
                         return Err(LuaError::external("invalid download task handle"));
                     };
 
-                    while !task.is_finished() {
-                        if let Some(on_update) = &on_update {
-                            let current = task.current();
-                            let total = task.total();
-
-                            on_update.call::<()>((current, total))?;
-                        }
-
-                        std::thread::sleep(DOWNLOADER_WAIT_UPDATE_INTERVAL);
-                    }
-
-                    let result = tasks::block_on(task.wait())
-                        .map_err(LuaError::external)?;
-
-                    if let Some(on_finish) = on_finish {
-                        on_finish.call::<()>(result)?;
-                    }
-
-                    Ok(result)
+                    Ok((task.is_finished(), task.current(), task.total()))
                 })?
             },
 
+            downloader_wait,
+            downloader_wait_async,
+
             downloader_abort: {
                 let tasks_handles = tasks_handles.clone();
 
@@ -210,8 +381,8 @@ impl DownloaderAPI {
                                 .context(err)
                         })?;
 
-                    if let Some((task, _, _)) = handles.remove(&handle) {
-                        task.abort();
+                    if let Some(task) = handles.remove(&handle) {
+                        task.task.abort();
                     }
 
                     Ok(())
@@ -244,12 +415,14 @@ impl DownloaderAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 6)?;
+        let env = self.lua.create_table_with_capacity(0, 8)?;
 
         env.raw_set("create", self.downloader_create.clone())?;
         env.raw_set("download", (self.downloader_download)(&self.lua, context)?)?;
         env.raw_set("progress", self.downloader_progress.clone())?;
+        env.raw_set("poll", self.downloader_poll.clone())?;
         env.raw_set("wait", self.downloader_wait.clone())?;
+        env.raw_set("wait_async", self.downloader_wait_async.clone())?;
         env.raw_set("abort", self.downloader_abort.clone())?;
         env.raw_set("close", self.downloader_close.clone())?;
 
@@ -305,3 +478,144 @@ impl DownloaderAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloader_progress() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-downloader-progress-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("source.txt"), b"Hello, World!")?;
+
+        let lua = Lua::new();
+        let api = DownloaderAPI::new(lua.clone(), Downloader::new().with_local_files(true))?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        lua.globals().raw_set("downloader", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        lua.load(r#"
+            local handle = downloader.create()
+
+            local task = downloader.download(handle, {
+                url = path .. "/source.txt",
+                output_file = path .. "/output.txt"
+            })
+
+            local progress = downloader.progress(task)
+
+            while not progress.finished do
+                progress = downloader.progress(task)
+            end
+
+            assert(progress.current == 13 and progress.total == 13)
+            assert(progress.fraction == 1)
+            assert(progress.speed >= 0 and progress.speed % 1 == 0)
+            assert(progress.eta == 0)
+
+            assert(downloader.wait(task) == 13)
+
+            -- Files outside of the module's accessible paths can't be copied.
+            for _, url in { "/etc/passwd", "file:///etc/passwd", path .. "/../../etc/passwd" } do
+                assert(not pcall(downloader.download, handle, {
+                    url = url,
+                    output_file = path .. "/passwd"
+                }))
+            end
+
+            downloader.close(handle)
+        "#).exec()?;
+
+        assert_eq!(std::fs::read(path.join("output.txt"))?, b"Hello, World!");
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn downloader_coroutines() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-downloader-coroutines-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("source.txt"), b"Hello, World!")?;
+
+        let lua = Lua::new();
+        let api = DownloaderAPI::new(lua.clone(), Downloader::new().with_local_files(true))?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        lua.globals().raw_set("downloader", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        let results = lua.load(r#"
+            local handle = downloader.create()
+
+            local workers = {}
+            local results = {}
+
+            for i = 1, 2 do
+                local task = downloader.download(handle, {
+                    url = path .. "/source.txt",
+                    output_file = path .. "/output-" .. i .. ".txt"
+                })
+
+                local done, current, total = downloader.poll(task)
+
+                assert(type(done) == "boolean")
+                assert(type(current) == "number" and type(total) == "number")
+
+                table.insert(workers, coroutine.create(function()
+                    results[i] = downloader.wait_async(task)
+                end))
+            end
+
+            -- Blocking waiting doesn't yield.
+            local task = downloader.download(handle, {
+                url = path .. "/source.txt",
+                output_file = path .. "/output-3.txt"
+            })
+
+            local worker = coroutine.create(function()
+                results[3] = downloader.wait(task)
+            end)
+
+            assert(coroutine.resume(worker))
+            assert(coroutine.status(worker) == "dead")
+
+            while #workers > 0 do
+                for i = #workers, 1, -1 do
+                    assert(coroutine.resume(workers[i]))
+
+                    if coroutine.status(workers[i]) == "dead" then
+                        table.remove(workers, i)
+                    end
+                end
+            end
+
+            assert(not pcall(downloader.poll, 0))
+
+            downloader.close(handle)
+
+            return results
+        "#).eval::<Vec<u64>>()?;
+
+        assert_eq!(results, [13, 13, 13]);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
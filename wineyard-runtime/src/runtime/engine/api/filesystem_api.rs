@@ -1,18 +1,74 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Receiver;
 use std::fs::File;
 use std::io::{Read, Write, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
 use std::time::{UNIX_EPOCH, Duration};
+use std::str::FromStr;
+
+use wineyard_core::hashes::HashAlgorithm;
 
 use mlua::prelude::*;
 
 use bufreaderwriter::rand::BufReaderWriterRand;
+use notify::{Watcher, RecommendedWatcher, RecursiveMode, Event, EventKind};
+use notify::event::{ModifyKind, RenameMode};
 
 use super::*;
+use super::path_api::matches_glob;
 
 pub const IO_READ_CHUNK_LEN: usize = 8192; // 8 KiB reads
 pub const IO_BUF_SIZE: usize = 16384; // 16 KiB read/write in-RAM cache
 
+/// Create unique temp file or directory in the context temp folder.
+///
+/// Return path to the created entry.
+fn create_temp_entry(
+    context: &Context,
+    prefix: Option<LuaString>,
+    is_dir: bool
+) -> Result<PathBuf, LuaError> {
+    let prefix = prefix.map(|prefix| prefix.to_string_lossy())
+        .unwrap_or_else(|| String::from(".wineyard-"));
+
+    if prefix.contains(['/', '\\']) {
+        return Err(LuaError::external("temp entry prefix can't contain path separators"));
+    }
+
+    if !context.temp_folder.is_dir() {
+        std::fs::create_dir_all(&context.temp_folder)?;
+    }
+
+    loop {
+        let path = context.temp_folder.join(format!("{prefix}{:016x}", rand::random::<u64>()));
+
+        if !context.is_accessible(&path) {
+            return Err(LuaError::external("path is inaccessible"));
+        }
+
+        context.track_temp(&path);
+
+        let result = if is_dir {
+            std::fs::create_dir(&path)
+        } else {
+            File::create_new(&path).map(|_| ())
+        };
+
+        match result {
+            Ok(()) => return Ok(path),
+
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into())
+        }
+    }
+}
+
+/// Filesystem watcher and the queue of its not yet polled events.
+///
+/// Dropping the watcher stops its thread.
+type FileWatcher = (RecommendedWatcher, Receiver<notify::Result<Event>>);
+
 pub struct FilesystemAPI {
     lua: Lua,
     file_handles: Arc<Mutex<HashMap<i32, BufReaderWriterRand<File>>>>,
@@ -31,18 +87,35 @@ pub struct FilesystemAPI {
     fs_flush: LuaFunction,
     fs_close: LuaFunction,
 
+    fs_open_mmap: LuaFunctionBuilder,
+    fs_mmap_len: LuaFunction,
+    fs_read_mmap: LuaFunction,
+    fs_close_mmap: LuaFunction,
+
+    fs_watch: LuaFunctionBuilder,
+    fs_watch_poll: LuaFunction,
+    fs_close_watch: LuaFunction,
+
     fs_create_file: LuaFunctionBuilder,
     fs_read_file: LuaFunctionBuilder,
     fs_write_file: LuaFunctionBuilder,
+    fs_write_atomic: LuaFunctionBuilder,
     fs_remove_file: LuaFunctionBuilder,
     fs_create_dir: LuaFunctionBuilder,
     fs_read_dir: LuaFunctionBuilder,
-    fs_remove_dir: LuaFunctionBuilder
+    fs_glob: LuaFunctionBuilder,
+    fs_hash_tree: LuaFunctionBuilder,
+    fs_remove_dir: LuaFunctionBuilder,
+
+    fs_temp_file: LuaFunctionBuilder,
+    fs_temp_dir: LuaFunctionBuilder
 }
 
 impl FilesystemAPI {
     pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
         let file_handles = Arc::new(Mutex::new(HashMap::new()));
+        let mmap_handles = Arc::new(Mutex::new(HashMap::<i32, File>::new()));
+        let watch_handles = Arc::new(Mutex::new(HashMap::<i32, FileWatcher>::new()));
 
         Ok(Self {
             fs_exists: Box::new(|lua: &Lua, context: &Context| {
@@ -143,6 +216,8 @@ impl FilesystemAPI {
                         return Err(LuaError::external("target path is inaccessible"));
                     }
 
+                    context.track_temp(&target);
+
                     fn try_copy(source: &Path, target: &Path) -> std::io::Result<()> {
                         if source.is_file() {
                             std::fs::copy(source, target)?;
@@ -209,6 +284,8 @@ impl FilesystemAPI {
                         return Err(LuaError::external("target path is inaccessible"));
                     }
 
+                    context.track_temp(&target);
+
                     fn try_move(source: &Path, target: &Path) -> std::io::Result<()> {
                         if source.is_file() {
                             // Try to rename the file (mv) or copy
@@ -301,6 +378,8 @@ impl FilesystemAPI {
                             return Err(LuaError::external("path is inaccessible"));
                         }
 
+                        context.track_temp(&path);
+
                         if let Some(parent) = path.parent() {
                             if !parent.is_dir() {
                                 std::fs::create_dir_all(parent)?;
@@ -509,6 +588,220 @@ impl FilesystemAPI {
                 })?
             },
 
+            fs_open_mmap: {
+                let mmap_handles = mmap_handles.clone();
+
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
+                    let mmap_handles = mmap_handles.clone();
+
+                    lua.create_function(move |_, path: LuaString| {
+                        let mut path = resolve_path(path.to_string_lossy())?;
+
+                        if path.is_relative() {
+                            path = context.module_folder.join(path);
+                        }
+
+                        if !context.is_accessible(&path) {
+                            return Err(LuaError::external("path is inaccessible"));
+                        }
+
+                        // Files are read using positioned reads instead of
+                        // real memory mapping because reading a mapped file
+                        // truncated by someone else kills the process.
+                        let file = File::open(path)?;
+
+                        let mut handles = mmap_handles.lock()
+                            .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?;
+
+                        let mut handle = rand::random::<i32>();
+
+                        while handles.contains_key(&handle) {
+                            handle = rand::random::<i32>();
+                        }
+
+                        handles.insert(handle, file);
+
+                        Ok(handle)
+                    })
+                })
+            },
+
+            fs_mmap_len: {
+                let mmap_handles = mmap_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let handles = mmap_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(file) = handles.get(&handle) else {
+                        return Err(LuaError::external("invalid mmap handle"));
+                    };
+
+                    Ok(file.metadata()?.len())
+                })?
+            },
+
+            fs_read_mmap: {
+                let mmap_handles = mmap_handles.clone();
+
+                lua.create_function(move |lua, (handle, offset, length): (i32, u64, Option<usize>)| {
+                    let handles = mmap_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(file) = handles.get(&handle) else {
+                        return Err(LuaError::external("invalid mmap handle"));
+                    };
+
+                    let file_len = file.metadata()?.len();
+
+                    if offset > file_len {
+                        return Err(LuaError::external("offset is out of bounds"));
+                    }
+
+                    // Read till the end of the file if there's not enough bytes.
+                    let length = length.unwrap_or(IO_READ_CHUNK_LEN)
+                        .min((file_len - offset) as usize);
+
+                    let mut buf = vec![0; length];
+                    let mut read = 0;
+
+                    while read < length {
+                        let len = file.read_at(&mut buf[read..], offset + read as u64)?;
+
+                        // The file was truncated after its length was read.
+                        if len == 0 {
+                            break;
+                        }
+
+                        read += len;
+                    }
+
+                    bytes_to_lua_table(lua, &buf[..read])
+                })?
+            },
+
+            fs_close_mmap: {
+                let mmap_handles = mmap_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    mmap_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?
+                        .remove(&handle);
+
+                    Ok(())
+                })?
+            },
+
+            fs_watch: {
+                let watch_handles = watch_handles.clone();
+
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
+                    let watch_handles = watch_handles.clone();
+
+                    lua.create_function(move |_, (path, recursive): (LuaString, Option<bool>)| {
+                        let mut path = resolve_path(path.to_string_lossy())?;
+
+                        if path.is_relative() {
+                            path = context.module_folder.join(path);
+                        }
+
+                        if !context.is_accessible(&path) {
+                            return Err(LuaError::external("path is inaccessible"));
+                        }
+
+                        let mode = if recursive.unwrap_or(true) {
+                            RecursiveMode::Recursive
+                        } else {
+                            RecursiveMode::NonRecursive
+                        };
+
+                        let (sender, receiver) = std::sync::mpsc::channel();
+
+                        let mut watcher = notify::recommended_watcher(sender)
+                            .map_err(|err| LuaError::external(format!("failed to create watcher: {err}")))?;
+
+                        watcher.watch(&path, mode)
+                            .map_err(|err| LuaError::external(format!("failed to watch path: {err}")))?;
+
+                        let mut handles = watch_handles.lock()
+                            .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?;
+
+                        let mut handle = rand::random::<i32>();
+
+                        while handles.contains_key(&handle) {
+                            handle = rand::random::<i32>();
+                        }
+
+                        handles.insert(handle, (watcher, receiver));
+
+                        Ok(handle)
+                    })
+                })
+            },
+
+            fs_watch_poll: {
+                let watch_handles = watch_handles.clone();
+
+                lua.create_function(move |lua, handle: i32| {
+                    let handles = watch_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some((_, receiver)) = handles.get(&handle) else {
+                        return Err(LuaError::external("invalid watch handle"));
+                    };
+
+                    let events = lua.create_table()?;
+
+                    // Collect events without blocking the lua engine.
+                    for event in receiver.try_iter() {
+                        let event = event.map_err(|err| {
+                            LuaError::external(format!("failed to watch path: {err}"))
+                        })?;
+
+                        // Renames are reported as deletion of the old path
+                        // and creation of the new one.
+                        let changes = match event.kind {
+                            EventKind::Create(_) |
+                            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => ["created"].repeat(event.paths.len()),
+
+                            EventKind::Remove(_) |
+                            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => ["deleted"].repeat(event.paths.len()),
+
+                            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => vec!["deleted", "created"],
+
+                            EventKind::Modify(_) => ["modified"].repeat(event.paths.len()),
+
+                            _ => vec![]
+                        };
+
+                        for (kind, path) in changes.into_iter().zip(event.paths) {
+                            let entry = lua.create_table_with_capacity(0, 2)?;
+
+                            entry.raw_set("type", kind)?;
+                            entry.raw_set("path", path.to_string_lossy().to_string())?;
+
+                            events.raw_push(entry)?;
+                        }
+                    }
+
+                    Ok(events)
+                })?
+            },
+
+            fs_close_watch: {
+                let watch_handles = watch_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    watch_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?
+                        .remove(&handle);
+
+                    Ok(())
+                })?
+            },
+
             fs_create_file: Box::new(|lua: &Lua, context: &Context| {
                 let context = context.to_owned();
 
@@ -523,6 +816,8 @@ impl FilesystemAPI {
                         return Err(LuaError::external("path is inaccessible"));
                     }
 
+                    context.track_temp(&path);
+
                     if let Some(parent) = path.parent() {
                         if !parent.is_dir() {
                             std::fs::create_dir_all(parent)?;
@@ -567,6 +862,8 @@ impl FilesystemAPI {
                         return Err(LuaError::external("path is inaccessible"));
                     }
 
+                    context.track_temp(&path);
+
                     if let Some(parent) = path.parent() {
                         if !parent.is_dir() {
                             std::fs::create_dir_all(parent)?;
@@ -596,6 +893,88 @@ impl FilesystemAPI {
                 })
             }),
 
+            fs_write_atomic: Box::new(|lua: &Lua, context: &Context| {
+                let context = context.to_owned();
+
+                lua.create_function(move |_, (path, content): (LuaString, LuaValue)| {
+                    let mut path = resolve_path(path.to_string_lossy())?;
+
+                    if path.is_relative() {
+                        path = context.module_folder.join(path);
+                    }
+
+                    let Some(file_name) = path.file_name() else {
+                        return Err(LuaError::external("invalid file path"));
+                    };
+
+                    // Temp file must be a sibling so it's (likely) stored
+                    // on the same filesystem and can be renamed atomically.
+                    let temp_path = path.with_file_name(format!(
+                        ".{}.{:x}.tmp",
+                        file_name.to_string_lossy(),
+                        rand::random::<u32>()
+                    ));
+
+                    if !context.is_accessible(&path) || !context.is_accessible(&temp_path) {
+                        return Err(LuaError::external("path is inaccessible"));
+                    }
+
+                    context.track_temp(&path);
+
+                    let content = match content {
+                        LuaValue::Table(bytes) => bytes.sequence_values()
+                            .collect::<Result<Vec<u8>, _>>()?,
+
+                        LuaValue::String(str) => str.as_bytes().to_vec(),
+
+                        _ => return Err(LuaError::FromLuaConversionError {
+                            from: "table | string",
+                            to: String::from("[u8]"),
+                            message: Some(String::from("bytes table or string expected"))
+                        })
+                    };
+
+                    if let Some(parent) = path.parent()
+                        && !parent.is_dir()
+                    {
+                        std::fs::create_dir_all(parent)?;
+                    }
+
+                    fn try_write(temp_path: &Path, path: &Path, content: &[u8]) -> std::io::Result<()> {
+                        let mut file = File::create(temp_path)?;
+
+                        file.write_all(content)?;
+
+                        // Flush the content to the disk before renaming
+                        // so the file can't end up empty after a crash.
+                        file.sync_all()?;
+
+                        drop(file);
+
+                        std::fs::rename(temp_path, path)?;
+
+                        // Sync the parent folder to persist the rename.
+                        if let Some(parent) = path.parent() {
+                            File::open(parent)?.sync_all()?;
+                        }
+
+                        Ok(())
+                    }
+
+                    if let Err(err) = try_write(&temp_path, &path, &content) {
+                        let _ = std::fs::remove_file(&temp_path);
+
+                        if err.kind() == std::io::ErrorKind::CrossesDevices {
+                            return Err(LuaError::external("temp and target files are stored on different filesystems"));
+                        }
+
+                        return Err(err.into());
+                    }
+
+                    Ok(())
+                })
+            }),
+
             fs_remove_file: Box::new(|lua: &Lua, context: &Context| {
                 let context = context.to_owned();
 
@@ -630,6 +1009,8 @@ impl FilesystemAPI {
                         return Err(LuaError::external("path is inaccessible"));
                     }
 
+                    context.track_temp(&path);
+
                     std::fs::create_dir_all(path)?;
 
                     Ok(())
@@ -655,7 +1036,20 @@ impl FilesystemAPI {
                     for entry in path.read_dir()? {
                         let entry = entry?;
 
-                        let entry_table = lua.create_table_with_capacity(0, 3)?;
+                        let mut target = resolve_path(entry.path().to_string_lossy())?;
+
+                        if target.is_relative() {
+                            target = path.join(target);
+                        }
+
+                        // Fallback to the symlink metadata if it's broken or
+                        // points to an inaccessible entry.
+                        let metadata = match target.metadata() {
+                            Ok(metadata) if context.is_accessible(&target) => metadata,
+                            _ => entry.metadata()?
+                        };
+
+                        let entry_table = lua.create_table_with_capacity(0, 6)?;
 
                         entry_table.raw_set("name", entry.file_name().to_string_lossy().to_string())?;
                         entry_table.raw_set("path", entry.path().to_string_lossy().to_string())?;
@@ -670,6 +1064,17 @@ impl FilesystemAPI {
                             }
                         })?;
 
+                        entry_table.raw_set("is_dir", metadata.is_dir())?;
+                        entry_table.raw_set("size", metadata.len())?;
+
+                        entry_table.raw_set("modified", {
+                            metadata.modified()?
+                                .duration_since(UNIX_EPOCH)
+                                .as_ref()
+                                .map(Duration::as_secs)
+                                .unwrap_or_default()
+                        })?;
+
                         entries.raw_push(entry_table)?;
                     }
 
@@ -677,6 +1082,145 @@ impl FilesystemAPI {
                 })
             }),
 
+            fs_glob: Box::new(|lua: &Lua, context: &Context| {
+                let context = context.to_owned();
+
+                lua.create_function(move |_, (base, pattern): (LuaString, LuaString)| {
+                    let mut base = resolve_path(base.to_string_lossy())?;
+
+                    if base.is_relative() {
+                        base = context.module_folder.join(base);
+                    }
+
+                    if !context.is_accessible(&base) {
+                        return Err(LuaError::external("path is inaccessible"));
+                    }
+
+                    let pattern = pattern.to_string_lossy();
+
+                    let mut paths = Vec::new();
+                    let mut folders = vec![base.clone()];
+
+                    while let Some(folder) = folders.pop() {
+                        for entry in folder.read_dir()? {
+                            let entry = entry?;
+                            let path = entry.path();
+
+                            // Skip symlinks pointing outside of the
+                            // accessible paths.
+                            if !context.is_accessible(resolve_path(path.to_string_lossy())?) {
+                                continue;
+                            }
+
+                            if let Ok(relative) = path.strip_prefix(&base)
+                                && matches_glob(&pattern, relative.to_string_lossy())
+                            {
+                                paths.push(path.to_string_lossy().to_string());
+                            }
+
+                            // Symlinked folders are not followed to avoid
+                            // infinite loops.
+                            if entry.file_type()?.is_dir() {
+                                folders.push(path);
+                            }
+                        }
+                    }
+
+                    paths.sort();
+
+                    Ok(paths)
+                })
+            }),
+
+            fs_hash_tree: Box::new(|lua: &Lua, context: &Context| {
+                let context = context.to_owned();
+
+                lua.create_function(move |lua, (path, algorithm): (LuaString, LuaString)| {
+                    let algorithm = HashAlgorithm::from_str(&algorithm.to_string_lossy())
+                        .map_err(LuaError::external)?;
+
+                    let mut base = resolve_path(path.to_string_lossy())?;
+
+                    if base.is_relative() {
+                        base = context.module_folder.join(base);
+                    }
+
+                    if !context.is_accessible(&base) {
+                        return Err(LuaError::external("path is inaccessible"));
+                    }
+
+                    // relative_path => digest
+                    let mut files = BTreeMap::new();
+
+                    if base.is_file() {
+                        let name = base.file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default();
+
+                        files.insert(name, Hash::for_entry_with(algorithm, &base)?);
+                    }
+
+                    else {
+                        let mut folders = vec![base.clone()];
+
+                        while let Some(folder) = folders.pop() {
+                            for entry in folder.read_dir()? {
+                                let entry = entry?;
+                                let path = entry.path();
+
+                                // Skip symlinks pointing outside of the
+                                // accessible paths.
+                                let target = resolve_path(path.to_string_lossy())?;
+
+                                if !context.is_accessible(&target) {
+                                    continue;
+                                }
+
+                                // Symlinked folders are not followed to avoid
+                                // infinite loops.
+                                if entry.file_type()?.is_dir() {
+                                    folders.push(path);
+                                }
+
+                                else if target.is_file()
+                                    && let Ok(relative) = path.strip_prefix(&base)
+                                {
+                                    files.insert(
+                                        relative.to_string_lossy().to_string(),
+                                        Hash::for_entry_with(algorithm, target)?
+                                    );
+                                }
+                            }
+                        }
+                    }
+
+                    // Root hash is the digest of sorted files' relative
+                    // paths and digests, so equal trees have equal roots.
+                    let mut manifest = Vec::new();
+
+                    for (path, digest) in &files {
+                        manifest.extend_from_slice(path.as_bytes());
+                        manifest.push(0);
+                        manifest.extend_from_slice(digest.as_bytes());
+                    }
+
+                    let root = Hash::for_slice_with(algorithm, manifest)?;
+
+                    let files_table = lua.create_table_with_capacity(0, files.len())?;
+
+                    for (path, digest) in files {
+                        files_table.raw_set(path, digest.to_hex())?;
+                    }
+
+                    let result = lua.create_table_with_capacity(0, 2)?;
+
+                    result.raw_set("root", root.to_hex())?;
+                    result.raw_set("files", files_table)?;
+
+                    Ok(result)
+                })
+            }),
+
             fs_remove_dir: Box::new(|lua: &Lua, context: &Context| {
                 let context = context.to_owned();
 
@@ -697,6 +1241,36 @@ impl FilesystemAPI {
                 })
             }),
 
+            fs_temp_file: Box::new(|lua: &Lua, context: &Context| {
+                let context = context.to_owned();
+
+                lua.create_function(move |_, prefix: Option<LuaString>| {
+                    let path = create_temp_entry(&context, prefix, false)?;
+
+                    // Removed by the engine with other temp entries.
+                    context.temp_entries.lock()
+                        .map_err(|err| LuaError::external(format!("failed to register temp file: {err}")))?
+                        .insert(path.clone());
+
+                    Ok(path.to_string_lossy().to_string())
+                })
+            }),
+
+            fs_temp_dir: Box::new(|lua: &Lua, context: &Context| {
+                let context = context.to_owned();
+
+                lua.create_function(move |_, prefix: Option<LuaString>| {
+                    let path = create_temp_entry(&context, prefix, true)?;
+
+                    // Removed by the engine with other temp entries.
+                    context.temp_entries.lock()
+                        .map_err(|err| LuaError::external(format!("failed to register temp folder: {err}")))?
+                        .insert(path.clone());
+
+                    Ok(path.to_string_lossy().to_string())
+                })
+            }),
+
             lua,
             file_handles
         })
@@ -714,7 +1288,7 @@ impl FilesystemAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 20)?;
+        let env = self.lua.create_table_with_capacity(0, 32)?;
 
         env.raw_set("exists", (self.fs_exists)(&self.lua, context)?)?;
         env.raw_set("metadata", (self.fs_metadata)(&self.lua, context)?)?;
@@ -730,14 +1304,29 @@ impl FilesystemAPI {
         env.raw_set("flush", self.fs_flush.clone())?;
         env.raw_set("close", self.fs_close.clone())?;
 
+        env.raw_set("open_mmap", (self.fs_open_mmap)(&self.lua, context)?)?;
+        env.raw_set("mmap_len", self.fs_mmap_len.clone())?;
+        env.raw_set("read_mmap", self.fs_read_mmap.clone())?;
+        env.raw_set("close_mmap", self.fs_close_mmap.clone())?;
+
+        env.raw_set("watch", (self.fs_watch)(&self.lua, context)?)?;
+        env.raw_set("watch_poll", self.fs_watch_poll.clone())?;
+        env.raw_set("close_watch", self.fs_close_watch.clone())?;
+
         env.raw_set("create_file", (self.fs_create_file)(&self.lua, context)?)?;
         env.raw_set("read_file", (self.fs_read_file)(&self.lua, context)?)?;
         env.raw_set("write_file", (self.fs_write_file)(&self.lua, context)?)?;
+        env.raw_set("write_atomic", (self.fs_write_atomic)(&self.lua, context)?)?;
         env.raw_set("remove_file", (self.fs_remove_file)(&self.lua, context)?)?;
         env.raw_set("create_dir", (self.fs_create_dir)(&self.lua, context)?)?;
         env.raw_set("read_dir", (self.fs_read_dir)(&self.lua, context)?)?;
+        env.raw_set("glob", (self.fs_glob)(&self.lua, context)?)?;
+        env.raw_set("hash_tree", (self.fs_hash_tree)(&self.lua, context)?)?;
         env.raw_set("remove_dir", (self.fs_remove_dir)(&self.lua, context)?)?;
 
+        env.raw_set("temp_file", (self.fs_temp_file)(&self.lua, context)?)?;
+        env.raw_set("temp_dir", (self.fs_temp_dir)(&self.lua, context)?)?;
+
         Ok(env)
     }
 }
@@ -1099,3 +1688,339 @@ impl FilesystemAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fs_read_dir() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-read-dir-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        let outside = std::env::temp_dir().join(".wineyard-runtime-fs-read-dir-outside-test");
+
+        if outside.exists() {
+            std::fs::remove_dir_all(&outside)?;
+        }
+
+        std::fs::create_dir_all(path.join("folder"))?;
+        std::fs::create_dir_all(outside.join("folder"))?;
+        std::fs::write(path.join("file"), b"Hello, World!")?;
+        std::fs::write(outside.join("file"), [0; 1024])?;
+        std::os::unix::fs::symlink(path.join("file"), path.join("symlink"))?;
+        std::os::unix::fs::symlink(outside.join("file"), path.join("symlink_outside_file"))?;
+        std::os::unix::fs::symlink(outside.join("folder"), path.join("symlink_outside_folder"))?;
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let mut entries = env.call_function::<Vec<LuaTable>>("read_dir", path.to_string_lossy().to_string())?;
+
+        entries.sort_by_key(|entry| entry.get::<String>("name").unwrap_or_default());
+
+        assert_eq!(entries.len(), 5);
+
+        assert_eq!(entries[0].get::<String>("name")?, "file");
+        assert!(!entries[0].get::<bool>("is_dir")?);
+        assert_eq!(entries[0].get::<u64>("size")?, 13);
+        assert!(entries[0].get::<u64>("modified")? > 0);
+
+        assert_eq!(entries[1].get::<String>("name")?, "folder");
+        assert!(entries[1].get::<bool>("is_dir")?);
+
+        assert_eq!(entries[2].get::<String>("name")?, "symlink");
+        assert_eq!(entries[2].get::<String>("type")?, "symlink");
+        assert_eq!(entries[2].get::<u64>("size")?, 13);
+
+        // Metadata of inaccessible targets is not exposed.
+        assert_eq!(entries[3].get::<String>("name")?, "symlink_outside_file");
+        assert_eq!(entries[3].get::<u64>("size")?, outside.join("file").as_os_str().len() as u64);
+
+        assert_eq!(entries[4].get::<String>("name")?, "symlink_outside_folder");
+        assert!(!entries[4].get::<bool>("is_dir")?);
+
+        assert!(env.call_function::<Vec<LuaTable>>("read_dir", "/").is_err());
+
+        std::fs::remove_dir_all(path)?;
+        std::fs::remove_dir_all(outside)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fs_glob() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-glob-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("prefix/system32"))?;
+        std::fs::write(path.join("prefix/a.dll"), b"")?;
+        std::fs::write(path.join("prefix/system32/b.dll"), b"")?;
+        std::fs::write(path.join("prefix/system32/c.exe"), b"")?;
+        std::os::unix::fs::symlink("/", path.join("prefix/root"))?;
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let prefix = path.join("prefix");
+
+        assert_eq!(env.call_function::<Vec<String>>("glob", ("prefix", "*.dll"))?, [
+            prefix.join("a.dll").to_string_lossy(),
+            prefix.join("system32/b.dll").to_string_lossy()
+        ]);
+
+        assert_eq!(env.call_function::<Vec<String>>("glob", ("prefix", "system32/*"))?, [
+            prefix.join("system32/b.dll").to_string_lossy(),
+            prefix.join("system32/c.exe").to_string_lossy()
+        ]);
+
+        assert_eq!(env.call_function::<Vec<String>>("glob", ("prefix", "**/?.exe"))?, [
+            prefix.join("system32/c.exe").to_string_lossy()
+        ]);
+
+        assert!(env.call_function::<Vec<String>>("glob", ("prefix", "root*"))?.is_empty());
+        assert!(env.call_function::<Vec<String>>("glob", ("/", "*.dll")).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fs_hash_tree() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-hash-tree-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("a/system32"))?;
+        std::fs::create_dir_all(path.join("b/system32"))?;
+
+        for install in ["a", "b"] {
+            std::fs::write(path.join(install).join("game.exe"), b"Hello, World!")?;
+            std::fs::write(path.join(install).join("system32/lib.dll"), b"Example library")?;
+            std::os::unix::fs::symlink("/", path.join(install).join("root"))?;
+        }
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let tree = env.call_function::<LuaTable>("hash_tree", ("a", "sha2-256"))?;
+        let files = tree.get::<HashMap<String, String>>("files")?;
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files["game.exe"], Hash::for_slice_with(HashAlgorithm::Sha2_256, b"Hello, World!")?.to_hex());
+        assert!(files.contains_key("system32/lib.dll"));
+
+        let root = |install: &str| -> Result<String, LuaError> {
+            env.call_function::<LuaTable>("hash_tree", (install, "sha2-256"))?
+                .get::<String>("root")
+        };
+
+        assert_eq!(root("a")?, root("b")?);
+
+        std::fs::write(path.join("b/system32/lib.dll"), b"Changed library")?;
+
+        assert_ne!(root("a")?, root("b")?);
+
+        assert!(env.call_function::<LuaTable>("hash_tree", ("/", "sha2-256")).is_err());
+        assert!(env.call_function::<LuaTable>("hash_tree", ("a", "invalid")).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fs_write_atomic() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-write-atomic-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let file = path.join("config.json").to_string_lossy().to_string();
+
+        env.call_function::<()>("write_atomic", (file.clone(), "Hello, World!"))?;
+        env.call_function::<()>("write_atomic", (file.clone(), vec![1, 2, 3]))?;
+
+        assert_eq!(std::fs::read(&file)?, &[1, 2, 3]);
+
+        // No temp files left.
+        assert_eq!(std::fs::read_dir(&path)?.count(), 1);
+
+        assert!(env.call_function::<()>("write_atomic", ("/.wineyard-runtime-fs-write-atomic-test", "")).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fs_mmap() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-mmap-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("file"), b"Hello, World!")?;
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let handle = env.call_function::<i32>("open_mmap", "file")?;
+
+        assert_eq!(env.call_function::<usize>("mmap_len", handle)?, 13);
+        assert_eq!(env.call_function::<Vec<u8>>("read_mmap", (handle, 7, 5))?, b"World");
+        assert_eq!(env.call_function::<Vec<u8>>("read_mmap", (handle, 7, 100))?, b"World!");
+        assert_eq!(env.call_function::<Vec<u8>>("read_mmap", (handle, 13))?.len(), 0);
+
+        assert!(env.call_function::<Vec<u8>>("read_mmap", (handle, 14, 1)).is_err());
+        assert!(env.call_function::<i32>("open_mmap", "/etc/hostname").is_err());
+
+        // Truncated files are read safely.
+        std::fs::File::options()
+            .write(true)
+            .open(path.join("file"))?
+            .set_len(5)?;
+
+        assert_eq!(env.call_function::<usize>("mmap_len", handle)?, 5);
+        assert_eq!(env.call_function::<Vec<u8>>("read_mmap", (handle, 0, 100))?, b"Hello");
+        assert!(env.call_function::<Vec<u8>>("read_mmap", (handle, 7, 5)).is_err());
+
+        env.call_function::<()>("close_mmap", handle)?;
+
+        assert!(env.call_function::<usize>("mmap_len", handle).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fs_watch() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-watch-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("config"))?;
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let handle = env.call_function::<i32>("watch", "config")?;
+
+        assert!(env.call_function::<Vec<LuaTable>>("watch_poll", handle)?.is_empty());
+
+        let file = path.join("config/game.ini");
+
+        std::fs::write(&file, b"fps = 60")?;
+        std::fs::write(&file, b"fps = 120")?;
+        std::fs::remove_file(&file)?;
+
+        let mut changes = Vec::new();
+
+        // Events are delivered by the watcher thread.
+        for _ in 0..100 {
+            for event in env.call_function::<Vec<LuaTable>>("watch_poll", handle)? {
+                changes.push((event.get::<String>("type")?, event.get::<String>("path")?));
+            }
+
+            if changes.iter().any(|(kind, _)| kind == "deleted") {
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let file = file.to_string_lossy().to_string();
+
+        assert_eq!(changes.first(), Some(&(String::from("created"), file.clone())));
+        assert!(changes.contains(&(String::from("modified"), file.clone())));
+        assert_eq!(changes.last(), Some(&(String::from("deleted"), file)));
+
+        env.call_function::<()>("close_watch", handle)?;
+
+        assert!(env.call_function::<Vec<LuaTable>>("watch_poll", handle).is_err());
+        assert!(env.call_function::<i32>("watch", "/etc").is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fs_temp_entries() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-fs-temp-entries-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let api = FilesystemAPI::new(Lua::new())?;
+
+        let context = Context {
+            temp_folder: path.join("temp"),
+            module_folder: path.join("module"),
+            persistent_folder: path.join("persistent"),
+            ..Context::test(&path, path.join("local_validator.json"))?
+        };
+
+        let env = api.create_env(&context)?;
+
+        let file = PathBuf::from(env.call_function::<String>("temp_file", ())?);
+        let folder = PathBuf::from(env.call_function::<String>("temp_dir", "cache-")?);
+
+        assert!(file.is_file());
+        assert!(folder.is_dir());
+
+        assert!(file.starts_with(path.join("temp")));
+        assert!(folder.starts_with(path.join("temp")));
+
+        assert!(folder.file_name().is_some_and(|name| name.to_string_lossy().starts_with("cache-")));
+        assert_ne!(file, PathBuf::from(env.call_function::<String>("temp_file", ())?));
+
+        assert!(env.call_function::<String>("temp_file", "../").is_err());
+
+        std::fs::write(folder.join("file.txt"), b"Hello, World!")?;
+
+        drop(env);
+        drop(api);
+
+        // Entries are removed by the engine, not the API.
+        assert!(file.exists());
+        assert!(folder.exists());
+
+        let temp_entries = context.temp_entries.lock()
+            .map_err(|_| PackagesEngineError::TempEntriesLock)?;
+
+        assert!(temp_entries.contains(&file));
+        assert!(temp_entries.contains(&folder));
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
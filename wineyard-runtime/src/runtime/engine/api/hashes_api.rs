@@ -11,11 +11,47 @@ use mlua::prelude::*;
 use super::filesystem_api::IO_READ_CHUNK_LEN;
 use super::*;
 
+/// Stream file content through the hasher without loading it in memory.
+fn hash_file(context: &Context, path: LuaString, algorithm: LuaString) -> Result<Box<[u8]>, LuaError> {
+    let algorithm = HashAlgorithm::from_str(&algorithm.to_string_lossy())
+        .map_err(LuaError::external)?;
+
+    let mut path = resolve_path(path.to_string_lossy())?;
+
+    if path.is_relative() {
+        path = context.module_folder.join(path);
+    }
+
+    if !context.is_accessible(&path) {
+        return Err(LuaError::external("path is inaccessible"));
+    }
+
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+
+    let mut buf = [0; IO_READ_CHUNK_LEN];
+
+    loop {
+        let n = file.read(&mut buf)?;
+
+        if n == 0 {
+            break;
+        }
+
+        hasher.write_all(&buf[..n])?;
+        hasher.flush()?;
+    }
+
+    Ok(hasher.finalize().0)
+}
+
 pub struct HashesAPI {
     lua: Lua,
 
     hashes_hash: LuaFunction,
+    hashes_with_seed: LuaFunction,
     hashes_file_hash: LuaFunctionBuilder,
+    hashes_file: LuaFunctionBuilder,
     hashes_hasher: LuaFunction,
     hashes_write: LuaFunction,
     hashes_finalize: LuaFunction
@@ -40,43 +76,38 @@ impl HashesAPI {
                 Ok(hash)
             })?,
 
-            hashes_file_hash: {
-                Box::new(move |lua: &Lua, context: &Context| {
-                    let context = context.to_owned();
-
-                    lua.create_function(move |_, (algorithm, path): (LuaString, LuaString)| {
-                        let algorithm = HashAlgorithm::from_str(&algorithm.to_string_lossy())
-                            .map_err(LuaError::external)?;
-
-                        let mut path = resolve_path(path.to_string_lossy())?;
-
-                        if path.is_relative() {
-                            path = context.module_folder.join(path);
-                        }
+            // Algorithms without native seed support get the seed
+            // prepended to the hashed data.
+            hashes_with_seed: lua.create_function(move |_, (algorithm, seed, value): (LuaString, LuaValue, LuaValue)| {
+                let algorithm = HashAlgorithm::from_str(&algorithm.to_string_lossy())
+                    .map_err(LuaError::external)?;
 
-                        if !context.is_accessible(&path) {
-                            return Err(LuaError::external("path is inaccessible"));
-                        }
+                let mut hasher = Hasher::with_seed(algorithm, lua_value_to_bytes(seed)?);
 
-                        let mut file = File::open(path)?;
-                        let mut hasher = Hasher::new(algorithm);
+                hasher.write_all(&lua_value_to_bytes(value)?)?;
+                hasher.flush()?;
 
-                        let mut buf = [0; IO_READ_CHUNK_LEN];
+                let hash = hasher.finalize().0;
 
-                        loop {
-                            let n = file.read(&mut buf)?;
+                Ok(hash)
+            })?,
 
-                            if n == 0 {
-                                break;
-                            }
+            hashes_file_hash: {
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
 
-                            hasher.write_all(&buf[..n])?;
-                            hasher.flush()?;
-                        }
+                    lua.create_function(move |_, (algorithm, path): (LuaString, LuaString)| {
+                        hash_file(&context, path, algorithm)
+                    })
+                })
+            },
 
-                        let hash = hasher.finalize().0;
+            hashes_file: {
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
 
-                        Ok(hash)
+                    lua.create_function(move |_, (path, algorithm): (LuaString, LuaString)| {
+                        hash_file(&context, path, algorithm)
                     })
                 })
             },
@@ -84,10 +115,15 @@ impl HashesAPI {
             hashes_hasher: {
                 let hasher_handles = hasher_handles.clone();
 
-                lua.create_function(move |_, algorithm: LuaString| {
+                lua.create_function(move |_, (algorithm, seed): (LuaString, LuaValue)| {
                     let algorithm = HashAlgorithm::from_str(&algorithm.to_string_lossy())
                         .map_err(LuaError::external)?;
 
+                    let hasher = match seed {
+                        LuaValue::Nil => Hasher::new(algorithm),
+                        seed => Hasher::with_seed(algorithm, lua_value_to_bytes(seed)?)
+                    };
+
                     let mut hashers = hasher_handles.lock()
                         .map_err(|err| {
                             LuaError::external("failed to register hasher handle")
@@ -100,7 +136,7 @@ impl HashesAPI {
                         handle = rand::random::<i32>();
                     }
 
-                    hashers.insert(handle, Hasher::new(algorithm));
+                    hashers.insert(handle, hasher);
 
                     Ok(handle)
                 })?
@@ -150,14 +186,20 @@ impl HashesAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 5)?;
+        let env = self.lua.create_table_with_capacity(0, 9)?;
 
         env.raw_set("hash", self.hashes_hash.clone())?;
+        env.raw_set("with_seed", self.hashes_with_seed.clone())?;
         env.raw_set("file_hash", (self.hashes_file_hash)(&self.lua, context)?)?;
+        env.raw_set("file", (self.hashes_file)(&self.lua, context)?)?;
         env.raw_set("hasher", self.hashes_hasher.clone())?;
         env.raw_set("write", self.hashes_write.clone())?;
         env.raw_set("finalize", self.hashes_finalize.clone())?;
 
+        // Aliases for incremental hashing.
+        env.raw_set("open", self.hashes_hasher.clone())?;
+        env.raw_set("update", self.hashes_write.clone())?;
+
         Ok(env)
     }
 }
@@ -226,3 +268,68 @@ impl HashesAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_streaming() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-hash-streaming-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("file"), b"Hello, World!")?;
+
+        let api = HashesAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        let expected = [
+            223, 253, 96, 33, 187, 43, 213, 176, 175, 103, 98, 144, 128, 158, 195, 165,
+            49, 145, 221, 129, 199, 247, 10, 75, 40, 104, 138, 54, 33, 130, 152, 111
+        ];
+
+        assert_eq!(env.call_function::<Vec<u8>>("file", ("file", "sha2-256"))?, expected);
+        assert!(env.call_function::<Vec<u8>>("file", ("/etc/hostname", "sha2-256")).is_err());
+
+        let handle = env.call_function::<i32>("open", "sha2-256")?;
+
+        env.call_function::<()>("update", (handle, "Hello"))?;
+        env.call_function::<()>("update", (handle, ", "))?;
+        env.call_function::<()>("update", (handle, b"World!".to_vec()))?;
+
+        assert_eq!(env.call_function::<Vec<u8>>("finalize", handle)?, expected);
+        assert!(env.call_function::<Vec<u8>>("finalize", handle).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn hash_with_seed() -> Result<(), PackagesEngineError> {
+        let api = HashesAPI::new(Lua::new())?;
+
+        for algorithm in ["seahash", "xxh64", "sha2-256"] {
+            let hash = |seed: &str| {
+                api.hashes_with_seed.call::<Vec<u8>>((algorithm, seed, "Hello, World!"))
+            };
+
+            assert_eq!(hash("seed 1")?, hash("seed 1")?);
+            assert_ne!(hash("seed 1")?, hash("seed 2")?);
+            assert_ne!(hash("seed 1")?, api.hashes_hash.call::<Vec<u8>>((algorithm, "Hello, World!"))?);
+
+            let handle = api.hashes_hasher.call::<i32>((algorithm, "seed 1"))?;
+
+            api.hashes_write.call::<()>((handle, "Hello, World!"))?;
+
+            assert_eq!(api.hashes_finalize.call::<Vec<u8>>(handle)?, hash("seed 1")?);
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,20 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use wineyard_core::export::network::reqwest;
+use wineyard_core::network::proxy::client_with_proxy;
 
 use mlua::prelude::*;
 use mlua::Variadic;
 
 use crate::hash::Hash;
+use crate::runtime::local_validator::LocalValidator;
 
-use super::{PackagesEngineError, Options};
+use super::{PackagesEngineError, Options, LogLevel};
 
 mod string_api;
+mod bytes_api;
 mod path_api;
 mod filesystem_api;
 mod network_api;
@@ -19,18 +24,22 @@ mod hashes_api;
 mod compression_api;
 mod sync_api;
 mod sqlite_api;
+mod time_api;
 // mod portals_api;
 mod process_api;
 
 pub use string_api::StringAPI;
+pub use bytes_api::BytesAPI;
 pub use path_api::PathAPI;
 pub use filesystem_api::FilesystemAPI;
-pub use network_api::NetworkAPI;
+pub use network_api::{NetworkAPI, NetworkLimits};
 pub use downloader_api::DownloaderAPI;
 pub use archives_api::ArchivesAPI;
 pub use hashes_api::HashesAPI;
+pub use compression_api::CompressionAPI;
 pub use sync_api::SyncAPI;
 pub use sqlite_api::SQLiteAPI;
+pub use time_api::TimeAPI;
 // pub use portals_api::{
 //     PortalsAPI,
 //     Options as PortalsAPIOptions,
@@ -42,20 +51,68 @@ pub use sqlite_api::SQLiteAPI;
 // };
 pub use process_api::ProcessAPI;
 
+/// Maximal amount of symlinks followed by the `resolve_path` function,
+/// same as in the linux kernel.
+pub const MAX_SYMLINK_HOPS: usize = 40;
+
 /// Resolve symlink path into an actual filesystem entry path.
+///
+/// Relative symlink targets are resolved against the symlink's parent
+/// folder. Return `ELOOP` error if more than `MAX_SYMLINK_HOPS` symlinks
+/// are followed, e.g. because of a symlinks loop.
 pub fn resolve_path(path: impl AsRef<str>) -> std::io::Result<PathBuf> {
     let mut path = PathBuf::from(path.as_ref());
+    let mut hops = 0;
 
     while path.is_symlink() {
-        path = path.read_link()?;
+        if hops == MAX_SYMLINK_HOPS {
+            return Err(std::io::Error::from_raw_os_error(libc::ELOOP));
+        }
+
+        let target = path.read_link()?;
+
+        path = match path.parent() {
+            Some(parent) if target.is_relative() => parent.join(target),
+            _ => target
+        };
+
+        hops += 1;
     }
 
     Ok(path)
 }
 
+/// Parse endianness name, returning `true` for little endian.
+///
+/// Big endian is used by default.
+pub fn is_little_endian(endianness: Option<LuaString>) -> Result<bool, LuaError> {
+    let Some(endianness) = endianness else {
+        return Ok(false);
+    };
+
+    match endianness.as_bytes().as_ref() {
+        b"le" | b"little" => Ok(true),
+        b"be" | b"big"    => Ok(false),
+
+        _ => Err(LuaError::external("unknown endianness"))
+    }
+}
+
 /// Convert arbitrary lua value into bytes slice some reasonable way.
+///
+/// Numbers are encoded as big endian.
+#[inline]
 pub fn lua_value_to_bytes(value: LuaValue) -> Result<Vec<u8>, LuaError> {
+    lua_value_to_bytes_with_endianness(value, false)
+}
+
+/// Convert arbitrary lua value into bytes slice some reasonable way,
+/// encoding numbers with the given endianness.
+pub fn lua_value_to_bytes_with_endianness(value: LuaValue, little_endian: bool) -> Result<Vec<u8>, LuaError> {
     match value {
+        LuaValue::Number(value) if little_endian  => Ok(value.to_le_bytes().to_vec()),
+        LuaValue::Integer(value) if little_endian => Ok(value.to_le_bytes().to_vec()),
+
         LuaValue::Number(value)  => Ok(value.to_be_bytes().to_vec()),
         LuaValue::Integer(value) => Ok(value.to_be_bytes().to_vec()),
         LuaValue::String(value)  => Ok(value.as_bytes().to_vec()),
@@ -89,6 +146,9 @@ pub fn bytes_to_lua_table(lua: &Lua, slice: impl AsRef<[u8]>) -> Result<LuaTable
 
 type LuaFunctionBuilder = Box<dyn Fn(&Lua, &Context) -> Result<LuaFunction, LuaError>>;
 
+/// Shared list of entries created by the modules in the temp folder.
+pub type TempEntries = Arc<Mutex<HashSet<PathBuf>>>;
+
 #[derive(Debug, Clone)]
 pub struct Context {
     pub resource_hash: Hash,
@@ -98,16 +158,42 @@ pub struct Context {
     pub persistent_folder: PathBuf,
     pub input_resources: Vec<PathBuf>,
 
+    /// Top-level entries of the temp folder created by the modules.
+    pub temp_entries: TempEntries,
+
     /// Include Process API in the environment.
     pub ext_process_api: bool,
 
     /// Allow to access extra paths.
     pub ext_allowed_paths: Vec<PathBuf>,
 
-    // pub local_validator: LocalValidator
+    /// Validator used to check paths granted after the context was created.
+    pub local_validator: LocalValidator
 }
 
 impl Context {
+    /// Remember the top-level entry of the temp folder containing the given
+    /// path if it doesn't exist yet, so it can be removed later. The temp
+    /// folder can be shared, so already existing entries are not tracked.
+    pub fn track_temp(&self, path: impl AsRef<Path>) {
+        let Ok(relative) = path.as_ref().strip_prefix(&self.temp_folder) else {
+            return;
+        };
+
+        let Some(name) = relative.components().next() else {
+            return;
+        };
+
+        let entry = self.temp_folder.join(name);
+
+        if let Ok(mut temp_entries) = self.temp_entries.lock()
+            && !temp_entries.contains(&entry)
+            && !entry.exists()
+        {
+            temp_entries.insert(entry);
+        }
+    }
+
     /// Check if given path is accessible from the current context.
     pub fn is_accessible(&self, path: impl AsRef<Path>) -> bool {
         let allowed_paths = [
@@ -128,18 +214,42 @@ impl Context {
             }
         }
 
-        // if let Some(allowed_paths) = self.local_validator.get_allowed_paths(&self.resource_hash) {
-        //     for allowed_path in allowed_paths {
-        //         if path.starts_with(allowed_path) {
-        //             return true;
-        //         }
-        //     }
-        // }
+        if let Some(allowed_paths) = self.local_validator.get_allowed_paths(&self.resource_hash) {
+            for allowed_path in allowed_paths {
+                if path.starts_with(allowed_path) {
+                    return true;
+                }
+            }
+        }
 
         false
     }
 }
 
+#[cfg(test)]
+impl Context {
+    /// Create context for tests with all the module folders set to the
+    /// given path and no extra privileges.
+    pub fn test(
+        folder: impl Into<PathBuf>,
+        local_validator: impl Into<PathBuf>
+    ) -> Result<Self, crate::runtime::local_validator::LocalValidatorError> {
+        let folder: PathBuf = folder.into();
+
+        Ok(Self {
+            resource_hash: Hash::rand(),
+            temp_folder: folder.clone(),
+            module_folder: folder.clone(),
+            persistent_folder: folder,
+            input_resources: vec![],
+            temp_entries: TempEntries::default(),
+            ext_process_api: false,
+            ext_allowed_paths: vec![],
+            local_validator: LocalValidator::open(local_validator)?
+        })
+    }
+}
+
 pub struct API {
     lua: Lua,
 
@@ -147,23 +257,35 @@ pub struct API {
     dbg: LuaFunction,
 
     string_api: StringAPI,
+    bytes_api: BytesAPI,
     path_api: PathAPI,
     filesystem_api: FilesystemAPI,
     network_api: NetworkAPI,
     downloader_api: DownloaderAPI,
     archives_api: ArchivesAPI,
     hashes_api: HashesAPI,
+    compression_api: CompressionAPI,
     sync_api: SyncAPI,
     sqlite_api: SQLiteAPI,
+    time_api: TimeAPI,
     // portals_api: PortalsAPI,
     process_api: ProcessAPI
 }
 
 impl API {
     /// Create new v1 standard using provided lua engine.
-    pub fn new(lua: Lua, _options: Options) -> Result<Self, PackagesEngineError> {
+    pub fn new(lua: Lua, options: Options) -> Result<Self, PackagesEngineError> {
         let filesystem_api = FilesystemAPI::new(lua.clone())?;
 
+        let client = match &options.proxy {
+            Some(proxy) => client_with_proxy(proxy, None)?,
+            None => reqwest::Client::new()
+        };
+
+        // Downloader shares network limits with the network API.
+        let network_api = NetworkAPI::new(lua.clone(), client, options.network_limits)?;
+        let downloader = network_api.downloader().with_local_files(true);
+
         Ok(Self {
             clone: lua.create_function(|lua, value: LuaValue| {
                 fn clone_value(lua: &Lua, value: LuaValue) -> Result<LuaValue, LuaError> {
@@ -198,26 +320,39 @@ impl API {
                 clone_value(lua, value)
             })?,
 
-            dbg: lua.create_function(|_, values: Variadic<LuaValue>| {
-                for value in values {
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("{value:#?}");
+            dbg: {
+                let on_log = options.on_log.clone();
 
-                    #[cfg(not(feature = "tracing"))]
-                    dbg!(value);
-                }
+                lua.create_function(move |_, values: Variadic<LuaValue>| {
+                    for value in values {
+                        if let Some(on_log) = &on_log {
+                            on_log(LogLevel::Debug, format!("{value:#?}"));
 
-                Ok(())
-            })?,
+                            continue;
+                        }
+
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("{value:#?}");
+
+                        #[cfg(not(feature = "tracing"))]
+                        dbg!(value);
+                    }
+
+                    Ok(())
+                })?
+            },
 
             string_api: StringAPI::new(lua.clone())?,
+            bytes_api: BytesAPI::new(lua.clone())?,
             path_api: PathAPI::new(lua.clone())?,
-            network_api: NetworkAPI::new(lua.clone(), reqwest::Client::new())?,
-            downloader_api: DownloaderAPI::new(lua.clone())?,
+            network_api,
+            downloader_api: DownloaderAPI::new(lua.clone(), downloader)?,
             archives_api: ArchivesAPI::new(lua.clone())?,
             hashes_api: HashesAPI::new(lua.clone())?,
-            sync_api: SyncAPI::new(lua.clone())?,
+            compression_api: CompressionAPI::new(lua.clone())?,
+            sync_api: SyncAPI::new(lua.clone(), options.execution_timeout)?,
             sqlite_api: SQLiteAPI::new(lua.clone())?,
+            time_api: TimeAPI::new(lua.clone())?,
             // portals_api: PortalsAPI::new(lua.clone(), PortalsAPIOptions {
             //     show_toast: options.show_toast,
             //     show_notification: options.show_notification,
@@ -238,20 +373,23 @@ impl API {
 
     /// Create new environment for the API modules using provided context.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 12)?;
+        let env = self.lua.create_table_with_capacity(0, 15)?;
 
         env.set("clone", self.clone.clone())?;
         env.set("dbg", self.dbg.clone())?;
 
         env.set("str", self.string_api.create_env()?)?;
+        env.set("bytes", self.bytes_api.create_env()?)?;
         env.set("path", self.path_api.create_env(context)?)?;
         env.set("fs", self.filesystem_api.create_env(context)?)?;
-        env.set("net", self.network_api.create_env()?)?;
+        env.set("net", self.network_api.create_env(context)?)?;
         env.set("downloader", self.downloader_api.create_env(context)?)?;
         env.set("archive", self.archives_api.create_env(context)?)?;
         env.set("hash", self.hashes_api.create_env(context)?)?;
+        env.set("compression", self.compression_api.create_env()?)?;
         env.set("sync", self.sync_api.create_env()?)?;
         env.set("sqlite", self.sqlite_api.create_env(context)?)?;
+        env.set("time", self.time_api.create_env()?)?;
         // env.set("portals", self.portals_api.create_env(context)?)?;
 
         // Extended privileges
@@ -263,3 +401,86 @@ impl API {
         Ok(env)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_symlinks() -> std::io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let path = std::env::temp_dir().join(".wineyard-runtime-resolve-symlinks-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("folder"))?;
+        std::fs::write(path.join("folder/file.txt"), b"Hello, World!")?;
+
+        symlink("folder/file.txt", path.join("relative"))?;
+        symlink(path.join("relative"), path.join("absolute"))?;
+
+        symlink("loop_b", path.join("loop_a"))?;
+        symlink("loop_a", path.join("loop_b"))?;
+
+        let resolve = |name: &str| resolve_path(path.join(name).to_string_lossy());
+
+        assert_eq!(resolve("relative")?, path.join("folder/file.txt"));
+        assert_eq!(resolve("absolute")?, path.join("folder/file.txt"));
+        assert_eq!(resolve("folder")?, path.join("folder"));
+
+        assert_eq!(resolve("loop_a").map_err(|err| err.raw_os_error()), Err(Some(libc::ELOOP)));
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn dbg_on_log() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-dbg-on-log-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let messages = Arc::new(Mutex::new(Vec::new()));
+
+        let lua = Lua::new();
+
+        let api = API::new(lua.clone(), Options {
+            on_log: Some({
+                let messages = messages.clone();
+
+                Arc::new(move |level, message| {
+                    messages.lock().unwrap().push((level, message));
+                })
+            }),
+            ..Options::test(&path)
+        })?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        env.call_function::<()>("dbg", ("Hello, World!", 123))?;
+
+        let messages = messages.lock().unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|(level, _)| *level == LogLevel::Debug));
+        assert!(messages[0].1.contains("Hello, World!"));
+        assert!(messages[1].1.contains("123"));
+
+        drop(messages);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
@@ -1,29 +1,263 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-
-use wineyard_core::export::network::reqwest::{Client, RequestBuilder, Method};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+
+use futures_util::Stream;
+
+use wineyard_core::export::network::reqwest::{
+    Body,
+    Client,
+    RequestBuilder,
+    Response,
+    Method,
+    StatusCode,
+    Url,
+    header
+};
+use wineyard_core::network::downloader::Downloader;
+use wineyard_core::network::transport::{
+    Transport,
+    TransportFuture,
+    TransportResponse,
+    TransportStream,
+    HttpTransport
+};
 use wineyard_core::tasks;
+use wineyard_core::tasks::io::AsyncReadExt;
+use wineyard_core::tasks::sync::{Semaphore, OwnedSemaphorePermit};
 
 use mlua::prelude::*;
 
 use super::*;
+use super::string_api::StringEncoding;
 
-fn create_request(
-    client: &Client,
-    url: impl AsRef<str>,
-    options: Option<LuaTable>
-) -> Result<RequestBuilder, LuaError> {
+/// Default max size of the response body fetched by `net.fetch_text`
+/// and `net.fetch_json`.
+pub const NET_FETCH_MAX_SIZE: u64 = 16 * 1024 * 1024; // 16 MiB
+
+/// Size of the chunks of the file uploaded by `net.upload`.
+pub const NET_UPLOAD_CHUNK_LEN: usize = 65536; // 64 KiB
+
+/// How often `net.upload` reports uploading progress.
+pub const NET_UPLOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Amount of hosts limiters after which idle ones are removed.
+pub const NET_MAX_LIMITED_HOSTS: usize = 256;
+
+/// Politeness limits of the network API requests.
+///
+/// Limits are applied per host and shared by all the modules of the engine,
+/// so a set of modules can't flood a server with requests.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NetworkLimits {
+    /// Maximal amount of simultaneous requests to the same host.
+    pub max_host_requests: Option<usize>,
+
+    /// Minimal delay between starts of the requests to the same host.
+    pub min_request_delay: Option<Duration>
+}
+
+impl NetworkLimits {
+    /// Check if any limit is set.
+    #[inline]
+    pub const fn is_limited(&self) -> bool {
+        self.max_host_requests.is_some() || self.min_request_delay.is_some()
+    }
+}
+
+/// Requests limiter of a single host.
+#[derive(Debug)]
+struct HostLimiter {
+    semaphore: Option<Arc<Semaphore>>,
+
+    /// Time when the next request can be started.
+    next_request: Mutex<Instant>
+}
+
+impl HostLimiter {
+    /// Check if the limiter has no active requests and doesn't
+    /// delay the next one, so it can be removed.
+    fn is_idle(&self) -> bool {
+        let is_delaying = self.next_request.lock()
+            .map(|next_request| *next_request > Instant::now())
+            .unwrap_or(true);
+
+        // Permits of active requests hold the semaphore.
+        let is_active = self.semaphore.as_ref()
+            .is_some_and(|semaphore| Arc::strong_count(semaphore) > 1);
+
+        !is_delaying && !is_active
+    }
+}
+
+/// HTTP client which applies network limits to the sent requests.
+#[derive(Debug, Clone)]
+struct LimitedClient {
+    client: Client,
+    limits: NetworkLimits,
+    hosts: Arc<Mutex<HashMap<String, Arc<HostLimiter>>>>
+}
+
+impl LimitedClient {
+    fn new(client: Client, limits: NetworkLimits) -> Self {
+        Self {
+            client,
+            limits,
+            hosts: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    /// Wait until a request to the URL is allowed by the limits.
+    ///
+    /// Returned permit must be kept until the response is read to count
+    /// the request as active.
+    async fn acquire(&self, url: &Url) -> Result<Option<OwnedSemaphorePermit>, LuaError> {
+        let mut permit = None;
+
+        if self.limits.is_limited()
+            && let Some(host) = url.host_str()
+        {
+            let host = format!("{host}:{}", url.port_or_known_default().unwrap_or_default());
+
+            let limiter = {
+                let mut hosts = self.hosts.lock()
+                    .map_err(|err| LuaError::external(format!("failed to lock network limiter: {err}")))?;
+
+                if hosts.len() >= NET_MAX_LIMITED_HOSTS && !hosts.contains_key(&host) {
+                    hosts.retain(|_, limiter| !limiter.is_idle());
+                }
+
+                hosts.entry(host)
+                    .or_insert_with(|| {
+                        Arc::new(HostLimiter {
+                            semaphore: self.limits.max_host_requests
+                                .map(|requests| Arc::new(Semaphore::new(requests.max(1)))),
+
+                            next_request: Mutex::new(Instant::now())
+                        })
+                    })
+                    .clone()
+            };
+
+            if let Some(semaphore) = &limiter.semaphore {
+                permit = Some(semaphore.clone().acquire_owned().await.map_err(LuaError::external)?);
+            }
+
+            if let Some(delay) = self.limits.min_request_delay {
+                // Reserve the start time so concurrent requests are spread.
+                let wait = {
+                    let mut next_request = limiter.next_request.lock()
+                        .map_err(|err| LuaError::external(format!("failed to lock network limiter: {err}")))?;
+
+                    let now = Instant::now();
+                    let start = now.max(*next_request);
+
+                    *next_request = start + delay;
+
+                    start - now
+                };
+
+                if !wait.is_zero() {
+                    tasks::time::sleep(wait).await;
+                }
+            }
+        }
+
+        Ok(permit)
+    }
+
+    /// Wait until the request is allowed by the limits and send it.
+    ///
+    /// Returned permit must be kept until the response is read to count
+    /// the request as active.
+    async fn send(
+        &self,
+        request: RequestBuilder
+    ) -> Result<(Response, Option<OwnedSemaphorePermit>), LuaError> {
+        let request = request.build()
+            .map_err(|err| LuaError::external(format!("failed to perform request: {err}")))?;
+
+        let permit = self.acquire(request.url()).await?;
+
+        let response = self.client.execute(request).await
+            .map_err(|err| LuaError::external(format!("failed to perform request: {err}")))?;
+
+        Ok((response, permit))
+    }
+}
+
+/// Downloader transport which applies network limits to the requests.
+#[derive(Debug)]
+struct LimitedTransport {
+    client: LimitedClient,
+    transport: HttpTransport
+}
+
+/// Content stream holding the request permit until it's dropped.
+struct LimitedStream {
+    stream: Box<dyn TransportStream>,
+    _permit: Option<OwnedSemaphorePermit>
+}
+
+impl Transport for LimitedTransport {
+    fn request<'a>(
+        &'a self,
+        url: &'a str,
+        offset: u64,
+        if_range: Option<&'a str>
+    ) -> TransportFuture<'a, TransportResponse> {
+        Box::pin(async move {
+            let parsed_url = Url::parse(url)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+
+            let permit = self.client.acquire(&parsed_url).await
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+
+            let response = match self.transport.request(url, offset, if_range).await? {
+                TransportResponse::Content { offset, total, etag, stream } => {
+                    TransportResponse::Content {
+                        offset,
+                        total,
+                        etag,
+                        stream: Box::new(LimitedStream {
+                            stream,
+                            _permit: permit
+                        })
+                    }
+                }
+
+                response => response
+            };
+
+            Ok(response)
+        })
+    }
+}
+
+impl TransportStream for LimitedStream {
+    #[inline]
+    fn read_chunk<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> TransportFuture<'a, bool> {
+        self.stream.read_chunk(buf)
+    }
+}
+
+/// Read request method from the options table.
+fn request_method(options: Option<&LuaTable>) -> Result<Method, LuaError> {
     let mut method = String::from("get");
 
     // Change the request method if provided.
-    if let Some(options) = &options {
+    if let Some(options) = options {
         method = options.get::<String>("method")
             .unwrap_or(String::from("get"));
     }
 
     let method = match method.to_ascii_lowercase().as_str() {
         "get"     => Method::GET,
-        "port"    => Method::POST,
+        "post"    => Method::POST,
         "head"    => Method::HEAD,
         "put"     => Method::PUT,
         "patch"   => Method::PATCH,
@@ -33,116 +267,598 @@ fn create_request(
         _ => return Err(LuaError::external("invalid request method"))
     };
 
-    let mut request = client.request(method, url.as_ref());
+    Ok(method)
+}
+
+fn create_request(
+    client: &LimitedClient,
+    method: Method,
+    url: impl AsRef<str>,
+    options: Option<LuaTable>
+) -> Result<RequestBuilder, LuaError> {
+    let mut request = client.client.request(method, url.as_ref());
 
     // Set request header and body if provided.
     if let Some(options) = &options {
         if let Ok(headers) = options.get::<LuaTable>("headers") {
+            // Header names are case insensitive so later values replace
+            // the earlier ones instead of sending the header twice.
+            let mut map = header::HeaderMap::new();
+
             for pair in headers.pairs::<LuaString, LuaString>() {
                 let (key, value) = pair?;
 
-                request = request.header(
-                    key.to_string_lossy().to_string(),
-                    value.to_string_lossy().to_string()
-                );
+                let key = header::HeaderName::from_bytes(&key.as_bytes())
+                    .map_err(|err| LuaError::external(format!("invalid header name: {err}")))?;
+
+                let value = header::HeaderValue::from_bytes(&value.as_bytes())
+                    .map_err(|err| LuaError::external(format!("invalid header value: {err}")))?;
+
+                map.insert(key, value);
             }
+
+            request = request.headers(map);
         }
 
-        if let Ok(body) = options.get::<LuaValue>("body") {
-            request = match body {
-                LuaValue::String(str) => request.body(str.as_bytes().to_vec()),
+        match options.get::<LuaValue>("body") {
+            Ok(LuaValue::Nil) => (),
 
-                LuaValue::Table(table) => {
-                    let mut body = Vec::with_capacity(table.raw_len());
+            Ok(body) => {
+                let body = lua_value_to_bytes(body)
+                    .map_err(|_| LuaError::external("invalid body value"))?;
 
-                    for byte in table.sequence_values::<u8>() {
-                        body.push(byte?);
-                    }
+                request = request.body(body);
+            }
+
+            Err(_) => return Err(LuaError::external("invalid body value"))
+        }
+    }
+
+    Ok(request)
+}
+
+/// Set the request header, replacing its values set before.
+fn replace_header(
+    request: RequestBuilder,
+    name: header::HeaderName,
+    value: header::HeaderValue
+) -> RequestBuilder {
+    request.headers(header::HeaderMap::from_iter([(name, value)]))
+}
+
+/// Create table with response URL, status and headers.
+fn response_header(lua: &Lua, response: &Response) -> Result<LuaTable, LuaError> {
+    let result = lua.create_table_with_capacity(0, 5)?;
+    let headers = lua.create_table()?;
+
+    result.raw_set("url", response.url().as_str())?;
+    result.raw_set("status", response.status().as_u16())?;
+    result.raw_set("is_ok", response.status().is_success())?;
+    result.raw_set("headers", headers.clone())?;
+
+    for (key, value) in response.headers() {
+        headers.raw_set(key.to_string(), lua.create_string(value.as_bytes())?)?;
+    }
+
+    Ok(result)
+}
+
+/// Read the response, returning table with its status, headers and body.
+///
+/// Redirects are followed and the final URL is stored in the `url` field.
+async fn read_response(lua: &Lua, response: Response) -> Result<LuaTable, LuaError> {
+    let result = response_header(lua, &response)?;
+
+    let body = response.bytes().await
+        .map_err(|err| LuaError::external(format!("failed to fetch body: {err}")))?;
+
+    result.raw_set("body", body.to_vec())?;
+
+    Ok(result)
+}
+
+/// Perform the request, returning table with response status,
+/// headers and body.
+fn perform_request(
+    lua: &Lua,
+    client: &LimitedClient,
+    request: RequestBuilder
+) -> Result<LuaTable, LuaError> {
+    tasks::block_on(async move {
+        let (response, _permit) = client.send(request).await?;
+
+        read_response(lua, response).await
+    })
+}
 
-                    request.body(body)
+/// Stream content of the file by chunks, counting read bytes.
+fn file_stream(
+    file: tasks::fs::File,
+    read: Arc<AtomicU64>
+) -> impl Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static {
+    futures_util::stream::unfold(Some(file), move |file| {
+        let read = read.clone();
+
+        async move {
+            let mut file = file?;
+            let mut chunk = vec![0; NET_UPLOAD_CHUNK_LEN];
+
+            match file.read(&mut chunk).await {
+                Ok(0) => None,
+
+                Ok(len) => {
+                    chunk.truncate(len);
+
+                    read.fetch_add(len as u64, Ordering::Relaxed);
+
+                    Some((Ok(chunk), Some(file)))
                 }
 
-                _ => return Err(LuaError::external("invalid body value"))
-            };
+                // Stop the stream after the first error.
+                Err(err) => Some((Err(err), None))
+            }
         }
+    })
+}
+
+/// Read max response body size from the options table.
+fn fetch_max_size(options: Option<&LuaTable>) -> Result<u64, LuaError> {
+    match options {
+        Some(options) => Ok({
+            options.get::<Option<u64>>("max_size")?
+                .unwrap_or(NET_FETCH_MAX_SIZE)
+        }),
+
+        None => Ok(NET_FETCH_MAX_SIZE)
     }
+}
 
-    Ok(request)
+/// Read the response body, failing if the response status is not successful
+/// or the body is larger than `max_size` bytes.
+async fn read_body(mut response: Response, max_size: u64) -> Result<Vec<u8>, LuaError> {
+    let status = response.status();
+
+    if !status.is_success() {
+        return Err(LuaError::external(format!("request failed with status {status}")));
+    }
+
+    if let Some(len) = response.content_length()
+        && len > max_size
+    {
+        return Err(LuaError::external(format!("response body is too large: {len} > {max_size} bytes")));
+    }
+
+    let mut body = Vec::new();
+
+    // Content length could be missing or wrong so limit actual reads too.
+    while let Some(chunk) = response.chunk().await.map_err(|err| {
+        LuaError::external(format!("failed to read body chunk: {err}"))
+    })? {
+        if (body.len() + chunk.len()) as u64 > max_size {
+            return Err(LuaError::external(format!("response body is larger than {max_size} bytes")));
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+/// Perform the request and return its body.
+fn fetch_body(
+    client: &LimitedClient,
+    request: RequestBuilder,
+    options: Option<&LuaTable>
+) -> Result<Vec<u8>, LuaError> {
+    let max_size = fetch_max_size(options)?;
+
+    tasks::block_on(async move {
+        let (response, _permit) = client.send(request).await?;
+
+        read_body(response, max_size).await
+    })
+}
+
+/// Validators of the cached response body.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(default)]
+    etag: Option<String>,
+
+    #[serde(default)]
+    last_modified: Option<String>
+}
+
+/// Perform the request with conditional headers, storing the response body
+/// in the cache folder and returning the cached body if the server reports
+/// that it wasn't modified.
+///
+/// Return the body and `true` if it was taken from the cache.
+fn fetch_cached(
+    client: &LimitedClient,
+    request: RequestBuilder,
+    cache_folder: &Path,
+    url: &str,
+    options: Option<&LuaTable>
+) -> Result<(Vec<u8>, bool), LuaError> {
+    let max_size = fetch_max_size(options)?;
+
+    let name = Hash::for_slice(url.as_bytes()).to_base32();
+
+    let body_path = cache_folder.join(&name);
+    let entry_path = cache_folder.join(format!("{name}.json"));
+
+    // Ignore broken cache entries, they will be overwritten.
+    let entry = std::fs::read(&entry_path).ok()
+        .filter(|_| body_path.is_file())
+        .and_then(|entry| serde_json::from_slice::<CacheEntry>(&entry).ok());
+
+    let mut request = request;
+
+    if let Some(entry) = &entry {
+        if let Some(etag) = entry.etag.as_deref().and_then(|etag| etag.parse().ok()) {
+            request = replace_header(request, header::IF_NONE_MATCH, etag);
+        }
+
+        if let Some(last_modified) = entry.last_modified.as_deref().and_then(|value| value.parse().ok()) {
+            request = replace_header(request, header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    tasks::block_on(async move {
+        let (response, _permit) = client.send(request).await?;
+
+        if entry.is_some() && response.status() == StatusCode::NOT_MODIFIED {
+            return Ok((std::fs::read(&body_path)?, true));
+        }
+
+        let header = |name: header::HeaderName| {
+            response.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        let entry = CacheEntry {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED)
+        };
+
+        let body = read_body(response, max_size).await?;
+
+        // Don't store responses which can't be validated later.
+        if entry != CacheEntry::default() {
+            std::fs::create_dir_all(cache_folder)?;
+
+            std::fs::write(&body_path, &body)?;
+            std::fs::write(&entry_path, serde_json::to_vec(&entry).map_err(LuaError::external)?)?;
+        }
+
+        Ok((body, false))
+    })
 }
 
 pub struct NetworkAPI {
     lua: Lua,
+    client: LimitedClient,
 
     net_fetch: LuaFunction,
+    net_get: LuaFunction,
+    net_post: LuaFunction,
+    net_put: LuaFunction,
+    net_delete: LuaFunction,
+    net_fetch_text: LuaFunction,
+    net_fetch_json: LuaFunction,
+    net_fetch_cached: LuaFunctionBuilder,
+    net_upload: LuaFunctionBuilder,
+    net_download_stream: LuaFunctionBuilder,
     net_open: LuaFunction,
     net_read: LuaFunction,
     net_close: LuaFunction
 }
 
 impl NetworkAPI {
-    pub fn new(lua: Lua, client: Client) -> Result<Self, PackagesEngineError> {
+    pub fn new(lua: Lua, client: Client, limits: NetworkLimits) -> Result<Self, PackagesEngineError> {
+        let client = LimitedClient::new(client, limits);
         let net_handles = Arc::new(Mutex::new(HashMap::new()));
 
         Ok(Self {
+            client: client.clone(),
+
             net_fetch: {
                 let client = client.clone();
 
                 lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
                     let url = url.to_string_lossy().to_string();
-                    let request = create_request(&client, url, options)?;
+                    let method = request_method(options.as_ref())?;
+                    let request = create_request(&client, method, url, options)?;
 
-                    // Perform the request.
-                    let response = tasks::block_on(async move {
-                        let result = lua.create_table_with_capacity(0, 4)?;
-                        let headers = lua.create_table()?;
+                    perform_request(lua, &client, request)
+                })?
+            },
 
-                        let response = request.send().await
-                            .map_err(|err| LuaError::external(format!("failed to perform request: {err}")))?;
+            net_get: {
+                let client = client.clone();
 
-                        result.raw_set("status", response.status().as_u16())?;
-                        result.raw_set("is_ok", response.status().is_success())?;
-                        result.raw_set("headers", headers.clone())?;
+                lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
+                    let url = url.to_string_lossy().to_string();
+                    let request = create_request(&client, Method::GET, url, options)?;
 
-                        for (key, value) in response.headers() {
-                            headers.raw_set(key.to_string(), lua.create_string(value.as_bytes())?)?;
-                        }
+                    perform_request(lua, &client, request)
+                })?
+            },
 
-                        let body = response.bytes().await
-                            .map_err(|err| LuaError::external(format!("failed to fetch body: {err}")))?;
+            net_post: {
+                let client = client.clone();
 
-                        result.raw_set("body", body.to_vec())?;
+                lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
+                    let url = url.to_string_lossy().to_string();
+                    let request = create_request(&client, Method::POST, url, options)?;
 
-                        Ok::<_, LuaError>(result)
-                    })?;
+                    perform_request(lua, &client, request)
+                })?
+            },
+
+            net_put: {
+                let client = client.clone();
+
+                lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
+                    let url = url.to_string_lossy().to_string();
+                    let request = create_request(&client, Method::PUT, url, options)?;
 
-                    Ok(response)
+                    perform_request(lua, &client, request)
                 })?
             },
 
-            net_open: {
+            net_delete: {
                 let client = client.clone();
-                let net_handles = net_handles.clone();
 
                 lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
                     let url = url.to_string_lossy().to_string();
-                    let request = create_request(&client, url, options)?;
+                    let request = create_request(&client, Method::DELETE, url, options)?;
+
+                    perform_request(lua, &client, request)
+                })?
+            },
+
+            net_fetch_text: {
+                let client = client.clone();
+
+                lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
+                    let url = url.to_string_lossy().to_string();
+                    let method = request_method(options.as_ref())?;
+                    let request = create_request(&client, method, url, options.clone())?;
+
+                    lua.create_string(fetch_body(&client, request, options.as_ref())?)
+                })?
+            },
+
+            net_fetch_json: {
+                let client = client.clone();
+
+                lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
+                    let url = url.to_string_lossy().to_string();
+                    let method = request_method(options.as_ref())?;
+                    let request = create_request(&client, method, url, options.clone())?;
+
+                    let body = lua.create_string(fetch_body(&client, request, options.as_ref())?)?;
+
+                    StringEncoding::Json.decode(lua, body)
+                })?
+            },
+
+            net_fetch_cached: {
+                let client = client.clone();
+
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let client = client.clone();
+                    let context = context.to_owned();
+
+                    lua.create_function(move |lua, (url, cache_folder, options): (LuaString, Option<LuaString>, Option<LuaTable>)| {
+                        let url = url.to_string_lossy().to_string();
+
+                        let cache_folder = match cache_folder {
+                            Some(cache_folder) => {
+                                let mut cache_folder = resolve_path(cache_folder.to_string_lossy())?;
+
+                                if cache_folder.is_relative() {
+                                    cache_folder = context.persistent_folder.join(cache_folder);
+                                }
+
+                                cache_folder
+                            }
+
+                            None => context.persistent_folder.join(".net-cache")
+                        };
+
+                        if !context.is_accessible(&cache_folder) {
+                            return Err(LuaError::external("path is inaccessible"));
+                        }
+
+                        context.track_temp(&cache_folder);
+
+                        let method = request_method(options.as_ref())?;
+                        let request = create_request(&client, method, &url, options.clone())?;
+
+                        let (body, cached) = fetch_cached(&client, request, &cache_folder, &url, options.as_ref())?;
+
+                        Ok((lua.create_string(body)?, cached))
+                    })
+                })
+            },
+
+            net_upload: {
+                let client = client.clone();
 
-                    let (response, header) = tasks::block_on(async move {
-                        let result = lua.create_table_with_capacity(0, 3)?;
-                        let headers = lua.create_table()?;
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let client = client.clone();
+                    let context = context.to_owned();
 
-                        let response = request.send().await
-                            .map_err(|err| LuaError::external(format!("failed to perform request: {err}")))?;
+                    lua.create_function(move |lua, (url, path, options): (LuaString, LuaString, Option<LuaTable>)| {
+                        let url = url.to_string_lossy().to_string();
 
-                        result.raw_set("status", response.status().as_u16())?;
-                        result.raw_set("is_ok", response.status().is_success())?;
-                        result.raw_set("headers", headers.clone())?;
+                        let mut path = resolve_path(path.to_string_lossy())?;
 
-                        for (key, value) in response.headers() {
-                            headers.raw_set(key.to_string(), lua.create_string(value.as_bytes())?)?;
+                        if path.is_relative() {
+                            path = context.module_folder.join(path);
                         }
 
-                        Ok::<_, LuaError>((response, result))
+                        if !context.is_accessible(&path) {
+                            return Err(LuaError::external("path is inaccessible"));
+                        }
+
+                        // Files are uploaded with the POST method by default.
+                        let method = match &options {
+                            Some(options) if options.contains_key("method")? => request_method(Some(options))?,
+                            _ => Method::POST
+                        };
+
+                        let on_progress = match &options {
+                            Some(options) => options.get::<Option<LuaFunction>>("on_progress")?,
+                            None => None
+                        };
+
+                        let file = std::fs::File::open(&path)?;
+                        let total = file.metadata()?.len();
+
+                        let uploaded = Arc::new(AtomicU64::new(0));
+
+                        let stream = file_stream(tasks::fs::File::from_std(file), uploaded.clone());
+
+                        let request = create_request(&client, method, url, options)?;
+
+                        let request = replace_header(request, header::CONTENT_LENGTH, total.into())
+                            .body(Body::wrap_stream(stream));
+
+                        // The request is sent in the background so the progress
+                        // callback could be called from the current thread.
+                        let task = tasks::spawn({
+                            let client = client.clone();
+
+                            async move {
+                                client.send(request).await
+                            }
+                        });
+
+                        while !task.is_finished() {
+                            if let Some(on_progress) = &on_progress
+                                && let Err(err) = on_progress.call::<()>((uploaded.load(Ordering::Relaxed), total))
+                            {
+                                task.abort();
+
+                                return Err(err);
+                            }
+
+                            std::thread::sleep(NET_UPLOAD_PROGRESS_INTERVAL);
+                        }
+
+                        if let Some(on_progress) = &on_progress {
+                            on_progress.call::<()>((uploaded.load(Ordering::Relaxed), total))?;
+                        }
+
+                        tasks::block_on(async move {
+                            let (response, _permit) = task.await
+                                .map_err(|err| LuaError::external(format!("failed to perform request: {err}")))??;
+
+                            read_response(lua, response).await
+                        })
+                    })
+                })
+            },
+
+            net_download_stream: {
+                let client = client.clone();
+
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let client = client.clone();
+                    let context = context.to_owned();
+
+                    lua.create_function(move |lua, (url, path, options): (LuaString, LuaString, Option<LuaTable>)| {
+                        let url = url.to_string_lossy().to_string();
+
+                        let mut path = resolve_path(path.to_string_lossy())?;
+
+                        if path.is_relative() {
+                            path = context.module_folder.join(path);
+                        }
+
+                        if !context.is_accessible(&path) {
+                            return Err(LuaError::external("path is inaccessible"));
+                        }
+
+                        context.track_temp(&path);
+
+                        let method = request_method(options.as_ref())?;
+
+                        let on_progress = match &options {
+                            Some(options) => options.get::<Option<LuaFunction>>("on_progress")?,
+                            None => None
+                        };
+
+                        let request = create_request(&client, method, url, options)?;
+
+                        tasks::block_on(async {
+                            let (mut response, _permit) = client.send(request).await?;
+
+                            let status = response.status();
+
+                            // Don't write error pages to the output file.
+                            if !status.is_success() {
+                                return Err(LuaError::external(format!("request failed with status {status}")));
+                            }
+
+                            let result = response_header(lua, &response)?;
+                            let total = response.content_length().unwrap_or_default();
+
+                            if let Some(parent) = path.parent()
+                                && !parent.is_dir()
+                            {
+                                std::fs::create_dir_all(parent)?;
+                            }
+
+                            let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                            let mut current = 0;
+
+                            while let Some(chunk) = response.chunk().await.map_err(|err| {
+                                LuaError::external(format!("failed to read body chunk: {err}"))
+                            })? {
+                                file.write_all(&chunk)?;
+
+                                current += chunk.len() as u64;
+
+                                if let Some(on_progress) = &on_progress {
+                                    on_progress.call::<()>((current, total))?;
+                                }
+                            }
+
+                            file.flush()?;
+
+                            result.raw_set("size", current)?;
+
+                            Ok(result)
+                        })
+                    })
+                })
+            },
+
+            net_open: {
+                let client = client.clone();
+                let net_handles = net_handles.clone();
+
+                lua.create_function(move |lua, (url, options): (LuaString, Option<LuaTable>)| {
+                    let url = url.to_string_lossy().to_string();
+                    let method = request_method(options.as_ref())?;
+                    let request = create_request(&client, method, url, options)?;
+
+                    // The request stops being counted as active when its
+                    // header is received so opened responses can't block
+                    // other requests of the module.
+                    let (response, header) = tasks::block_on(async {
+                        let (response, _permit) = client.send(request).await?;
+
+                        let header = response_header(lua, &response)?;
+
+                        Ok::<_, LuaError>((response, header))
                     })?;
 
                     let mut handles = net_handles.lock()
@@ -210,11 +926,28 @@ impl NetworkAPI {
         &self.lua
     }
 
+    /// Create new downloader which shares network limits with this API.
+    pub fn downloader(&self) -> Downloader {
+        Downloader::with_transport(LimitedTransport {
+            client: self.client.clone(),
+            transport: HttpTransport::new(self.client.client.clone())
+        })
+    }
+
     /// Create new lua table with API functions.
-    pub fn create_env(&self) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 4)?;
+    pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
+        let env = self.lua.create_table_with_capacity(0, 13)?;
 
         env.raw_set("fetch", self.net_fetch.clone())?;
+        env.raw_set("get", self.net_get.clone())?;
+        env.raw_set("post", self.net_post.clone())?;
+        env.raw_set("put", self.net_put.clone())?;
+        env.raw_set("delete", self.net_delete.clone())?;
+        env.raw_set("fetch_text", self.net_fetch_text.clone())?;
+        env.raw_set("fetch_json", self.net_fetch_json.clone())?;
+        env.raw_set("fetch_cached", (self.net_fetch_cached)(&self.lua, context)?)?;
+        env.raw_set("upload", (self.net_upload)(&self.lua, context)?)?;
+        env.raw_set("download_stream", (self.net_download_stream)(&self.lua, context)?)?;
         env.raw_set("open", self.net_open.clone())?;
         env.raw_set("read", self.net_read.clone())?;
         env.raw_set("close", self.net_close.clone())?;
@@ -266,3 +999,408 @@ impl NetworkAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Serve given HTTP responses to the incoming connections one by one.
+    fn serve(responses: Vec<String>) -> std::io::Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+
+        std::thread::spawn(move || {
+            for response in responses {
+                let Ok((mut stream, _)) = listener.accept() else {
+                    return;
+                };
+
+                let mut buf = [0; 4096];
+
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        Ok(address)
+    }
+
+    fn response(status: &str, body: &str) -> String {
+        format!("HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+    }
+
+    fn response_with_etag(status: &str, etag: &str, body: &str) -> String {
+        format!("HTTP/1.1 {status}\r\nETag: {etag}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+    }
+
+    #[test]
+    fn net_fetch_text_json() -> Result<(), PackagesEngineError> {
+        let lua = Lua::new();
+        let api = NetworkAPI::new(lua.clone(), Client::new(), NetworkLimits::default())?;
+
+        let address = serve(vec![
+            response("200 OK", "Hello, World!"),
+            response("200 OK", "{ \"name\": \"wineyard\", \"version\": 1 }"),
+            response("404 Not Found", "not found"),
+            response("200 OK", "Hello, World!")
+        ])?;
+
+        assert_eq!(api.net_fetch_text.call::<String>(address.as_str())?, "Hello, World!");
+
+        let json = api.net_fetch_json.call::<LuaTable>(address.as_str())?;
+
+        assert_eq!(json.get::<String>("name")?, "wineyard");
+        assert_eq!(json.get::<u32>("version")?, 1);
+
+        let err = api.net_fetch_text.call::<String>(address.as_str()).unwrap_err();
+
+        assert!(err.to_string().contains("404"));
+
+        let options = lua.create_table()?;
+
+        options.set("max_size", 5)?;
+
+        assert!(api.net_fetch_text.call::<String>((address.as_str(), options)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn net_request_headers() -> Result<(), PackagesEngineError> {
+        // Send the request headers back as the response body.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+
+        std::thread::spawn(move || {
+            let Ok((mut stream, _)) = listener.accept() else {
+                return;
+            };
+
+            let mut buf = [0; 4096];
+
+            let Ok(len) = stream.read(&mut buf) else {
+                return;
+            };
+
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+
+            let _ = stream.write_all(response("200 OK", &request).as_bytes());
+        });
+
+        let lua = Lua::new();
+        let api = NetworkAPI::new(lua.clone(), Client::new(), NetworkLimits::default())?;
+
+        let headers = lua.create_table()?;
+
+        headers.set("X-Test", "a")?;
+        headers.set("x-test", "b")?;
+
+        let options = lua.create_table()?;
+
+        options.set("headers", headers)?;
+
+        let request = api.net_fetch_text.call::<String>((address.as_str(), options))?;
+
+        let values = request.lines()
+            .filter_map(|line| line.split_once(": "))
+            .filter(|(name, _)| name.eq_ignore_ascii_case("x-test"))
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+
+        assert!(values == ["a"] || values == ["b"]);
+
+        let headers = lua.create_table()?;
+
+        headers.set("X-Test\n", "a")?;
+
+        let options = lua.create_table()?;
+
+        options.set("headers", headers)?;
+
+        assert!(api.net_fetch_text.call::<String>((address.as_str(), options)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn net_limits() -> Result<(), PackagesEngineError> {
+        let lua = Lua::new();
+
+        let api = NetworkAPI::new(lua.clone(), Client::new(), NetworkLimits {
+            max_host_requests: Some(1),
+            min_request_delay: Some(Duration::from_millis(200))
+        })?;
+
+        let address = serve(vec![
+            response("200 OK", "first"),
+            response("200 OK", "second"),
+            response("200 OK", "third")
+        ])?;
+
+        let start = Instant::now();
+
+        assert_eq!(api.net_fetch_text.call::<String>(address.as_str())?, "first");
+
+        // Opened responses don't block other requests.
+        let header = api.net_open.call::<LuaTable>(address.as_str())?;
+
+        assert_eq!(api.net_fetch_text.call::<String>(address.as_str())?, "third");
+
+        api.net_close.call::<()>(header.get::<i32>("handle")?)?;
+
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        Ok(())
+    }
+
+    #[test]
+    fn net_host_requests() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-net-host-requests-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+
+        let active = Arc::new(AtomicU64::new(0));
+        let max_active = Arc::new(AtomicU64::new(0));
+
+        // Serve slow responses concurrently, counting active connections.
+        {
+            let active = active.clone();
+            let max_active = max_active.clone();
+
+            std::thread::spawn(move || {
+                for stream in listener.incoming().take(3) {
+                    let Ok(mut stream) = stream else {
+                        return;
+                    };
+
+                    let active = active.clone();
+                    let max_active = max_active.clone();
+
+                    std::thread::spawn(move || {
+                        max_active.fetch_max(active.fetch_add(1, Ordering::AcqRel) + 1, Ordering::AcqRel);
+
+                        let mut buf = [0; 4096];
+
+                        let _ = stream.read(&mut buf);
+                        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\n");
+
+                        std::thread::sleep(Duration::from_millis(100));
+
+                        active.fetch_sub(1, Ordering::AcqRel);
+
+                        let _ = stream.write_all(b"Hello");
+                    });
+                }
+            });
+        }
+
+        let api = NetworkAPI::new(Lua::new(), Client::new(), NetworkLimits {
+            max_host_requests: Some(1),
+            min_request_delay: None
+        })?;
+
+        // Downloads share the limits with the API requests.
+        let downloader = api.downloader();
+
+        let a = downloader.download(&address, path.join("a"));
+        let b = downloader.download(&address, path.join("b"));
+
+        assert_eq!(api.net_fetch_text.call::<String>(address.as_str())?, "Hello");
+
+        assert_eq!(tasks::block_on(a.wait()).map_err(LuaError::external)?, 5);
+        assert_eq!(tasks::block_on(b.wait()).map_err(LuaError::external)?, 5);
+
+        assert_eq!(max_active.load(Ordering::Acquire), 1);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn net_limited_hosts() -> Result<(), LuaError> {
+        let client = LimitedClient::new(Client::new(), NetworkLimits {
+            max_host_requests: Some(1),
+            min_request_delay: None
+        });
+
+        let url = |i: usize| Url::parse(&format!("http://host-{i}.example.com")).map_err(LuaError::external);
+
+        let active = tasks::block_on(client.acquire(&url(0)?))?;
+
+        for i in 1..NET_MAX_LIMITED_HOSTS {
+            tasks::block_on(client.acquire(&url(i)?))?;
+        }
+
+        assert_eq!(client.hosts.lock().unwrap().len(), NET_MAX_LIMITED_HOSTS);
+
+        // Idle hosts are removed when the limit is reached.
+        tasks::block_on(client.acquire(&url(NET_MAX_LIMITED_HOSTS)?))?;
+
+        assert_eq!(client.hosts.lock().unwrap().len(), 2);
+
+        drop(active);
+
+        Ok(())
+    }
+
+    #[test]
+    fn net_fetch_cached() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-net-fetch-cached-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let lua = Lua::new();
+        let api = NetworkAPI::new(lua.clone(), Client::new(), NetworkLimits::default())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("validator.json"))?)?;
+
+        let fetch_cached = env.get::<LuaFunction>("fetch_cached")?;
+
+        let address = serve(vec![
+            response_with_etag("200 OK", "\"v1\"", "Hello, World!"),
+            response_with_etag("304 Not Modified", "\"v1\"", ""),
+            response_with_etag("200 OK", "\"v2\"", "Updated")
+        ])?;
+
+        assert_eq!(fetch_cached.call::<(String, bool)>(address.as_str())?, (String::from("Hello, World!"), false));
+        assert_eq!(fetch_cached.call::<(String, bool)>(address.as_str())?, (String::from("Hello, World!"), true));
+        assert_eq!(fetch_cached.call::<(String, bool)>(address.as_str())?, (String::from("Updated"), false));
+
+        assert!(fetch_cached.call::<(String, bool)>((address.as_str(), "/")).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn net_upload_download_stream() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-net-upload-download-stream-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("save.dat"), vec![7; 200000])?;
+
+        // Read the whole request and send its body back.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("http://{}", listener.local_addr()?);
+
+        std::thread::spawn(move || {
+            let Ok((mut stream, _)) = listener.accept() else {
+                return;
+            };
+
+            let mut request = Vec::new();
+            let mut buf = [0; 65536];
+
+            loop {
+                let Ok(len) = stream.read(&mut buf) else {
+                    return;
+                };
+
+                request.extend_from_slice(&buf[..len]);
+
+                let text = String::from_utf8_lossy(&request);
+
+                if let Some((header, body)) = text.split_once("\r\n\r\n")
+                    && header.lines()
+                        .filter(|line| line.to_ascii_lowercase().starts_with("content-length:"))
+                        .map(|line| line.to_ascii_lowercase())
+                        .eq(["content-length: 200000"])
+                    && body.len() >= 200000
+                {
+                    break;
+                }
+
+                if len == 0 {
+                    return;
+                }
+            }
+
+            let header = String::from_utf8_lossy(&request);
+            let method = header.split(' ').next().unwrap_or_default().to_string();
+
+            let _ = stream.write_all(response("200 OK", &method).as_bytes());
+        });
+
+        let download_address = serve(vec![
+            response("200 OK", "Hello, World!"),
+            response("404 Not Found", "not found")
+        ])?;
+
+        let lua = Lua::new();
+        let api = NetworkAPI::new(lua.clone(), Client::new(), NetworkLimits::default())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("validator.json"))?)?;
+
+        lua.globals().raw_set("net", env)?;
+        lua.globals().raw_set("address", address)?;
+        lua.globals().raw_set("download_address", download_address)?;
+
+        lua.load(r#"
+            local uploaded, total = 0, 0
+
+            local response = net.upload(address, "save.dat", {
+                method = "put",
+                headers = {
+                    ["Content-Length"] = "5"
+                },
+                on_progress = function(curr, size)
+                    uploaded, total = curr, size
+                end
+            })
+
+            assert(response.is_ok)
+            assert(uploaded == 200000 and total == 200000)
+
+            local body = ""
+
+            for _, byte in ipairs(response.body) do
+                body = body .. string.char(byte)
+            end
+
+            assert(body == "PUT")
+
+            assert(not pcall(net.upload, address, "/etc/hostname"))
+
+            local downloaded = 0
+
+            local response = net.download_stream(download_address, "output/file.txt", {
+                on_progress = function(curr, size)
+                    downloaded = curr
+                end
+            })
+
+            assert(response.status == 200 and response.size == 13)
+            assert(downloaded == 13)
+
+            assert(not pcall(net.download_stream, download_address, "output/missing.txt"))
+            assert(not pcall(net.download_stream, download_address, "/output.txt"))
+        "#).exec()?;
+
+        assert_eq!(std::fs::read(path.join("output/file.txt"))?, b"Hello, World!");
+        assert!(!path.join("output/missing.txt").exists());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
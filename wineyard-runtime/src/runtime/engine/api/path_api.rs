@@ -48,6 +48,30 @@ fn split_path(path: impl AsRef<str>) -> Option<Vec<String>> {
     normalize_path_parts(&raw_parts)
 }
 
+/// Split file name into its stem and extension.
+///
+/// Dotfiles like `.bashrc` have no extension.
+fn split_extension(file_name: &str) -> (&str, Option<&str>) {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => {
+            (stem, Some(extension))
+        }
+
+        _ => (file_name, None)
+    }
+}
+
+/// Check if the path matches the glob pattern.
+///
+/// See `wineyard_core::glob::matches` for the supported syntax. Backslashes
+/// are treated as path separators.
+pub fn matches_glob(pattern: impl AsRef<str>, path: impl AsRef<str>) -> bool {
+    let pattern = pattern.as_ref().replace('\\', "/");
+    let path = path.as_ref().replace('\\', "/");
+
+    wineyard_core::glob::matches(pattern, path)
+}
+
 pub struct PathAPI {
     lua: Lua,
 
@@ -59,7 +83,12 @@ pub struct PathAPI {
     path_parts: LuaFunction,
     path_parent: LuaFunction,
     path_file_name: LuaFunction,
+    path_extension: LuaFunction,
+    path_with_extension: LuaFunction,
+    path_is_absolute: LuaFunction,
+    path_relative_to: LuaFunction,
     path_exists: LuaFunction,
+    path_matches: LuaFunction,
     path_accessible: LuaFunctionBuilder
 }
 
@@ -263,12 +292,111 @@ impl PathAPI {
                     .map(LuaValue::String)
             })?,
 
+            path_extension: lua.create_function(|lua, path: LuaString| {
+                let path = path.to_string_lossy()
+                    .to_string();
+
+                let path = path.strip_prefix("/")
+                    .unwrap_or(&path);
+
+                let Some(mut parts) = split_path(path) else {
+                    return Ok(LuaNil);
+                };
+
+                let Some(file_name) = parts.pop() else {
+                    return Ok(LuaNil);
+                };
+
+                match split_extension(&file_name).1 {
+                    Some(extension) => lua.create_string(extension)
+                        .map(LuaValue::String),
+
+                    None => Ok(LuaNil)
+                }
+            })?,
+
+            path_with_extension: lua.create_function(|lua, (path, extension): (LuaString, LuaString)| {
+                let path = path.to_string_lossy()
+                    .to_string();
+
+                let extension = extension.to_string_lossy();
+                let extension = extension.trim_start_matches('.');
+
+                let (path, is_absolute) = match path.strip_prefix("/") {
+                    Some(path) => (path, true),
+                    None => (path.as_str(), false)
+                };
+
+                let Some(mut parts) = split_path(path) else {
+                    return Ok(LuaNil);
+                };
+
+                let Some(file_name) = parts.pop() else {
+                    return Ok(LuaNil);
+                };
+
+                let stem = split_extension(&file_name).0;
+
+                if extension.is_empty() {
+                    parts.push(stem.to_string());
+                } else {
+                    parts.push(format!("{stem}.{extension}"));
+                }
+
+                let mut path = parts.join("/");
+
+                if is_absolute {
+                    path = format!("/{path}");
+                }
+
+                lua.create_string(path)
+                    .map(LuaValue::String)
+            })?,
+
+            path_is_absolute: lua.create_function(|_, path: LuaString| {
+                Ok(path.as_bytes().starts_with(b"/"))
+            })?,
+
+            path_relative_to: lua.create_function(|lua, (base, path): (LuaString, LuaString)| {
+                fn parse(path: LuaString) -> (Vec<String>, bool) {
+                    let path = path.to_string_lossy()
+                        .to_string();
+
+                    let (path, is_absolute) = match path.strip_prefix("/") {
+                        Some(path) => (path, true),
+                        None => (path.as_str(), false)
+                    };
+
+                    (split_path(path).unwrap_or_default(), is_absolute)
+                }
+
+                let (base, base_absolute) = parse(base);
+                let (path, path_absolute) = parse(path);
+
+                if base_absolute != path_absolute || !path.starts_with(&base) {
+                    return Ok(LuaNil);
+                }
+
+                let relative = &path[base.len()..];
+
+                if relative.is_empty() {
+                    return Ok(LuaNil);
+                }
+
+                lua.create_string(relative.join("/"))
+                    .map(LuaValue::String)
+            })?,
+
             path_exists: lua.create_function(|_, path: LuaString| {
                 let path = resolve_path(path.to_string_lossy())?;
 
                 Ok(path.exists())
             })?,
 
+            path_matches: lua.create_function(|_, (pattern, path): (LuaString, LuaString)| {
+                Ok(matches_glob(pattern.to_string_lossy(), path.to_string_lossy()))
+            })?,
+
             path_accessible: Box::new(|lua: &Lua, context: &Context| {
                 let context = context.to_owned();
 
@@ -290,7 +418,7 @@ impl PathAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 10)?;
+        let env = self.lua.create_table_with_capacity(0, 15)?;
 
         env.raw_set("temp_dir", (self.path_temp_dir)(&self.lua, context)?)?;
         env.raw_set("module_dir", (self.path_module_dir)(&self.lua, context)?)?;
@@ -300,70 +428,135 @@ impl PathAPI {
         env.raw_set("parts", self.path_parts.clone())?;
         env.raw_set("parent", self.path_parent.clone())?;
         env.raw_set("file_name", self.path_file_name.clone())?;
+        env.raw_set("extension", self.path_extension.clone())?;
+        env.raw_set("with_extension", self.path_with_extension.clone())?;
+        env.raw_set("is_absolute", self.path_is_absolute.clone())?;
+        env.raw_set("relative_to", self.path_relative_to.clone())?;
         env.raw_set("exists", self.path_exists.clone())?;
+        env.raw_set("matches", self.path_matches.clone())?;
         env.raw_set("accessible", (self.path_accessible)(&self.lua, context)?)?;
 
         Ok(env)
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-
-//     #[test]
-//     fn path_actions() -> anyhow::Result<()> {
-//         let api = PathAPI::new(Lua::new())?;
-
-//         assert_eq!(api.path_normalize.call::<String>("/")?, "/");
-//         assert_eq!(api.path_normalize.call::<String>("a/b/c")?, "a/b/c");
-//         assert_eq!(api.path_normalize.call::<String>("/a/b/c")?, "/a/b/c");
-//         assert_eq!(api.path_normalize.call::<String>("a/./c")?, "a/c");
-//         assert_eq!(api.path_normalize.call::<String>("a/../c")?, "c");
-//         assert_eq!(api.path_normalize.call::<String>("a/../c/./")?, "c");
-//         assert_eq!(api.path_normalize.call::<String>("./a//\\./../b")?, "b");
-//         assert_eq!(api.path_normalize.call::<String>(" ")?, " "); // space is a correct entry name
-//         assert_eq!(api.path_normalize.call::<Option<String>>("")?, None); // entry name cannot be empty
-//         assert_eq!(api.path_normalize.call::<Option<String>>(".")?, None); // we do not support relative paths
-//         assert_eq!(api.path_normalize.call::<Option<String>>("..")?, None);
-//         assert_eq!(api.path_normalize.call::<Option<String>>("./..")?, None);
-//         assert_eq!(api.path_normalize.call::<Option<String>>("a/..")?, None);
-
-//         assert_eq!(api.path_join.call::<String>(("a", "b", "c"))?, "a/b/c");
-//         assert_eq!(api.path_join.call::<String>(("/", "a", "b", "c"))?, "/a/b/c");
-//         assert_eq!(api.path_join.call::<String>(("a", "..", "b"))?, "b");
-//         assert_eq!(api.path_join.call::<String>((".", "a", ".", "b"))?, "a/b");
-//         assert_eq!(api.path_join.call::<Option<String>>("")?, None);
-//         assert_eq!(api.path_join.call::<Option<String>>(".")?, None);
-//         assert_eq!(api.path_join.call::<Option<String>>("..")?, None);
-//         assert_eq!(api.path_join.call::<Option<String>>((".", ".."))?, None);
-//         assert_eq!(api.path_join.call::<Option<String>>(("a", ".."))?, None);
-
-//         assert_eq!(api.path_parts.call::<Vec<String>>("a/b/c")?, &["a", "b", "c"]);
-//         assert_eq!(api.path_parts.call::<Vec<String>>("a/./c")?, &["a", "c"]);
-//         assert_eq!(api.path_parts.call::<Vec<String>>("a/./c/..")?, &["a"]);
-//         assert_eq!(api.path_parts.call::<Vec<String>>("\\a/b/// /c")?, &["a", "b", " ", "c"]);
-//         assert_eq!(api.path_parts.call::<Option<Vec<String>>>("")?, None);
-//         assert_eq!(api.path_parts.call::<Option<Vec<String>>>(".")?, None);
-//         assert_eq!(api.path_parts.call::<Option<Vec<String>>>("..")?, None);
-//         assert_eq!(api.path_parts.call::<Option<Vec<String>>>("./..")?, None);
-//         assert_eq!(api.path_parts.call::<Option<Vec<String>>>("a/..")?, None);
-
-//         assert_eq!(api.path_parent.call::<String>("a/b/c")?, "a/b");
-//         assert_eq!(api.path_parent.call::<String>("/a/b/c")?, "/a/b");
-//         assert_eq!(api.path_parent.call::<String>("a\\./b")?, "a");
-//         assert_eq!(api.path_parent.call::<Option<Vec<String>>>("a")?, None);
-//         assert_eq!(api.path_parent.call::<Option<Vec<String>>>("a/.")?, None);
-//         assert_eq!(api.path_parent.call::<Option<Vec<String>>>("a/../b")?, None);
-
-//         assert_eq!(api.path_file_name.call::<String>("/")?, "/");
-//         assert_eq!(api.path_file_name.call::<String>("a")?, "a");
-//         assert_eq!(api.path_file_name.call::<String>("a/b/c")?, "c");
-//         assert_eq!(api.path_file_name.call::<String>("/a/b/c")?, "c");
-//         assert_eq!(api.path_file_name.call::<String>("a\\./b")?, "b");
-//         assert_eq!(api.path_file_name.call::<Option<Vec<String>>>(".")?, None);
-//         assert_eq!(api.path_file_name.call::<Option<Vec<String>>>("a/..")?, None);
-
-//         Ok(())
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_actions() -> Result<(), PackagesEngineError> {
+        let api = PathAPI::new(Lua::new())?;
+
+        assert_eq!(api.path_normalize.call::<String>("/")?, "/");
+        assert_eq!(api.path_normalize.call::<String>("a/b/c")?, "a/b/c");
+        assert_eq!(api.path_normalize.call::<String>("/a/b/c")?, "/a/b/c");
+        assert_eq!(api.path_normalize.call::<String>("a/./c")?, "a/c");
+        assert_eq!(api.path_normalize.call::<String>("a/../c")?, "c");
+        assert_eq!(api.path_normalize.call::<String>("a/../c/./")?, "c");
+        assert_eq!(api.path_normalize.call::<String>("./a//\\./../b")?, "b");
+        assert_eq!(api.path_normalize.call::<String>(" ")?, " "); // space is a correct entry name
+        assert_eq!(api.path_normalize.call::<Option<String>>("")?, None); // entry name cannot be empty
+        assert_eq!(api.path_normalize.call::<Option<String>>(".")?, None); // we do not support relative paths
+        assert_eq!(api.path_normalize.call::<Option<String>>("..")?, None);
+        assert_eq!(api.path_normalize.call::<Option<String>>("./..")?, None);
+        assert_eq!(api.path_normalize.call::<Option<String>>("a/..")?, None);
+
+        assert_eq!(api.path_join.call::<String>(("a", "b", "c"))?, "a/b/c");
+        assert_eq!(api.path_join.call::<String>(("/", "a", "b", "c"))?, "/a/b/c");
+        assert_eq!(api.path_join.call::<String>(("a", "..", "b"))?, "b");
+        assert_eq!(api.path_join.call::<String>((".", "a", ".", "b"))?, "a/b");
+        assert_eq!(api.path_join.call::<Option<String>>("")?, None);
+        assert_eq!(api.path_join.call::<Option<String>>(".")?, None);
+        assert_eq!(api.path_join.call::<Option<String>>("..")?, None);
+        assert_eq!(api.path_join.call::<Option<String>>((".", ".."))?, None);
+        assert_eq!(api.path_join.call::<Option<String>>(("a", ".."))?, None);
+
+        assert_eq!(api.path_parts.call::<Vec<String>>("a/b/c")?, &["a", "b", "c"]);
+        assert_eq!(api.path_parts.call::<Vec<String>>("a/./c")?, &["a", "c"]);
+        assert_eq!(api.path_parts.call::<Vec<String>>("a/./c/..")?, &["a"]);
+        assert_eq!(api.path_parts.call::<Vec<String>>("\\a/b/// /c")?, &["a", "b", " ", "c"]);
+        assert_eq!(api.path_parts.call::<Option<Vec<String>>>("")?, None);
+        assert_eq!(api.path_parts.call::<Option<Vec<String>>>(".")?, None);
+        assert_eq!(api.path_parts.call::<Option<Vec<String>>>("..")?, None);
+        assert_eq!(api.path_parts.call::<Option<Vec<String>>>("./..")?, None);
+        assert_eq!(api.path_parts.call::<Option<Vec<String>>>("a/..")?, None);
+
+        assert_eq!(api.path_parent.call::<String>("a/b/c")?, "a/b");
+        assert_eq!(api.path_parent.call::<String>("/a/b/c")?, "/a/b");
+        assert_eq!(api.path_parent.call::<String>("a\\./b")?, "a");
+        assert_eq!(api.path_parent.call::<Option<Vec<String>>>("a")?, None);
+        assert_eq!(api.path_parent.call::<Option<Vec<String>>>("a/.")?, None);
+        assert_eq!(api.path_parent.call::<Option<Vec<String>>>("a/../b")?, None);
+
+        assert_eq!(api.path_file_name.call::<String>("/")?, "/");
+        assert_eq!(api.path_file_name.call::<String>("a")?, "a");
+        assert_eq!(api.path_file_name.call::<String>("a/b/c")?, "c");
+        assert_eq!(api.path_file_name.call::<String>("/a/b/c")?, "c");
+        assert_eq!(api.path_file_name.call::<String>("a\\./b")?, "b");
+        assert_eq!(api.path_file_name.call::<Option<Vec<String>>>(".")?, None);
+        assert_eq!(api.path_file_name.call::<Option<Vec<String>>>("a/..")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_extension() -> Result<(), PackagesEngineError> {
+        let api = PathAPI::new(Lua::new())?;
+
+        assert_eq!(api.path_extension.call::<String>("a.txt")?, "txt");
+        assert_eq!(api.path_extension.call::<String>("/a/b/c.tar.gz")?, "gz");
+        assert_eq!(api.path_extension.call::<String>("a/.config/b.json")?, "json");
+        assert_eq!(api.path_extension.call::<String>(".bashrc.bak")?, "bak");
+        assert_eq!(api.path_extension.call::<Option<String>>(".bashrc")?, None);
+        assert_eq!(api.path_extension.call::<Option<String>>("a/b")?, None);
+        assert_eq!(api.path_extension.call::<Option<String>>("a.")?, None);
+        assert_eq!(api.path_extension.call::<Option<String>>("")?, None);
+        assert_eq!(api.path_extension.call::<Option<String>>("a.txt/..")?, None);
+
+        assert_eq!(api.path_with_extension.call::<String>(("a.txt", "json"))?, "a.json");
+        assert_eq!(api.path_with_extension.call::<String>(("/a/b/c.tar.gz", ".xz"))?, "/a/b/c.tar.xz");
+        assert_eq!(api.path_with_extension.call::<String>(("a\\./b", "txt"))?, "a/b.txt");
+        assert_eq!(api.path_with_extension.call::<String>(("a/b.txt", ""))?, "a/b");
+        assert_eq!(api.path_with_extension.call::<String>((".bashrc", "bak"))?, ".bashrc.bak");
+        assert_eq!(api.path_with_extension.call::<Option<String>>(("", "txt"))?, None);
+        assert_eq!(api.path_with_extension.call::<Option<String>>(("/", "txt"))?, None);
+        assert_eq!(api.path_with_extension.call::<Option<String>>(("a/..", "txt"))?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_relative() -> Result<(), PackagesEngineError> {
+        let api = PathAPI::new(Lua::new())?;
+
+        assert!(api.path_is_absolute.call::<bool>("/")?);
+        assert!(api.path_is_absolute.call::<bool>("/a/b")?);
+        assert!(!api.path_is_absolute.call::<bool>("a/b")?);
+        assert!(!api.path_is_absolute.call::<bool>("")?);
+
+        assert_eq!(api.path_relative_to.call::<String>(("/a", "/a/b/c"))?, "b/c");
+        assert_eq!(api.path_relative_to.call::<String>(("/", "/a/b"))?, "a/b");
+        assert_eq!(api.path_relative_to.call::<String>(("a/./b", "a/b/../b/c"))?, "c");
+        assert_eq!(api.path_relative_to.call::<Option<String>>(("/a/b", "/a/bc"))?, None);
+        assert_eq!(api.path_relative_to.call::<Option<String>>(("/a", "a/b"))?, None);
+        assert_eq!(api.path_relative_to.call::<Option<String>>(("/a/b", "/a"))?, None);
+        assert_eq!(api.path_relative_to.call::<Option<String>>(("/a", "/a"))?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_matches() -> Result<(), PackagesEngineError> {
+        let api = PathAPI::new(Lua::new())?;
+
+        assert!(api.path_matches.call::<bool>(("*.dll", "a.dll"))?);
+        assert!(api.path_matches.call::<bool>(("*.dll", "/prefix/system32/a.dll"))?);
+        assert!(api.path_matches.call::<bool>(("system32/*.dll", "system32\\a.dll"))?);
+        assert!(api.path_matches.call::<bool>(("**/lib?.so", "a/b/lib1.so"))?);
+        assert!(!api.path_matches.call::<bool>(("system32/*.dll", "system32/a/b.dll"))?);
+        assert!(!api.path_matches.call::<bool>(("*.dll", "a.dll.bak"))?);
+
+        Ok(())
+    }
+}
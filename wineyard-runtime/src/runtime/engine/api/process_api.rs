@@ -1,22 +1,176 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::io::{Read, Write};
-use std::process::{Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::io::{Read, Write, BufRead, BufReader};
+use std::process::{Command, Stdio, Child, Output};
+use std::time::{Duration, Instant};
 
 use mlua::prelude::*;
 
 use super::*;
 
 const PROCESS_READ_CHUNK_LEN: usize = 4096;
+const PROCESS_WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Output line of the process with its stream name.
+type ProcessLine = (&'static str, Vec<u8>);
+
+/// Spawned process with its execution deadline.
+struct Process {
+    child: Child,
+    deadline: Option<Instant>,
+
+    /// Output lines read by the background threads.
+    lines: Option<Arc<Mutex<Receiver<ProcessLine>>>>
+}
+
+impl Process {
+    #[inline]
+    fn new(child: Child, deadline: Option<Instant>) -> Self {
+        Self {
+            child,
+            deadline,
+            lines: None
+        }
+    }
+
+    /// Start background threads reading the process's stdout and stderr
+    /// line by line, and return the lines receiver.
+    fn lines(&mut self) -> Arc<Mutex<Receiver<ProcessLine>>> {
+        if let Some(lines) = &self.lines {
+            return lines.clone();
+        }
+
+        let (send, recv) = std::sync::mpsc::channel();
+
+        fn read_lines(pipe: impl Read + Send + 'static, stream: &'static str, send: std::sync::mpsc::Sender<ProcessLine>) {
+            std::thread::spawn(move || {
+                let mut pipe = BufReader::new(pipe);
+
+                loop {
+                    let mut line = Vec::new();
+
+                    match pipe.read_until(b'\n', &mut line) {
+                        Ok(0) | Err(_) => break,
+
+                        Ok(_) => {
+                            if send.send((stream, line)).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+
+        if let Some(stdout) = self.child.stdout.take() {
+            read_lines(stdout, "stdout", send.clone());
+        }
+
+        if let Some(stderr) = self.child.stderr.take() {
+            read_lines(stderr, "stderr", send);
+        }
+
+        let lines = Arc::new(Mutex::new(recv));
+
+        self.lines = Some(lines.clone());
+
+        lines
+    }
+
+    /// Wait until the process has finished and read its output.
+    /// Kill the process if the deadline is reached.
+    ///
+    /// Return process output and whether it was timed out.
+    fn wait_with_output(self) -> std::io::Result<(Output, bool)> {
+        let (mut output, timed_out) = match self.deadline {
+            Some(deadline) => Self::wait_with_deadline(self.child, deadline)?,
+            None => (self.child.wait_with_output()?, false)
+        };
+
+        // Return unread lines if the output was read line by line.
+        if let Some(lines) = self.lines {
+            let lines = lines.lock()
+                .map_err(|_| std::io::Error::other("failed to read process output"))?;
+
+            for (stream, line) in lines.iter() {
+                if stream == "stdout" {
+                    output.stdout.extend(line);
+                } else {
+                    output.stderr.extend(line);
+                }
+            }
+        }
+
+        Ok((output, timed_out))
+    }
+
+    fn wait_with_deadline(mut child: Child, deadline: Instant) -> std::io::Result<(Output, bool)> {
+        // Read pipes in background so the process can't be blocked
+        // by a filled pipe buffer.
+        fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+            std::thread::spawn(move || {
+                let mut buf = Vec::new();
+
+                if let Some(mut pipe) = pipe {
+                    pipe.read_to_end(&mut buf)?;
+                }
+
+                Ok(buf)
+            })
+        }
+
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+
+        let (status, timed_out) = loop {
+            if let Some(status) = child.try_wait()? {
+                break (status, false);
+            }
+
+            if Instant::now() >= deadline {
+                child.kill()?;
+
+                break (child.wait()?, true);
+            }
+
+            std::thread::sleep(PROCESS_WAIT_INTERVAL);
+        };
+
+        let join = |reader: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+            reader.join()
+                .map_err(|_| std::io::Error::other("failed to read process output"))?
+        };
+
+        let output = Output {
+            status,
+            stdout: join(stdout)?,
+            stderr: join(stderr)?
+        };
+
+        Ok((output, timed_out))
+    }
+}
+
+/// Convert output line into lua string without the line break.
+fn line_to_lua(lua: &Lua, line: &[u8]) -> Result<LuaValue, LuaError> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    lua.create_string(line).map(LuaValue::String)
+}
 
 pub struct ProcessAPI {
     lua: Lua,
 
     process_exec: LuaFunctionBuilder,
     process_open: LuaFunctionBuilder,
+    process_spawn: LuaFunctionBuilder,
     process_stdin: LuaFunction,
     process_stdout: LuaFunction,
     process_stderr: LuaFunction,
+    process_read_line: LuaFunction,
+    process_read_line_blocking: LuaFunction,
     process_kill: LuaFunction,
     process_wait: LuaFunction,
     process_finished: LuaFunction
@@ -140,7 +294,107 @@ impl ProcessAPI {
                         #[cfg(feature = "tracing")]
                         tracing::debug!(?command, "spawned process");
 
-                        handles.insert(handle, command.spawn()?);
+                        handles.insert(handle, Process::new(command.spawn()?, None));
+
+                        Ok(handle)
+                    })
+                })
+            },
+
+            process_spawn: {
+                let process_handles = process_handles.clone();
+
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
+                    let process_handles = process_handles.clone();
+
+                    lua.create_function(move |_, options: LuaTable| {
+                        let path = resolve_path(options.get::<LuaString>("command")?.to_string_lossy())?;
+
+                        let mut command = Command::new(path);
+
+                        // Resolve and validate the working folder.
+                        let current_dir = match options.get::<Option<LuaString>>("cwd")? {
+                            Some(cwd) => {
+                                let mut cwd = resolve_path(cwd.to_string_lossy())?;
+
+                                if cwd.is_relative() {
+                                    cwd = context.module_folder.join(cwd);
+                                }
+
+                                if !context.is_accessible(&cwd) {
+                                    return Err(LuaError::external("path is inaccessible"));
+                                }
+
+                                cwd
+                            }
+
+                            None => context.module_folder.clone()
+                        };
+
+                        // Create working folder if it doesn't exist.
+                        if !current_dir.is_dir() {
+                            std::fs::create_dir_all(&current_dir)?;
+                        }
+
+                        let mut command = command
+                            .current_dir(current_dir)
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped());
+
+                        // Apply command arguments.
+                        if let Some(args) = options.get::<Option<LuaTable>>("args")? {
+                            for arg in args.sequence_values::<LuaString>() {
+                                command = command.arg(arg?.to_string_lossy());
+                            }
+                        }
+
+                        // Apply command environment.
+                        if let Some(env) = options.get::<Option<LuaTable>>("env")? {
+                            for pair in env.pairs::<LuaString, LuaString>() {
+                                let (key, value) = pair?;
+
+                                command = command.env(
+                                    key.to_string_lossy(),
+                                    value.to_string_lossy()
+                                );
+                            }
+                        }
+
+                        let stdin = match options.get::<LuaValue>("stdin")? {
+                            LuaValue::Nil => None,
+                            value => Some(lua_value_to_bytes(value)?)
+                        };
+
+                        let deadline = options.get::<Option<u64>>("timeout")?
+                            .map(|timeout| Instant::now() + Duration::from_millis(timeout));
+
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(?command, "spawned process");
+
+                        let mut child = command.spawn()?;
+
+                        // Write stdin in background and close it so the
+                        // process knows there's no more input.
+                        if let Some(stdin) = stdin
+                            && let Some(mut child_stdin) = child.stdin.take()
+                        {
+                            std::thread::spawn(move || {
+                                let _ = child_stdin.write_all(&stdin);
+                            });
+                        }
+
+                        let mut handles = process_handles.lock()
+                            .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?;
+
+                        let mut handle = rand::random::<i32>();
+
+                        while handles.contains_key(&handle) {
+                            handle = rand::random::<i32>();
+                        }
+
+                        handles.insert(handle, Process::new(child, deadline));
 
                         Ok(handle)
                     })
@@ -159,7 +413,7 @@ impl ProcessAPI {
                     };
 
                     // Try to write data to the process's stdin.
-                    if let Some(stdin) = &mut process.stdin {
+                    if let Some(stdin) = &mut process.child.stdin {
                         stdin.write_all(&lua_value_to_bytes(data)?)?;
                     }
 
@@ -179,7 +433,7 @@ impl ProcessAPI {
                     };
 
                     // Read the process's stdout chunk.
-                    if let Some(stdout) = &mut process.stdout {
+                    if let Some(stdout) = &mut process.child.stdout {
                         let mut buf = [0; PROCESS_READ_CHUNK_LEN];
 
                         let len = stdout.read(&mut buf)?;
@@ -204,7 +458,7 @@ impl ProcessAPI {
                     };
 
                     // Read the process's stderr chunk.
-                    if let Some(stderr) = &mut process.stderr {
+                    if let Some(stderr) = &mut process.child.stderr {
                         let mut buf = [0; PROCESS_READ_CHUNK_LEN];
 
                         let len = stderr.read(&mut buf)?;
@@ -217,6 +471,60 @@ impl ProcessAPI {
                 })?
             },
 
+            process_read_line: {
+                let process_handles = process_handles.clone();
+
+                lua.create_function(move |lua, handle: i32| {
+                    let mut handles = process_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(process) = handles.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid process handle"));
+                    };
+
+                    let lines = process.lines();
+
+                    let lines = lines.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read process output: {err}")))?;
+
+                    match lines.try_recv() {
+                        Ok((stream, line)) => Ok((line_to_lua(lua, &line)?, LuaValue::String(lua.create_string(stream)?))),
+                        Err(_) => Ok((LuaNil, LuaNil))
+                    }
+                })?
+            },
+
+            process_read_line_blocking: {
+                let process_handles = process_handles.clone();
+
+                lua.create_function(move |lua, (handle, timeout): (i32, Option<u64>)| {
+                    let mut handles = process_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(process) = handles.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid process handle"));
+                    };
+
+                    let lines = process.lines();
+
+                    // Don't block other handles while waiting.
+                    drop(handles);
+
+                    let lines = lines.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read process output: {err}")))?;
+
+                    let line = match timeout {
+                        Some(timeout) => lines.recv_timeout(Duration::from_millis(timeout)),
+                        None => lines.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                    };
+
+                    match line {
+                        Ok((stream, line)) => Ok((line_to_lua(lua, &line)?, LuaValue::String(lua.create_string(stream)?))),
+                        Err(_) => Ok((LuaNil, LuaNil))
+                    }
+                })?
+            },
+
             process_kill: {
                 let process_handles = process_handles.clone();
 
@@ -229,7 +537,7 @@ impl ProcessAPI {
                     };
 
                     // Kill the process and remove its handle.
-                    process.kill()?;
+                    process.child.kill()?;
                     handles.remove(&handle);
 
                     Ok(())
@@ -247,14 +555,18 @@ impl ProcessAPI {
                         return Err(LuaError::external("invalid process handle"));
                     };
 
+                    // Don't block other handles while waiting.
+                    drop(handles);
+
                     // Wait until the process has finished.
-                    let output = process.wait_with_output()?;
+                    let (output, timed_out) = process.wait_with_output()?;
 
                     // Prepare lua result.
-                    let result = lua.create_table_with_capacity(0, 4)?;
+                    let result = lua.create_table_with_capacity(0, 5)?;
 
                     result.raw_set("status", output.status.code())?;
                     result.raw_set("is_ok", output.status.success())?;
+                    result.raw_set("timed_out", timed_out)?;
                     result.raw_set("stdout", output.stdout)?;
                     result.raw_set("stderr", output.stderr)?;
 
@@ -273,7 +585,7 @@ impl ProcessAPI {
                         return Err(LuaError::external("invalid process handle"));
                     };
 
-                    Ok(process.try_wait()?.is_some())
+                    Ok(process.child.try_wait()?.is_some())
                 })?
             },
 
@@ -288,13 +600,16 @@ impl ProcessAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 8)?;
+        let env = self.lua.create_table_with_capacity(0, 11)?;
 
         env.raw_set("exec", (self.process_exec)(&self.lua, context)?)?;
         env.raw_set("open", (self.process_open)(&self.lua, context)?)?;
+        env.raw_set("spawn", (self.process_spawn)(&self.lua, context)?)?;
         env.raw_set("stdin", self.process_stdin.clone())?;
         env.raw_set("stdout", self.process_stdout.clone())?;
         env.raw_set("stderr", self.process_stderr.clone())?;
+        env.raw_set("read_line", self.process_read_line.clone())?;
+        env.raw_set("read_line_blocking", self.process_read_line_blocking.clone())?;
         env.raw_set("wait", self.process_wait.clone())?;
         env.raw_set("kill", self.process_kill.clone())?;
         env.raw_set("finished", self.process_finished.clone())?;
@@ -373,3 +688,96 @@ impl ProcessAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_spawn() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-process-spawn-test");
+
+        let api = ProcessAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context {
+            ext_process_api: true,
+            ..Context::test(&path, path.join("local_validator.json"))?
+        })?;
+
+        let spawn = env.get::<LuaFunction>("spawn")?;
+
+        let options = api.lua().create_table()?;
+
+        options.raw_set("command", "bash")?;
+        options.raw_set("args", ["-c", "echo $TEST; pwd; cat"])?;
+        options.raw_set("env", HashMap::from([("TEST", "Hello, World!")]))?;
+        options.raw_set("cwd", "folder")?;
+        options.raw_set("stdin", "input")?;
+
+        let output = api.process_wait.call::<LuaTable>(spawn.call::<i32>(options)?)?;
+
+        assert_eq!(output.get::<i32>("status")?, 0);
+        assert!(output.get::<bool>("is_ok")?);
+        assert!(!output.get::<bool>("timed_out")?);
+
+        assert_eq!(
+            String::from_utf8(output.get::<Vec<u8>>("stdout")?).map_err(LuaError::external)?,
+            format!("Hello, World!\n{}\ninput", path.join("folder").display())
+        );
+
+        let options = api.lua().create_table()?;
+
+        options.raw_set("command", "sleep")?;
+        options.raw_set("args", ["10"])?;
+        options.raw_set("timeout", 100)?;
+
+        let output = api.process_wait.call::<LuaTable>(spawn.call::<i32>(options)?)?;
+
+        assert!(!output.get::<bool>("is_ok")?);
+        assert!(output.get::<bool>("timed_out")?);
+
+        let options = api.lua().create_table()?;
+
+        options.raw_set("command", "bash")?;
+        options.raw_set("cwd", "/")?;
+
+        assert!(spawn.call::<i32>(options).is_err());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn process_read_line() -> Result<(), PackagesEngineError> {
+        let api = ProcessAPI::new(Lua::new())?;
+
+        let env = api.create_env(&Context {
+            ext_process_api: true,
+            ..Context::test(
+                std::env::temp_dir(),
+                std::env::temp_dir().join(".wineyard-runtime-process-read-line-test.json")
+            )?
+        })?;
+
+        let handle = env.call_function::<i32>("open", (
+            "bash", ["-c", "echo line 1; sleep 0.1; echo line 2 >&2; sleep 0.1; echo line 3"]
+        ))?;
+
+        let read_line = |timeout: u64| {
+            api.process_read_line_blocking.call::<(Option<String>, Option<String>)>((handle, timeout))
+        };
+
+        assert_eq!(read_line(5000)?, (Some(String::from("line 1")), Some(String::from("stdout"))));
+        assert_eq!(api.process_read_line.call::<(Option<String>, Option<String>)>(handle)?, (None, None));
+        assert_eq!(read_line(5000)?, (Some(String::from("line 2")), Some(String::from("stderr"))));
+
+        let output = api.process_wait.call::<LuaTable>(handle)?;
+
+        assert!(output.get::<bool>("is_ok")?);
+        assert_eq!(output.get::<Vec<u8>>("stdout")?, b"line 3\n");
+        assert!(output.get::<Vec<u8>>("stderr")?.is_empty());
+
+        Ok(())
+    }
+}
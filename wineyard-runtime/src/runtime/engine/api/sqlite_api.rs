@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::ptr::NonNull;
 
 use mlua::prelude::*;
 
-use rusqlite::{Connection, ToSql};
+use rusqlite::{Connection, Statement, OpenFlags, ToSql};
 use rusqlite::types::{ValueRef, ToSqlOutput, FromSql, FromSqlResult};
+use rusqlite::backup::{Backup, StepResult};
 
 use super::*;
 
@@ -92,6 +95,350 @@ impl FromSql for SqliteParam {
     }
 }
 
+/// Options of the opened database connection.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct SqliteOpenOptions {
+    journal_mode: Option<String>,
+    synchronous: Option<String>,
+    busy_timeout: Option<u32>,
+    foreign_keys: Option<bool>,
+    read_only: bool
+}
+
+impl SqliteOpenOptions {
+    pub fn from_lua(options: &LuaTable) -> Result<Self, LuaError> {
+        let journal_mode = options.get::<Option<LuaString>>("journal_mode")?
+            .map(|mode| mode.to_string_lossy().to_lowercase());
+
+        if let Some(mode) = &journal_mode
+            && !["delete", "truncate", "persist", "memory", "wal", "off"].contains(&mode.as_str())
+        {
+            return Err(LuaError::external(format!("unsupported journal mode: {mode}")));
+        }
+
+        let synchronous = options.get::<Option<LuaString>>("synchronous")?
+            .map(|mode| mode.to_string_lossy().to_lowercase());
+
+        if let Some(mode) = &synchronous
+            && !["off", "normal", "full", "extra"].contains(&mode.as_str())
+        {
+            return Err(LuaError::external(format!("unsupported synchronous mode: {mode}")));
+        }
+
+        Ok(Self {
+            journal_mode,
+            synchronous,
+            busy_timeout: options.get("busy_timeout")?,
+            foreign_keys: options.get("foreign_keys")?,
+            read_only: options.get::<Option<bool>>("read_only")?.unwrap_or_default()
+        })
+    }
+
+    /// Open database connection with the current options.
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<Connection, LuaError> {
+        let connection = if self.read_only {
+            Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        } else {
+            Connection::open(path)
+        };
+
+        let connection = connection.map_err(LuaError::external)?;
+
+        if let Some(timeout) = self.busy_timeout {
+            connection.busy_timeout(std::time::Duration::from_millis(timeout as u64))
+                .map_err(LuaError::external)?;
+        }
+
+        // Journal mode pragma returns the new mode so it can't be executed
+        // as a simple update.
+        if let Some(mode) = &self.journal_mode {
+            connection.pragma_update_and_check(None, "journal_mode", mode, |row| row.get::<_, String>(0))
+                .map_err(LuaError::external)?;
+        }
+
+        if let Some(mode) = &self.synchronous {
+            connection.pragma_update(None, "synchronous", mode)
+                .map_err(LuaError::external)?;
+        }
+
+        if let Some(enabled) = self.foreign_keys {
+            connection.pragma_update(None, "foreign_keys", enabled)
+                .map_err(LuaError::external)?;
+        }
+
+        Ok(connection)
+    }
+}
+
+/// Opened database connection with its prepared statements.
+///
+/// Statements borrow the connection, so it's stored on the heap and freed
+/// only after all of them are finalized.
+struct SqliteConnection {
+    connection: NonNull<Connection>,
+    statements: HashMap<i32, Statement<'static>>
+}
+
+// SAFETY: statements are always moved together with the connection they
+// borrow, and the connection itself is `Send`.
+unsafe impl Send for SqliteConnection {}
+
+impl SqliteConnection {
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection: NonNull::from(Box::leak(Box::new(connection))),
+            statements: HashMap::new()
+        }
+    }
+
+    /// Prepare the query and store its statement with the given handle.
+    pub fn prepare(&mut self, handle: i32, query: &str) -> Result<(), LuaError> {
+        // SAFETY: the connection outlives its statements, see `drop`.
+        let connection = unsafe { &*self.connection.as_ptr() };
+
+        let statement = connection.prepare(query)
+            .map_err(LuaError::external)?;
+
+        self.statements.insert(handle, statement);
+
+        Ok(())
+    }
+
+    /// Get prepared statement with the given handle and its connection.
+    pub fn statement(&mut self, handle: i32) -> Option<(&Connection, &mut Statement<'static>)> {
+        // SAFETY: the connection is never borrowed mutably.
+        let connection = unsafe { self.connection.as_ref() };
+
+        self.statements.get_mut(&handle)
+            .map(|statement| (connection, statement))
+    }
+
+    /// Run the callback with a mutable connection.
+    ///
+    /// Prepared statements borrow the connection, so the callback gets
+    /// another connection object which uses the same database handle.
+    pub fn with_mut<T>(
+        &self,
+        callback: impl FnOnce(&mut Connection) -> Result<T, LuaError>
+    ) -> Result<T, LuaError> {
+        // SAFETY: the returned connection doesn't close the database handle
+        // and is dropped before the current connection.
+        let mut connection = unsafe { Connection::from_handle(self.handle()) }
+            .map_err(LuaError::external)?;
+
+        callback(&mut connection)
+    }
+}
+
+impl std::ops::Deref for SqliteConnection {
+    type Target = Connection;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the connection is never borrowed mutably.
+        unsafe { self.connection.as_ref() }
+    }
+}
+
+impl Drop for SqliteConnection {
+    fn drop(&mut self) {
+        // Finalize statements before closing the connection.
+        self.statements.clear();
+
+        // SAFETY: the connection was leaked in `new` and no statements
+        // borrow it anymore.
+        drop(unsafe { Box::from_raw(self.connection.as_ptr()) });
+    }
+}
+
+/// Amount of database pages copied by a single backup step.
+const SQLITE_BACKUP_STEP_PAGES: i32 = 256;
+
+/// Delay before retrying the backup step if one of databases is locked.
+const SQLITE_BACKUP_BUSY_DELAY: Duration = Duration::from_millis(10);
+
+/// Max time the backup can wait for the locked databases without copying
+/// any pages.
+const SQLITE_BACKUP_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Copy content of the `from` database to the `to` database using online
+/// backup API, reporting `(copied, total)` pages to the `progress` callback
+/// after each step.
+fn copy_database(
+    from: &Connection,
+    to: &mut Connection,
+    progress: Option<LuaFunction>
+) -> Result<(), LuaError> {
+    let backup = Backup::new(from, to)
+        .map_err(|err| LuaError::external(format!("failed to start database backup: {err}")))?;
+
+    let mut busy_since = None;
+
+    loop {
+        let result = backup.step(SQLITE_BACKUP_STEP_PAGES)
+            .map_err(|err| LuaError::external(format!("failed to copy database: {err}")))?;
+
+        if let Some(progress) = &progress {
+            let status = backup.progress();
+
+            progress.call::<()>((status.pagecount - status.remaining, status.pagecount))?;
+        }
+
+        match result {
+            StepResult::Done => return Ok(()),
+            StepResult::More => busy_since = None,
+
+            StepResult::Busy | StepResult::Locked => {
+                let busy_since = *busy_since.get_or_insert_with(Instant::now);
+
+                if busy_since.elapsed() >= SQLITE_BACKUP_BUSY_TIMEOUT {
+                    return Err(LuaError::external("failed to copy database: database is locked"));
+                }
+
+                std::thread::sleep(SQLITE_BACKUP_BUSY_DELAY);
+            }
+
+            _ => return Err(LuaError::external("failed to copy database: unexpected backup step result"))
+        }
+    }
+}
+
+/// Temporarily take the connection from the handles table and run the
+/// callback with it. The connection can't be used by other API functions
+/// while the callback is running.
+fn with_taken_connection<T>(
+    connection_handles: &Mutex<HashMap<i32, SqliteConnection>>,
+    handle: i32,
+    callback: impl FnOnce(&mut SqliteConnection) -> Result<T, LuaError>
+) -> Result<T, LuaError> {
+    let mut connection = connection_handles.lock()
+        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?
+        .remove(&handle)
+        .ok_or_else(|| LuaError::external("invalid database connection handle"))?;
+
+    let result = callback(&mut connection);
+
+    connection_handles.lock()
+        .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?
+        .insert(handle, connection);
+
+    result
+}
+
+/// Bind params from the lua table to the query.
+///
+/// Sequence tables are bound as positional params (`?1`), tables with
+/// string keys are bound as named params (`:name`).
+fn bind_query_params(query: &mut rusqlite::Statement, params: Option<LuaTable>) -> Result<(), LuaError> {
+    let mut positional = Vec::new();
+    let mut named = Vec::new();
+
+    if let Some(params) = params {
+        for pair in params.pairs::<LuaValue, LuaValue>() {
+            let (key, value) = pair?;
+
+            match key {
+                LuaValue::Integer(index) if index > 0 => {
+                    positional.push((index as usize, SqliteParam::from_lua(&value)?));
+                }
+
+                LuaValue::String(name) => {
+                    let mut name = name.to_string_lossy();
+
+                    if !name.starts_with([':', '@', '$']) {
+                        name = format!(":{name}");
+                    }
+
+                    named.push((name, SqliteParam::from_lua(&value)?));
+                }
+
+                _ => return Err(LuaError::external("invalid query param key"))
+            }
+        }
+    }
+
+    if !positional.is_empty() && !named.is_empty() {
+        return Err(LuaError::external("positional and named query params can't be mixed"));
+    }
+
+    if named.is_empty() {
+        let expected = query.parameter_count();
+
+        if positional.len() != expected {
+            return Err(LuaError::external(format!("wrong number of query params: expected {expected}, got {}", positional.len())));
+        }
+
+        for (index, value) in positional {
+            query.raw_bind_parameter(index, value)
+                .map_err(LuaError::external)?;
+        }
+    }
+
+    else {
+        let mut bound = vec![false; query.parameter_count()];
+
+        for (name, value) in named {
+            let Some(index) = query.parameter_index(&name).map_err(LuaError::external)? else {
+                return Err(LuaError::external(format!("unknown query param: {name}")));
+            };
+
+            // Same param can be given with different prefixes.
+            if std::mem::replace(&mut bound[index - 1], true) {
+                return Err(LuaError::external(format!("duplicate query param: {name}")));
+            }
+
+            query.raw_bind_parameter(index, value)
+                .map_err(LuaError::external)?;
+        }
+
+        // Don't bind missing params as NULL.
+        if let Some(index) = bound.iter().position(|bound| !bound) {
+            let name = query.parameter_name(index + 1).unwrap_or("?");
+
+            return Err(LuaError::external(format!("missing query param: {name}")));
+        }
+    }
+
+    Ok(())
+}
+
+/// Read all the columns of the queried row.
+fn read_row(row: &rusqlite::Row) -> rusqlite::Result<Vec<SqliteParam>> {
+    let mut columns = Vec::new();
+
+    let mut i = 0;
+
+    while let Ok(column) = row.get::<_, SqliteParam>(i) {
+        columns.push(column);
+
+        i += 1;
+    }
+
+    Ok(columns)
+}
+
+/// Convert queried rows into a lua table.
+fn rows_to_lua(
+    lua: &Lua,
+    rows: impl Iterator<Item = rusqlite::Result<Vec<SqliteParam>>>
+) -> Result<LuaTable, LuaError> {
+    let result = lua.create_table()?;
+
+    for row in rows {
+        let row = row.map_err(LuaError::external)?;
+
+        let result_row = lua.create_table_with_capacity(row.len(), 0)?;
+
+        for column in row {
+            result_row.raw_push(column.to_lua(lua)?)?;
+        }
+
+        result.raw_push(result_row)?;
+    }
+
+    Ok(result)
+}
+
 pub struct SQLiteAPI {
     lua: Lua,
 
@@ -100,12 +447,19 @@ pub struct SQLiteAPI {
     sqlite_batch: LuaFunction,
     sqlite_query: LuaFunction,
     sqlite_query_row: LuaFunction,
+    sqlite_transaction: LuaFunction,
+    sqlite_prepare: LuaFunction,
+    sqlite_stmt_execute: LuaFunction,
+    sqlite_stmt_query: LuaFunction,
+    sqlite_stmt_finalize: LuaFunction,
+    sqlite_backup: LuaFunctionBuilder,
+    sqlite_restore: LuaFunctionBuilder,
     sqlite_close: LuaFunction
 }
 
 impl SQLiteAPI {
     pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
-        let connection_handles = Arc::new(Mutex::new(HashMap::new()));
+        let connection_handles = Arc::new(Mutex::new(HashMap::<i32, SqliteConnection>::new()));
 
         Ok(Self {
             sqlite_open: {
@@ -115,21 +469,28 @@ impl SQLiteAPI {
                     let context = context.to_owned();
                     let connection_handles = connection_handles.clone();
 
-                    lua.create_function(move |_, path: LuaString| {
+                    lua.create_function(move |_, (path, options): (LuaString, Option<LuaTable>)| {
                         let path = resolve_path(path.to_string_lossy())?;
 
                         if !context.is_accessible(&path) {
                             return Err(LuaError::external("path is inaccessible"));
                         }
 
-                        if let Some(parent) = path.parent() {
-                            if !parent.is_dir() {
-                                std::fs::create_dir_all(parent)?;
-                            }
+                        context.track_temp(&path);
+
+                        let options = match options {
+                            Some(options) => SqliteOpenOptions::from_lua(&options)?,
+                            None => SqliteOpenOptions::default()
+                        };
+
+                        if !options.read_only
+                            && let Some(parent) = path.parent()
+                            && !parent.is_dir()
+                        {
+                            std::fs::create_dir_all(parent)?;
                         }
 
-                        let connection = Connection::open(path)
-                            .map_err(LuaError::external)?;
+                        let connection = options.open(path)?;
 
                         let mut handles = connection_handles.lock()
                             .map_err(|err| LuaError::external(format!("failed to register handle: {err}")))?;
@@ -140,7 +501,7 @@ impl SQLiteAPI {
                             handle = rand::random::<i32>();
                         }
 
-                        handles.insert(handle, connection);
+                        handles.insert(handle, SqliteConnection::new(connection));
 
                         Ok(handle)
                     })
@@ -161,20 +522,9 @@ impl SQLiteAPI {
                     let mut query = connection.prepare_cached(&command.to_string_lossy())
                         .map_err(LuaError::external)?;
 
-                    // raw_len for better performance.
-                    let params_len = params.as_ref()
-                        .map(LuaTable::raw_len)
-                        .unwrap_or_default();
-
-                    let mut query_params = Vec::with_capacity(params_len);
-
-                    if let Some(params) = params {
-                        for param in params.sequence_values::<LuaValue>() {
-                            query_params.push(SqliteParam::from_lua(&param?)?);
-                        }
-                    }
+                    bind_query_params(&mut query, params)?;
 
-                    query.execute(rusqlite::params_from_iter(query_params))
+                    query.raw_execute()
                         .map_err(LuaError::external)?;
 
                     Ok(connection.last_insert_rowid())
@@ -213,111 +563,260 @@ impl SQLiteAPI {
                     let mut query = connection.prepare_cached(&query.to_string_lossy())
                         .map_err(LuaError::external)?;
 
-                    // raw_len for better performance.
-                    let params_len = params.as_ref()
-                        .map(LuaTable::raw_len)
-                        .unwrap_or_default();
+                    bind_query_params(&mut query, params)?;
 
-                    let mut query_params = Vec::with_capacity(params_len);
+                    let rows = query.raw_query().mapped(read_row);
 
-                    if let Some(params) = params {
-                        for param in params.sequence_values::<LuaValue>() {
-                            query_params.push(SqliteParam::from_lua(&param?)?);
-                        }
+                    rows_to_lua(lua, rows)
+                })?
+            },
+
+            sqlite_query_row: {
+                let connection_handles = connection_handles.clone();
+
+                lua.create_function(move |lua, (handle, query, params): (i32, LuaString, Option<LuaTable>)| -> Result<LuaValue, LuaError> {
+                    let mut handles = connection_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some(connection) = handles.get_mut(&handle) else {
+                        return Err(LuaError::external("invalid database connection handle"));
+                    };
+
+                    let mut query = connection.prepare_cached(&query.to_string_lossy())
+                        .map_err(LuaError::external)?;
+
+                    bind_query_params(&mut query, params)?;
+
+                    let mut rows = query.raw_query();
+
+                    let row = match rows.next() {
+                        Ok(Some(row)) => read_row(row).map_err(LuaError::external)?,
+
+                        Ok(None) => return Ok(LuaValue::Nil),
+                        Err(err) => return Err(LuaError::external(err))
+                    };
+
+                    if row.is_empty() {
+                        return Ok(LuaValue::Nil);
                     }
 
-                    let rows = query.query_map(rusqlite::params_from_iter(query_params), |row| {
-                        let mut columns = Vec::new();
+                    let result = lua.create_table_with_capacity(row.len(), 0)?;
 
-                        let mut i = 0;
+                    for column in row {
+                        result.raw_push(column.to_lua(lua)?)?;
+                    }
 
-                        while let Ok(column) = row.get::<_, SqliteParam>(i) {
-                            columns.push(column);
+                    Ok(LuaValue::Table(result))
+                })?
+            },
 
-                            i += 1;
-                        }
+            sqlite_transaction: {
+                let connection_handles = connection_handles.clone();
+
+                lua.create_function(move |_, (handle, callback): (i32, LuaFunction)| {
+                    // Connection can't be locked while the callback is running
+                    // because it calls other API functions, so savepoints are
+                    // used instead of rusqlite transactions. They also allow
+                    // nested transactions.
+                    let batch = |command: &str| -> Result<(), LuaError> {
+                        let mut handles = connection_handles.lock()
+                            .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                        let Some(connection) = handles.get_mut(&handle) else {
+                            return Err(LuaError::external("invalid database connection handle"));
+                        };
 
-                        Ok(columns)
-                    }).map_err(LuaError::external)?;
+                        connection.execute_batch(command)
+                            .map_err(LuaError::external)
+                    };
+
+                    batch("SAVEPOINT lua_transaction")?;
 
-                    let result = lua.create_table()?;
+                    match callback.call::<LuaValue>(()) {
+                        Ok(LuaValue::Boolean(false)) => {
+                            batch("ROLLBACK TO lua_transaction; RELEASE lua_transaction")?;
 
-                    for row in rows {
-                        let row = row.map_err(LuaError::external)?;
+                            Ok(false)
+                        }
 
-                        let result_row = lua.create_table_with_capacity(row.len(), 0)?;
+                        Ok(_) => {
+                            batch("RELEASE lua_transaction")?;
 
-                        for column in row {
-                            result_row.raw_push(column.to_lua(lua)?)?;
+                            Ok(true)
                         }
 
-                        result.raw_push(result_row)?;
-                    }
+                        Err(err) => {
+                            batch("ROLLBACK TO lua_transaction; RELEASE lua_transaction")?;
 
-                    Ok(result)
+                            Err(err)
+                        }
+                    }
                 })?
             },
 
-            sqlite_query_row: {
+            sqlite_prepare: {
                 let connection_handles = connection_handles.clone();
 
-                lua.create_function(move |lua, (handle, query, params): (i32, LuaString, Option<LuaTable>)| -> Result<LuaValue, LuaError> {
+                lua.create_function(move |_, (connection_handle, query): (i32, LuaString)| {
                     let mut handles = connection_handles.lock()
                         .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
 
-                    let Some(connection) = handles.get_mut(&handle) else {
+                    if !handles.contains_key(&connection_handle) {
                         return Err(LuaError::external("invalid database connection handle"));
+                    }
+
+                    // Statement handles are unique across all connections.
+                    let mut handle = rand::random::<i32>();
+
+                    while handles.values().any(|connection| connection.statements.contains_key(&handle)) {
+                        handle = rand::random::<i32>();
+                    }
+
+                    if let Some(connection) = handles.get_mut(&connection_handle) {
+                        connection.prepare(handle, &query.to_string_lossy())?;
+                    }
+
+                    Ok(handle)
+                })?
+            },
+
+            sqlite_stmt_execute: {
+                let connection_handles = connection_handles.clone();
+
+                lua.create_function(move |_, (handle, params): (i32, Option<LuaTable>)| {
+                    let mut handles = connection_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some((connection, statement)) = handles.values_mut().find_map(|connection| connection.statement(handle)) else {
+                        return Err(LuaError::external("invalid statement handle"));
                     };
 
-                    let mut query = connection.prepare_cached(&query.to_string_lossy())
+                    bind_query_params(statement, params)?;
+
+                    statement.raw_execute()
                         .map_err(LuaError::external)?;
 
-                    // raw_len for better performance.
-                    let params_len = params.as_ref()
-                        .map(LuaTable::raw_len)
-                        .unwrap_or_default();
+                    Ok(connection.last_insert_rowid())
+                })?
+            },
 
-                    let mut query_params = Vec::with_capacity(params_len);
+            sqlite_stmt_query: {
+                let connection_handles = connection_handles.clone();
 
-                    if let Some(params) = params {
-                        for param in params.sequence_values::<LuaValue>() {
-                            query_params.push(SqliteParam::from_lua(&param?)?);
-                        }
+                lua.create_function(move |lua, (handle, params): (i32, Option<LuaTable>)| -> Result<LuaTable, LuaError> {
+                    let mut handles = connection_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    let Some((_, statement)) = handles.values_mut().find_map(|connection| connection.statement(handle)) else {
+                        return Err(LuaError::external("invalid statement handle"));
+                    };
+
+                    bind_query_params(statement, params)?;
+
+                    let rows = statement.raw_query().mapped(read_row);
+
+                    rows_to_lua(lua, rows)
+                })?
+            },
+
+            sqlite_stmt_finalize: {
+                let connection_handles = connection_handles.clone();
+
+                lua.create_function(move |_, handle: i32| {
+                    let mut handles = connection_handles.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read handle: {err}")))?;
+
+                    // Dropped statement is finalized.
+                    if handles.values_mut().find_map(|connection| connection.statements.remove(&handle)).is_none() {
+                        return Err(LuaError::external("invalid statement handle"));
                     }
 
-                    let row = query.query_row(rusqlite::params_from_iter(query_params), |row| {
-                        let mut columns = Vec::new();
+                    Ok(())
+                })?
+            },
 
-                        let mut i = 0;
+            sqlite_backup: {
+                let connection_handles = connection_handles.clone();
 
-                        while let Ok(column) = row.get::<_, SqliteParam>(i) {
-                            columns.push(column);
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
+                    let connection_handles = connection_handles.clone();
 
-                            i += 1;
+                    lua.create_function(move |_, (handle, path, progress): (i32, LuaString, Option<LuaFunction>)| {
+                        let path = resolve_path(path.to_string_lossy())?;
+
+                        if !context.is_accessible(&path) {
+                            return Err(LuaError::external("path is inaccessible"));
                         }
 
-                        Ok(columns)
-                    });
+                        context.track_temp(&path);
 
-                    let row = match row {
-                        Ok(row) => row,
+                        if let Some(parent) = path.parent() {
+                            if !parent.is_dir() {
+                                std::fs::create_dir_all(parent)?;
+                            }
+                        }
 
-                        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(LuaValue::Nil),
-                        Err(err) => return Err(LuaError::external(err))
-                    };
+                        // Copy database to a temp file first so the
+                        // destination is replaced atomically.
+                        let mut temp_path = path.clone().into_os_string();
 
-                    if row.is_empty() {
-                        return Ok(LuaValue::Nil);
-                    }
+                        temp_path.push(".tmp");
 
-                    let result = lua.create_table_with_capacity(row.len(), 0)?;
+                        let temp_path = PathBuf::from(temp_path);
 
-                    for column in row {
-                        result.raw_push(column.to_lua(lua)?)?;
-                    }
+                        if temp_path.exists() {
+                            std::fs::remove_file(&temp_path)?;
+                        }
 
-                    Ok(LuaValue::Table(result))
-                })?
+                        let result = with_taken_connection(&connection_handles, handle, |connection| {
+                            let mut backup = Connection::open(&temp_path)
+                                .map_err(|err| LuaError::external(format!("failed to open backup database: {err}")))?;
+
+                            copy_database(connection, &mut backup, progress)
+                        });
+
+                        match result {
+                            Ok(()) => std::fs::rename(&temp_path, &path)?,
+
+                            Err(err) => {
+                                let _ = std::fs::remove_file(&temp_path);
+
+                                return Err(err);
+                            }
+                        }
+
+                        Ok(())
+                    })
+                })
+            },
+
+            sqlite_restore: {
+                let connection_handles = connection_handles.clone();
+
+                Box::new(move |lua: &Lua, context: &Context| {
+                    let context = context.to_owned();
+                    let connection_handles = connection_handles.clone();
+
+                    lua.create_function(move |_, (handle, path, progress): (i32, LuaString, Option<LuaFunction>)| {
+                        let path = resolve_path(path.to_string_lossy())?;
+
+                        if !context.is_accessible(&path) {
+                            return Err(LuaError::external("path is inaccessible"));
+                        }
+
+                        if !path.is_file() {
+                            return Err(LuaError::external("backup database doesn't exist"));
+                        }
+
+                        let backup = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                            .map_err(|err| LuaError::external(format!("failed to open backup database: {err}")))?;
+
+                        with_taken_connection(&connection_handles, handle, |connection| {
+                            connection.with_mut(|connection| copy_database(&backup, connection, progress))
+                        })
+                    })
+                })
             },
 
             sqlite_close: {
@@ -331,6 +830,9 @@ impl SQLiteAPI {
                         return Err(LuaError::external("invalid database connection handle"));
                     };
 
+                    // Finalize prepared statements of the connection.
+                    connection.statements.clear();
+
                     connection.execute("PRAGMA optimize", []).map_err(LuaError::external)?;
                     connection.cache_flush().map_err(LuaError::external)?;
 
@@ -351,13 +853,20 @@ impl SQLiteAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self, context: &Context) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 6)?;
+        let env = self.lua.create_table_with_capacity(0, 13)?;
 
         env.raw_set("open", (self.sqlite_open)(&self.lua, context)?)?;
         env.raw_set("execute", self.sqlite_execute.clone())?;
         env.raw_set("batch", self.sqlite_batch.clone())?;
         env.raw_set("query", self.sqlite_query.clone())?;
         env.raw_set("query_row", self.sqlite_query_row.clone())?;
+        env.raw_set("transaction", self.sqlite_transaction.clone())?;
+        env.raw_set("prepare", self.sqlite_prepare.clone())?;
+        env.raw_set("stmt_execute", self.sqlite_stmt_execute.clone())?;
+        env.raw_set("stmt_query", self.sqlite_stmt_query.clone())?;
+        env.raw_set("stmt_finalize", self.sqlite_stmt_finalize.clone())?;
+        env.raw_set("backup", (self.sqlite_backup)(&self.lua, context)?)?;
+        env.raw_set("restore", (self.sqlite_restore)(&self.lua, context)?)?;
         env.raw_set("close", self.sqlite_close.clone())?;
 
         Ok(env)
@@ -443,3 +952,328 @@ impl SQLiteAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_transactions() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-sqlite-transactions-test.db");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let lua = Lua::new();
+        let api = SQLiteAPI::new(lua.clone())?;
+
+        let context = Context::test(
+            std::env::temp_dir(),
+            std::env::temp_dir().join(".wineyard-runtime-sqlite-transactions-test.json")
+        )?;
+
+        let env = api.create_env(&context)?;
+
+        lua.globals().raw_set("sqlite", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        let values = lua.load(r#"
+            local handle = sqlite.open(path)
+
+            sqlite.batch(handle, "CREATE TABLE test (value INTEGER NOT NULL)")
+
+            local committed = sqlite.transaction(handle, function()
+                sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 1 })
+            end)
+
+            assert(committed)
+
+            local committed = sqlite.transaction(handle, function()
+                sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 2 })
+
+                return false
+            end)
+
+            assert(not committed)
+
+            local result, err = pcall(sqlite.transaction, handle, function()
+                sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 3 })
+
+                error("test error")
+            end)
+
+            assert(not result and string.find(tostring(err), "test error"))
+
+            sqlite.transaction(handle, function()
+                sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 4 })
+
+                sqlite.transaction(handle, function()
+                    sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 5 })
+
+                    return false
+                end)
+            end)
+
+            local values = {}
+
+            for _, row in ipairs(sqlite.query(handle, "SELECT value FROM test ORDER BY value")) do
+                table.insert(values, row[1])
+            end
+
+            sqlite.close(handle)
+
+            return values
+        "#).eval::<Vec<i32>>()?;
+
+        assert_eq!(values, [1, 4]);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_statements() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-sqlite-statements-test.db");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let lua = Lua::new();
+        let api = SQLiteAPI::new(lua.clone())?;
+
+        let context = Context::test(
+            std::env::temp_dir(),
+            std::env::temp_dir().join(".wineyard-runtime-sqlite-statements-test.json")
+        )?;
+
+        let env = api.create_env(&context)?;
+
+        lua.globals().raw_set("sqlite", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        let sum = lua.load(r#"
+            local handle = sqlite.open(path)
+
+            sqlite.batch(handle, "CREATE TABLE test (value INTEGER NOT NULL)")
+
+            local insert = sqlite.prepare(handle, "INSERT INTO test (value) VALUES (?1)")
+            local select = sqlite.prepare(handle, "SELECT SUM(value) FROM test WHERE value > ?1")
+
+            for i = 1, 100 do
+                assert(sqlite.stmt_execute(insert, { i }) == i)
+            end
+
+            sqlite.stmt_finalize(insert)
+
+            assert(not pcall(sqlite.stmt_execute, insert, { 0 }))
+            assert(not pcall(sqlite.stmt_finalize, insert))
+
+            assert(not pcall(sqlite.prepare, handle, "SELECT * FROM missing"))
+
+            local sum = sqlite.stmt_query(select, { 50 })[1][1]
+
+            assert(sqlite.stmt_query(select, { 90 })[1][1] == 955)
+
+            sqlite.close(handle)
+
+            assert(not pcall(sqlite.stmt_query, select, { 50 }))
+            assert(not pcall(sqlite.stmt_finalize, select))
+            assert(not pcall(sqlite.prepare, handle, "SELECT 1"))
+
+            return sum
+        "#).eval::<i32>()?;
+
+        assert_eq!(sum, (51..=100).sum::<i32>());
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_named_params() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-sqlite-named-params-test.db");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let lua = Lua::new();
+        let api = SQLiteAPI::new(lua.clone())?;
+
+        let context = Context::test(
+            std::env::temp_dir(),
+            std::env::temp_dir().join(".wineyard-runtime-sqlite-named-params-test.json")
+        )?;
+
+        let env = api.create_env(&context)?;
+
+        lua.globals().raw_set("sqlite", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        let row = lua.load(r#"
+            local handle = sqlite.open(path)
+
+            sqlite.batch(handle, "CREATE TABLE test (name TEXT NOT NULL, value INTEGER NOT NULL)")
+
+            sqlite.execute(handle, "INSERT INTO test (name, value) VALUES (?1, ?2)", { "a", 1 })
+            sqlite.execute(handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { name = "b", value = 2 })
+            sqlite.execute(handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { [":name"] = "c", [":value"] = 3 })
+
+            assert(not pcall(sqlite.execute, handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { "d", value = 4 }))
+            assert(not pcall(sqlite.execute, handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { name = "d", unknown = 4 }))
+            assert(not pcall(sqlite.execute, handle, "INSERT INTO test (name, value) VALUES (?1, ?2)", { "d" }))
+            assert(not pcall(sqlite.execute, handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { name = "d" }))
+            assert(not pcall(sqlite.execute, handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { name = "d", [":name"] = "e", value = 4 }))
+
+            local ok, err = pcall(sqlite.execute, handle, "INSERT INTO test (name, value) VALUES (:name, :value)", { value = 4 })
+
+            assert(not ok and tostring(err):find("missing query param: :name", 1, true))
+
+            local row = sqlite.query_row(handle, "SELECT COUNT(*), SUM(value) FROM test WHERE name != :name", { name = "a" })
+
+            sqlite.close(handle)
+
+            return row
+        "#).eval::<Vec<i32>>()?;
+
+        assert_eq!(row, [2, 5]);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_backup() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-sqlite-backup-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let lua = Lua::new();
+        let api = SQLiteAPI::new(lua.clone())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        lua.globals().raw_set("sqlite", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        // Keep the database locked so the restore can't write to it.
+        let locked = Connection::open(path.join("locked.db"))
+            .map_err(LuaError::external)?;
+
+        locked.execute_batch("CREATE TABLE test (value INTEGER NOT NULL); BEGIN EXCLUSIVE;")
+            .map_err(LuaError::external)?;
+
+        let values = lua.load(r#"
+            local handle = sqlite.open(path .. "/database.db")
+
+            sqlite.batch(handle, "CREATE TABLE test (value INTEGER NOT NULL)")
+            sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 1 })
+
+            local copied, total = 0, 0
+
+            sqlite.backup(handle, path .. "/backup/database.db", function(curr, pages)
+                copied, total = curr, pages
+            end)
+
+            assert(total > 0 and copied == total)
+
+            sqlite.execute(handle, "INSERT INTO test (value) VALUES (?1)", { 2 })
+
+            assert(not pcall(sqlite.backup, handle, "/database.db"))
+            assert(not pcall(sqlite.restore, handle, path .. "/missing.db"))
+
+            local locked = sqlite.open(path .. "/locked.db", { busy_timeout = 0 })
+
+            local ok, err = pcall(sqlite.restore, locked, path .. "/backup/database.db")
+
+            assert(not ok and tostring(err):find("database is locked", 1, true))
+
+            local count = sqlite.prepare(handle, "SELECT COUNT(*) FROM test")
+
+            assert(sqlite.stmt_query(count)[1][1] == 2)
+
+            sqlite.restore(handle, path .. "/backup/database.db")
+
+            -- Prepared statements are kept after restoring.
+            assert(sqlite.stmt_query(count)[1][1] == 1)
+
+            local values = {}
+
+            for _, row in ipairs(sqlite.query(handle, "SELECT value FROM test ORDER BY value")) do
+                table.insert(values, row[1])
+            end
+
+            sqlite.close(handle)
+
+            return values
+        "#).eval::<Vec<i32>>()?;
+
+        assert_eq!(values, [1]);
+        assert!(!path.join("backup/database.db.tmp").exists());
+
+        drop(locked);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_open_options() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-sqlite-open-options-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+
+        let lua = Lua::new();
+        let api = SQLiteAPI::new(lua.clone())?;
+
+        let env = api.create_env(&Context::test(&path, path.join("local_validator.json"))?)?;
+
+        lua.globals().raw_set("sqlite", env)?;
+        lua.globals().raw_set("path", path.to_string_lossy().to_string())?;
+
+        lua.load(r#"
+            local handle = sqlite.open(path .. "/database.db", {
+                journal_mode = "WAL",
+                synchronous = "normal",
+                busy_timeout = 1000,
+                foreign_keys = true
+            })
+
+            assert(sqlite.query_row(handle, "PRAGMA journal_mode")[1] == "wal")
+            assert(sqlite.query_row(handle, "PRAGMA synchronous")[1] == 1)
+            assert(sqlite.query_row(handle, "PRAGMA busy_timeout")[1] == 1000)
+            assert(sqlite.query_row(handle, "PRAGMA foreign_keys")[1] == 1)
+
+            sqlite.batch(handle, "CREATE TABLE test (value INTEGER NOT NULL)")
+            sqlite.close(handle)
+
+            assert(not pcall(sqlite.open, path .. "/database.db", { journal_mode = "invalid" }))
+            assert(not pcall(sqlite.open, path .. "/missing/database.db", { read_only = true }))
+
+            local handle = sqlite.open(path .. "/database.db", { read_only = true })
+
+            assert(sqlite.query_row(handle, "SELECT COUNT(*) FROM test")[1] == 0)
+            assert(not pcall(sqlite.execute, handle, "INSERT INTO test (value) VALUES (1)"))
+
+            sqlite.close(handle)
+        "#).exec()?;
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
@@ -1,6 +1,17 @@
+use std::str::FromStr;
+use std::io::{Read, Write};
+
+use wineyard_core::compression::{
+    Compressor,
+    Decompressor,
+    CompressionAlgorithm,
+    CompressionLevel
+};
+
 use mlua::prelude::*;
 
-use encoding_rs::Encoding;
+use encoding_rs::{Encoding, UTF_8};
+use chardetng::EncodingDetector;
 
 use super::*;
 
@@ -26,14 +37,43 @@ fn fix_lua_type(lua: &Lua, value: LuaValue) -> Result<LuaValue, LuaError> {
     }
 }
 
+/// Try to detect charset of the given bytes.
+///
+/// Return `None` if detection is inconclusive.
+fn detect_charset(value: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(value) {
+        return Some(encoding);
+    }
+
+    if std::str::from_utf8(value).is_ok() {
+        return Some(UTF_8);
+    }
+
+    let mut detector = EncodingDetector::new();
+
+    detector.feed(value, true);
+
+    let (encoding, confident) = detector.guess_assess(None, false);
+
+    confident.then_some(encoding)
+}
+
 #[allow(clippy::large_enum_variant)]
-enum StringEncoding {
+pub(super) enum StringEncoding {
     Base16,
     Base32(base32::Alphabet),
     Base64(base64::engine::GeneralPurpose),
+    Base58,
+    Bech32(Option<bech32::Hrp>),
     Json,
     Toml,
-    Yaml
+    Yaml,
+
+    #[cfg(feature = "runtime-msgpack")]
+    MessagePack,
+
+    #[cfg(feature = "runtime-cbor")]
+    Cbor
 }
 
 impl StringEncoding {
@@ -99,10 +139,29 @@ impl StringEncoding {
                 Some(Self::Base64(encoding))
             }
 
+            b"base58" => Some(Self::Base58),
+
+            // Bech32 with human-readable part, e.g. "bech32/bc".
+            // Decoding with just "bech32" accepts any prefix.
+            b"bech32" => Some(Self::Bech32(None)),
+
+            name if name.starts_with(b"bech32/") => {
+                let hrp = std::str::from_utf8(&name[7..]).ok()?;
+
+                bech32::Hrp::parse(hrp).ok()
+                    .map(|hrp| Self::Bech32(Some(hrp)))
+            }
+
             b"json" => Some(Self::Json),
             b"toml" => Some(Self::Toml),
             b"yaml" => Some(Self::Yaml),
 
+            #[cfg(feature = "runtime-msgpack")]
+            b"msgpack" | b"messagepack" => Some(Self::MessagePack),
+
+            #[cfg(feature = "runtime-cbor")]
+            b"cbor" => Some(Self::Cbor),
+
             _ => None
         }
     }
@@ -129,6 +188,25 @@ impl StringEncoding {
                 lua.create_string(engine.encode(value))
             }
 
+            Self::Base58 => {
+                let value = lua_value_to_bytes(value)?;
+
+                lua.create_string(bs58::encode(value).into_string())
+            }
+
+            Self::Bech32(hrp) => {
+                let Some(hrp) = hrp else {
+                    return Err(LuaError::external("bech32 human-readable part expected"));
+                };
+
+                let value = lua_value_to_bytes(value)?;
+
+                let value = bech32::encode::<bech32::Bech32>(*hrp, &value)
+                    .map_err(LuaError::external)?;
+
+                lua.create_string(value)
+            }
+
             Self::Json => {
                 let value = serde_json::to_vec_pretty(&value)
                     .map_err(LuaError::external)?;
@@ -149,6 +227,24 @@ impl StringEncoding {
 
                 lua.create_string(value)
             }
+
+            #[cfg(feature = "runtime-msgpack")]
+            Self::MessagePack => {
+                let value = rmp_serde::to_vec_named(&value)
+                    .map_err(LuaError::external)?;
+
+                lua.create_string(value)
+            }
+
+            #[cfg(feature = "runtime-cbor")]
+            Self::Cbor => {
+                let mut buf = Vec::new();
+
+                ciborium::into_writer(&value, &mut buf)
+                    .map_err(LuaError::external)?;
+
+                lua.create_string(buf)
+            }
         }
     }
 
@@ -183,6 +279,32 @@ impl StringEncoding {
                     .map(LuaValue::Table)
             }
 
+            Self::Base58 => {
+                let value = bs58::decode(string.as_bytes())
+                    .into_vec()
+                    .map_err(LuaError::external)?;
+
+                bytes_to_lua_table(lua, value)
+                    .map(LuaValue::Table)
+            }
+
+            Self::Bech32(expected_hrp) => {
+                let string = string.to_string_lossy()
+                    .to_string();
+
+                let (hrp, value) = bech32::decode(&string)
+                    .map_err(LuaError::external)?;
+
+                if let Some(expected_hrp) = expected_hrp
+                    && expected_hrp != &hrp
+                {
+                    return Err(LuaError::external("invalid bech32 human-readable part"));
+                }
+
+                bytes_to_lua_table(lua, value)
+                    .map(LuaValue::Table)
+            }
+
             Self::Json => {
                 let value = serde_json::from_slice::<serde_json::Value>(&string.as_bytes())
                     .map_err(LuaError::external)?;
@@ -206,6 +328,22 @@ impl StringEncoding {
 
                 Ok(fix_lua_type(lua, lua.to_value(&value)?)?)
             }
+
+            #[cfg(feature = "runtime-msgpack")]
+            Self::MessagePack => {
+                let value = rmp_serde::from_slice::<rmpv::Value>(&string.as_bytes())
+                    .map_err(LuaError::external)?;
+
+                Ok(fix_lua_type(lua, lua.to_value(&value)?)?)
+            }
+
+            #[cfg(feature = "runtime-cbor")]
+            Self::Cbor => {
+                let value = ciborium::from_reader::<ciborium::Value, _>(string.as_bytes().as_ref())
+                    .map_err(LuaError::external)?;
+
+                Ok(fix_lua_type(lua, lua.to_value(&value)?)?)
+            }
         }
     }
 }
@@ -215,15 +353,21 @@ pub struct StringAPI {
 
     str_to_bytes: LuaFunction,
     str_from_bytes: LuaFunction,
+    str_integer_from_bytes: LuaFunction,
+    str_number_from_bytes: LuaFunction,
+    str_detect_charset: LuaFunction,
     str_encode: LuaFunction,
-    str_decode: LuaFunction
+    str_decode: LuaFunction,
+    str_compress: LuaFunction,
+    str_decompress: LuaFunction
 }
 
 impl StringAPI {
     pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
         Ok(Self {
-            str_to_bytes: lua.create_function(|_, (value, charset): (LuaValue, Option<LuaString>)| {
-                let value = lua_value_to_bytes(value)?;
+            // The third argument is the endianness of numbers.
+            str_to_bytes: lua.create_function(|_, (value, charset, endianness): (LuaValue, Option<LuaString>, Option<LuaString>)| {
+                let value = lua_value_to_bytes_with_endianness(value, is_little_endian(endianness)?)?;
 
                 let Some(charset) = charset else {
                     return Ok(value);
@@ -239,20 +383,83 @@ impl StringAPI {
                 Ok(charset.encode(&value).0.to_vec())
             })?,
 
-            str_from_bytes: lua.create_function(|lua, (value, charset): (Vec<u8>, Option<LuaString>)| {
+            // Without charset the bytes are passed to the lua string as is,
+            // so it can contain invalid UTF-8. The third argument changes
+            // this: "strict" mode errors on invalid UTF-8 (or invalid
+            // sequences of the given charset), "lossy" mode replaces them
+            // with U+FFFD. Charsets are always decoded lossily otherwise.
+            str_from_bytes: lua.create_function(|lua, (value, charset, mode): (Vec<u8>, Option<LuaString>, Option<LuaString>)| {
+                let is_strict = match mode.as_ref().map(LuaString::as_bytes).as_deref() {
+                    None | Some(b"passthrough") => None,
+                    Some(b"strict") => Some(true),
+                    Some(b"lossy") => Some(false),
+
+                    Some(_) => return Err(LuaError::external("invalid utf-8 mode"))
+                };
+
                 let Some(charset) = charset else {
-                    return lua.create_string(value);
+                    return match is_strict {
+                        None => lua.create_string(value),
+
+                        Some(true) => {
+                            let value = String::from_utf8(value)
+                                .map_err(|err| LuaError::external(format!("invalid utf-8 string: {err}")))?;
+
+                            lua.create_string(value)
+                        }
+
+                        Some(false) => lua.create_string(String::from_utf8_lossy(&value).as_bytes())
+                    };
                 };
 
-                let Some(charset) = Encoding::for_label(&charset.as_bytes()) else {
-                    return Err(LuaError::external("invalid charset"));
+                let charset = if charset == "auto" {
+                    detect_charset(&value)
+                        .ok_or_else(|| LuaError::external("failed to detect charset"))?
+                } else {
+                    Encoding::for_label(&charset.as_bytes())
+                        .ok_or_else(|| LuaError::external("invalid charset"))?
                 };
 
+                if is_strict == Some(true) {
+                    let value = charset.decode_without_bom_handling_and_without_replacement(&value)
+                        .ok_or_else(|| LuaError::external(format!("invalid {} string", charset.name())))?;
+
+                    return lua.create_string(value.as_bytes());
+                }
+
                 let value = charset.decode(&value).0;
 
                 lua.create_string(value.as_bytes())
             })?,
 
+            str_integer_from_bytes: lua.create_function(|_, (value, endianness): (Vec<u8>, Option<LuaString>)| {
+                let value = <[u8; size_of::<LuaInteger>()]>::try_from(value)
+                    .map_err(|_| LuaError::external(format!("{} bytes expected", size_of::<LuaInteger>())))?;
+
+                if is_little_endian(endianness)? {
+                    Ok(LuaInteger::from_le_bytes(value))
+                } else {
+                    Ok(LuaInteger::from_be_bytes(value))
+                }
+            })?,
+
+            str_number_from_bytes: lua.create_function(|_, (value, endianness): (Vec<u8>, Option<LuaString>)| {
+                let value = <[u8; 8]>::try_from(value)
+                    .map_err(|_| LuaError::external("8 bytes expected"))?;
+
+                if is_little_endian(endianness)? {
+                    Ok(f64::from_le_bytes(value))
+                } else {
+                    Ok(f64::from_be_bytes(value))
+                }
+            })?,
+
+            str_detect_charset: lua.create_function(|_, value: LuaValue| {
+                let value = lua_value_to_bytes(value)?;
+
+                Ok(detect_charset(&value).map(Encoding::name))
+            })?,
+
             str_encode: lua.create_function(|lua, (value, encoding): (LuaValue, LuaString)| {
                 let Some(encoding) = StringEncoding::from_name(encoding.as_bytes()) else {
                     return Err(LuaError::external("invalid encoding"));
@@ -269,6 +476,58 @@ impl StringAPI {
                 encoding.decode(lua, value)
             })?,
 
+            str_compress: lua.create_function(|lua, (value, algorithm, level): (LuaValue, LuaString, LuaValue)| {
+                let algorithm = CompressionAlgorithm::from_str(&algorithm.to_string_lossy())
+                    .map_err(LuaError::external)?;
+
+                let level = match level {
+                    LuaValue::Nil => CompressionLevel::Default,
+                    LuaValue::Integer(level) => CompressionLevel::from(level),
+
+                    LuaValue::String(level) => CompressionLevel::from_str(&level.to_string_lossy())
+                        .map_err(LuaError::external)?,
+
+                    _ => return Err(LuaError::external("invalid compression level"))
+                };
+
+                let mut compressor = Compressor::new(algorithm, level)
+                    .map_err(LuaError::external)?;
+
+                compressor.write_all(&lua_value_to_bytes(value)?)?;
+                compressor.flush()?;
+                compressor.try_finish()?;
+
+                let mut result = Vec::new();
+
+                compressor.read_to_end(&mut result)?;
+
+                bytes_to_lua_table(lua, result)
+            })?,
+
+            str_decompress: lua.create_function(|lua, (value, algorithm): (LuaValue, LuaString)| {
+                let value = lua_value_to_bytes(value)?;
+                let algorithm = algorithm.to_string_lossy();
+
+                // Predict the algorithm from the magic bytes.
+                let decompressor = if algorithm == "auto" {
+                    Decompressor::auto(&value)
+                } else {
+                    CompressionAlgorithm::from_str(&algorithm)
+                        .and_then(Decompressor::new)
+                };
+
+                let mut decompressor = decompressor.map_err(LuaError::external)?;
+
+                decompressor.write_all(&value)?;
+                decompressor.flush()?;
+
+                let mut result = Vec::new();
+
+                decompressor.read_to_end(&mut result)?;
+
+                bytes_to_lua_table(lua, result)
+            })?,
+
             lua
         })
     }
@@ -280,12 +539,17 @@ impl StringAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 4)?;
+        let env = self.lua.create_table_with_capacity(0, 9)?;
 
         env.raw_set("to_bytes", self.str_to_bytes.clone())?;
         env.raw_set("from_bytes", self.str_from_bytes.clone())?;
+        env.raw_set("integer_from_bytes", self.str_integer_from_bytes.clone())?;
+        env.raw_set("number_from_bytes", self.str_number_from_bytes.clone())?;
+        env.raw_set("detect_charset", self.str_detect_charset.clone())?;
         env.raw_set("encode", self.str_encode.clone())?;
         env.raw_set("decode", self.str_decode.clone())?;
+        env.raw_set("compress", self.str_compress.clone())?;
+        env.raw_set("decompress", self.str_decompress.clone())?;
 
         Ok(env)
     }
@@ -331,7 +595,9 @@ impl StringAPI {
 //             ("base64/pad",           "SGVsbG8sIFdvcmxkIQ=="),
 //             // ("base64/nopad",         "SGVsbG8sIFdvcmxkIQ"),
 //             ("base64/urlsafe-pad",   "SGVsbG8sIFdvcmxkIQ=="),
-//             // ("base64/urlsafe-nopad", "SGVsbG8sIFdvcmxkIQ")
+//             // ("base64/urlsafe-nopad", "SGVsbG8sIFdvcmxkIQ"),
+//             ("base58",               "72k1xXWG59fYdzSNoA"),
+//             ("bech32/test",          "test1fpjkcmr09ss9wmmjd3jzzw2fmd3")
 //         ];
 
 //         for (name, value) in encodings {
@@ -374,3 +640,153 @@ impl StringAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn str_detect_charset() -> Result<(), PackagesEngineError> {
+        let api = StringAPI::new(Lua::new())?;
+
+        let text = "吾輩は猫である。名前はまだ無い。どこで生れたかとんと見当がつかぬ。何でも薄暗いじめじめした所でニャーニャー泣いていた事だけは記憶している。";
+
+        let shift_jis = encoding_rs::SHIFT_JIS.encode(text).0.to_vec();
+        let gbk = encoding_rs::GBK.encode("我们在这里讨论的是一个非常重要的问题，希望大家能够认真地思考一下这个问题的答案。").0.to_vec();
+
+        assert_eq!(api.str_detect_charset.call::<Option<String>>(text)?.as_deref(), Some("UTF-8"));
+        assert_eq!(api.str_detect_charset.call::<Option<String>>(shift_jis.clone())?.as_deref(), Some("Shift_JIS"));
+        assert_eq!(api.str_detect_charset.call::<Option<String>>(gbk)?.as_deref(), Some("GBK"));
+        assert_eq!(api.str_detect_charset.call::<Option<String>>(vec![0xff, 0xfe, 0x41, 0x00])?.as_deref(), Some("UTF-16LE"));
+
+        assert_eq!(api.str_from_bytes.call::<String>((shift_jis, "auto"))?, text);
+
+        Ok(())
+    }
+
+    #[test]
+    fn str_from_bytes_utf8_mode() -> Result<(), PackagesEngineError> {
+        let api = StringAPI::new(Lua::new())?;
+
+        let invalid = vec![b'a', 0xff, b'b'];
+
+        // Bytes are passed as is by default.
+        assert_eq!(api.str_from_bytes.call::<LuaString>(invalid.clone())?.as_bytes().as_ref(), invalid);
+        assert_eq!(api.str_from_bytes.call::<LuaString>((invalid.clone(), LuaNil, "passthrough"))?.as_bytes().as_ref(), invalid);
+
+        assert_eq!(api.str_from_bytes.call::<String>((invalid.clone(), LuaNil, "lossy"))?, "a\u{FFFD}b");
+        assert!(api.str_from_bytes.call::<LuaString>((invalid.clone(), LuaNil, "strict")).is_err());
+        assert_eq!(api.str_from_bytes.call::<String>(("абоба".as_bytes().to_vec(), LuaNil, "strict"))?, "абоба");
+
+        let cp1251 = vec![224, 225, 238, 225, 224];
+
+        assert_eq!(api.str_from_bytes.call::<String>((cp1251, "cp1251", "strict"))?, "абоба");
+        assert!(api.str_from_bytes.call::<LuaString>((invalid.clone(), "utf-8", "strict")).is_err());
+        assert!(api.str_from_bytes.call::<LuaString>((invalid, LuaNil, "invalid")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn str_numbers_endianness() -> Result<(), PackagesEngineError> {
+        let api = StringAPI::new(Lua::new())?;
+
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>(258)?, [0, 0, 1, 2]);
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>((258, LuaNil, "be"))?, [0, 0, 1, 2]);
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>((258, LuaNil, "le"))?, [2, 1, 0, 0]);
+
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>(0.5)?, [63, 224, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>((0.5, LuaNil, "big"))?, [63, 224, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>((0.5, LuaNil, "little"))?, [0, 0, 0, 0, 0, 0, 224, 63]);
+
+        assert!(api.str_to_bytes.call::<Vec<u8>>((258, LuaNil, "middle")).is_err());
+
+        // Second argument is still the charset for numbers.
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>((258, "cp1251"))?, [0, 0, 1, 2]);
+        assert_eq!(api.str_to_bytes.call::<Vec<u8>>((258, "cp1251", "le"))?, [2, 1, 0, 0]);
+        assert!(api.str_to_bytes.call::<Vec<u8>>((258, "le")).is_err());
+
+        for endianness in ["be", "le"] {
+            let bytes = api.str_to_bytes.call::<Vec<u8>>((-258, LuaNil, endianness))?;
+
+            assert_eq!(api.str_integer_from_bytes.call::<i32>((bytes, endianness))?, -258);
+
+            let bytes = api.str_to_bytes.call::<Vec<u8>>((0.5, LuaNil, endianness))?;
+
+            assert_eq!(api.str_number_from_bytes.call::<f64>((bytes, endianness))?, 0.5);
+        }
+
+        assert_eq!(api.str_integer_from_bytes.call::<i32>(vec![0, 0, 1, 2])?, 258);
+        assert!(api.str_integer_from_bytes.call::<i32>(vec![1, 2]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn str_compression() -> Result<(), PackagesEngineError> {
+        let api = StringAPI::new(Lua::new())?;
+
+        let value = "Hello, World!".repeat(100);
+
+        for algorithm in ["gzip", "zstd"] {
+            for level in [LuaValue::Nil, LuaValue::Integer(1), LuaValue::String(api.lua().create_string("best")?)] {
+                let compressed = api.str_compress.call::<Vec<u8>>((value.clone(), algorithm, level))?;
+
+                assert!(compressed.len() < value.len());
+
+                let decompressed = api.str_decompress.call::<Vec<u8>>((compressed.clone(), algorithm))?;
+
+                assert_eq!(decompressed, value.as_bytes());
+
+                let decompressed = api.str_decompress.call::<Vec<u8>>((compressed, "auto"))?;
+
+                assert_eq!(decompressed, value.as_bytes());
+            }
+        }
+
+        assert!(api.str_decompress.call::<Vec<u8>>((value.clone(), "auto")).is_err());
+
+        assert!(api.str_compress.call::<Vec<u8>>((value.clone(), "unknown")).is_err());
+        assert!(api.str_decompress.call::<Vec<u8>>((value, "unknown")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(feature = "runtime-msgpack", feature = "runtime-cbor"))]
+    fn str_binary_encodings() -> Result<(), PackagesEngineError> {
+        let lua = Lua::new();
+        let api = StringAPI::new(lua.clone())?;
+
+        let table = lua.create_table()?;
+
+        table.set("test_string", "str")?;
+        table.set("test_integer", 17)?;
+        table.set("test_double", 0.5)?;
+        table.set("test_bool", true)?;
+        table.set("test_array", vec![1, 2, 3])?;
+
+        let encodings = [
+            #[cfg(feature = "runtime-msgpack")]
+            "msgpack",
+
+            #[cfg(feature = "runtime-cbor")]
+            "cbor"
+        ];
+
+        for name in encodings {
+            let encoded = api.str_encode.call::<LuaString>((table.clone(), name))?;
+            let decoded = api.str_decode.call::<LuaTable>((encoded, name))?;
+
+            assert_eq!(decoded.get::<LuaString>("test_string")?, "str");
+            assert_eq!(decoded.get::<i32>("test_integer")?, 17);
+            assert_eq!(decoded.get::<f64>("test_double")?, 0.5);
+            assert_eq!(decoded.get::<LuaValue>("test_bool")?, LuaValue::Boolean(true));
+            assert_eq!(decoded.get::<Vec<i32>>("test_array")?, [1, 2, 3]);
+
+            assert!(api.str_decode.call::<LuaTable>(("invalid", name)).is_err());
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,25 @@
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, Instant};
+
+use wineyard_core::rw_sync::RwSync;
 
 use mlua::prelude::*;
 
 use super::*;
 
-const MUTEX_LOCK_TIMEOUT: Duration = Duration::from_millis(100);
-
 // Workaround for lifetimes fuckery.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 enum ChannelMessage {
     Table(Vec<(Self, Self)>),
     String(String),
+
+    /// Lua string which is not a valid UTF-8 string.
+    Bytes(Vec<u8>),
+
     Double(f64),
-    Integer(i32),
+    Integer(i64),
     Boolean(bool),
     Nil
 }
@@ -25,8 +30,17 @@ impl ChannelMessage {
             Self::String(value) => lua.create_string(value)
                 .map(LuaValue::String),
 
+            Self::Bytes(value) => lua.create_string(value)
+                .map(LuaValue::String),
+
             Self::Double(value)  => Ok(LuaValue::Number(*value)),
-            Self::Integer(value) => Ok(LuaValue::Integer(*value)),
+
+            // Lua integers could be narrower than i64.
+            Self::Integer(value) => match LuaInteger::try_from(*value) {
+                Ok(value) => Ok(LuaValue::Integer(value)),
+                Err(_) => Ok(LuaValue::Number(*value as f64))
+            },
+
             Self::Boolean(value) => Ok(LuaValue::Boolean(*value)),
             Self::Nil            => Ok(LuaNil),
 
@@ -47,9 +61,17 @@ impl ChannelMessage {
 
     pub fn from_lua(value: &LuaValue) -> Result<Self, LuaError> {
         match value {
-            LuaValue::String(value)  => Ok(Self::String(value.to_string_lossy().to_string())),
+            LuaValue::String(value) => {
+                let value = value.as_bytes().to_vec();
+
+                match String::from_utf8(value) {
+                    Ok(value) => Ok(Self::String(value)),
+                    Err(err) => Ok(Self::Bytes(err.into_bytes()))
+                }
+            }
+
             LuaValue::Number(value)  => Ok(Self::Double(*value)),
-            LuaValue::Integer(value) => Ok(Self::Integer(*value)),
+            LuaValue::Integer(value) => Ok(Self::Integer(i64::from(*value))),
             LuaValue::Boolean(value) => Ok(Self::Boolean(*value)),
             LuaValue::Nil            => Ok(Self::Nil),
 
@@ -73,34 +95,165 @@ impl ChannelMessage {
     }
 }
 
+/// Messages delivery mode of the channel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ChannelMode {
+    /// Send each message to all the other consumers.
+    #[default]
+    Broadcast,
+
+    /// Send each message to only one of the other consumers in round-robin.
+    Queue
+}
+
+impl ChannelMode {
+    pub fn from_name(name: impl AsRef<[u8]>) -> Option<Self> {
+        match name.as_ref() {
+            b"broadcast" => Some(Self::Broadcast),
+            b"queue"     => Some(Self::Queue),
+
+            _ => None
+        }
+    }
+}
+
+/// Consumers of the channel key.
+#[derive(Debug, Default)]
+struct ChannelConsumers {
+    mode: ChannelMode,
+
+    /// Handles in the order they were opened.
+    handles: Vec<i32>,
+
+    /// Index of the next handle which will receive a queued message.
+    next: usize
+}
+
+/// Named mutexes locked by handles.
+#[derive(Debug, Default, Clone)]
+struct MutexLocks(RwSync<HashMap<Hash, Option<i32>>>); // key => curr_lock_handle
+
+impl MutexLocks {
+    /// Lock the mutex by the handle, blocking the current thread until
+    /// it's unlocked by its current owner or the timeout is reached.
+    ///
+    /// `is_open` is checked on every mutex state change to stop waiting
+    /// if the handle was closed.
+    fn lock(
+        &self,
+        key: Hash,
+        handle: i32,
+        timeout: Option<Duration>,
+        is_open: impl Fn() -> bool
+    ) -> Result<bool, LuaError> {
+        let locks = self.0.write_when(
+            |locks| !is_open() || locks.get(&key).is_none_or(Option::is_none),
+            timeout
+        );
+
+        let Some(mut locks) = locks else {
+            return Ok(false);
+        };
+
+        if !is_open() {
+            return Err(LuaError::external("invalid mutex handle"));
+        }
+
+        locks.insert(key, Some(handle));
+
+        Ok(true)
+    }
+
+    /// Unlock the mutex, failing if it's locked by another handle.
+    fn unlock(&self, key: Hash, handle: i32) -> Result<(), LuaError> {
+        let mut locks = self.0.write();
+
+        if let Some(lock) = locks.get_mut(&key)
+            && let Some(lock_handle) = lock
+        {
+            if *lock_handle != handle {
+                return Err(LuaError::external("can't unlock mutex locked by another handle"));
+            }
+
+            *lock = None;
+        }
+
+        Ok(())
+    }
+
+    /// Unlock the mutex if it's locked by the handle.
+    fn release(&self, key: Hash, handle: i32) {
+        let mut locks = self.0.write();
+
+        if let Some(lock) = locks.get_mut(&key)
+            && let Some(lock_handle) = lock
+            && *lock_handle == handle
+        {
+            *lock = None;
+        }
+    }
+}
+
+/// Get the time to wait for a blocking operation with the timeout given
+/// in milliseconds, capped by the maximal wait time.
+fn wait_timeout(timeout: Option<u64>, max_wait: Option<Duration>) -> Option<Duration> {
+    match (timeout.map(Duration::from_millis), max_wait) {
+        (Some(timeout), Some(max_wait)) => Some(timeout.min(max_wait)),
+        (timeout, max_wait) => timeout.or(max_wait)
+    }
+}
+
 pub struct SyncAPI {
     lua: Lua,
 
     sync_channel_open: LuaFunction,
     sync_channel_send: LuaFunction,
     sync_channel_recv: LuaFunction,
+    sync_channel_recv_blocking: LuaFunction,
     sync_channel_close: LuaFunction,
 
     sync_mutex_open: LuaFunction,
     sync_mutex_lock: LuaFunction,
     sync_mutex_unlock: LuaFunction,
-    sync_mutex_close: LuaFunction
+    sync_mutex_close: LuaFunction,
+
+    sync_once: LuaFunction,
+    sync_counter: LuaFunction
 }
 
 impl SyncAPI {
-    pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
-        let sync_channels_consumers = Arc::new(Mutex::new(HashMap::new())); // key => handle
-        let sync_channels_data = Arc::new(Mutex::new(HashMap::new())); // handle => (key, data)
+    /// Create new sync API.
+    ///
+    /// `max_wait` limits the time of all the blocking operations since
+    /// the engine can't interrupt native waits.
+    pub fn new(lua: Lua, max_wait: Option<Duration>) -> Result<Self, PackagesEngineError> {
+        let sync_channels_consumers = Arc::new(Mutex::new(HashMap::<Hash, ChannelConsumers>::new())); // key => consumers
+        let sync_channels_data = Arc::new(Mutex::new(HashMap::new())); // handle => (key, capacity, data)
+        let sync_channels_updates = Arc::new(Condvar::new());
 
         let sync_mutex_consumers = Arc::new(Mutex::new(HashMap::<i32, Hash>::new())); // handle => key
-        let sync_mutex_locks = Arc::new(Mutex::new(HashMap::<Hash, Option<i32>>::new())); // key => curr_lock_handle
+        let sync_mutex_locks = MutexLocks::default();
+
+        let sync_once_keys = Arc::new(Mutex::new(HashSet::<Hash>::new()));
+        let sync_counters = Arc::new(Mutex::new(HashMap::<Hash, Arc<AtomicI64>>::new())); // key => value
 
         Ok(Self {
             sync_channel_open: {
                 let sync_channels_consumers = sync_channels_consumers.clone();
                 let sync_channels_data = sync_channels_data.clone();
 
-                lua.create_function(move |_, key: LuaString| {
+                lua.create_function(move |_, (key, capacity, mode): (LuaString, Option<usize>, Option<LuaString>)| {
+                    let mode = match mode {
+                        Some(mode) => {
+                            let mode = ChannelMode::from_name(mode.as_bytes())
+                                .ok_or_else(|| LuaError::external("invalid channel mode"))?;
+
+                            Some(mode)
+                        }
+
+                        None => None
+                    };
+
                     let mut listeners = sync_channels_data.lock()
                         .map_err(|err| LuaError::external(format!("failed to register channel listeners: {err}")))?;
 
@@ -114,13 +267,19 @@ impl SyncAPI {
                     let mut consumers = sync_channels_consumers.lock()
                         .map_err(|err| LuaError::external(format!("failed to register channel consumers: {err}")))?;
 
-                    consumers.entry(key).or_insert_with(HashSet::new);
+                    // Channel mode is chosen by its first consumer.
+                    let consumers = consumers.entry(key).or_insert_with(|| ChannelConsumers {
+                        mode: mode.unwrap_or_default(),
+                        ..ChannelConsumers::default()
+                    });
 
-                    if let Some(listeners) = consumers.get_mut(&key) {
-                        listeners.insert(handle);
+                    if mode.is_some_and(|mode| mode != consumers.mode) {
+                        return Err(LuaError::external("channel is already opened with another mode"));
                     }
 
-                    listeners.insert(handle, (key, VecDeque::new()));
+                    consumers.handles.push(handle);
+
+                    listeners.insert(handle, (key, capacity, VecDeque::new()));
 
                     Ok(handle)
                 })?
@@ -129,72 +288,189 @@ impl SyncAPI {
             sync_channel_send: {
                 let sync_channels_consumers = sync_channels_consumers.clone();
                 let sync_channels_data = sync_channels_data.clone();
+                let sync_channels_updates = sync_channels_updates.clone();
 
-                lua.create_function(move |_, (handle, message): (i32, LuaValue)| {
+                lua.create_function(move |_, (handle, message, timeout): (i32, LuaValue, Option<u64>)| {
                     let message = ChannelMessage::from_lua(&message)?;
 
+                    let deadline = wait_timeout(timeout, max_wait).map(|timeout| Instant::now() + timeout);
+
                     let mut listeners = sync_channels_data.lock()
                         .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?;
 
-                    let Some((key, _)) = listeners.get(&handle) else {
-                        return Err(LuaError::external("invalid channel handle"));
-                    };
+                    loop {
+                        let Some((key, _, _)) = listeners.get(&handle) else {
+                            return Err(LuaError::external("invalid channel handle"));
+                        };
+
+                        let mut channels_consumers = sync_channels_consumers.lock()
+                            .map_err(|err| LuaError::external(format!("failed to read channel consumers: {err}")))?;
+
+                        let Some(consumers) = channels_consumers.get_mut(key) else {
+                            return Err(LuaError::external("invalid channel handle"));
+                        };
+
+                        let is_full = |consumer: &i32| {
+                            listeners.get(consumer).is_some_and(|(_, capacity, data)| {
+                                capacity.is_some_and(|capacity| data.len() >= capacity)
+                            })
+                        };
+
+                        let other_consumers = consumers.handles.iter()
+                            .filter(|consumer| *consumer != &handle);
+
+                        match consumers.mode {
+                            // Wait until all the bounded consumers have free space.
+                            ChannelMode::Broadcast => {
+                                if !other_consumers.clone().any(is_full) {
+                                    for consumer in other_consumers {
+                                        if let Some((_, _, data)) = listeners.get_mut(consumer) {
+                                            data.push_back(message.clone());
+                                        }
+                                    }
+
+                                    sync_channels_updates.notify_all();
+
+                                    return Ok(true);
+                                }
+                            }
 
-                    let consumers = sync_channels_consumers.lock()
-                        .map_err(|err| LuaError::external(format!("failed to read channel consumers: {err}")))?;
+                            // Wait until any of the consumers has free space.
+                            ChannelMode::Queue => {
+                                let len = consumers.handles.len();
 
-                    let Some(consumers) = consumers.get(key) else {
-                        return Err(LuaError::external("invalid channel handle"));
-                    };
+                                let next = (0..len)
+                                    .map(|i| (consumers.next + i) % len)
+                                    .find(|i| consumers.handles[*i] != handle && !is_full(&consumers.handles[*i]));
+
+                                if let Some(next) = next {
+                                    if let Some((_, _, data)) = listeners.get_mut(&consumers.handles[next]) {
+                                        data.push_back(message);
+                                    }
+
+                                    consumers.next = next + 1;
+
+                                    sync_channels_updates.notify_all();
 
-                    for consumer in consumers {
-                        if consumer != &handle {
-                            if let Some((_, data)) = listeners.get_mut(consumer) {
-                                data.push_back(message.clone());
+                                    return Ok(true);
+                                }
+
+                                // Nobody will receive the message.
+                                if other_consumers.count() == 0 {
+                                    return Ok(true);
+                                }
                             }
                         }
-                    }
 
-                    Ok(())
+                        // Don't block other channels while waiting.
+                        drop(channels_consumers);
+
+                        listeners = match deadline {
+                            Some(deadline) => {
+                                let now = Instant::now();
+
+                                if now >= deadline {
+                                    return Ok(false);
+                                }
+
+                                sync_channels_updates.wait_timeout(listeners, deadline - now)
+                                    .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?
+                                    .0
+                            }
+
+                            None => sync_channels_updates.wait(listeners)
+                                .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?
+                        };
+                    }
                 })?
             },
 
             sync_channel_recv: {
                 let sync_channels_data = sync_channels_data.clone();
+                let sync_channels_updates = sync_channels_updates.clone();
 
                 lua.create_function(move |lua, handle: i32| {
                     let mut listeners = sync_channels_data.lock()
                         .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?;
 
-                    let Some((_, data)) = listeners.get_mut(&handle) else {
+                    let Some((_, _, data)) = listeners.get_mut(&handle) else {
                         return Err(LuaError::external("invalid channel handle"));
                     };
 
                     match data.pop_front() {
-                        Some(message) => Ok((message.to_lua(lua)?, true)),
+                        Some(message) => {
+                            sync_channels_updates.notify_all();
+
+                            Ok((message.to_lua(lua)?, true))
+                        }
+
                         None => Ok((LuaNil, false))
                     }
                 })?
             },
 
+            // Note: this function blocks the whole lua engine thread until
+            // a message is sent from another thread.
+            sync_channel_recv_blocking: {
+                let sync_channels_data = sync_channels_data.clone();
+                let sync_channels_updates = sync_channels_updates.clone();
+
+                lua.create_function(move |lua, (handle, timeout): (i32, Option<u64>)| {
+                    let deadline = wait_timeout(timeout, max_wait).map(|timeout| Instant::now() + timeout);
+
+                    let mut listeners = sync_channels_data.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?;
+
+                    loop {
+                        let Some((_, _, data)) = listeners.get_mut(&handle) else {
+                            return Err(LuaError::external("invalid channel handle"));
+                        };
+
+                        if let Some(message) = data.pop_front() {
+                            sync_channels_updates.notify_all();
+
+                            return Ok((message.to_lua(lua)?, true));
+                        }
+
+                        listeners = match deadline {
+                            Some(deadline) => {
+                                let now = Instant::now();
+
+                                if now >= deadline {
+                                    return Ok((LuaNil, false));
+                                }
+
+                                sync_channels_updates.wait_timeout(listeners, deadline - now)
+                                    .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?
+                                    .0
+                            }
+
+                            None => sync_channels_updates.wait(listeners)
+                                .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?
+                        };
+                    }
+                })?
+            },
+
             sync_channel_close: {
                 let sync_channels_consumers = sync_channels_consumers.clone();
                 let sync_channels_data = sync_channels_data.clone();
+                let sync_channels_updates = sync_channels_updates.clone();
 
                 lua.create_function(move |_, handle: i32| {
-                    let mut consumers = sync_channels_consumers.lock()
-                        .map_err(|err| LuaError::external(format!("failed to read channel consumers: {err}")))?;
-
                     let mut listeners = sync_channels_data.lock()
                         .map_err(|err| LuaError::external(format!("failed to read channel listeners: {err}")))?;
 
-                    if let Some((hash, _)) = listeners.remove(&handle) {
+                    let mut consumers = sync_channels_consumers.lock()
+                        .map_err(|err| LuaError::external(format!("failed to read channel consumers: {err}")))?;
+
+                    if let Some((hash, _, _)) = listeners.remove(&handle) {
                         let mut empty = false;
 
-                        if let Some(listeners) = consumers.get_mut(&hash) {
-                            listeners.remove(&handle);
+                        if let Some(consumers) = consumers.get_mut(&hash) {
+                            consumers.handles.retain(|consumer| consumer != &handle);
 
-                            empty = listeners.is_empty();
+                            empty = consumers.handles.is_empty();
                         }
 
                         if empty {
@@ -202,6 +478,9 @@ impl SyncAPI {
                         }
                     }
 
+                    // Wake up senders waiting for this consumer.
+                    sync_channels_updates.notify_all();
+
                     Ok(())
                 })?
             },
@@ -231,37 +510,20 @@ impl SyncAPI {
                 let sync_mutex_consumers = sync_mutex_consumers.clone();
                 let sync_mutex_locks = sync_mutex_locks.clone();
 
-                lua.create_function(move |_, handle: i32| {
+                lua.create_function(move |_, (handle, timeout): (i32, Option<u64>)| {
                     let key = sync_mutex_consumers.lock()
                         .map_err(|err| LuaError::external(format!("failed to read mutex consumers: {err}")))?
                         .get(&handle)
                         .copied()
                         .ok_or_else(|| LuaError::external("invalid mutex handle"))?;
 
-                    loop {
-                        let mut locks = sync_mutex_locks.lock()
-                            .map_err(|err| LuaError::external(format!("failed to read mutex locks: {err}")))?;
-
-                        match locks.get_mut(&key) {
-                            Some(lock) => {
-                                if lock.is_none() {
-                                    *lock = Some(handle);
-
-                                    return Ok(());
-                                }
-                            }
-
-                            None => {
-                                locks.insert(key, Some(handle));
-
-                                return Ok(());
-                            }
-                        }
-
-                        drop(locks);
+                    let is_open = || {
+                        sync_mutex_consumers.lock()
+                            .map(|consumers| consumers.contains_key(&handle))
+                            .unwrap_or_default()
+                    };
 
-                        std::thread::sleep(MUTEX_LOCK_TIMEOUT);
-                    }
+                    sync_mutex_locks.lock(key, handle, wait_timeout(timeout, max_wait), is_open)
                 })?
             },
 
@@ -276,20 +538,7 @@ impl SyncAPI {
                         .copied()
                         .ok_or_else(|| LuaError::external("invalid mutex handle"))?;
 
-                    let mut locks = sync_mutex_locks.lock()
-                        .map_err(|err| LuaError::external(format!("failed to read mutex locks: {err}")))?;
-
-                    if let Some(lock) = locks.get_mut(&key) {
-                        if let Some(lock_handle) = lock {
-                            if *lock_handle != handle {
-                                return Err(LuaError::external("can't unlock mutex locked by another handle"));
-                            }
-
-                            *lock = None;
-                        }
-                    }
-
-                    Ok(())
+                    sync_mutex_locks.unlock(key, handle)
                 })?
             },
 
@@ -303,22 +552,50 @@ impl SyncAPI {
                         .remove(&handle);
 
                     if let Some(key) = key {
-                        let mut locks = sync_mutex_locks.lock()
-                            .map_err(|err| LuaError::external(format!("failed to read mutex locks: {err}")))?;
-
-                        if let Some(lock) = locks.get_mut(&key) {
-                            if let Some(lock_handle) = lock {
-                                if *lock_handle == handle {
-                                    *lock = None;
-                                }
-                            }
-                        }
+                        sync_mutex_locks.release(key, handle);
                     }
 
                     Ok(())
                 })?
             },
 
+            sync_once: lua.create_function(move |_, key: LuaString| {
+                let key = Hash::for_slice(key.as_bytes());
+
+                let mut keys = sync_once_keys.lock()
+                    .map_err(|err| LuaError::external(format!("failed to read once keys: {err}")))?;
+
+                Ok(keys.insert(key))
+            })?,
+
+            sync_counter: lua.create_function(move |lua, key: LuaString| {
+                let key = Hash::for_slice(key.as_bytes());
+
+                let counter = sync_counters.lock()
+                    .map_err(|err| LuaError::external(format!("failed to read counters: {err}")))?
+                    .entry(key)
+                    .or_default()
+                    .clone();
+
+                let counter_table = lua.create_table_with_capacity(0, 2)?;
+
+                counter_table.raw_set("add", {
+                    let counter = counter.clone();
+
+                    lua.create_function(move |_, n: Option<i64>| {
+                        let n = n.unwrap_or(1);
+
+                        Ok(counter.fetch_add(n, Ordering::SeqCst).wrapping_add(n))
+                    })?
+                })?;
+
+                counter_table.raw_set("get", lua.create_function(move |_, ()| {
+                    Ok(counter.load(Ordering::SeqCst))
+                })?)?;
+
+                Ok(counter_table)
+            })?,
+
             lua
         })
     }
@@ -330,19 +607,22 @@ impl SyncAPI {
 
     /// Create new lua table with API functions.
     pub fn create_env(&self) -> Result<LuaTable, PackagesEngineError> {
-        let env = self.lua.create_table_with_capacity(0, 2)?;
+        let env = self.lua.create_table_with_capacity(0, 4)?;
 
-        let sync_channel = self.lua.create_table_with_capacity(0, 4)?;
+        let sync_channel = self.lua.create_table_with_capacity(0, 5)?;
         let sync_mutex = self.lua.create_table_with_capacity(0, 4)?;
 
         env.raw_set("channel", sync_channel.clone())?;
         env.raw_set("mutex", sync_mutex.clone())?;
+        env.raw_set("once", self.sync_once.clone())?;
+        env.raw_set("counter", self.sync_counter.clone())?;
 
         // Channel
 
         sync_channel.raw_set("open", self.sync_channel_open.clone())?;
         sync_channel.raw_set("send", self.sync_channel_send.clone())?;
         sync_channel.raw_set("recv", self.sync_channel_recv.clone())?;
+        sync_channel.raw_set("recv_blocking", self.sync_channel_recv_blocking.clone())?;
         sync_channel.raw_set("close", self.sync_channel_close.clone())?;
 
         // Mutex
@@ -362,7 +642,7 @@ impl SyncAPI {
 
 //     #[test]
 //     fn sync_channels() -> anyhow::Result<()> {
-//         let api = SyncAPI::new(Lua::new())?;
+//         let api = SyncAPI::new(Lua::new(), None)?;
 
 //         assert!(api.sync_channel_send.call::<()>((0, String::new())).is_err());
 //         assert!(api.sync_channel_recv.call::<Option<String>>(0).is_err());
@@ -423,3 +703,229 @@ impl SyncAPI {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_once() -> Result<(), PackagesEngineError> {
+        let api = SyncAPI::new(Lua::new(), None)?;
+
+        assert!(api.sync_once.call::<bool>("test")?);
+        assert!(!api.sync_once.call::<bool>("test")?);
+        assert!(api.sync_once.call::<bool>("test 2")?);
+        assert!(!api.sync_once.call::<bool>("test 2")?);
+        assert!(!api.sync_once.call::<bool>("test")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_mutex() -> Result<(), PackagesEngineError> {
+        let api = SyncAPI::new(Lua::new(), None)?;
+
+        let a = api.sync_mutex_open.call::<i32>("test")?;
+        let b = api.sync_mutex_open.call::<i32>("test")?;
+        let c = api.sync_mutex_open.call::<i32>("test 2")?;
+
+        assert!(api.sync_mutex_lock.call::<bool>(a)?);
+        assert!(!api.sync_mutex_lock.call::<bool>((b, 10))?);
+        assert!(api.sync_mutex_lock.call::<bool>((c, 10))?);
+
+        assert!(api.sync_mutex_unlock.call::<()>(b).is_err());
+
+        api.sync_mutex_unlock.call::<()>(a)?;
+
+        assert!(api.sync_mutex_lock.call::<bool>((b, 10))?);
+        assert!(!api.sync_mutex_lock.call::<bool>((a, 10))?);
+
+        api.sync_mutex_close.call::<()>(b)?;
+
+        assert!(api.sync_mutex_lock.call::<bool>((a, 10))?);
+        assert!(api.sync_mutex_lock.call::<bool>(b).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_mutex_handoff() -> Result<(), LuaError> {
+        let locks = MutexLocks::default();
+        let key = Hash::for_slice(b"test");
+
+        assert!(locks.lock(key, 1, None, || true)?);
+
+        let waiter = {
+            let locks = locks.clone();
+
+            std::thread::spawn(move || {
+                let locked = locks.lock(key, 2, Some(Duration::from_secs(5)), || true)?;
+
+                Ok::<_, LuaError>((locked, Instant::now()))
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+
+        let unlocked_at = Instant::now();
+
+        locks.unlock(key, 1)?;
+
+        let (locked, locked_at) = waiter.join()
+            .expect("failed to join waiter thread")?;
+
+        assert!(locked);
+
+        // Previously waiters were polling the lock every 100 ms.
+        assert!(locked_at.duration_since(unlocked_at) < Duration::from_millis(20));
+
+        // Waiters stop when their handle is closed.
+        assert!(locks.lock(key, 3, None, || false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_channel_payloads() -> Result<(), PackagesEngineError> {
+        let lua = Lua::new();
+        let api = SyncAPI::new(lua.clone(), None)?;
+
+        let a = api.sync_channel_open.call::<i32>("test")?;
+        let b = api.sync_channel_open.call::<i32>("test")?;
+
+        let binary = lua.create_string([0x00, 0xFF, 0xFE, 0x80, 0x41])?;
+
+        // Integers above 2^53 are passed as doubles by the lua engine.
+        let large = 2_f64.powi(53) + 2.0;
+
+        api.sync_channel_send.call::<bool>((a, binary))?;
+        api.sync_channel_send.call::<bool>((a, large))?;
+        api.sync_channel_send.call::<bool>((a, LuaInteger::MAX))?;
+        api.sync_channel_send.call::<bool>((a, vec![0_u8, 1, 255]))?;
+
+        assert_eq!(api.sync_channel_recv.call::<LuaString>(b)?, [0x00, 0xFF, 0xFE, 0x80, 0x41]);
+        assert_eq!(api.sync_channel_recv.call::<f64>(b)?, large);
+        assert_eq!(api.sync_channel_recv.call::<LuaValue>(b)?, LuaValue::Integer(LuaInteger::MAX));
+        assert_eq!(api.sync_channel_recv.call::<Vec<u8>>(b)?, [0, 1, 255]);
+
+        // Wide integers don't wrap when converted to lua values.
+        let value = ChannelMessage::Integer(1 << 53).to_lua(&lua)?;
+
+        assert_eq!(value.as_f64(), Some(2_f64.powi(53)));
+        assert_eq!(ChannelMessage::from_lua(&value)?, ChannelMessage::Double(2_f64.powi(53)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_channel_modes() -> Result<(), PackagesEngineError> {
+        let api = SyncAPI::new(Lua::new(), None)?;
+
+        let recv = |handle: i32| api.sync_channel_recv.call::<Option<i32>>(handle);
+
+        // Broadcast.
+        let a = api.sync_channel_open.call::<i32>("broadcast")?;
+        let b = api.sync_channel_open.call::<i32>(("broadcast", LuaNil, "broadcast"))?;
+        let c = api.sync_channel_open.call::<i32>("broadcast")?;
+
+        assert!(api.sync_channel_open.call::<i32>(("broadcast", LuaNil, "queue")).is_err());
+
+        api.sync_channel_send.call::<bool>((a, 1))?;
+
+        assert_eq!(recv(a)?, None);
+        assert_eq!(recv(b)?, Some(1));
+        assert_eq!(recv(c)?, Some(1));
+
+        // Queue.
+        let a = api.sync_channel_open.call::<i32>(("queue", LuaNil, "queue"))?;
+        let b = api.sync_channel_open.call::<i32>("queue")?;
+        let c = api.sync_channel_open.call::<i32>(("queue", 1, "queue"))?;
+
+        assert!(api.sync_channel_open.call::<i32>(("queue", LuaNil, "broadcast")).is_err());
+        assert!(api.sync_channel_open.call::<i32>(("queue", LuaNil, "unknown")).is_err());
+
+        for i in 1..=4 {
+            assert!(api.sync_channel_send.call::<bool>((a, i))?);
+        }
+
+        // Bounded consumer is skipped when full.
+        assert_eq!(recv(a)?, None);
+        assert_eq!(recv(b)?, Some(1));
+        assert_eq!(recv(b)?, Some(3));
+        assert_eq!(recv(b)?, Some(4));
+        assert_eq!(recv(c)?, Some(2));
+        assert_eq!(recv(c)?, None);
+
+        api.sync_channel_close.call::<()>(b)?;
+        api.sync_channel_close.call::<()>(c)?;
+
+        // No receivers left.
+        assert!(api.sync_channel_send.call::<bool>((a, 5))?);
+        assert_eq!(recv(a)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_max_wait() -> Result<(), PackagesEngineError> {
+        let api = SyncAPI::new(Lua::new(), Some(Duration::from_millis(50)))?;
+
+        let a = api.sync_channel_open.call::<i32>(("test", 1))?;
+        let b = api.sync_channel_open.call::<i32>("test")?;
+
+        // Waits without timeout are capped.
+        let started_at = Instant::now();
+
+        assert_eq!(api.sync_channel_recv_blocking.call::<(LuaValue, bool)>(a)?, (LuaNil, false));
+
+        assert!(api.sync_channel_send.call::<bool>((b, 1))?);
+        assert!(!api.sync_channel_send.call::<bool>((b, 2))?);
+
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+
+        // Shorter timeouts are kept.
+        let started_at = Instant::now();
+
+        assert!(!api.sync_channel_send.call::<bool>((b, 3, 10))?);
+        assert!(started_at.elapsed() < Duration::from_millis(50));
+
+        // Mutex locks are capped too.
+        let c = api.sync_mutex_open.call::<i32>("test")?;
+        let d = api.sync_mutex_open.call::<i32>("test")?;
+
+        assert!(api.sync_mutex_lock.call::<bool>(c)?);
+        assert!(!api.sync_mutex_lock.call::<bool>((d, 60_000))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_counter() -> Result<(), PackagesEngineError> {
+        let api = SyncAPI::new(Lua::new(), None)?;
+
+        let a = api.sync_counter.call::<LuaTable>("test")?;
+        let b = api.sync_counter.call::<LuaTable>("test")?;
+        let c = api.sync_counter.call::<LuaTable>("test 2")?;
+
+        let add = |counter: &LuaTable, n: Option<i64>| {
+            counter.get::<LuaFunction>("add")?.call::<i64>(n)
+        };
+
+        let get = |counter: &LuaTable| {
+            counter.get::<LuaFunction>("get")?.call::<i64>(())
+        };
+
+        assert_eq!(get(&a)?, 0);
+        assert_eq!(add(&a, None)?, 1);
+        assert_eq!(add(&b, Some(5))?, 6);
+        assert_eq!(add(&a, Some(-2))?, 4);
+
+        assert_eq!(get(&a)?, 4);
+        assert_eq!(get(&b)?, 4);
+        assert_eq!(get(&c)?, 0);
+
+        assert_eq!(get(&api.sync_counter.call::<LuaTable>("test")?)?, 4);
+
+        Ok(())
+    }
+}
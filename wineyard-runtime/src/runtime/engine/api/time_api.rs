@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use mlua::prelude::*;
+
+use wineyard_core::tasks;
+
+use super::*;
+
+pub struct TimeAPI {
+    lua: Lua,
+
+    time_now: LuaFunction,
+    time_now_ms: LuaFunction,
+    time_monotonic_ms: LuaFunction,
+    time_sleep: LuaFunction
+}
+
+impl TimeAPI {
+    pub fn new(lua: Lua) -> Result<Self, PackagesEngineError> {
+        // Monotonic clock is measured from the API creation time.
+        let start = Instant::now();
+
+        Ok(Self {
+            time_now: lua.create_function(|_, ()| {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|err| LuaError::external(format!("failed to get current time: {err}")))?;
+
+                Ok(time.as_secs())
+            })?,
+
+            time_now_ms: lua.create_function(|_, ()| {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|err| LuaError::external(format!("failed to get current time: {err}")))?;
+
+                Ok(time.as_millis() as u64)
+            })?,
+
+            time_monotonic_ms: lua.create_function(move |_, ()| {
+                Ok(start.elapsed().as_millis() as u64)
+            })?,
+
+            // Note: this function blocks the whole lua engine thread
+            // because all the modules share the same lua state.
+            time_sleep: lua.create_function(|_, duration: u64| {
+                tasks::block_on(async move {
+                    tasks::time::sleep(Duration::from_millis(duration)).await;
+                });
+
+                Ok(())
+            })?,
+
+            lua
+        })
+    }
+
+    #[inline(always)]
+    pub const fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    /// Create new lua table with API functions.
+    pub fn create_env(&self) -> Result<LuaTable, PackagesEngineError> {
+        let env = self.lua.create_table_with_capacity(0, 4)?;
+
+        env.raw_set("now", self.time_now.clone())?;
+        env.raw_set("now_ms", self.time_now_ms.clone())?;
+        env.raw_set("monotonic_ms", self.time_monotonic_ms.clone())?;
+        env.raw_set("sleep", self.time_sleep.clone())?;
+
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time() -> Result<(), PackagesEngineError> {
+        let api = TimeAPI::new(Lua::new())?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let now_secs = api.time_now.call::<u64>(())?;
+        let now_ms = api.time_now_ms.call::<u64>(())?;
+
+        assert!(now_secs.abs_diff(now.as_secs()) <= 1);
+        assert!(now_ms.abs_diff(now.as_millis() as u64) <= 1000);
+        assert_eq!(now_ms / 1000, now_secs);
+
+        let start = api.time_monotonic_ms.call::<u64>(())?;
+
+        api.time_sleep.call::<()>(50)?;
+
+        let elapsed = api.time_monotonic_ms.call::<u64>(())? - start;
+
+        assert!(elapsed >= 50);
+        assert!(elapsed < 5000);
+
+        Ok(())
+    }
+}
@@ -1,16 +1,25 @@
 use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use std::path::PathBuf;
 use std::str::FromStr;
 
 use mlua::prelude::*;
+use mlua::{ChunkMode, Compiler};
+
+use wineyard_core::network::proxy::ProxyError;
+use wineyard_core::hashes::HashAlgorithm;
 
 use crate::hash::Hash;
-use crate::packages::lock_file::LockFile;
-use crate::packages::store::ResourceStore;
+use crate::packages::lock_file::{LockFile, ResourceLock};
+use crate::packages::store::{ResourceStore, ResourceStoreError};
+use crate::packages::graph::{DependencyKind, find_cycle};
 use crate::packages::manifest::{ResourceFormat, ResourceModuleFormat};
 
+use super::local_validator::{LocalValidator, LocalValidatorError};
+
 pub mod api;
 
 #[derive(Debug, thiserror::Error)]
@@ -21,18 +30,125 @@ pub enum PackagesEngineError {
     #[error("Lua engine error: {0}")]
     Lua(#[from] LuaError),
 
+    #[error(transparent)]
+    LocalValidator(#[from] LocalValidatorError),
+
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+
+    #[error(transparent)]
+    ResourceStore(#[from] ResourceStoreError),
+
     #[error("Failed to lock lua registry key")]
     LuaRegistryKeyLock,
 
+    #[error("Failed to lock temp entries list")]
+    TempEntriesLock,
+
     #[error("Invalid resource format: {0}")]
-    InvalidResourceFormat(String)
+    InvalidResourceFormat(String),
+
+    #[error("Resource {} has hash {}", expected.to_base32(), current.to_base32())]
+    HashMismatch {
+        expected: Hash,
+        current: Hash
+    },
+
+    #[error("Missing resources in the store: {}", .0.iter().map(Hash::to_base32).collect::<Vec<_>>().join(", "))]
+    MissingResources(Vec<Hash>),
+
+    #[error("Circular modules dependency: {}", .0.iter().map(Hash::to_base32).collect::<Vec<_>>().join(" -> "))]
+    CircularDependency(Vec<Hash>),
+
+    #[error("Resource {} exceeded {limit} limit", resource_hash.to_base32())]
+    ResourceLimitExceeded {
+        resource_hash: Hash,
+        limit: ResourceLimit
+    },
+
+    #[error("Resource not found: {0}")]
+    ResourceNotFound(String),
+
+    #[error("Resource {0} is not a module")]
+    NotAModule(String),
+
+    #[error("Module {resource} doesn't export function {name}")]
+    MissingModuleFunction {
+        resource: String,
+        name: String
+    }
+}
+
+/// Level of the log message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error
+}
+
+impl LogLevel {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info  => "info",
+            Self::Warn  => "warn",
+            Self::Error => "error"
+        }
+    }
+}
+
+impl std::fmt::Display for LogLevel {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Callback which receives log messages of the engine and its modules.
+pub type LogCallback = Arc<dyn Fn(LogLevel, String) + Send + Sync>;
+
+/// Send message to the log callback if it's set.
+#[inline]
+fn log(on_log: &Option<LogCallback>, level: LogLevel, message: impl FnOnce() -> String) {
+    if let Some(on_log) = on_log {
+        on_log(level, message());
+    }
+}
+
+/// Limit of the resources available to the lua engine.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    #[default]
+    Unlimited,
+
+    Bytes(u64)
+}
+
+/// Resource limit exceeded by a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceLimit {
+    Memory,
+    ExecutionTime
+}
+
+impl std::fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Memory        => write!(f, "memory"),
+            Self::ExecutionTime => write!(f, "execution time")
+        }
+    }
 }
 
 /// Packages engine options.
 pub struct Options {
     /// Path to the temporary storage folder. It will be used by runtime modules
     /// to store temporary files which will be automatically wiped out by either
-    /// operating system or some other logic.
+    /// operating system or the engine itself, see `clean_temp_on_drop`.
     pub temp_store_path: PathBuf,
 
     /// Path to the persistent storage folder. It will be used by runtime
@@ -43,7 +159,52 @@ pub struct Options {
     /// to store their own, private information, which couldn't be read by other
     /// modules. It should act like persistent storage in terms of data storage
     /// duration.
-    pub modules_store_path: PathBuf
+    pub modules_store_path: PathBuf,
+
+    /// Maximal amount of memory the lua engine can allocate.
+    /// The limit stays installed after the engine is created.
+    pub memory_limit: Limit,
+
+    /// Maximal time of a single module evaluation.
+    pub execution_timeout: Option<Duration>,
+
+    /// URL of the proxy used by the network and downloader APIs. Hosts from
+    /// the `NO_PROXY` env variable bypass it. Proxy env variables are used
+    /// if not set.
+    pub proxy: Option<String>,
+
+    /// Per-host limits of the network API requests.
+    pub network_limits: api::NetworkLimits,
+
+    /// Remove entries created by the modules in the temp storage folder when
+    /// the engine is dropped. The folder itself and entries which existed
+    /// before are kept, so it can be shared with other applications.
+    pub clean_temp_on_drop: bool,
+
+    /// Callback for the engine diagnostics and messages of the lua `dbg`
+    /// function. Works regardless of the `tracing` feature.
+    pub on_log: Option<LogCallback>
+}
+
+#[cfg(test)]
+impl Options {
+    /// Create options for tests with all the storage folders inside of the
+    /// given path and no limits.
+    pub fn test(path: impl AsRef<std::path::Path>) -> Self {
+        let path = path.as_ref();
+
+        Self {
+            temp_store_path: path.join("temp"),
+            persist_store_path: path.join("persist"),
+            modules_store_path: path.join("modules"),
+            memory_limit: Limit::Unlimited,
+            execution_timeout: None,
+            proxy: None,
+            network_limits: api::NetworkLimits::default(),
+            clean_temp_on_drop: false,
+            on_log: None
+        }
+    }
 }
 
 // pub struct PackagesEngineOptions {
@@ -52,24 +213,130 @@ pub struct Options {
 //     pub show_dialog: Box<dyn Fn(v1_standard::DialogOptions) -> Option<String> + Send>
 // }
 
+/// Find modules which can import each other in a loop and therefore can't be
+/// evaluated in dependency order, returning hashes of the modules forming
+/// the loop.
+///
+/// Modules are outputs of packages and can import inputs of their parent
+/// package: input modules and outputs of input packages.
+fn find_modules_cycle(lock_file: &LockFile) -> Option<Vec<Hash>> {
+    let graph = lock_file.graph();
+    let nodes = graph.nodes();
+
+    let is_module = |index: usize| matches!(nodes[index].format, ResourceFormat::Module(_));
+
+    let references = |index: usize, kind: DependencyKind| {
+        graph.edges()
+            .iter()
+            .filter(move |edge| edge.from == index && edge.kind == kind)
+            .map(|edge| edge.to)
+    };
+
+    // module_index => modules it can import
+    let mut dependencies = vec![Vec::new(); nodes.len()];
+
+    for package in nodes.iter().filter(|node| node.format == ResourceFormat::Package) {
+        let imports = references(package.index, DependencyKind::Input)
+            .flat_map(|input| {
+                let modules = match nodes[input].format {
+                    ResourceFormat::Module(_) => vec![input],
+                    ResourceFormat::Package => references(input, DependencyKind::Output).collect(),
+                    _ => vec![]
+                };
+
+                modules.into_iter().filter(|index| is_module(*index))
+            })
+            .collect::<Vec<_>>();
+
+        for output in references(package.index, DependencyKind::Output).filter(|index| is_module(*index)) {
+            dependencies[output].extend(imports.iter().copied());
+        }
+    }
+
+    let path = find_cycle(&dependencies)?;
+
+    Some(path.into_iter().map(|index| nodes[index].hash).collect())
+}
+
+/// Check if the lua error was caused by the memory limit.
+fn is_memory_error(err: &LuaError) -> bool {
+    match err {
+        LuaError::MemoryError(_) => true,
+        LuaError::CallbackError { cause, .. } => is_memory_error(cause),
+        LuaError::WithContext { cause, .. } => is_memory_error(cause),
+
+        _ => false
+    }
+}
+
+/// Algorithm of the digest stored next to the compiled luau bytecode.
+const BYTECODE_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha2_256;
+
+/// Read luau bytecode compiled by `PackagesEngine::compile_module` from
+/// the source module with the given hash.
+///
+/// Return `None` if there's no bytecode or if it doesn't match the digest
+/// stored next to it.
+fn read_bytecode(store: &ResourceStore, hash: &Hash) -> std::io::Result<Option<Vec<u8>>> {
+    let bytecode_path = store.get_bytecode_path(hash);
+
+    if !bytecode_path.is_file() {
+        return Ok(None);
+    }
+
+    let Ok(expected) = std::fs::read_to_string(store.get_bytecode_hash_path(hash)) else {
+        return Ok(None);
+    };
+
+    let bytecode = std::fs::read(bytecode_path)?;
+
+    if Hash::for_slice_with(BYTECODE_HASH_ALGORITHM, &bytecode)?.to_hex() != expected.trim() {
+        return Ok(None);
+    }
+
+    Ok(Some(bytecode))
+}
+
 pub struct PackagesEngine {
     lua: Lua,
     engine_registry: Arc<RwLock<LuaRegistryKey>>,
     lock_file: LockFile,
+    temp_entries: api::TempEntries,
+    clean_temp_on_drop: bool,
     _api: api::API
 }
 
 impl PackagesEngine {
     /// Create new packages engine and load all the resources from the provided
-    /// lock file with permissions granted in the provided authority indexes.
+    /// lock file with permissions granted in the provided local validator.
+    ///
+    /// Modules get privileges granted to their own hash and to the hash of
+    /// their parent package.
     pub fn create(
         lua: Lua,
         store: &ResourceStore,
         lock_file: LockFile,
-        // validator: AuthorityValidator,
-        // local_validator: LocalValidator,
+        local_validator: LocalValidator,
         options: Options
     ) -> Result<Self, PackagesEngineError> {
+        // Verify that all the resources are installed before loading them.
+        let missing_resources = store.missing_resources(&lock_file);
+
+        if !missing_resources.is_empty() {
+            return Err(PackagesEngineError::MissingResources(missing_resources));
+        }
+
+        // Modules are evaluated in dependency order which can't be done
+        // if they import each other.
+        if let Some(path) = find_modules_cycle(&lock_file) {
+            log(&options.on_log, LogLevel::Error, || format!(
+                "Circular modules dependency: {}",
+                path.iter().map(Hash::to_base32).collect::<Vec<_>>().join(" -> ")
+            ));
+
+            return Err(PackagesEngineError::CircularDependency(path));
+        }
+
         let engine_table = lua.create_table()?;
         let resources_table = lua.create_table()?;
 
@@ -87,6 +354,14 @@ impl PackagesEngine {
         let persist_store_path = options.persist_store_path.clone();
         let modules_store_path = options.modules_store_path.clone();
 
+        let memory_limit = options.memory_limit;
+        let execution_timeout = options.execution_timeout;
+        let on_log = options.on_log.clone();
+        let clean_temp_on_drop = options.clean_temp_on_drop;
+
+        // Entries created by all the modules in the temp folder.
+        let temp_entries = api::TempEntries::default();
+
         if !persist_store_path.exists() {
             std::fs::create_dir_all(&persist_store_path)?;
         }
@@ -162,8 +437,32 @@ impl PackagesEngine {
                 }
 
                 ResourceFormat::Module(standard) => {
-                    let module = std::fs::read(&path)?;
-                    let module = lua.load(module);
+                    // Bytecode is not validated by the luau VM so only
+                    // the one compiled locally by `compile_module` is loaded,
+                    // and the module resource itself is always a source code.
+                    let module = match read_bytecode(store, &resource.lock.hash)? {
+                        Some(bytecode) => lua.load(bytecode)
+                            .set_mode(ChunkMode::Binary),
+
+                        None => {
+                            let source = std::fs::read(&path)?;
+
+                            // Modified bytecode means that the store could
+                            // be tampered with so verify the source module.
+                            if store.get_bytecode_path(&resource.lock.hash).exists() {
+                                let hash = Hash::for_slice(&source);
+
+                                if hash != resource.lock.hash {
+                                    return Err(PackagesEngineError::HashMismatch {
+                                        expected: resource.lock.hash,
+                                        current: hash
+                                    });
+                                }
+                            }
+
+                            lua.load(source).set_mode(ChunkMode::Text)
+                        }
+                    };
 
                     let mut input_resources = vec![path.clone()];
                     let mut parent_hash = None;
@@ -229,25 +528,24 @@ impl PackagesEngine {
                             .join(resource.lock.hash.to_base32()),
 
                         input_resources,
+                        temp_entries: temp_entries.clone(),
 
                         ext_process_api: false,
                         ext_allowed_paths: vec![],
 
-                        // local_validator: local_validator.clone()
+                        local_validator: local_validator.clone()
                     };
 
-                    // Update values specified in the authority index.
-                    // for hash in [Some(resource.lock.hash), parent_hash].iter().flatten() {
-                    //     if let Some(ResourceStatus::Trusted { ext_process_api, allowed_paths, .. }) = validator.get_status(hash) {
-                    //         if ext_process_api == Some(true) {
-                    //             context.ext_process_api = true;
-                    //         }
+                    // Update values granted by the local validator.
+                    for hash in [Some(resource.lock.hash), parent_hash].iter().flatten() {
+                        if let Some(grants) = local_validator.get_grants(hash) {
+                            if grants.ext_process_api {
+                                context.ext_process_api = true;
+                            }
 
-                    //         if let Some(allowed_paths) = allowed_paths {
-                    //             context.ext_allowed_paths.extend(allowed_paths);
-                    //         }
-                    //     }
-                    // }
+                            context.ext_allowed_paths.extend(grants.allowed_paths);
+                        }
+                    }
 
                     #[cfg(feature = "tracing")]
                     tracing::debug!(
@@ -258,6 +556,12 @@ impl PackagesEngine {
                         "Building package module environment"
                     );
 
+                    log(&on_log, LogLevel::Debug, || format!(
+                        "Building package module environment: {} ({})",
+                        resource.url,
+                        resource.lock.hash.to_base32()
+                    ));
+
                     // Build the luau environment.
                     let env = api.create_env(&context)?;
 
@@ -283,11 +587,14 @@ impl PackagesEngine {
 
                             {
                                 let engine_registry = engine_registry.clone();
+                                let on_log = on_log.clone();
 
                                 env.set("load", lua.create_function(move |lua, name: String| {
                                     #[cfg(feature = "tracing")]
                                     tracing::trace!(?name, ?parent_context, "Loading package input");
 
+                                    log(&on_log, LogLevel::Trace, || format!("Loading package input: {name}"));
+
                                     // Read the parent package if it exists (must be at this point).
                                     if let Some(parent_context) = parent_context {
                                         let engine_registry = engine_registry.read()
@@ -360,11 +667,14 @@ impl PackagesEngine {
 
                             {
                                 let engine_registry = engine_registry.clone();
+                                let on_log = on_log.clone();
 
                                 env.set("import", lua.create_function(move |lua, name: String| {
                                     #[cfg(feature = "tracing")]
                                     tracing::trace!(?name, ?parent_context, "Importing package input");
 
+                                    log(&on_log, LogLevel::Trace, || format!("Importing package input: {name}"));
+
                                     // Read the parent package if it exists (must be at this point).
                                     if let Some(parent_context) = parent_context {
                                         let engine_registry = engine_registry.read()
@@ -444,7 +754,7 @@ impl PackagesEngine {
 
                     // Push module to the evaluation queue
                     // to execute dependencies first.
-                    evaluation_queue.push((resource_table, module, env));
+                    evaluation_queue.push((resource.lock.hash, resource_table, module, env));
                 }
 
                 ResourceFormat::File |
@@ -457,16 +767,79 @@ impl PackagesEngine {
         // Enable sandbox for modules execution.
         lua.sandbox(true)?;
 
+        // Zero means no limit for lua so at least one byte is set.
+        if let Limit::Bytes(memory_limit) = memory_limit {
+            lua.set_memory_limit(usize::try_from(memory_limit).unwrap_or(usize::MAX).max(1))?;
+        }
+
+        log(&on_log, LogLevel::Trace, || format!(
+            "Modules evaluation order: {}",
+            evaluation_queue.iter()
+                .map(|(hash, ..)| hash.to_base32())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+
         // Evaluate all the modules in dependency growth order.
-        for (resource_table, module, env) in evaluation_queue {
+        for (resource_hash, resource_table, module, env) in evaluation_queue {
             #[cfg(feature = "tracing")]
             tracing::debug!(
                 resource_table = format!("{resource_table:#?}"),
                 "Evaluating lua module"
             );
 
+            log(&on_log, LogLevel::Debug, || format!("Evaluating lua module: {}", resource_hash.to_base32()));
+
+            let timed_out = Arc::new(AtomicBool::new(false));
+
+            // Interrupt module execution if it takes too long.
+            if let Some(execution_timeout) = execution_timeout {
+                let deadline = Instant::now() + execution_timeout;
+                let timed_out = timed_out.clone();
+
+                lua.set_interrupt(move |_| {
+                    if Instant::now() > deadline {
+                        timed_out.store(true, Ordering::Release);
+
+                        return Err(LuaError::external("module execution timeout"));
+                    }
+
+                    Ok(LuaVmState::Continue)
+                });
+            }
+
             let value = module.set_environment(env)
-                .call::<LuaValue>(())?;
+                .call::<LuaValue>(());
+
+            lua.remove_interrupt();
+
+            let value = match value {
+                Ok(value) => value,
+
+                Err(_) if timed_out.load(Ordering::Acquire) => {
+                    log(&on_log, LogLevel::Error, || format!("Module {} exceeded execution time limit", resource_hash.to_base32()));
+
+                    return Err(PackagesEngineError::ResourceLimitExceeded {
+                        resource_hash,
+                        limit: ResourceLimit::ExecutionTime
+                    });
+                }
+
+                Err(err) if is_memory_error(&err) => {
+                    log(&on_log, LogLevel::Error, || format!("Module {} exceeded memory limit", resource_hash.to_base32()));
+
+                    return Err(PackagesEngineError::ResourceLimitExceeded {
+                        resource_hash,
+                        limit: ResourceLimit::Memory
+                    });
+                }
+
+                Err(err) => {
+                    log(&on_log, LogLevel::Error, || format!("Failed to evaluate module {}: {err}", resource_hash.to_base32()));
+
+                    return Err(err.into());
+                }
+            };
 
             resource_table.set("value", value)?;
         }
@@ -475,10 +848,98 @@ impl PackagesEngine {
             lua,
             engine_registry,
             lock_file,
+            temp_entries,
+            clean_temp_on_drop,
             _api: api
         })
     }
 
+    /// Remove entries created by the modules in the temp storage folder.
+    ///
+    /// Entries which existed before the modules started using them are kept
+    /// because the temp folder can be shared. Entries which can't be removed
+    /// now are kept to be removed by the next call.
+    pub fn clean_temp(&self) -> Result<(), PackagesEngineError> {
+        let mut temp_entries = self.temp_entries.lock()
+            .map_err(|_| PackagesEngineError::TempEntriesLock)?;
+
+        let mut result = Ok(());
+
+        temp_entries.retain(|path| {
+            let removed = if path.is_dir() && !path.is_symlink() {
+                std::fs::remove_dir_all(path)
+            } else if path.symlink_metadata().is_ok() {
+                std::fs::remove_file(path)
+            } else {
+                Ok(())
+            };
+
+            match removed {
+                Ok(()) => false,
+
+                Err(err) => {
+                    result = Err(err);
+
+                    true
+                }
+            }
+        });
+
+        Ok(result?)
+    }
+
+    /// Precompile the luau source module resource to bytecode and cache it
+    /// in the store next to the source module.
+    ///
+    /// Bytecode is loaded faster than the source code, so it's worth to
+    /// compile all the modules of the lock file after resolving it. The
+    /// source module is verified before compilation, and the bytecode is
+    /// removed by the store garbage collector together with it.
+    ///
+    /// Luau doesn't validate bytecode, so there's no bytecode module format
+    /// and packages can't ship precompiled modules. Only the bytecode
+    /// compiled by this method is loaded, and only if it matches the SHA-256
+    /// digest stored next to it. Otherwise the verified source module is
+    /// loaded instead.
+    ///
+    /// The store is locked while the bytecode is written so this method must
+    /// not be called while holding the store lock.
+    pub fn compile_module(
+        store: &ResourceStore,
+        resource: &ResourceLock
+    ) -> Result<(), PackagesEngineError> {
+        if !matches!(resource.format, ResourceFormat::Module(_)) {
+            return Err(PackagesEngineError::InvalidResourceFormat(resource.format.to_string()));
+        }
+
+        let source_path = store.get_path(&resource.lock.hash);
+        let hash = Hash::for_entry(&source_path)?;
+
+        if hash != resource.lock.hash {
+            return Err(PackagesEngineError::HashMismatch {
+                expected: resource.lock.hash,
+                current: hash
+            });
+        }
+
+        let bytecode = Compiler::new().compile(std::fs::read(source_path)?)?;
+        let digest = Hash::for_slice_with(BYTECODE_HASH_ALGORITHM, &bytecode)?;
+
+        let _lock = store.lock()?;
+
+        // Write the files to a temp path first so partially written
+        // bytecode is never loaded.
+        let temp_path = store.get_temp_path(&hash);
+
+        std::fs::write(&temp_path, digest.to_hex())?;
+        std::fs::rename(&temp_path, store.get_bytecode_hash_path(&hash))?;
+
+        std::fs::write(&temp_path, &bytecode)?;
+        std::fs::rename(temp_path, store.get_bytecode_path(&hash))?;
+
+        Ok(())
+    }
+
     /// Try to load root resources from the engine.
     ///
     /// Resource keys are taken from the lock file.
@@ -548,6 +1009,8 @@ impl PackagesEngine {
     /// This function will try to find the resource
     /// by given identifier. It can be a direct index
     /// to the resource, or a hash (or a part of hash).
+    /// Hashes can be given in base32, hex, multibase
+    /// or numeric form.
     pub fn load_resource(&self, identrifier: impl std::fmt::Display) -> Result<Option<LuaTable>, PackagesEngineError> {
         let engine_registry = self.engine_registry.read()
             .map_err(|_| PackagesEngineError::LuaRegistryKeyLock)?;
@@ -561,6 +1024,9 @@ impl PackagesEngine {
         let identifier = identrifier.to_string();
         let numeric_identifier = identifier.parse::<u64>().ok();
 
+        let hash_identifier = Hash::from_hex(&identifier)
+            .or_else(|| Hash::from_multibase(&identifier));
+
         // Try to directly load the resource.
         if let Some(index) = numeric_identifier {
             if resources.contains_key(index as u32)? {
@@ -568,9 +1034,15 @@ impl PackagesEngine {
             }
         }
 
-        // Otherwise search it through the whole list of resources.
-        for (id, resource) in resources.sequence_values::<LuaTable>().enumerate() {
-            let resource = resource?;
+        // Otherwise search it through the whole list of resources. Resources
+        // are keyed by their lock file indexes which start from 0 and can
+        // have gaps, so they're not a lua sequence.
+        let mut resources = resources.pairs::<u64, LuaTable>()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        resources.sort_by_key(|(id, _)| *id);
+
+        for (id, resource) in resources {
 
             // Check the base32 encoded hash.
             let hash = resource.get::<String>("hash")?;
@@ -579,9 +1051,16 @@ impl PackagesEngine {
                 return Ok(Some(resource));
             }
 
+            // Check hex or multibase encoded hash.
+            if let Some(hash_identifier) = hash_identifier
+                && Hash::from_base32(&hash) == Some(hash_identifier)
+            {
+                return Ok(Some(resource));
+            }
+
             // Or if can - check integer representation of the hash.
             if let Some(numeric_identifier) = numeric_identifier {
-                if id as u64 == numeric_identifier {
+                if id == numeric_identifier {
                     return Ok(Some(resource));
                 }
 
@@ -595,6 +1074,44 @@ impl PackagesEngine {
 
         Ok(None)
     }
+
+    /// Call a function exported by a module resource.
+    ///
+    /// Resource is searched by the given identifier the same way as in
+    /// `load_resource`. Then the function with the given name is taken from
+    /// the module's value and called with provided arguments.
+    pub fn call_module_function(
+        &self,
+        identifier: impl std::fmt::Display,
+        name: impl AsRef<str>,
+        args: impl IntoLuaMulti
+    ) -> Result<LuaValue, PackagesEngineError> {
+        let identifier = identifier.to_string();
+        let name = name.as_ref();
+
+        let Some(resource) = self.load_resource(&identifier)? else {
+            return Err(PackagesEngineError::ResourceNotFound(identifier));
+        };
+
+        if !resource.get::<LuaString>("format")?.as_bytes().starts_with(b"module") {
+            return Err(PackagesEngineError::NotAModule(identifier));
+        }
+
+        let function = match resource.get::<LuaValue>("value")? {
+            LuaValue::Table(value) => value.get::<LuaValue>(name)?,
+
+            _ => LuaValue::Nil
+        };
+
+        let LuaValue::Function(function) = function else {
+            return Err(PackagesEngineError::MissingModuleFunction {
+                resource: identifier,
+                name: name.to_string()
+            });
+        };
+
+        Ok(function.call::<LuaValue>(args)?)
+    }
 }
 
 impl Drop for PackagesEngine {
@@ -607,6 +1124,10 @@ impl Drop for PackagesEngine {
 
         let _ = self.lua.gc_collect();
         let _ = self.lua.gc_collect();
+
+        if self.clean_temp_on_drop {
+            let _ = self.clean_temp();
+        }
     }
 }
 
@@ -664,3 +1185,437 @@ impl Drop for PackagesEngine {
 //         Ok(())
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::packages::lock_file::{LockFileInfo, ResourceLockData};
+
+    use super::*;
+
+    #[test]
+    fn bytecode_modules() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-bytecode-modules-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("store"))?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let module = b"return { greeting = function(name) return `Hello, {name}!` end }";
+        let module_hash = Hash::for_slice(module);
+
+        std::fs::write(store.get_path(&module_hash), module)?;
+
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![ResourceLock {
+                lock: ResourceLockData {
+                    hash: module_hash,
+                    size: module.len() as u64
+                },
+                ..ResourceLock::test("module.lua", ResourceFormat::Module(ResourceModuleFormat::Luau), module_hash)
+            }]
+        };
+
+        PackagesEngine::compile_module(&store, &lock_file.resources[0])?;
+
+        let bytecode_path = store.get_bytecode_path(&module_hash);
+
+        assert!(bytecode_path.is_file());
+
+        let create_engine = |lock_file: LockFile| {
+            PackagesEngine::create(
+                Lua::new(),
+                &store,
+                lock_file,
+                LocalValidator::open(path.join("local_validator.json"))?,
+                Options::test(&path)
+            )
+        };
+
+        let engine = create_engine(lock_file.clone())?;
+
+        let value = engine.call_module_function(0, "greeting", "World")?;
+
+        assert_eq!(value.as_str().as_deref(), Some("Hello, World!"));
+
+        drop(engine);
+
+        // Compiled bytecode is loaded instead of the source module.
+        std::fs::write(store.get_path(&module_hash), b"return { greeting = function() return 'source' end }")?;
+
+        let engine = create_engine(lock_file.clone())?;
+
+        let value = engine.call_module_function(0, "greeting", "World")?;
+
+        assert_eq!(value.as_str().as_deref(), Some("Hello, World!"));
+
+        drop(engine);
+
+        // Modified bytecode is not executed and the source module is
+        // verified instead.
+        let bytecode = std::fs::read(&bytecode_path)?;
+
+        std::fs::write(&bytecode_path, Compiler::new().compile("return { greeting = function() return 'tampered' end }")?)?;
+
+        assert!(matches!(
+            create_engine(lock_file.clone()),
+            Err(PackagesEngineError::HashMismatch { .. })
+        ));
+
+        std::fs::write(store.get_path(&module_hash), module)?;
+
+        let engine = create_engine(lock_file.clone())?;
+
+        let value = engine.call_module_function(0, "greeting", "World")?;
+
+        assert_eq!(value.as_str().as_deref(), Some("Hello, World!"));
+
+        drop(engine);
+
+        std::fs::write(&bytecode_path, bytecode)?;
+        std::fs::write(store.get_path(&module_hash), b"return { greeting = function() return 'source' end }")?;
+
+        // Modified source modules are not compiled.
+        assert!(matches!(
+            PackagesEngine::compile_module(&store, &lock_file.resources[0]),
+            Err(PackagesEngineError::HashMismatch { .. })
+        ));
+
+        std::fs::remove_file(&bytecode_path)?;
+
+        let engine = create_engine(lock_file)?;
+
+        let value = engine.call_module_function(0, "greeting", "World")?;
+
+        assert_eq!(value.as_str().as_deref(), Some("source"));
+
+        drop(engine);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn circular_modules() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-circular-modules-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("store"))?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let resource = |url: &str, format, inputs: Option<(&str, u32)>, outputs: Option<(&str, u32)>| {
+            let hash = Hash::for_slice(url);
+
+            std::fs::write(store.get_path(&hash), format!("return import('{}')", url.replace(".lua", "")))?;
+
+            Ok::<_, std::io::Error>(ResourceLock {
+                inputs: inputs.map(|(name, key)| HashMap::from([(name.to_string(), key)])),
+                outputs: outputs.map(|(name, key)| HashMap::from([(name.to_string(), key)])),
+                ..ResourceLock::test(url, format, hash)
+            })
+        };
+
+        let module = ResourceFormat::Module(ResourceModuleFormat::Luau);
+
+        // Module "a" imports package "b" which imports package "a".
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![
+                resource("a", ResourceFormat::Package, Some(("b", 1)), Some(("module", 2)))?,
+                resource("b", ResourceFormat::Package, Some(("a", 0)), Some(("module", 3)))?,
+                resource("b.lua", module, None, None)?,
+                resource("a.lua", module, None, None)?
+            ]
+        };
+
+        let result = PackagesEngine::create(
+            Lua::new(),
+            &store,
+            lock_file.clone(),
+            LocalValidator::open(path.join("local_validator.json"))?,
+            Options::test(&path)
+        );
+
+        let Err(PackagesEngineError::CircularDependency(path_hashes)) = result else {
+            panic!("circular dependency error expected");
+        };
+
+        assert_eq!(path_hashes, [
+            lock_file.resources[2].lock.hash,
+            lock_file.resources[3].lock.hash,
+            lock_file.resources[2].lock.hash
+        ]);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_module_function() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-call-module-function-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("store"))?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let module = b"return { add = function(a, b) return a + b end, version = 1 }";
+        let module_hash = Hash::for_slice(module);
+
+        let file = b"Hello, World!";
+        let file_hash = Hash::for_slice(file);
+
+        std::fs::write(store.get_path(&module_hash), module)?;
+        std::fs::write(store.get_path(&file_hash), file)?;
+
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0, 1]
+            },
+            resources: vec![
+                ResourceLock {
+                    lock: ResourceLockData {
+                        hash: module_hash,
+                        size: module.len() as u64
+                    },
+                    ..ResourceLock::test("module.lua", ResourceFormat::Module(ResourceModuleFormat::Luau), module_hash)
+                },
+                ResourceLock {
+                    lock: ResourceLockData {
+                        hash: file_hash,
+                        size: file.len() as u64
+                    },
+                    ..ResourceLock::test_file("file.txt", file_hash)
+                }
+            ]
+        };
+
+        let engine = PackagesEngine::create(
+            Lua::new(),
+            &store,
+            lock_file,
+            LocalValidator::open(path.join("local_validator.json"))?,
+            Options::test(&path)
+        )?;
+
+        let value = engine.call_module_function(0, "add", (2, 3))?;
+
+        assert_eq!(value.as_i32(), Some(5));
+
+        // Resources can be found by their hash as well.
+        let value = engine.call_module_function(module_hash.to_base32(), "add", (4, 5))?;
+
+        assert_eq!(value.as_i32(), Some(9));
+
+        assert!(matches!(
+            engine.call_module_function(0, "version", ()),
+            Err(PackagesEngineError::MissingModuleFunction { resource, name }) if resource == "0" && name == "version"
+        ));
+
+        assert!(matches!(
+            engine.call_module_function(0, "missing", ()),
+            Err(PackagesEngineError::MissingModuleFunction { .. })
+        ));
+
+        assert!(matches!(
+            engine.call_module_function(1, "add", (2, 3)),
+            Err(PackagesEngineError::NotAModule(resource)) if resource == "1"
+        ));
+
+        assert!(matches!(
+            engine.call_module_function(2, "add", (2, 3)),
+            Err(PackagesEngineError::ResourceNotFound(resource)) if resource == "2"
+        ));
+
+        drop(engine);
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn resource_limits() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-resource-limits-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("store"))?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let create_engine = |module: &[u8], options: Options| {
+            let module_hash = Hash::for_slice(module);
+
+            std::fs::write(store.get_path(&module_hash), module)?;
+
+            let lock_file = LockFile {
+                lock: LockFileInfo {
+                    root: vec![0]
+                },
+                resources: vec![ResourceLock {
+                    lock: ResourceLockData {
+                        hash: module_hash,
+                        size: module.len() as u64
+                    },
+                    ..ResourceLock::test("module.lua", ResourceFormat::Module(ResourceModuleFormat::Luau), module_hash)
+                }]
+            };
+
+            let engine = PackagesEngine::create(
+                Lua::new(),
+                &store,
+                lock_file,
+                LocalValidator::open(path.join("local_validator.json"))?,
+                options
+            );
+
+            Ok::<_, PackagesEngineError>((module_hash, engine))
+        };
+
+        let allocate = b"local t = {} for i = 1, 1e8 do t[i] = string.rep('a', 1024) .. i end return t";
+        let block = b"while true do end return {}";
+
+        let (module_hash, engine) = create_engine(allocate, Options {
+            memory_limit: Limit::Bytes(64 * 1024 * 1024),
+            ..Options::test(&path)
+        })?;
+
+        assert!(matches!(
+            engine,
+            Err(PackagesEngineError::ResourceLimitExceeded { resource_hash, limit: ResourceLimit::Memory }) if resource_hash == module_hash
+        ));
+
+        let (module_hash, engine) = create_engine(block, Options {
+            execution_timeout: Some(Duration::from_millis(100)),
+            ..Options::test(&path)
+        })?;
+
+        assert!(matches!(
+            engine,
+            Err(PackagesEngineError::ResourceLimitExceeded { resource_hash, limit: ResourceLimit::ExecutionTime }) if resource_hash == module_hash
+        ));
+
+        // Limits don't affect modules which fit them.
+        let (_, engine) = create_engine(b"return { value = string.rep('a', 1024) }", Options {
+            memory_limit: Limit::Bytes(64 * 1024 * 1024),
+            execution_timeout: Some(Duration::from_secs(10)),
+            ..Options::test(&path)
+        })?;
+
+        assert!(engine.is_ok());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn clean_temp() -> Result<(), PackagesEngineError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-clean-temp-test");
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(path.join("store"))?;
+        std::fs::create_dir_all(path.join("temp"))?;
+
+        // Entries of the shared temp folder must be kept.
+        std::fs::write(path.join("temp/shared.txt"), "shared")?;
+
+        let store = ResourceStore::new(path.join("store"));
+
+        let module = br#"
+            fs.write_file(path.join(path.temp_dir(), "created", "file.txt"), "created")
+            fs.write_file(path.join(path.temp_dir(), "shared.txt"), "modified")
+
+            return {
+                write = function()
+                    fs.write_file(path.join(path.temp_dir(), "later.txt"), "later")
+                end,
+
+                temp_file = function()
+                    return fs.temp_file("helper-")
+                end
+            }
+        "#;
+
+        let module_hash = Hash::for_slice(module);
+
+        std::fs::write(store.get_path(&module_hash), module)?;
+
+        let lock_file = LockFile {
+            lock: LockFileInfo {
+                root: vec![0]
+            },
+            resources: vec![ResourceLock {
+                lock: ResourceLockData {
+                    hash: module_hash,
+                    size: module.len() as u64
+                },
+                ..ResourceLock::test("module.lua", ResourceFormat::Module(ResourceModuleFormat::Luau), module_hash)
+            }]
+        };
+
+        let engine = PackagesEngine::create(
+            Lua::new(),
+            &store,
+            lock_file,
+            LocalValidator::open(path.join("local_validator.json"))?,
+            Options {
+                clean_temp_on_drop: true,
+                ..Options::test(&path)
+            }
+        )?;
+
+        assert!(path.join("temp/created/file.txt").exists());
+
+        engine.clean_temp()?;
+
+        assert!(!path.join("temp/created").exists());
+        assert!(path.join("temp/shared.txt").exists());
+
+        engine.call_module_function(0, "write", ())?;
+
+        assert!(path.join("temp/later.txt").exists());
+
+        let temp_file = engine.call_module_function(0, "temp_file", ())?
+            .as_string()
+            .map(|path| PathBuf::from(path.to_string_lossy()))
+            .unwrap_or_default();
+
+        assert!(temp_file.exists());
+
+        drop(engine);
+
+        assert!(!path.join("temp/later.txt").exists());
+        assert!(!temp_file.exists());
+        assert!(path.join("temp/shared.txt").exists());
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use serde::{Serialize, Deserialize};
+
+use crate::hash::Hash;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LocalValidatorError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("Failed to lock local validator grants")]
+    Lock
+}
+
+/// Extended privileges granted to a resource.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceGrants {
+    /// Allow the resource to use Process API.
+    #[serde(default)]
+    pub ext_process_api: bool,
+
+    /// Extra paths accessible by the resource.
+    #[serde(default)]
+    pub allowed_paths: Vec<PathBuf>
+}
+
+/// Locally stored privileges granted to the resources by the user.
+///
+/// Grants are stored in a JSON file and updated every time they change.
+/// Cloned validators share the same grants table.
+#[derive(Debug, Clone)]
+pub struct LocalValidator {
+    path: PathBuf,
+    grants: Arc<RwLock<HashMap<Hash, ResourceGrants>>>
+}
+
+impl LocalValidator {
+    /// Open local validator file or create an empty validator
+    /// if it doesn't exist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, LocalValidatorError> {
+        let path: PathBuf = path.into();
+
+        let mut grants = HashMap::new();
+
+        if path.exists() {
+            let file = std::fs::read(&path)?;
+            let file = serde_json::from_slice::<HashMap<String, ResourceGrants>>(&file)?;
+
+            for (hash, resource_grants) in file {
+                if let Some(hash) = Hash::from_base32(hash) {
+                    grants.insert(hash, resource_grants);
+                }
+            }
+        }
+
+        Ok(Self {
+            path,
+            grants: Arc::new(RwLock::new(grants))
+        })
+    }
+
+    /// Get path to the local validator file.
+    #[inline(always)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get privileges granted to the resource.
+    pub fn get_grants(&self, hash: &Hash) -> Option<ResourceGrants> {
+        self.grants.read().ok()?
+            .get(hash)
+            .cloned()
+    }
+
+    /// Get extra paths accessible by the resource.
+    #[inline]
+    pub fn get_allowed_paths(&self, hash: &Hash) -> Option<Vec<PathBuf>> {
+        self.get_grants(hash)
+            .map(|grants| grants.allowed_paths)
+    }
+
+    /// Allow or disallow the resource to use Process API.
+    pub fn allow_process_api(&self, hash: Hash, allow: bool) -> Result<(), LocalValidatorError> {
+        self.grants.write()
+            .map_err(|_| LocalValidatorError::Lock)?
+            .entry(hash)
+            .or_default()
+            .ext_process_api = allow;
+
+        self.save()
+    }
+
+    /// Allow the resource to access given path.
+    pub fn allow_path(&self, hash: Hash, path: impl Into<PathBuf>) -> Result<(), LocalValidatorError> {
+        let path: PathBuf = path.into();
+
+        {
+            let mut grants = self.grants.write()
+                .map_err(|_| LocalValidatorError::Lock)?;
+
+            let allowed_paths = &mut grants.entry(hash)
+                .or_default()
+                .allowed_paths;
+
+            if allowed_paths.contains(&path) {
+                return Ok(());
+            }
+
+            allowed_paths.push(path);
+        }
+
+        self.save()
+    }
+
+    /// Remove all the privileges granted to the resource.
+    pub fn revoke(&self, hash: &Hash) -> Result<(), LocalValidatorError> {
+        self.grants.write()
+            .map_err(|_| LocalValidatorError::Lock)?
+            .remove(hash);
+
+        self.save()
+    }
+
+    /// Write grants to the local validator file.
+    fn save(&self) -> Result<(), LocalValidatorError> {
+        let grants = self.grants.read()
+            .map_err(|_| LocalValidatorError::Lock)?
+            .iter()
+            .map(|(hash, grants)| (hash.to_base32(), grants.clone()))
+            .collect::<HashMap<_, _>>();
+
+        if let Some(parent) = self.path.parent()
+            && !parent.is_dir()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&grants)?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants() -> Result<(), LocalValidatorError> {
+        let path = std::env::temp_dir().join(".wineyard-runtime-local-validator-test.json");
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let hash = Hash::for_slice(b"Hello, World!");
+
+        let validator = LocalValidator::open(&path)?;
+
+        assert_eq!(validator.get_grants(&hash), None);
+
+        validator.allow_process_api(hash, true)?;
+        validator.allow_path(hash, "/tmp")?;
+        validator.allow_path(hash, "/tmp")?;
+
+        let validator = LocalValidator::open(&path)?;
+
+        assert_eq!(validator.get_grants(&hash), Some(ResourceGrants {
+            ext_process_api: true,
+            allowed_paths: vec![PathBuf::from("/tmp")]
+        }));
+
+        validator.revoke(&hash)?;
+
+        assert_eq!(LocalValidator::open(&path)?.get_grants(&hash), None);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+}